@@ -1,24 +1,38 @@
 // analytics.rs
 use rusqlite::Connection;
-use chrono::Local;
-use crate::models::{Analytics, AppError};
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use crate::models::{Analytics, AppError, CommandRun, ComparisonRecord, DailyUsage, ErrorRecord, LatencyCorrelation, ModelFeedback, PluginRequest};
+use crate::db::Database;
+use crate::writer::DbCommand;
+use crate::keywords;
 
+// A gap longer than this between consecutive requests starts a new session
+pub const SESSION_GAP_MINUTES: i64 = 30;
+const RECENT_ERRORS: usize = 5;
+const MIN_TOPIC_CHARS: usize = 4;
+const MAX_SCATTER_POINTS: usize = 2000;
+
+// Rows a branch copied from its parent session (branched_from set) are left out of every
+// figure, each of those requests was only made once
 #[derive(Clone)]
 pub struct AnalyticsEngine {
-    db_path: PathBuf,
+    db: Database,
 }
 
 impl AnalyticsEngine {
-    pub fn new(db_path: PathBuf) -> Self {
-        Self { db_path }
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    // Opens its own connection to the database in `dir`. The app shares RagSystem's instead.
+    pub fn new_in(dir: impl Into<PathBuf>) -> Result<Self, AppError> {
+        Ok(Self::new(Database::open_in(&dir.into())?))
     }
 
     pub async fn get_analytics(&self) -> Result<Analytics, AppError> {
-        let db_path = self.db_path.clone();
-        
-        let analytics = tokio::task::spawn_blocking(move || -> Result<Analytics, AppError> {
-            let connection = Connection::open(&db_path)?;
+        self.db.read(|connection| {
             let mut analytics = Analytics::default();
             
             // Total requests
@@ -27,29 +41,283 @@ impl AnalyticsEngine {
             // Average response time
             analytics.avg_response_time = Self::get_avg_response_time(&connection)?;
             
+            // Streamed and buffered requests measure different things, so keep them apart
+            analytics.avg_streamed_response_time = Self::get_optional_average(
+                &connection,
+                "SELECT AVG(response_time_ms) FROM conversations WHERE first_token_ms IS NOT NULL AND branched_from IS NULL",
+            )?;
+            analytics.avg_buffered_response_time = Self::get_optional_average(
+                &connection,
+                "SELECT AVG(response_time_ms) FROM conversations WHERE first_token_ms IS NULL AND branched_from IS NULL",
+            )?;
+            analytics.avg_first_token_time = Self::get_optional_average(
+                &connection,
+                "SELECT AVG(first_token_ms) FROM conversations WHERE first_token_ms IS NOT NULL AND branched_from IS NULL",
+            )?;
+            
+            // Response time distribution
+            let response_times = Self::get_sorted_response_times(&connection)?;
+            analytics.min_response_time = response_times.first().copied().unwrap_or(0);
+            analytics.max_response_time = response_times.last().copied().unwrap_or(0);
+            analytics.p50_response_time = Self::percentile(&response_times, 50.0);
+            analytics.p90_response_time = Self::percentile(&response_times, 90.0);
+            analytics.p99_response_time = Self::percentile(&response_times, 99.0);
+            
             // Most used model
             analytics.most_used_model = Self::get_most_used_model(&connection)?;
             
-            // Sessions today
-            analytics.sessions_today = Self::get_sessions_today(&connection)?;
+            // Today's requests and sessions
+            let today = Self::get_timestamps_today(&connection)?;
+            let sessions = Self::split_sessions(&today);
+            analytics.requests_today = today.len();
+            analytics.sessions_today = sessions.len();
+            analytics.avg_session_minutes = if sessions.is_empty() {
+                0.0
+            } else {
+                let total: i64 = sessions.iter().map(|session| session.num_seconds()).sum();
+                total as f64 / sessions.len() as f64 / 60.0
+            };
+            
+            // Failures
+            let total_errors = Self::count_errors(&connection, None)?;
+            let total_ok = Self::count_successful_requests(&connection)?;
+            analytics.errors_today = Self::count_errors(&connection, Some(&Self::start_of_today()))?;
+            analytics.error_rate = if total_errors + total_ok > 0 {
+                total_errors as f64 / (total_errors + total_ok) as f64
+            } else {
+                0.0
+            };
+            analytics.recent_errors = Self::get_recent_errors(&connection, RECENT_ERRORS)?;
+            
+            // Token count
+            analytics.total_tokens = Self::get_token_count(&connection)?;
+            
+            analytics.feedback_by_model = Self::get_feedback_by_model(&connection)?;
             
-            // Approximate token count
-            analytics.total_tokens_approx = Self::get_token_count(&connection)?;
+            // Plugin generations, kept out of the request counts above
+            analytics.plugin_requests = Self::count_plugin_requests(&connection, None)?;
+            analytics.plugin_requests_today = Self::count_plugin_requests(&connection, Some(&Self::start_of_today()))?;
             
             Ok(analytics)
-        }).await.map_err(|e| AppError(e.to_string()))??;
+        }).await
+    }
+
+    // Requests and average response time per day for the last `days` days, oldest first
+    pub async fn get_daily_counts(&self, days: u32) -> Result<Vec<DailyUsage>, AppError> {
+        let today = Local::now().date_naive();
+        let first_day = today - Duration::days(days.max(1) as i64 - 1);
+        let cutoff = first_day
+            .and_hms_opt(0, 0, 0)
+            .and_then(|start| start.and_local_timezone(Local).earliest())
+            .unwrap_or_else(Local::now)
+            .to_rfc3339();
+
+        let rows = self.db.read(move |connection| {
+            // Range on the raw column so idx_conversations_timestamp is used. Buckets come from
+            // the stored local date prefix, DATE() would shift late-evening rows to UTC.
+            let mut stmt = connection.prepare(
+                "SELECT substr(timestamp, 1, 10) AS day, COUNT(*), AVG(response_time_ms)
+                 FROM conversations
+                 WHERE timestamp >= ?1 AND branched_from IS NULL
+                 GROUP BY day"
+            )?;
+            let rows = stmt.query_map([&cutoff], |row| {
+                let day: String = row.get(0)?;
+                let requests: i64 = row.get(1)?;
+                let avg: Option<f64> = row.get(2)?;
+                Ok((day, requests as usize, avg.unwrap_or(0.0)))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        }).await?;
+
+        let by_day: HashMap<NaiveDate, (usize, f64)> = rows
+            .into_iter()
+            .filter_map(|(day, requests, avg)| {
+                NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok().map(|date| (date, (requests, avg)))
+            })
+            .collect();
+
+        Ok(first_day
+            .iter_days()
+            .take_while(|date| *date <= today)
+            .map(|date| {
+                let (requests, avg_response_ms) = by_day.get(&date).copied().unwrap_or((0, 0.0));
+                DailyUsage { date, requests, avg_response_ms }
+            })
+            .collect())
+    }
+
+    // Most frequent prompt terms, optionally limited to the last `days` days
+    pub async fn top_keywords(&self, n: usize, days: Option<u32>) -> Result<Vec<(String, usize)>, AppError> {
+        let cutoff = days.map(|days| (Local::now() - Duration::days(days as i64)).to_rfc3339());
+        
+        self.db.read(move |connection| {
+            let mut stmt = connection.prepare(
+                "SELECT prompt FROM conversations WHERE branched_from IS NULL AND (?1 IS NULL OR timestamp >= ?1)"
+            )?;
+            let mut rows = stmt.query([&cutoff])?;
+            
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            while let Some(row) = rows.next()? {
+                let prompt: String = row.get(0)?;
+                for word in keywords::tokenize(&prompt) {
+                    if word.chars().count() >= MIN_TOPIC_CHARS {
+                        *counts.entry(word).or_insert(0) += 1;
+                    }
+                }
+            }
+            
+            let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            ranked.truncate(n);
+            Ok(ranked)
+        }).await
+    }
+
+    // Prompt tokens vs response time for successful requests in the last `days` days
+    pub async fn get_latency_correlation(&self, days: u32) -> Result<LatencyCorrelation, AppError> {
+        let cutoff = (Local::now() - Duration::days(days as i64)).to_rfc3339();
+        
+        let pairs = self.db.read(move |connection| {
+            // Legacy rows without stored counts fall back to the chars / 4 estimate
+            let mut stmt = connection.prepare(
+                "SELECT COALESCE(prompt_tokens, LENGTH(prompt) / 4), response_time_ms
+                 FROM conversations
+                 WHERE timestamp >= ?1 AND status != 'error' AND branched_from IS NULL
+                 ORDER BY timestamp"
+            )?;
+            let pairs = stmt.query_map([&cutoff], |row| {
+                let tokens: i64 = row.get(0)?;
+                let time: i64 = row.get(1)?;
+                Ok([tokens as f64, time as f64])
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+            Ok(pairs)
+        }).await?;
+        
+        let total_rows = pairs.len();
+        let (pearson, slope, intercept) = Self::linear_fit(&pairs);
+        
+        // Evenly spaced sample keeps the plot responsive, the fit above uses every row
+        let points = if total_rows > MAX_SCATTER_POINTS {
+            let step = total_rows as f64 / MAX_SCATTER_POINTS as f64;
+            (0..MAX_SCATTER_POINTS).map(|i| pairs[(i as f64 * step) as usize]).collect()
+        } else {
+            pairs
+        };
+        
+        Ok(LatencyCorrelation { points, total_rows, pearson, slope, intercept })
+    }
+
+    // Pearson correlation plus slope and intercept of the least-squares line
+    fn linear_fit(points: &[[f64; 2]]) -> (Option<f64>, f64, f64) {
+        let n = points.len() as f64;
+        if points.len() < 2 {
+            return (None, 0.0, points.first().map_or(0.0, |point| point[1]));
+        }
+        
+        let mean_x = points.iter().map(|point| point[0]).sum::<f64>() / n;
+        let mean_y = points.iter().map(|point| point[1]).sum::<f64>() / n;
+        let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+        for point in points {
+            let dx = point[0] - mean_x;
+            let dy = point[1] - mean_y;
+            covariance += dx * dy;
+            variance_x += dx * dx;
+            variance_y += dy * dy;
+        }
+        
+        if variance_x == 0.0 {
+            return (None, 0.0, mean_y);
+        }
+        let slope = covariance / variance_x;
+        let intercept = mean_y - slope * mean_x;
+        let pearson = (variance_y > 0.0).then(|| covariance / (variance_x.sqrt() * variance_y.sqrt()));
+        
+        (pearson, slope, intercept)
+    }
+
+    pub async fn record_error(&self, record: ErrorRecord) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::RecordError { record, reply }).await
+    }
+
+    pub async fn record_comparison(&self, record: ComparisonRecord) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::RecordComparison { record, reply }).await
+    }
+
+    pub async fn record_command(&self, run: CommandRun) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::RecordCommand { run, reply }).await
+    }
+
+    pub async fn record_plugin_request(&self, request: PluginRequest) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::RecordPluginRequest { request, reply }).await
+    }
+
+    fn count_plugin_requests(connection: &Connection, since: Option<&str>) -> Result<usize, AppError> {
+        let count: i64 = match since {
+            Some(since) => connection.query_row(
+                "SELECT COUNT(*) FROM plugin_requests WHERE timestamp >= ?1",
+                [since],
+                |row| row.get(0),
+            )?,
+            None => connection.query_row("SELECT COUNT(*) FROM plugin_requests", [], |row| row.get(0))?,
+        };
+        Ok(count as usize)
+    }
+
+    fn count_errors(connection: &Connection, since: Option<&str>) -> Result<usize, AppError> {
+        let count: i64 = match since {
+            Some(since) => connection.query_row(
+                "SELECT COUNT(*) FROM errors WHERE timestamp >= ?1",
+                [since],
+                |row| row.get(0),
+            )?,
+            None => connection.query_row("SELECT COUNT(*) FROM errors", [], |row| row.get(0))?,
+        };
+        Ok(count as usize)
+    }
+
+    fn count_successful_requests(connection: &Connection) -> Result<usize, AppError> {
+        let count: i64 = connection.query_row(
+            "SELECT COUNT(*) FROM conversations WHERE status = 'ok' AND branched_from IS NULL",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    fn get_recent_errors(connection: &Connection, limit: usize) -> Result<Vec<ErrorRecord>, AppError> {
+        let mut stmt = connection.prepare(
+            "SELECT timestamp, kind, message, model, url FROM errors ORDER BY timestamp DESC LIMIT ?1"
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
         
-        Ok(analytics)
+        rows.into_iter()
+            .map(|(timestamp, kind, message, model, url)| -> Result<ErrorRecord, AppError> {
+                let timestamp = DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Local);
+                Ok(ErrorRecord { timestamp, kind, message, model, url })
+            })
+            .collect()
     }
 
     fn get_total_requests(connection: &Connection) -> Result<usize, AppError> {
-        let mut stmt = connection.prepare("SELECT COUNT(*) FROM conversations")?;
+        let mut stmt = connection.prepare("SELECT COUNT(*) FROM conversations WHERE branched_from IS NULL")?;
         let total: i64 = stmt.query_row([], |row| row.get(0))?;
         Ok(total as usize)
     }
 
     fn get_avg_response_time(connection: &Connection) -> Result<f64, AppError> {
-        let mut stmt = connection.prepare("SELECT AVG(response_time_ms) FROM conversations")?;
+        let mut stmt = connection.prepare("SELECT AVG(response_time_ms) FROM conversations WHERE branched_from IS NULL")?;
         let avg = stmt.query_row([], |row| {
             let avg: Option<f64> = row.get(0)?;
             Ok(avg.unwrap_or(0.0))
@@ -57,9 +325,30 @@ impl AnalyticsEngine {
         Ok(avg)
     }
 
+    fn get_optional_average(connection: &Connection, sql: &str) -> Result<Option<f64>, AppError> {
+        let avg: Option<f64> = connection.query_row(sql, [], |row| row.get(0))?;
+        Ok(avg)
+    }
+
+    fn get_sorted_response_times(connection: &Connection) -> Result<Vec<i64>, AppError> {
+        let mut stmt = connection.prepare("SELECT response_time_ms FROM conversations WHERE branched_from IS NULL ORDER BY response_time_ms")?;
+        let times = stmt.query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(times)
+    }
+
+    // Nearest-rank percentile over an ascending slice
+    fn percentile(sorted: &[i64], percent: f64) -> i64 {
+        if sorted.is_empty() {
+            return 0;
+        }
+        let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
     fn get_most_used_model(connection: &Connection) -> Result<String, AppError> {
         let mut stmt = connection.prepare(
-            "SELECT model_used, COUNT(*) as count FROM conversations GROUP BY model_used ORDER BY count DESC LIMIT 1"
+            "SELECT model_used, COUNT(*) as count FROM conversations WHERE branched_from IS NULL GROUP BY model_used ORDER BY count DESC LIMIT 1"
         )?;
         let model = stmt.query_row([], |row| {
             let model: String = row.get(0)?;
@@ -68,18 +357,79 @@ impl AnalyticsEngine {
         Ok(model)
     }
 
-    fn get_sessions_today(connection: &Connection) -> Result<usize, AppError> {
-        let today = Local::now().format("%Y-%m-%d").to_string();
+    // Only rated responses count, models nobody rated are left out
+    fn get_feedback_by_model(connection: &Connection) -> Result<Vec<ModelFeedback>, AppError> {
         let mut stmt = connection.prepare(
-            "SELECT COUNT(DISTINCT DATE(timestamp)) FROM conversations WHERE DATE(timestamp) = ?1"
+            "SELECT model_used, SUM(feedback = 1), SUM(feedback = -1)
+             FROM conversations WHERE feedback != 0 AND branched_from IS NULL
+             GROUP BY model_used ORDER BY COUNT(*) DESC"
         )?;
-        let sessions: i64 = stmt.query_row([&today], |row| row.get(0))?;
-        Ok(sessions as usize)
+        let feedback = stmt
+            .query_map([], |row| {
+                Ok(ModelFeedback {
+                    model: row.get(0)?,
+                    positive: row.get::<_, i64>(1)? as usize,
+                    negative: row.get::<_, i64>(2)? as usize,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(feedback)
+    }
+
+    fn start_of_today() -> String {
+        Local::now()
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .and_then(|start| start.and_local_timezone(Local).earliest())
+            .unwrap_or_else(Local::now)
+            .to_rfc3339()
+    }
+
+    fn get_timestamps_today(connection: &Connection) -> Result<Vec<DateTime<Local>>, AppError> {
+        let start_of_day = Self::start_of_today();
+        
+        let mut stmt = connection.prepare(
+            "SELECT timestamp FROM conversations WHERE timestamp >= ?1 AND branched_from IS NULL ORDER BY timestamp"
+        )?;
+        let raw = stmt.query_map([&start_of_day], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        // Parsed in Rust rather than with DATE(), which would compare in UTC
+        let mut timestamps = raw.iter()
+            .map(|timestamp| DateTime::parse_from_rfc3339(timestamp).map(|t| t.with_timezone(&Local)))
+            .collect::<Result<Vec<_>, _>>()?;
+        timestamps.sort();
+        Ok(timestamps)
+    }
+
+    // Durations of each session in an ascending list of request timestamps
+    fn split_sessions(timestamps: &[DateTime<Local>]) -> Vec<Duration> {
+        let mut sessions = Vec::new();
+        let Some(&first) = timestamps.first() else {
+            return sessions;
+        };
+        
+        let mut session_start = first;
+        let mut previous = first;
+        for &timestamp in &timestamps[1..] {
+            if timestamp - previous > Duration::minutes(SESSION_GAP_MINUTES) {
+                sessions.push(previous - session_start);
+                session_start = timestamp;
+            }
+            previous = timestamp;
+        }
+        sessions.push(previous - session_start);
+        
+        sessions
     }
 
     fn get_token_count(connection: &Connection) -> Result<usize, AppError> {
-        let mut stmt = connection.prepare("SELECT SUM(LENGTH(prompt) + LENGTH(response)) FROM conversations")?;
-        let total_chars: Option<i64> = stmt.query_row([], |row| row.get(0))?;
-        Ok(total_chars.unwrap_or(0) as usize / 4) // Rough approximation
+        // Stored counts where available, chars / 4 for legacy rows
+        let mut stmt = connection.prepare(
+            "SELECT SUM(COALESCE(prompt_tokens + response_tokens, (LENGTH(prompt) + LENGTH(response)) / 4))
+             FROM conversations WHERE branched_from IS NULL"
+        )?;
+        let total: Option<i64> = stmt.query_row([], |row| row.get(0))?;
+        Ok(total.unwrap_or(0) as usize)
     }
 }
\ No newline at end of file
@@ -0,0 +1,177 @@
+// archive.rs
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path};
+use flate2::read::GzDecoder;
+use crate::file_handler::FileHandler;
+use crate::models::{AppError, ArchiveEntry, ArchiveListing, Attachment, LoadOptions};
+
+// Zip bomb guards. Entry sizes come from the archive's own headers, so extraction
+// also stops writing once the real bytes go over the total.
+const MAX_ENTRIES: usize = 1000;
+pub const MAX_EXTRACTED_BYTES: u64 = 200 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    TarGz,
+}
+
+fn format_of(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".zip") {
+        Some(Format::Zip)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Format::TarGz)
+    } else {
+        None
+    }
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    format_of(path).is_some()
+}
+
+// The files in an archive with their sizes, all selected. Directories, links and entries
+// whose paths would land outside the extraction directory are left out.
+pub fn list(path: &Path) -> Result<ArchiveListing, AppError> {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let mut entries = Vec::new();
+
+    match format_of(path) {
+        Some(Format::Zip) => {
+            let mut archive = open_zip(path)?;
+            if archive.len() > MAX_ENTRIES {
+                return Err(too_many_entries(&name));
+            }
+            for index in 0..archive.len() {
+                let file = archive.by_index(index).map_err(|e| AppError::Other(format!("{}: {}", name, e)))?;
+                if !file.is_file() {
+                    continue;
+                }
+                let Some(inner) = file.enclosed_name().map(|inner| inner.to_path_buf()) else {
+                    continue;
+                };
+                entries.push(ArchiveEntry { index, path: entry_name(&inner), size: file.size(), selected: true });
+            }
+        }
+        Some(Format::TarGz) => {
+            let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+            for (index, entry) in archive.entries()?.enumerate() {
+                if index >= MAX_ENTRIES {
+                    return Err(too_many_entries(&name));
+                }
+                let entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let inner = entry.path()?.to_path_buf();
+                if !is_enclosed(&inner) {
+                    continue;
+                }
+                entries.push(ArchiveEntry { index, path: entry_name(&inner), size: entry.size(), selected: true });
+            }
+        }
+        None => return Err(AppError::Other(format!("{} is not a zip or tar.gz archive", name))),
+    }
+
+    Ok(ArchiveListing { path: path.to_path_buf(), name, entries })
+}
+
+// Extracts the selected entries into a temporary directory, loads each through the normal
+// attachment path and removes the directory again. Each file's result is returned so one
+// unreadable entry doesn't stop the rest.
+pub fn extract(listing: &ArchiveListing, options: LoadOptions) -> Result<Vec<Result<Attachment, AppError>>, AppError> {
+    let selected: Vec<&ArchiveEntry> = listing.entries.iter().filter(|entry| entry.selected).collect();
+    let declared: u64 = selected.iter().map(|entry| entry.size).sum();
+    if declared > MAX_EXTRACTED_BYTES {
+        return Err(too_large(&listing.name));
+    }
+
+    // Removed with everything in it when dropped, including on an early return
+    let dir = tempfile::Builder::new().prefix("rustai-archive-").tempdir()?;
+    let mut budget = MAX_EXTRACTED_BYTES;
+    let mut extracted = Vec::new();
+
+    match format_of(&listing.path) {
+        Some(Format::Zip) => {
+            let mut archive = open_zip(&listing.path)?;
+            for entry in &selected {
+                let file = archive.by_index(entry.index).map_err(|e| AppError::Other(format!("{}: {}", listing.name, e)))?;
+                let target = dir.path().join(&entry.path);
+                write_limited(file, &target, &mut budget, &listing.name)?;
+                extracted.push((*entry, target));
+            }
+        }
+        Some(Format::TarGz) => {
+            let mut archive = tar::Archive::new(GzDecoder::new(File::open(&listing.path)?));
+            let mut wanted = selected.iter().copied().peekable();
+            for (index, entry) in archive.entries()?.enumerate() {
+                let Some(&next) = wanted.peek() else {
+                    break;
+                };
+                if next.index != index {
+                    continue;
+                }
+                let target = dir.path().join(&next.path);
+                write_limited(entry?, &target, &mut budget, &listing.name)?;
+                extracted.push((next, target));
+                wanted.next();
+            }
+        }
+        None => return Err(AppError::Other(format!("{} is not a zip or tar.gz archive", listing.name))),
+    }
+
+    Ok(extracted
+        .into_iter()
+        .map(|(entry, target)| {
+            let mut attachment = FileHandler::load_attachment(&target, options)?;
+            attachment.name = format!("{}/{}", listing.name, entry.path);
+            // The extracted copy is gone by the time anyone looks
+            attachment.path = Some(listing.path.clone());
+            attachment.modified = None;
+            Ok(attachment)
+        })
+        .collect())
+}
+
+fn open_zip(path: &Path) -> Result<zip::ZipArchive<File>, AppError> {
+    zip::ZipArchive::new(File::open(path)?).map_err(|e| AppError::Other(format!("{} is not a readable zip archive: {}", path.display(), e)))
+}
+
+// Copies at most what's left of the budget, one byte more shows the entry lied about its size
+fn write_limited(reader: impl Read, target: &Path, budget: &mut u64, archive: &str) -> Result<(), AppError> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = File::create(target)?;
+    let written = io::copy(&mut reader.take(*budget + 1), &mut out)?;
+    if written > *budget {
+        return Err(too_large(archive));
+    }
+    *budget -= written;
+    Ok(())
+}
+
+// No absolute paths or `..`, so joining onto the extraction directory stays inside it
+fn is_enclosed(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn too_many_entries(name: &str) -> AppError {
+    AppError::Other(format!("{} has more than {} entries and was not opened", name, MAX_ENTRIES))
+}
+
+fn too_large(name: &str) -> AppError {
+    AppError::Other(format!("{} would extract to more than {} MB and was not extracted", name, MAX_EXTRACTED_BYTES / (1024 * 1024)))
+}
@@ -0,0 +1,265 @@
+// backend.rs
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use tokio::sync::Notify;
+use crate::models::{AppError, ChatTurn, Generation};
+
+// Everything the app asks of a model server. OllamaClient talks to a real one, MockBackend
+// answers from a script so the rest of the app runs without one.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    // `num_predict` caps the response length, None leaves it to the model
+    async fn generate_response(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[Arc<str>],
+        num_predict: Option<u32>,
+    ) -> Result<String, AppError>;
+
+    // Answers the last of `messages` with the earlier ones as context
+    async fn chat(&self, model: &str, messages: &[ChatTurn], num_predict: Option<u32>) -> Result<String, AppError>;
+
+    // Like `generate_response`, but the model is held to a JSON reply
+    async fn generate_json(&self, model: &str, prompt: &str, num_predict: u32) -> Result<String, AppError>;
+
+    // Streams the response, passing each piece of text to `on_chunk` as it arrives.
+    // Notifying `cancel` stops reading and returns whatever text arrived so far.
+    async fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[Arc<str>],
+        cancel: &Notify,
+        on_chunk: &mut (dyn FnMut(String) + Send),
+    ) -> Result<Generation, AppError>;
+
+    async fn embed(&self, model: &str, text: &str) -> Result<Vec<f32>, AppError>;
+
+    // The num_ctx the model runs with, if the server knows it
+    async fn context_window(&self, model: &str) -> Result<Option<usize>, AppError>;
+
+    async fn list_models(&self) -> Result<Vec<String>, AppError>;
+
+    fn base_url(&self) -> &str;
+
+    // Stored with each saved conversation
+    fn name(&self) -> &'static str;
+
+    // The same kind of backend pointed at another server, swapped in when the URL changes
+    fn with_url(&self, url: String) -> Arc<dyn LlmBackend>;
+
+    // The same backend for work nobody is waiting on, which then never holds up a chat request
+    fn background(&self) -> Arc<dyn LlmBackend>;
+
+    // Requests waiting for their turn, for backends that queue them
+    fn queue_depth(&self) -> usize {
+        0
+    }
+}
+
+const MOCK_EMBEDDING_DIMS: usize = 64;
+
+enum MockStep {
+    Reply(String),
+    Fail(fn(&str) -> AppError),
+}
+
+// Scripted stand-in for a model server. Queued replies and failures are used up in order,
+// after that every request gets the default reply. Each request waits `latency` first.
+#[derive(Clone)]
+pub struct MockBackend {
+    script: Arc<Mutex<VecDeque<MockStep>>>,
+    prompts: Arc<Mutex<Vec<String>>>,
+    default_reply: String,
+    models: Vec<String>,
+    latency: Duration,
+    chunk_delay: Duration,
+    url: String,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self {
+            script: Arc::new(Mutex::new(VecDeque::new())),
+            prompts: Arc::new(Mutex::new(Vec::new())),
+            default_reply: "This is a mock response.".to_string(),
+            models: vec!["mock".to_string()],
+            latency: Duration::ZERO,
+            chunk_delay: Duration::ZERO,
+            url: "mock://".to_string(),
+        }
+    }
+
+    pub fn with_default_reply(mut self, reply: impl Into<String>) -> Self {
+        self.default_reply = reply.into();
+        self
+    }
+
+    pub fn with_models(mut self, models: Vec<String>) -> Self {
+        self.models = models;
+        self
+    }
+
+    // Wait before the first byte of every request
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    // Wait between the words of a streamed reply
+    pub fn with_chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+
+    pub fn then_reply(self, reply: impl Into<String>) -> Self {
+        self.push(MockStep::Reply(reply.into()));
+        self
+    }
+
+    // The next request fails with whatever `failure` builds from the model name,
+    // e.g. `|_| AppError::Timeout` or `|model| AppError::ModelNotFound(model.to_string())`
+    pub fn then_fail(self, failure: fn(&str) -> AppError) -> Self {
+        self.push(MockStep::Fail(failure));
+        self
+    }
+
+    // Every prompt and embedded text received so far, oldest first
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    fn push(&self, step: MockStep) {
+        self.script.lock().unwrap_or_else(PoisonError::into_inner).push_back(step);
+    }
+
+    async fn next_reply(&self, model: &str, prompt: &str) -> Result<String, AppError> {
+        tokio::time::sleep(self.latency).await;
+        self.prompts.lock().unwrap_or_else(PoisonError::into_inner).push(prompt.to_string());
+        let step = self.script.lock().unwrap_or_else(PoisonError::into_inner).pop_front();
+        match step {
+            Some(MockStep::Reply(reply)) => Ok(reply),
+            Some(MockStep::Fail(failure)) => Err(failure(model)),
+            None => Ok(self.default_reply.clone()),
+        }
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmBackend for MockBackend {
+    async fn generate_response(
+        &self,
+        model: &str,
+        prompt: &str,
+        _images: &[Arc<str>],
+        _num_predict: Option<u32>,
+    ) -> Result<String, AppError> {
+        self.next_reply(model, prompt).await
+    }
+
+    // Recorded under the last message's text, which is what the script answers
+    async fn chat(&self, model: &str, messages: &[ChatTurn], _num_predict: Option<u32>) -> Result<String, AppError> {
+        let prompt = messages.last().map_or("", |message| message.content.as_str());
+        self.next_reply(model, prompt).await
+    }
+
+    async fn generate_json(&self, model: &str, prompt: &str, _num_predict: u32) -> Result<String, AppError> {
+        self.next_reply(model, prompt).await
+    }
+
+    async fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        _images: &[Arc<str>],
+        cancel: &Notify,
+        on_chunk: &mut (dyn FnMut(String) + Send),
+    ) -> Result<Generation, AppError> {
+        let started = Instant::now();
+        let mut generation = Generation::default();
+        let reply = tokio::select! {
+            reply = self.next_reply(model, prompt) => reply?,
+            _ = cancel.notified() => {
+                generation.cancelled = true;
+                return Ok(generation);
+            }
+        };
+
+        for word in reply.split_inclusive(' ') {
+            generation.first_token_ms.get_or_insert(started.elapsed().as_millis() as i64);
+            generation.text.push_str(word);
+            on_chunk(word.to_string());
+            tokio::select! {
+                _ = tokio::time::sleep(self.chunk_delay) => {}
+                _ = cancel.notified() => {
+                    generation.cancelled = true;
+                    return Ok(generation);
+                }
+            }
+        }
+        Ok(generation)
+    }
+
+    async fn embed(&self, model: &str, text: &str) -> Result<Vec<f32>, AppError> {
+        // Queued failures apply to embeddings too, queued replies are left for generations
+        let failing = matches!(self.script.lock().unwrap_or_else(PoisonError::into_inner).front(), Some(MockStep::Fail(_)));
+        if failing {
+            return self.next_reply(model, text).await.map(|_| Vec::new());
+        }
+        tokio::time::sleep(self.latency).await;
+        self.prompts.lock().unwrap_or_else(PoisonError::into_inner).push(text.to_string());
+        Ok(mock_embedding(text))
+    }
+
+    async fn context_window(&self, _model: &str) -> Result<Option<usize>, AppError> {
+        Ok(None)
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AppError> {
+        tokio::time::sleep(self.latency).await;
+        Ok(self.models.clone())
+    }
+
+    fn base_url(&self) -> &str {
+        &self.url
+    }
+
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    // Shares the script, only the reported URL changes
+    fn with_url(&self, url: String) -> Arc<dyn LlmBackend> {
+        Arc::new(Self { url, ..self.clone() })
+    }
+
+    fn background(&self) -> Arc<dyn LlmBackend> {
+        Arc::new(self.clone())
+    }
+}
+
+// Words hashed into buckets and normalized, so texts sharing words come out similar
+fn mock_embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; MOCK_EMBEDDING_DIMS];
+    for word in text.split_whitespace() {
+        let hash = word
+            .to_lowercase()
+            .bytes()
+            .fold(0usize, |hash, byte| hash.wrapping_mul(31).wrapping_add(byte as usize));
+        vector[hash % MOCK_EMBEDDING_DIMS] += 1.0;
+    }
+    let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|value| *value /= norm);
+    }
+    vector
+}
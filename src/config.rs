@@ -0,0 +1,412 @@
+// config.rs
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use crate::models::{AppError, BackendKind, SessionSnapshot, TextDump};
+use crate::ollama;
+use crate::openai;
+use crate::plugins::RegexRule;
+
+pub const DATA_DIR: &str = "./tourist_data";
+const CONFIG_FILE: &str = "config.json";
+const DRAFT_FILE: &str = "draft.txt";
+const RECOVERY_FILE: &str = "recovery.json";
+
+// Set once at startup by --data-dir, so separate profiles don't share history or settings
+static DATA_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+pub const MIN_ZOOM: f32 = 0.8;
+pub const MAX_ZOOM: f32 = 1.6;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct WindowGeometry {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+}
+
+// User preferences that survive restarts. Missing fields fall back to their defaults,
+// so older config files keep loading as new settings are added.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AppConfig {
+    pub window: Option<WindowGeometry>,
+    pub show_sidebar: bool,
+    pub show_history: bool,
+    pub open_sections: Vec<String>,
+    pub zoom: f32,
+    // Shown as clickable cards on the empty chat screen
+    pub example_prompts: Vec<String>,
+    // Longer answers are folded behind "Show more", 0 never folds
+    pub collapse_after_lines: usize,
+    // Context window assumed for models that don't set num_ctx
+    pub default_num_ctx: usize,
+    // Desktop notification when a response finishes while the window is in the background
+    pub notify_on_finish: bool,
+    pub notify_sound: bool,
+    // A second, short generation after each answer, off by default for the GPU time
+    pub suggest_follow_ups: bool,
+    // Failed generations are saved too, for debugging. They are never used as RAG context.
+    pub keep_failed_generations: bool,
+    // Send earlier messages through the chat endpoint, trimmed to fit the context window
+    pub chat_history: bool,
+    // Sent first with every chat request, empty sends none
+    pub system_prompt: String,
+    // Messages always sent however long they are, counting the new prompt
+    pub history_keep_turns: usize,
+    // Blend embedding similarity into RAG retrieval, weighted against keyword matches
+    pub rag_hybrid: bool,
+    // Share of a hybrid score from keywords, the rest from vectors
+    pub hybrid_keyword_weight: f32,
+    // Plugin names in the order they run, plugins not listed run after these
+    pub plugin_order: Vec<String>,
+    // Find/replace rules for the regex rewrite plugin, applied in order
+    pub regex_rules: Vec<RegexRule>,
+    // Each plugin's own settings, keyed by plugin name
+    pub plugin_settings: BTreeMap<String, Value>,
+    // Larger text files are refused or cut down to this size
+    pub max_attachment_kb: u64,
+    pub text_dump: TextDump,
+    // Cell outputs are included when a notebook is attached
+    pub notebook_outputs: bool,
+    // How often the open chat is written to the recovery file, 0 only saves when a message is added
+    pub autosave_seconds: u64,
+    pub backend: BackendKind,
+    // Root of an OpenAI-style API, e.g. LM Studio's http://localhost:1234/v1
+    pub openai_url: String,
+    // Sent as a bearer token, empty sends none
+    pub openai_api_key: String,
+    // The local HTTP API, off unless turned on in settings
+    pub api_enabled: bool,
+    pub api_bind: String,
+    // Required as a bearer token on every request, only optional on a loopback address
+    pub api_token: String,
+    // Requests to Ollama at the same time, the rest wait their turn
+    pub ollama_max_concurrent: usize,
+    // Least time between the starts of two Ollama requests
+    pub ollama_min_interval_ms: u64,
+    // Every saved conversation is POSTed here, empty turns it off
+    pub webhook_url: String,
+    // Sent in the X-Webhook-Secret header, empty sends none
+    pub webhook_secret: String,
+    // Folder notes are exported to, empty until one is chosen
+    pub obsidian_vault: String,
+    // Re-export the open session after every answer
+    pub obsidian_auto_export: bool,
+    // Text-to-speech voice id, empty for the system default
+    pub tts_voice: String,
+    // Multiple of the voice's normal speed
+    pub tts_rate: f32,
+    // A whisper.cpp ggml model file for voice input
+    pub whisper_model: String,
+    // Send the prompt as soon as it's transcribed
+    pub voice_auto_send: bool,
+    // Encrypt the database at the next start. The passphrase itself is never saved.
+    pub encrypt_database: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            window: None,
+            show_sidebar: false,
+            show_history: false,
+            open_sections: Vec::new(),
+            zoom: 1.0,
+            example_prompts: vec![
+                "Explain this error message: ".to_string(),
+                "Summarize the attached file".to_string(),
+                "Write a unit test for this function: ".to_string(),
+                "What are the trade-offs between ".to_string(),
+                "Review this code for bugs: ".to_string(),
+                "Draft a short, friendly email about ".to_string(),
+            ],
+            collapse_after_lines: 25,
+            default_num_ctx: 2048,
+            notify_on_finish: true,
+            notify_sound: false,
+            suggest_follow_ups: false,
+            keep_failed_generations: false,
+            chat_history: false,
+            system_prompt: String::new(),
+            history_keep_turns: 4,
+            rag_hybrid: false,
+            hybrid_keyword_weight: 0.5,
+            plugin_order: Vec::new(),
+            regex_rules: Vec::new(),
+            plugin_settings: BTreeMap::new(),
+            max_attachment_kb: 1024,
+            text_dump: TextDump::default(),
+            notebook_outputs: true,
+            autosave_seconds: 30,
+            backend: BackendKind::Ollama,
+            openai_url: openai::DEFAULT_URL.to_string(),
+            openai_api_key: String::new(),
+            api_enabled: false,
+            api_bind: "127.0.0.1:8765".to_string(),
+            api_token: String::new(),
+            ollama_max_concurrent: ollama::DEFAULT_MAX_CONCURRENT,
+            ollama_min_interval_ms: 0,
+            webhook_url: String::new(),
+            webhook_secret: String::new(),
+            obsidian_vault: String::new(),
+            obsidian_auto_export: false,
+            tts_voice: String::new(),
+            tts_rate: 1.0,
+            whisper_model: String::new(),
+            voice_auto_send: false,
+            encrypt_database: false,
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn path() -> PathBuf {
+        data_dir().join(CONFIG_FILE)
+    }
+
+    // A missing or unreadable config is not fatal, the app starts with defaults
+    pub fn load() -> Self {
+        Self::load_from(&Self::path())
+    }
+
+    pub fn load_from(path: &Path) -> Self {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            eprintln!("Ignoring invalid config {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), AppError> {
+        self.save_to(&Self::path())
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), AppError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| AppError::Other(format!("Failed to serialize config: {}", e)))?;
+        
+        // Write then rename so a crash mid-write never leaves a truncated config
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
+// Must be called before anything is loaded, later calls are ignored
+pub fn set_data_dir(dir: PathBuf) {
+    if let Err(dir) = DATA_DIR_OVERRIDE.set(dir) {
+        eprintln!("Data directory already chosen, ignoring {}", dir.display());
+    }
+}
+
+// Where the config, drafts and database live
+pub fn data_dir() -> &'static Path {
+    DATA_DIR_OVERRIDE.get().map(PathBuf::as_path).unwrap_or(Path::new(DATA_DIR))
+}
+
+// The unsent input, kept outside the config so typing never rewrites preferences
+pub fn load_draft() -> Option<String> {
+    fs::read_to_string(data_dir().join(DRAFT_FILE))
+        .ok()
+        .filter(|draft| !draft.trim().is_empty())
+}
+
+pub fn save_draft(draft: &str) -> Result<(), AppError> {
+    let path = data_dir().join(DRAFT_FILE);
+    if draft.trim().is_empty() {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        return Ok(());
+    }
+    
+    fs::create_dir_all(data_dir())?;
+    fs::write(&path, draft)?;
+    Ok(())
+}
+
+// Left behind only when the app didn't exit cleanly
+pub fn load_recovery() -> Option<SessionSnapshot> {
+    load_recovery_in(data_dir())
+}
+
+pub fn load_recovery_in(dir: &Path) -> Option<SessionSnapshot> {
+    let path = dir.join(RECOVERY_FILE);
+    let content = fs::read_to_string(&path).ok()?;
+    serde_json::from_str(&content)
+        .map_err(|e| eprintln!("Ignoring invalid recovery file {}: {}", path.display(), e))
+        .ok()
+}
+
+pub fn save_recovery(snapshot: &SessionSnapshot) -> Result<(), AppError> {
+    save_recovery_in(data_dir(), snapshot)
+}
+
+pub fn save_recovery_in(dir: &Path, snapshot: &SessionSnapshot) -> Result<(), AppError> {
+    let path = dir.join(RECOVERY_FILE);
+    fs::create_dir_all(dir)?;
+    
+    let content = serde_json::to_string(snapshot)
+        .map_err(|e| AppError::Other(format!("Failed to serialize session: {}", e)))?;
+    
+    // Same write-then-rename as the config, a crash mid-write keeps the previous snapshot
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, &path)?;
+    Ok(())
+}
+
+pub fn clear_recovery() -> Result<(), AppError> {
+    let path = data_dir().join(RECOVERY_FILE);
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::file_handler::FileHandler;
+    use crate::models::ChatMessage;
+    use chrono::Local;
+    use crate::plugins::{CommandQueue, ModelAccess, PluginManager, RuleSet, Stage};
+    use serde_json::json;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn plugins(config: &AppConfig) -> PluginManager {
+        let rules = RuleSet::default();
+        assert!(rules.replace(&config.regex_rules).iter().all(Option::is_none));
+        let models = ModelAccess::new(Arc::new(MockBackend::new()), None);
+        let mut manager = PluginManager::with_defaults(rules, CommandQueue::default(), models);
+        manager.set_order(&config.plugin_order);
+        manager.apply_configs(&config.plugin_settings);
+        manager
+    }
+
+    fn settings(manager: &mut PluginManager) -> BTreeMap<String, Value> {
+        manager.plugins_mut().map(|plugin| (plugin.name().to_string(), plugin.config())).collect()
+    }
+
+    #[test]
+    fn plugin_settings_survive_save_and_load() {
+        let mut config = AppConfig {
+            regex_rules: vec![RegexRule {
+                pattern: r"(?i)\bcolour\b".to_string(),
+                replacement: "color".to_string(),
+                stage: Stage::PostResponse,
+            }],
+            ..Default::default()
+        };
+        config.plugin_settings.insert("Commands".to_string(), json!({ "allowed_programs": "cargo, just" }));
+        config.plugin_settings.insert("JSON extractor".to_string(), json!({ "retry": false, "replace": true }));
+        config.plugin_settings.insert("Rust formatter".to_string(), json!({ "rustfmt_path": "/opt/rust/bin/rustfmt" }));
+        config.plugin_settings.insert(
+            "Translator".to_string(),
+            json!({ "target_language": "German", "model": "qwen", "min_confidence": 70 }),
+        );
+
+        let mut manager = plugins(&config);
+        let mut order = manager.order();
+        order.reverse();
+        manager.set_order(&order);
+        config.plugin_order = manager.order();
+        // What the plugins report back is what the settings panel saves
+        config.plugin_settings = settings(&mut manager);
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("profile").join(CONFIG_FILE);
+        config.save_to(&path).unwrap();
+        let loaded = AppConfig::load_from(&path);
+        assert_eq!(loaded, config);
+
+        let mut reloaded = plugins(&loaded);
+        assert_eq!(reloaded.order(), order);
+        assert_eq!(settings(&mut reloaded), config.plugin_settings);
+        for (name, expected) in [
+            ("Commands", json!({ "allowed_programs": "cargo, just" })),
+            ("JSON extractor", json!({ "retry": false, "replace": true })),
+            ("Rust formatter", json!({ "rustfmt_path": "/opt/rust/bin/rustfmt" })),
+            ("Translator", json!({ "target_language": "German", "model": "qwen", "min_confidence": 70 })),
+        ] {
+            assert_eq!(loaded.plugin_settings[name], expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn missing_or_invalid_file_loads_defaults() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(AppConfig::load_from(&dir.path().join(CONFIG_FILE)), AppConfig::default());
+
+        let path = dir.path().join("broken.json");
+        fs::write(&path, "{ not json").unwrap();
+        assert_eq!(AppConfig::load_from(&path), AppConfig::default());
+    }
+
+    #[test]
+    fn keep_failed_generations_is_saved_and_defaults_off() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+
+        // Written before the setting existed
+        fs::write(&path, r#"{ "suggest_follow_ups": true }"#).unwrap();
+        assert!(!AppConfig::load_from(&path).keep_failed_generations);
+
+        let config = AppConfig { keep_failed_generations: true, ..AppConfig::default() };
+        config.save_to(&path).unwrap();
+        assert!(AppConfig::load_from(&path).keep_failed_generations);
+    }
+
+    #[test]
+    fn recovery_snapshot_round_trips() {
+        let dir = TempDir::new().unwrap();
+        let message: ChatMessage = serde_json::from_value(json!({
+            "content": "What does `?` do in Rust?",
+            "is_user": true,
+            "timestamp": "2026-10-16T09:30:00+02:00",
+        }))
+        .unwrap();
+        let snapshot = SessionSnapshot {
+            saved_at: Local::now(),
+            messages: vec![message],
+            attachments: vec![Arc::new(FileHandler::attachment_from_text("fn main() {}\n".to_string()))],
+            draft: "and for Option?".to_string(),
+            model: "llama3".to_string(),
+            session_id: "session-42".to_string(),
+        };
+
+        save_recovery_in(dir.path(), &snapshot).unwrap();
+        let loaded = load_recovery_in(dir.path()).unwrap();
+
+        assert_eq!(loaded.saved_at, snapshot.saved_at);
+        assert_eq!(loaded.draft, "and for Option?");
+        assert_eq!(loaded.model, "llama3");
+        assert_eq!(loaded.session_id, "session-42");
+        assert_eq!(loaded.messages.len(), 1);
+        assert_eq!(loaded.messages[0].content, "What does `?` do in Rust?");
+        assert!(loaded.messages[0].is_user);
+        assert_eq!(loaded.attachments.len(), 1);
+        assert_eq!(loaded.attachments[0].name, snapshot.attachments[0].name);
+        assert_eq!(&*loaded.attachments[0].content, "fn main() {}\n");
+        assert_eq!(loaded.attachments[0].kind, snapshot.attachments[0].kind);
+    }
+
+    #[test]
+    fn no_recovery_file_means_nothing_to_restore() {
+        let dir = TempDir::new().unwrap();
+        assert!(load_recovery_in(dir.path()).is_none());
+    }
+}
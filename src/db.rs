@@ -0,0 +1,291 @@
+// db.rs
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+#[cfg(feature = "encryption")]
+use rusqlite::{params, ErrorCode};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use crate::models::AppError;
+use crate::migrations;
+use crate::writer::{self, DbCommand, Reply};
+
+const DATABASE_FILE: &str = "conversations.db";
+// Room for every statement the app prepares with prepare_cached
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+// Read connections kept open between queries, more are opened when they're all busy
+const IDLE_READERS: usize = 4;
+
+// Every unencrypted SQLite file starts with this, a SQLCipher file looks like random bytes
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+// Every write is a DbCommand sent to one thread that owns the only writable connection and
+// runs them in the order they were sent. Reads use a pool of read-only connections, which WAL
+// lets run alongside the writer, so neither side ever sees "database is locked".
+#[derive(Clone)]
+pub struct Database {
+    writer: mpsc::UnboundedSender<DbCommand>,
+    readers: Arc<ReadPool>,
+    // SQLCipher passphrase, only ever held in memory
+    key: Option<Arc<String>>,
+}
+
+struct ReadPool {
+    path: PathBuf,
+    #[cfg(feature = "encryption")]
+    key: Option<Arc<String>>,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self, AppError> {
+        Self::open_with_key(path, None)
+    }
+
+    // The database inside a data directory, creating the directory if needed
+    pub fn open_in(dir: &Path) -> Result<Self, AppError> {
+        fs::create_dir_all(dir)?;
+        Self::open(&dir.join(DATABASE_FILE))
+    }
+
+    // Opens, or creates, a SQLCipher database. A wrong passphrase fails here rather than
+    // on the first query.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted_in(dir: &Path, key: &str) -> Result<Self, AppError> {
+        fs::create_dir_all(dir)?;
+        Self::open_with_key(&dir.join(DATABASE_FILE), Some(key))
+    }
+
+    fn open_with_key(path: &Path, key: Option<&str>) -> Result<Self, AppError> {
+        let mut connection = Connection::open(path)?;
+        #[cfg(feature = "encryption")]
+        if let Some(key) = key {
+            unlock(&connection, key)?;
+        }
+
+        connection.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.pragma_update(None, "foreign_keys", "ON")?;
+        connection.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+
+        migrations::run(&mut connection)?;
+
+        let key = key.map(|key| Arc::new(key.to_string()));
+        let (writer, commands) = mpsc::unbounded_channel();
+        let writer_key = key.clone();
+        std::thread::Builder::new()
+            .name("database-writer".to_string())
+            .spawn(move || writer::run(connection, writer_key, commands))?;
+
+        Ok(Self {
+            writer,
+            readers: Arc::new(ReadPool {
+                path: path.to_path_buf(),
+                #[cfg(feature = "encryption")]
+                key: key.clone(),
+                idle: Mutex::new(Vec::new()),
+            }),
+            key,
+        })
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    // Queues the command `command` builds around its reply channel behind every write sent
+    // before it, and waits for the result, e.g.
+    // `db.send(|reply| DbCommand::DeleteConversation { conversation_id, reply })`
+    pub async fn send<T>(&self, command: impl FnOnce(Reply<T>) -> DbCommand) -> Result<T, AppError> {
+        let (reply, result) = oneshot::channel();
+        self.writer
+            .send(command(reply))
+            .map_err(|_| AppError::WriterStopped)?;
+        result.await.map_err(|_| AppError::WriterStopped)?
+    }
+
+    // Runs `f` on a read-only connection, writes from it fail
+    pub async fn read<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, AppError> + Send + 'static,
+    {
+        let readers = self.readers.clone();
+
+        tokio::task::spawn_blocking(move || -> Result<T, AppError> {
+            let mut connection = readers.take()?;
+            let result = f(&mut connection);
+            readers.put_back(connection);
+            result
+        }).await?
+    }
+
+    pub async fn backup_to(&self, path: PathBuf) -> Result<(), AppError> {
+        self.send(|reply| DbCommand::Backup { path, reply }).await
+    }
+
+    pub async fn restore_from(&self, path: PathBuf) -> Result<(), AppError> {
+        if self.key.is_some() {
+            return Err(AppError::Other("Restoring a backup into an encrypted database isn't supported".to_string()));
+        }
+        self.send(|reply| DbCommand::Restore { path, reply }).await
+    }
+
+    fn validate_backup(path: &Path) -> Result<(), AppError> {
+        let source = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        
+        let version = migrations::current_version(&source)
+            .map_err(|_| AppError::Other("Not a conversations database (missing schema_version)".to_string()))?;
+        if version < 1 || version > migrations::latest_version() {
+            return Err(AppError::Other(format!("Unsupported schema version {}", version)));
+        }
+        
+        let has_conversations: i64 = source.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'conversations'",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_conversations == 0 {
+            return Err(AppError::Other("Not a conversations database (missing conversations table)".to_string()));
+        }
+        
+        Ok(())
+    }
+}
+
+// Online backup, safe while reads carry on. Backups of an encrypted database are encrypted
+// with the same passphrase. Run by the writer thread.
+pub fn backup(connection: &Connection, path: &Path, key: Option<&str>) -> Result<(), AppError> {
+    #[cfg(feature = "encryption")]
+    if let Some(key) = key {
+        return export_to(connection, path, key);
+    }
+    // Only an encrypted database has a key, and those need the encryption feature to open
+    #[cfg(not(feature = "encryption"))]
+    debug_assert!(key.is_none());
+    connection.backup(DatabaseName::Main, path, None)?;
+    Ok(())
+}
+
+// Replaces the database with a validated backup. Run by the writer thread.
+pub fn restore(connection: &mut Connection, path: &Path) -> Result<(), AppError> {
+    Database::validate_backup(path)?;
+    connection.restore(DatabaseName::Main, path, None::<fn(rusqlite::backup::Progress)>)?;
+
+    // Older backups are brought up to the current schema
+    migrations::run(connection)?;
+    Ok(())
+}
+
+impl ReadPool {
+    fn take(&self) -> Result<Connection, AppError> {
+        if let Some(connection) = self.idle.lock().unwrap_or_else(PoisonError::into_inner).pop() {
+            return Ok(connection);
+        }
+        let connection = Connection::open_with_flags(
+            &self.path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI,
+        )?;
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            unlock(&connection, key)?;
+        }
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Ok(connection)
+    }
+
+    fn put_back(&self, connection: Connection) {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < IDLE_READERS {
+            idle.push(connection);
+        }
+    }
+}
+
+// Whether the database in `dir` exists and is SQLCipher-encrypted
+pub fn is_encrypted_in(dir: &Path) -> bool {
+    let mut header = [0u8; 16];
+    match fs::File::open(dir.join(DATABASE_FILE)) {
+        Ok(mut file) => std::io::Read::read_exact(&mut file, &mut header).is_ok() && header != SQLITE_HEADER,
+        Err(_) => false,
+    }
+}
+
+pub fn exists_in(dir: &Path) -> bool {
+    dir.join(DATABASE_FILE).is_file()
+}
+
+// Encrypts the plaintext database in `dir`. The encrypted copy is written next to it and
+// only replaces the original once every table has the same number of rows in both.
+#[cfg(feature = "encryption")]
+pub fn encrypt_in(dir: &Path, key: &str) -> Result<(), AppError> {
+    let path = dir.join(DATABASE_FILE);
+    let encrypted_path = dir.join(format!("{}.encrypting", DATABASE_FILE));
+    if encrypted_path.exists() {
+        fs::remove_file(&encrypted_path)?;
+    }
+
+    let plain = Connection::open(&path)?;
+    // Everything still in the write-ahead log goes into the main file first
+    plain.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    export_to(&plain, &encrypted_path, key)?;
+
+    let encrypted = Connection::open(&encrypted_path)?;
+    unlock(&encrypted, key)?;
+    let tables: Vec<String> = plain
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for table in tables {
+        let query = format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\""));
+        let before: i64 = plain.query_row(&query, [], |row| row.get(0))?;
+        let after: i64 = encrypted.query_row(&query, [], |row| row.get(0))?;
+        if before != after {
+            drop(encrypted);
+            fs::remove_file(&encrypted_path)?;
+            return Err(AppError::Other(format!(
+                "Encryption check failed: {} has {} rows, the encrypted copy {}. The original is unchanged.",
+                table, before, after
+            )));
+        }
+    }
+    drop(encrypted);
+    drop(plain);
+
+    fs::rename(&encrypted_path, &path)?;
+    // Leftover WAL files belong to the plaintext database
+    for suffix in ["-wal", "-shm"] {
+        let leftover = dir.join(format!("{}{}", DATABASE_FILE, suffix));
+        if leftover.exists() {
+            fs::remove_file(leftover)?;
+        }
+    }
+    Ok(())
+}
+
+// PRAGMA key has to come before anything else on the connection. SQLCipher only checks
+// the key once the file is read, so a query turns a wrong one into an error here.
+#[cfg(feature = "encryption")]
+fn unlock(connection: &Connection, key: &str) -> Result<(), AppError> {
+    connection.pragma_update(None, "key", key)?;
+    match connection.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(error, _)) if error.code == ErrorCode::NotADatabase => {
+            Err(AppError::Other("Wrong passphrase, or the file isn't an encrypted database".to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Copies the whole database into a new SQLCipher file at `path`
+#[cfg(feature = "encryption")]
+fn export_to(connection: &Connection, path: &Path, key: &str) -> Result<(), AppError> {
+    let path = path.to_str().ok_or_else(|| AppError::Other(format!("Unusable path {}", path.display())))?;
+    connection.execute("ATTACH DATABASE ?1 AS encrypted_copy KEY ?2", params![path, key])?;
+    let exported = connection.query_row("SELECT sqlcipher_export('encrypted_copy')", [], |_| Ok(()));
+    connection.execute("DETACH DATABASE encrypted_copy", [])?;
+    exported?;
+    Ok(())
+}
@@ -0,0 +1,82 @@
+// diff.rs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffKind {
+    Same,
+    Added,
+    Removed,
+}
+
+// Beyond this many token pairs the texts are shown as one removal and one insertion
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+// Word-level diff from `old` to `new`. Whitespace runs are tokens of their own, so
+// joining the Same and Added spans gives back `new` exactly.
+pub fn diff_words(old: &str, new: &str) -> Vec<(DiffKind, String)> {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+    let (n, m) = (old_tokens.len(), new_tokens.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        return [(DiffKind::Removed, old), (DiffKind::Added, new)]
+            .into_iter()
+            .filter(|(_, text)| !text.is_empty())
+            .map(|(kind, text)| (kind, text.to_string()))
+            .collect();
+    }
+
+    // Longest common subsequence lengths of every pair of suffixes
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_tokens[i] == new_tokens[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut spans: Vec<(DiffKind, String)> = Vec::new();
+    let mut push = |kind: DiffKind, token: &str| match spans.last_mut() {
+        Some((last_kind, text)) if *last_kind == kind => text.push_str(token),
+        _ => spans.push((kind, token.to_string())),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old_tokens[i] == new_tokens[j] {
+            push(DiffKind::Same, old_tokens[i]);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            push(DiffKind::Removed, old_tokens[i]);
+            i += 1;
+        } else {
+            push(DiffKind::Added, new_tokens[j]);
+            j += 1;
+        }
+    }
+    for token in &old_tokens[i..] {
+        push(DiffKind::Removed, token);
+    }
+    for token in &new_tokens[j..] {
+        push(DiffKind::Added, token);
+    }
+    spans
+}
+
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous_space = None;
+    for (index, c) in text.char_indices() {
+        let space = c.is_whitespace();
+        if previous_space.is_some_and(|previous| previous != space) {
+            tokens.push(&text[start..index]);
+            start = index;
+        }
+        previous_space = Some(space);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
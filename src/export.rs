@@ -0,0 +1,169 @@
+// export.rs
+use chrono::Local;
+use crate::models::{AppError, ChatExport, ChatMessage, ExportSettings};
+
+pub const CHAT_EXPORT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExportOptions {
+    pub include_reasoning: bool,
+    pub include_response_times: bool,
+}
+
+pub fn to_text(messages: &[ChatMessage], options: &ExportOptions) -> String {
+    messages
+        .iter()
+        .map(|msg| {
+            let role = if msg.is_user { "User" } else { "Assistant" };
+            let timestamp = msg.timestamp.format("%Y-%m-%d %H:%M:%S");
+            let mut text = String::new();
+            if let Some(reasoning) = msg.reasoning.as_ref().filter(|_| options.include_reasoning) {
+                text.push_str(&format!("[{}] {} (reasoning): {}\n", timestamp, role, reasoning));
+            }
+            text.push_str(&format!("[{}] {}: {}\n", timestamp, role, msg.content));
+            if let Some(ms) = msg.response_time.filter(|_| options.include_response_times) {
+                text.push_str(&format!("[{}] Response time: {} ms\n", timestamp, ms));
+            }
+            text
+        })
+        .collect()
+}
+
+// Message bodies are written as-is so code fences survive, only an unterminated one is closed
+pub fn to_markdown(messages: &[ChatMessage], model: &str, options: &ExportOptions) -> String {
+    let mut markdown = format!(
+        "---\ndate: {}\nmodel: {}\nmessages: {}\n---\n",
+        Local::now().to_rfc3339(),
+        model,
+        messages.len(),
+    );
+
+    for msg in messages {
+        let role = if msg.is_user { "User" } else { "Assistant" };
+        markdown.push_str(&format!("\n### {}\n\n", role));
+
+        let mut details = vec![msg.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()];
+        if let Some(model) = msg.model_used.as_ref().filter(|_| !msg.is_user) {
+            details.push(model.clone());
+        }
+        if let Some(ms) = msg.response_time.filter(|_| options.include_response_times) {
+            details.push(format!("{} ms", ms));
+        }
+        markdown.push_str(&format!("_{}_\n\n", details.join(" · ")));
+
+        if let Some(reasoning) = msg.reasoning.as_ref().filter(|_| options.include_reasoning) {
+            markdown.push_str("<details>\n<summary>Reasoning</summary>\n\n");
+            push_block(&mut markdown, reasoning);
+            markdown.push_str("\n</details>\n\n");
+        }
+        push_block(&mut markdown, &msg.content);
+    }
+
+    markdown
+}
+
+pub fn to_json(
+    messages: &[ChatMessage],
+    model: &str,
+    settings: ExportSettings,
+    options: &ExportOptions,
+) -> Result<String, AppError> {
+    let messages: Vec<ChatMessage> = messages
+        .iter()
+        .cloned()
+        .map(|mut msg| {
+            if !options.include_reasoning {
+                msg.reasoning = None;
+            }
+            if !options.include_response_times {
+                msg.response_time = None;
+            }
+            msg
+        })
+        .collect();
+
+    let export = ChatExport {
+        version: CHAT_EXPORT_VERSION,
+        exported_at: Local::now(),
+        model: model.to_string(),
+        settings,
+        messages,
+    };
+    serde_json::to_string_pretty(&export)
+        .map_err(|e| AppError::Other(format!("Failed to serialize chat: {}", e)))
+}
+
+// The version is checked before the full parse so newer files get a clear message
+pub fn from_json(json: &str) -> Result<ChatExport, AppError> {
+    let value: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| AppError::Parse(format!("Not a valid JSON file: {}", e)))?;
+
+    let version = value.get("version")
+        .ok_or_else(|| AppError::Parse("Not a chat export: missing \"version\" field".to_string()))?
+        .as_u64()
+        .ok_or_else(|| AppError::Parse("Not a chat export: \"version\" must be a number".to_string()))?;
+    if version != CHAT_EXPORT_VERSION as u64 {
+        return Err(AppError::Parse(format!(
+            "Unsupported chat export version {} (expected {})",
+            version, CHAT_EXPORT_VERSION
+        )));
+    }
+
+    serde_json::from_value(value)
+        .map_err(|e| AppError::Parse(format!("Malformed chat export: {}", e)))
+}
+
+fn push_block(markdown: &mut String, text: &str) {
+    markdown.push_str(text.trim_end());
+    markdown.push('\n');
+
+    let fences = text.lines().filter(|line| line.trim_start().starts_with("```")).count();
+    if fences % 2 == 1 {
+        markdown.push_str("```\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str, is_user: bool) -> ChatMessage {
+        serde_json::from_value(serde_json::json!({
+            "content": content,
+            "is_user": is_user,
+            "timestamp": "2026-10-16T12:00:00+00:00",
+        }))
+        .unwrap()
+    }
+
+    const CODE_ANSWER: &str = "Like this:\n\n```rust\nfn main() {\n    let _x = \"### not a heading\";\n\n    println!(\"{}\", 1 * 2);\n}\n```\n\nThen run it.";
+
+    #[test]
+    fn code_blocks_are_written_verbatim() {
+        let messages = [message("Show me main", true), message(CODE_ANSWER, false)];
+        let markdown = to_markdown(&messages, "test-model", &ExportOptions::default());
+
+        assert!(markdown.contains(CODE_ANSWER));
+        let fences = markdown.lines().filter(|line| line.trim_start().starts_with("```")).count();
+        assert_eq!(fences, 2);
+    }
+
+    #[test]
+    fn unterminated_fence_is_closed_before_the_next_message() {
+        let messages = [message("```python\nprint(1)", true), message("Prints 1.", false)];
+        let markdown = to_markdown(&messages, "test-model", &ExportOptions::default());
+
+        assert!(markdown.contains("```python\nprint(1)\n```\n"));
+        let answer = markdown.find("### Assistant").unwrap();
+        let fences_before = markdown[..answer].lines().filter(|line| line.starts_with("```")).count();
+        assert_eq!(fences_before % 2, 0);
+    }
+
+    #[test]
+    fn json_export_round_trips_code_blocks() {
+        let messages = [message(CODE_ANSWER, false)];
+        let json = to_json(&messages, "test-model", ExportSettings::default(), &ExportOptions::default()).unwrap();
+        let export = from_json(&json).unwrap();
+        assert_eq!(export.messages[0].content, CODE_ANSWER);
+    }
+}
@@ -1,28 +1,255 @@
 // file_handler.rs
-use std::path::PathBuf;
-use crate::models::AppError;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Local, NaiveDate};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use serde_json::Value;
+use crate::models::{AppError, Attachment, AttachmentKind, AttachmentOrigin, ExportFormat, LoadOptions, LoadedFile, Truncation};
+use crate::text;
+
+const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+// How much of a file is looked at to decide whether it's binary
+const BINARY_SNIFF_BYTES: usize = 8192;
+const CSV_PREVIEW_ROWS: usize = 10;
+const JSON_MAX_DEPTH: usize = 4;
+const JSON_MAX_KEYS: usize = 30;
+const JSON_SAMPLE_CHARS: usize = 40;
+// Each cell output is cut to this many lines
+const NOTEBOOK_OUTPUT_LINES: usize = 20;
 
 pub struct FileHandler;
 
 impl FileHandler {
-    pub fn load_text_file() -> Option<String> {
-        let path = rfd::FileDialog::new()
-            .add_filter("Text files", &["txt", "md", "rs", "py", "js", "json"])
-            .pick_file()?;
+    pub fn pick_attachment() -> Option<PathBuf> {
+        rfd::FileDialog::new()
+            .add_filter("Text files", &["txt", "md", "rs", "py", "js", "json", "csv", "ipynb"])
+            .add_filter("Images", IMAGE_EXTENSIONS)
+            .add_filter("Archives", &["zip", "gz", "tgz"])
+            .pick_file()
+    }
+
+    pub fn pick_repository() -> Option<PathBuf> {
+        rfd::FileDialog::new().set_title("Attach git repository").pick_folder()
+    }
+
+    pub fn pick_whisper_model() -> Option<PathBuf> {
+        rfd::FileDialog::new()
+            .set_title("Choose whisper model")
+            .add_filter("Whisper models", &["bin", "gguf"])
+            .pick_file()
+    }
+
+    pub fn pick_vault() -> Option<PathBuf> {
+        rfd::FileDialog::new().set_title("Choose Obsidian vault folder").pick_folder()
+    }
+
+    // Text files over the size limit are refused unless the options say which part to keep
+    pub fn load_attachment(path: &Path, options: LoadOptions) -> Result<Attachment, AppError> {
+        if !is_image(&file_name(path)) {
+            let file = Self::load_text_file(path, options.max_text_bytes, options.truncation)?;
+            let (summary, warning) = summarize(&file.name, &file.content, options);
+            return Ok(Attachment {
+                name: file.name,
+                path: Some(file.path),
+                content: file.content.into(),
+                kind: AttachmentKind::Text,
+                origin: AttachmentOrigin::File,
+                len: file.len,
+                modified: file.modified,
+                encoding: Some(file.encoding),
+                summary,
+                include_raw: false,
+                warning,
+            });
+        }
+        
+        let metadata = std::fs::metadata(path)?;
+        // Checked before reading so a huge file is never pulled into memory
+        if metadata.len() > MAX_IMAGE_BYTES {
+            return Err(too_large(&file_name(path), metadata.len(), MAX_IMAGE_BYTES));
+        }
+        let mut attachment = Self::attachment_from_bytes(file_name(path), std::fs::read(path)?, options)?;
+        attachment.path = Some(path.to_path_buf());
+        attachment.origin = AttachmentOrigin::File;
+        attachment.modified = metadata.modified().ok().map(DateTime::<Local>::from);
+        Ok(attachment)
+    }
+
+    // Reads at most `max_bytes` of the file. Over the limit it fails, or with a truncation
+    // keeps that end of the file and says so in the content.
+    pub fn load_text_file(path: &Path, max_bytes: u64, truncation: Option<Truncation>) -> Result<LoadedFile, AppError> {
+        let name = file_name(path);
+        let metadata = std::fs::metadata(path)?;
+        let len = metadata.len();
+        let modified = metadata.modified().ok().map(DateTime::<Local>::from);
+        
+        let (content, encoding) = if len <= max_bytes {
+            text_from_bytes(&name, std::fs::read(path)?)?
+        } else {
+            let Some(truncation) = truncation else {
+                return Err(too_large(&name, len, max_bytes));
+            };
+            let mut file = File::open(path)?;
+            if truncation == Truncation::Tail {
+                file.seek(SeekFrom::End(-(max_bytes as i64)))?;
+            }
+            let mut bytes = Vec::new();
+            file.take(max_bytes).read_to_end(&mut bytes)?;
+            let (part, encoding) = text_from_bytes(&name, trim_partial_chars(bytes, truncation))?;
+            let content = match truncation {
+                Truncation::Head => format!("{}\n\n(truncated, showing first {} KB of {} KB)", part, max_bytes / 1024, len / 1024),
+                Truncation::Tail => format!("(truncated, showing last {} KB of {} KB)\n\n{}", max_bytes / 1024, len / 1024, part),
+            };
+            (content, encoding)
+        };
+        
+        Ok(LoadedFile {
+            path: path.to_path_buf(),
+            name,
+            len,
+            modified,
+            content,
+            encoding,
+        })
+    }
+
+    // Size of a text file that is over the limit, None for images and files within it
+    pub fn oversized_text(path: &Path, max_bytes: u64) -> Option<u64> {
+        if is_image(&file_name(path)) {
+            return None;
+        }
+        std::fs::metadata(path).ok().map(|metadata| metadata.len()).filter(|&len| len > max_bytes)
+    }
+
+    // Images are kept as raw bytes, anything else has to be reasonably sized text
+    pub fn attachment_from_bytes(name: String, bytes: Vec<u8>, options: LoadOptions) -> Result<Attachment, AppError> {
+        let len = bytes.len() as u64;
+        if is_image(&name) {
+            if len > MAX_IMAGE_BYTES {
+                return Err(too_large(&name, len, MAX_IMAGE_BYTES));
+            }
+            return Ok(Attachment {
+                name,
+                path: None,
+                content: BASE64.encode(&bytes).into(),
+                kind: AttachmentKind::Image,
+                origin: AttachmentOrigin::Dropped,
+                len,
+                modified: None,
+                encoding: None,
+                summary: None,
+                include_raw: false,
+                warning: None,
+            });
+        }
+        
+        if len > options.max_text_bytes {
+            return Err(too_large(&name, len, options.max_text_bytes));
+        }
+        let (content, encoding) = text_from_bytes(&name, bytes)?;
+        let (summary, warning) = summarize(&name, &content, options);
         
-        std::fs::read_to_string(&path).ok()
+        Ok(Attachment {
+            summary,
+            warning,
+            name,
+            path: None,
+            content: content.into(),
+            kind: AttachmentKind::Text,
+            origin: AttachmentOrigin::Dropped,
+            len,
+            modified: None,
+            encoding: Some(encoding),
+            include_raw: false,
+        })
     }
 
-    pub fn save_text_file(content: &str, default_name: &str) -> Result<(), AppError> {
+    // The image on the clipboard as a PNG attachment, None when there isn't one
+    pub fn clipboard_image() -> Result<Option<Attachment>, AppError> {
+        let mut clipboard = arboard::Clipboard::new()
+            .map_err(|e| AppError::Other(format!("Clipboard unavailable: {}", e)))?;
+        let image = match clipboard.get_image() {
+            Ok(image) => image,
+            Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+            Err(e) => return Err(AppError::Other(format!("Could not read the clipboard: {}", e))),
+        };
+        
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, image.width as u32, image.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| {
+                writer.write_image_data(&image.bytes)?;
+                writer.finish()
+            })
+            .map_err(|e| AppError::Other(format!("Could not encode the pasted image: {}", e)))?;
+        
+        if bytes.len() as u64 > MAX_IMAGE_BYTES {
+            return Err(too_large("Pasted image", bytes.len() as u64, MAX_IMAGE_BYTES));
+        }
+        Ok(Some(Attachment {
+            name: format!("pasted-{}.png", Local::now().format("%H%M%S")),
+            path: None,
+            len: bytes.len() as u64,
+            content: BASE64.encode(&bytes).into(),
+            kind: AttachmentKind::Image,
+            origin: AttachmentOrigin::Clipboard,
+            modified: None,
+            encoding: None,
+            summary: None,
+            include_raw: false,
+            warning: None,
+        }))
+    }
+
+    // Pasted text kept as an attachment instead of going into the input box
+    pub fn attachment_from_text(text: String) -> Attachment {
+        Attachment {
+            name: format!("pasted-{}.txt", Local::now().format("%H%M%S")),
+            path: None,
+            len: text.len() as u64,
+            content: text.into(),
+            kind: AttachmentKind::Text,
+            origin: AttachmentOrigin::Clipboard,
+            modified: None,
+            encoding: Some("UTF-8"),
+            summary: None,
+            include_raw: false,
+            warning: None,
+        }
+    }
+
+    pub fn save_text_file(content: &str, default_name: &str, format: ExportFormat) -> Result<(), AppError> {
         let path = rfd::FileDialog::new()
-            .set_file_name(default_name)
+            .add_filter(format.label(), &[format.extension()])
+            .set_file_name(format!("{}.{}", default_name, format.extension()))
             .save_file()
-            .ok_or_else(|| AppError("No file selected".to_string()))?;
+            .ok_or_else(|| AppError::Other("No file selected".to_string()))?;
         
         std::fs::write(&path, content)?;
         Ok(())
     }
 
+    pub fn pick_save_path(default_name: &str, filter_name: &str, extensions: &[&str]) -> Option<PathBuf> {
+        rfd::FileDialog::new()
+            .add_filter(filter_name, extensions)
+            .set_file_name(default_name)
+            .save_file()
+    }
+
+    pub fn pick_open_path(filter_name: &str, extensions: &[&str]) -> Option<PathBuf> {
+        rfd::FileDialog::new()
+            .add_filter(filter_name, extensions)
+            .pick_file()
+    }
+
     pub fn open_directory(path: &PathBuf) {
         #[cfg(target_os = "windows")]
         std::process::Command::new("explorer")
@@ -43,11 +270,372 @@ impl FileHandler {
             .ok();
     }
 
-    pub fn create_prompt_with_file_context(file_content: &str, input_text: &str) -> String {
-        if file_content.is_empty() {
+    // Text attachments each get their own header, images are sent separately. CSV and JSON
+    // files go in as their summary unless the raw content was asked for.
+    pub fn create_prompt_with_file_context(attachments: &[Arc<Attachment>], input_text: &str) -> String {
+        let file_context: String = attachments.iter()
+            .filter(|attachment| attachment.kind == AttachmentKind::Text)
+            .map(|attachment| format!("File context ({}):\n{}\n\n", attachment.name, attachment.prompt_content()))
+            .collect();
+        
+        if file_context.is_empty() {
             input_text.to_string()
         } else {
-            format!("{}\n\n{}", file_content, input_text)
+            format!("{}User message: {}", file_context, input_text)
         }
     }
-}
\ No newline at end of file
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "attachment".to_string())
+}
+
+fn is_image(name: &str) -> bool {
+    let extension = Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    IMAGE_EXTENSIONS.contains(&extension.as_str())
+}
+
+fn too_large(name: &str, len: u64, limit: u64) -> AppError {
+    if limit >= 1024 * 1024 {
+        AppError::Other(format!("{} is too large ({} MB, limit {} MB)", name, len / (1024 * 1024), limit / (1024 * 1024)))
+    } else {
+        AppError::Other(format!("{} is too large ({} KB, limit {} KB)", name, len / 1024, limit / 1024))
+    }
+}
+
+// Decodes text in UTF-8, UTF-16 with a byte order mark, or failing both Latin-1, returning
+// the encoding used. Content that looks binary is refused.
+fn text_from_bytes(name: &str, bytes: Vec<u8>) -> Result<(String, &'static str), AppError> {
+    // UTF-16 is full of NUL bytes, so the BOM has to be checked before sniffing for binary
+    if let Some((encoding, bom_len)) = Encoding::for_bom(&bytes) {
+        let (text, had_errors) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        if had_errors {
+            return Err(AppError::Other(format!("{} is not valid {} text", name, encoding.name())));
+        }
+        return Ok((text.into_owned(), encoding.name()));
+    }
+    if looks_binary(&bytes) {
+        return Err(AppError::Other(format!("{} looks like a binary file", name)));
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok((text, UTF_8.name())),
+        // windows-1252 is what browsers use for ISO-8859-1, and it decodes any byte
+        Err(e) => Ok((WINDOWS_1252.decode_without_bom_handling(e.as_bytes()).0.into_owned(), "ISO-8859-1")),
+    }
+}
+
+// NUL bytes or a high share of control characters near the start
+fn looks_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(BINARY_SNIFF_BYTES)];
+    if sample.contains(&0) {
+        return true;
+    }
+    let control = sample
+        .iter()
+        .filter(|&&byte| byte < 0x20 && !matches!(byte, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B))
+        .count();
+    control * 10 > sample.len()
+}
+
+// A cut at an arbitrary byte can split a character, drop the pieces at the cut end
+fn trim_partial_chars(mut bytes: Vec<u8>, truncation: Truncation) -> Vec<u8> {
+    match truncation {
+        Truncation::Head => {
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if e.error_len().is_none() {
+                    bytes.truncate(e.valid_up_to());
+                }
+            }
+            bytes
+        }
+        Truncation::Tail => {
+            let start = bytes.iter().take(3).take_while(|&&byte| byte & 0xC0 == 0x80).count();
+            bytes.split_off(start)
+        }
+    }
+}
+
+// What goes into the prompt instead of the raw content for CSV, JSON and notebook files.
+// A file that doesn't parse as its type is still attached, raw and with a warning.
+fn summarize(name: &str, content: &str, options: LoadOptions) -> (Option<String>, Option<String>) {
+    let extension = Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let result = match extension.as_str() {
+        "csv" => summarize_csv(name, content).map_err(|e| e.to_string()),
+        "json" => summarize_json(name, content).map_err(|e| e.to_string()),
+        "ipynb" => render_notebook(content, options.notebook_outputs),
+        _ => return (None, None),
+    };
+    match result {
+        Ok(summary) => (Some(summary), None),
+        Err(e) => (None, Some(format!("{} is attached as raw text: {}", name, e))),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColumnType {
+    Empty,
+    Boolean,
+    Integer,
+    Float,
+    Date,
+    Text,
+}
+
+impl ColumnType {
+    fn of(value: &str) -> Self {
+        let value = value.trim();
+        if value.is_empty() {
+            ColumnType::Empty
+        } else if value.parse::<i64>().is_ok() {
+            ColumnType::Integer
+        } else if value.parse::<f64>().is_ok() {
+            ColumnType::Float
+        } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            ColumnType::Boolean
+        } else if NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+            ColumnType::Date
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    // The narrowest type both values fit in
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Empty, other) | (other, ColumnType::Empty) => other,
+            (ColumnType::Integer, ColumnType::Float) | (ColumnType::Float, ColumnType::Integer) => ColumnType::Float,
+            _ => ColumnType::Text,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ColumnType::Empty => "empty",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "decimal",
+            ColumnType::Date => "date",
+            ColumnType::Text => "text",
+        }
+    }
+}
+
+// Rows are streamed, only the preview rows are kept
+fn summarize_csv(name: &str, content: &str) -> Result<String, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut types = vec![ColumnType::Empty; headers.len()];
+    let mut preview = Vec::new();
+    let mut rows = 0;
+    for record in reader.records() {
+        let record = record?;
+        for (column_type, value) in types.iter_mut().zip(record.iter()) {
+            *column_type = column_type.merge(ColumnType::of(value));
+        }
+        if preview.len() < CSV_PREVIEW_ROWS {
+            preview.push(record);
+        }
+        rows += 1;
+    }
+    
+    let mut summary = format!("CSV summary of {}: {} rows, {} columns\n\nColumns:\n", name, rows, headers.len());
+    for (header, column_type) in headers.iter().zip(&types) {
+        summary.push_str(&format!("- {} ({})\n", header, column_type.label()));
+    }
+    summary.push_str(&format!("\nFirst {} rows:\n", preview.len()));
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&headers)?;
+    for record in &preview {
+        writer.write_record(record)?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    summary.push_str(&String::from_utf8_lossy(&bytes));
+    Ok(summary)
+}
+
+fn summarize_json(name: &str, content: &str) -> Result<String, serde_json::Error> {
+    let value: Value = serde_json::from_str(content)?;
+    let mut summary = format!("JSON summary of {}: {}\n", name, json_type(&value));
+    describe_json(&value, 1, &mut summary);
+    Ok(summary)
+}
+
+// One line per key with its type and a sample value, nested structures indented below
+fn describe_json(value: &Value, depth: usize, out: &mut String) {
+    if depth > JSON_MAX_DEPTH {
+        return;
+    }
+    let indent = "  ".repeat(depth);
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter().take(JSON_MAX_KEYS) {
+                match field {
+                    Value::Object(_) | Value::Array(_) => {
+                        out.push_str(&format!("{}\"{}\": {}\n", indent, key, json_type(field)));
+                        describe_json(field, depth + 1, out);
+                    }
+                    _ => out.push_str(&format!("{}\"{}\": {}, e.g. {}\n", indent, key, json_type(field), json_sample(field))),
+                }
+            }
+            if fields.len() > JSON_MAX_KEYS {
+                out.push_str(&format!("{}… {} more keys\n", indent, fields.len() - JSON_MAX_KEYS));
+            }
+        }
+        // Items are assumed to share the first one's shape
+        Value::Array(items) => match items.first() {
+            Some(first @ (Value::Object(_) | Value::Array(_))) => describe_json(first, depth, out),
+            Some(first) => out.push_str(&format!("{}e.g. {}\n", indent, json_sample(first))),
+            None => {}
+        },
+        _ => {}
+    }
+}
+
+fn json_type(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(_) => "boolean".to_string(),
+        Value::Number(_) => "number".to_string(),
+        Value::String(_) => "string".to_string(),
+        Value::Array(items) => match items.first() {
+            Some(first) => format!("array of {} × {}", items.len(), json_type(first)),
+            None => "empty array".to_string(),
+        },
+        Value::Object(fields) => format!("object with {} keys", fields.len()),
+    }
+}
+
+fn json_sample(value: &Value) -> String {
+    text::preview(&value.to_string(), JSON_SAMPLE_CHARS)
+}
+
+// Notebook cells in order as prose and fenced code, with text outputs below their cell.
+// Only nbformat 4 is understood, older notebooks keep their cells under "worksheets".
+fn render_notebook(content: &str, outputs: bool) -> Result<String, String> {
+    let notebook: Value = serde_json::from_str(content).map_err(|e| format!("not valid JSON ({})", e))?;
+    match notebook.get("nbformat").and_then(Value::as_u64) {
+        Some(4) => {}
+        Some(version) => return Err(format!("nbformat {} is not supported", version)),
+        None => return Err("not a Jupyter notebook".to_string()),
+    }
+    let cells = notebook.get("cells").and_then(Value::as_array).ok_or("the notebook has no cells")?;
+    let language = notebook.pointer("/metadata/kernelspec/language")
+        .or_else(|| notebook.pointer("/metadata/language_info/name"))
+        .and_then(Value::as_str)
+        .unwrap_or("python");
+    
+    let mut document = String::new();
+    for cell in cells {
+        let source = notebook_text(cell.get("source"));
+        match cell.get("cell_type").and_then(Value::as_str) {
+            Some("markdown") => {
+                document.push_str(source.trim_end());
+                document.push_str("\n\n");
+            }
+            Some("code") => {
+                if source.trim().is_empty() {
+                    continue;
+                }
+                document.push_str(&format!("```{}\n{}\n```\n\n", language, source.trim_end()));
+                if outputs {
+                    for output in cell.get("outputs").and_then(Value::as_array).into_iter().flatten() {
+                        if let Some(text) = notebook_output(output) {
+                            document.push_str(&format!("Output:\n```\n{}\n```\n\n", text));
+                        }
+                    }
+                }
+            }
+            // Raw cells are passed through untouched by nbconvert, and so here
+            _ => {
+                document.push_str(source.trim_end());
+                document.push_str("\n\n");
+            }
+        }
+    }
+    Ok(document.trim_end().to_string())
+}
+
+// Sources and outputs are either one string or a list of lines
+fn notebook_text(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn notebook_output(output: &Value) -> Option<String> {
+    let text = match output.get("output_type").and_then(Value::as_str)? {
+        "stream" => notebook_text(output.get("text")),
+        "execute_result" | "display_data" => notebook_text(output.pointer("/data/text~1plain")),
+        "error" => format!(
+            "{}: {}",
+            output.get("ename").and_then(Value::as_str).unwrap_or("Error"),
+            output.get("evalue").and_then(Value::as_str).unwrap_or(""),
+        ),
+        _ => return None,
+    };
+    let text = text.trim_end();
+    if text.is_empty() {
+        return None;
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() > NOTEBOOK_OUTPUT_LINES {
+        Some(format!("{}\n… {} more lines", lines[..NOTEBOOK_OUTPUT_LINES].join("\n"), lines.len() - NOTEBOOK_OUTPUT_LINES))
+    } else {
+        Some(text.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The 8-byte signature and the start of an IHDR chunk
+    const PNG_BYTES: &[u8] = &[
+        0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R',
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn utf8_is_decoded_as_is() {
+        let (text, encoding) = text_from_bytes("notes.txt", "naïve café ✓".as_bytes().to_vec()).unwrap();
+        assert_eq!(text, "naïve café ✓");
+        assert_eq!(encoding, "UTF-8");
+    }
+
+    #[test]
+    fn utf16le_with_bom_is_decoded() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("héllo\r\nwörld".encode_utf16().flat_map(u16::to_le_bytes));
+        let (text, encoding) = text_from_bytes("notes.txt", bytes).unwrap();
+        assert_eq!(text, "héllo\r\nwörld");
+        assert_eq!(encoding, "UTF-16LE");
+    }
+
+    #[test]
+    fn latin1_falls_back_when_not_utf8() {
+        // "café résumé" in ISO-8859-1, é is the lone byte 0xE9
+        let bytes = b"caf\xE9 r\xE9sum\xE9".to_vec();
+        let (text, encoding) = text_from_bytes("notes.txt", bytes).unwrap();
+        assert_eq!(text, "café résumé");
+        assert_eq!(encoding, "ISO-8859-1");
+    }
+
+    #[test]
+    fn png_bytes_are_refused_as_text() {
+        assert!(looks_binary(PNG_BYTES));
+        let options = LoadOptions { max_text_bytes: 1024, truncation: None, notebook_outputs: false };
+        let error = FileHandler::attachment_from_bytes("image.txt".to_string(), PNG_BYTES.to_vec(), options).unwrap_err();
+        assert!(error.to_string().contains("binary"), "{}", error);
+    }
+}
@@ -0,0 +1,87 @@
+// followups.rs
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use serde_json::Value;
+use crate::backend::LlmBackend;
+use crate::models::AppError;
+use crate::text::truncate_chars;
+
+const FOLLOW_UP_COUNT: usize = 3;
+const FOLLOW_UP_NUM_PREDICT: u32 = 160;
+const QUESTION_MAX_CHARS: usize = 120;
+const EXCERPT_CHARS: usize = 2000;
+const BUSY_POLL: Duration = Duration::from_millis(250);
+
+// Asks the model for a few short questions the user might ask next. An unusable reply,
+// or the user sending something else first, just means no suggestions.
+pub struct FollowUpSuggester {
+    pub client: Arc<dyn LlmBackend>,
+    pub model: String,
+    pub prompt: String,
+    pub answer: String,
+    pub interactive_busy: Arc<AtomicBool>,
+}
+
+impl FollowUpSuggester {
+    pub async fn run(self) -> Result<Vec<String>, AppError> {
+        let prompt = format!(
+            "Suggest {} short follow-up questions the user might ask next about this exchange. \
+             Reply with a JSON array of strings only.\n\nUser: {}\n\nAssistant: {}",
+            FOLLOW_UP_COUNT,
+            excerpt(&self.prompt),
+            excerpt(&self.answer),
+        );
+
+        // The user's next request wins, by then these questions are stale anyway
+        let text = tokio::select! {
+            result = self.client.generate_json(&self.model, &prompt, FOLLOW_UP_NUM_PREDICT) => result,
+            _ = wait_until_busy(&self.interactive_busy) => return Ok(Vec::new()),
+        };
+
+        Ok(parse_questions(&text?))
+    }
+}
+
+async fn wait_until_busy(busy: &AtomicBool) {
+    while !busy.load(Ordering::Relaxed) {
+        tokio::time::sleep(BUSY_POLL).await;
+    }
+}
+
+fn excerpt(text: &str) -> String {
+    truncate_chars(text, EXCERPT_CHARS).to_string()
+}
+
+// Models answer with a bare array, an object wrapping one, or objects per question
+fn parse_questions(text: &str) -> Vec<String> {
+    let Ok(value) = serde_json::from_str::<Value>(text.trim()) else {
+        return Vec::new();
+    };
+    let items = match value {
+        Value::Array(items) => items,
+        Value::Object(fields) => fields
+            .into_iter()
+            .find_map(|(_, value)| match value {
+                Value::Array(items) => Some(items),
+                _ => None,
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    items
+        .into_iter()
+        .filter_map(|item| match item {
+            Value::String(question) => Some(question),
+            Value::Object(fields) => fields.into_iter().find_map(|(_, value)| match value {
+                Value::String(question) => Some(question),
+                _ => None,
+            }),
+            _ => None,
+        })
+        .map(|question| truncate_chars(question.trim(), QUESTION_MAX_CHARS).to_string())
+        .filter(|question| !question.is_empty())
+        .take(FOLLOW_UP_COUNT)
+        .collect()
+}
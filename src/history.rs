@@ -0,0 +1,89 @@
+// history.rs
+use crate::models::ChatTurn;
+
+// Role markers and separators each message costs on top of its text
+const TURN_OVERHEAD: usize = 4;
+
+// The turns sent with a chat request, and how many older ones were left out to fit
+#[derive(Clone, Debug, PartialEq)]
+pub struct FittedHistory {
+    pub turns: Vec<ChatTurn>,
+    pub dropped: usize,
+}
+
+// Fits a chat into `budget` tokens. Ollama would otherwise cut from the front, system prompt
+// and all. The system prompt and the last `keep_last` turns, the newest being the prompt
+// being asked, are always sent; older turns are left out oldest first until the rest fits.
+pub fn fit_history(
+    system: &str,
+    history: &[ChatTurn],
+    keep_last: usize,
+    budget: usize,
+    count: impl Fn(&str) -> usize,
+) -> FittedHistory {
+    let system = Some(system.trim()).filter(|system| !system.is_empty()).map(ChatTurn::system);
+    let costs: Vec<usize> = history.iter().map(|turn| count(&turn.content) + TURN_OVERHEAD).collect();
+    let mut used = system.as_ref().map_or(0, |system| count(&system.content) + TURN_OVERHEAD) + costs.iter().sum::<usize>();
+
+    let droppable = history.len().saturating_sub(keep_last.max(1));
+    let mut dropped = 0;
+    while dropped < droppable && used > budget {
+        used -= costs[dropped];
+        dropped += 1;
+    }
+
+    FittedHistory {
+        turns: system.into_iter().chain(history[dropped..].iter().cloned()).collect(),
+        dropped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One token per word keeps the arithmetic readable
+    fn words(text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    fn chat(turns: usize) -> Vec<ChatTurn> {
+        (0..turns)
+            .map(|i| if i % 2 == 0 { ChatTurn::user(format!("question {} here", i)) } else { ChatTurn::assistant(format!("answer {} here", i)) })
+            .collect()
+    }
+
+    #[test]
+    fn everything_is_sent_when_it_fits() {
+        let history = chat(4);
+        let fitted = fit_history("Be brief.", &history, 2, 1000, words);
+        assert_eq!(fitted.dropped, 0);
+        assert_eq!(fitted.turns.len(), 5);
+        assert_eq!(fitted.turns[0], ChatTurn::system("Be brief."));
+    }
+
+    #[test]
+    fn oldest_turns_go_first_and_the_system_prompt_stays() {
+        let history = chat(6);
+        // Each turn costs 3 words + 4 overhead, the system prompt 2 + 4
+        let fitted = fit_history("Be brief.", &history, 2, 6 + 3 * 7, words);
+        assert_eq!(fitted.dropped, 3);
+        assert_eq!(fitted.turns[0], ChatTurn::system("Be brief."));
+        assert_eq!(&fitted.turns[1..], &history[3..]);
+    }
+
+    #[test]
+    fn the_last_turns_are_kept_even_over_budget() {
+        let history = chat(6);
+        let fitted = fit_history("", &history, 2, 0, words);
+        assert_eq!(fitted.dropped, 4);
+        assert_eq!(fitted.turns, history[4..].to_vec());
+    }
+
+    #[test]
+    fn the_prompt_itself_is_always_sent() {
+        let history = chat(3);
+        let fitted = fit_history("", &history, 0, 0, words);
+        assert_eq!(fitted.turns, vec![history[2].clone()]);
+    }
+}
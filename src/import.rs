@@ -0,0 +1,257 @@
+// import.rs
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use crate::models::{AppError, ConversationEntry, ConversationStatus, ImportReport, ImportedSession};
+use crate::rag::RagSystem;
+
+pub const CHATGPT_SOURCE: &str = "chatgpt-import";
+// Used when an answer doesn't say which model wrote it
+const CHATGPT_MODEL: &str = "chatgpt";
+
+// The parts of a conversations.json entry that are read. Each conversation is a tree of
+// messages keyed by node id, `current_node` is the leaf of the branch last shown.
+#[derive(Deserialize)]
+struct ExportConversation {
+    #[serde(default)]
+    title: Option<String>,
+    create_time: Option<f64>,
+    #[serde(default)]
+    mapping: HashMap<String, ExportNode>,
+    current_node: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ExportNode {
+    message: Option<ExportMessage>,
+    parent: Option<String>,
+    #[serde(default)]
+    children: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ExportMessage {
+    author: ExportAuthor,
+    content: Option<ExportContent>,
+    create_time: Option<f64>,
+    // Anything but "all" is a call to a tool, not text for the user
+    #[serde(default)]
+    recipient: Option<String>,
+    #[serde(default)]
+    metadata: ExportMetadata,
+}
+
+#[derive(Deserialize)]
+struct ExportAuthor {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct ExportContent {
+    #[serde(default)]
+    content_type: String,
+    // Strings for text, objects for images and attachments
+    #[serde(default)]
+    parts: Vec<Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct ExportMetadata {
+    model_slug: Option<String>,
+    #[serde(default)]
+    is_visually_hidden_from_conversation: bool,
+}
+
+// Consecutive messages from the same side, merged
+struct Turn {
+    is_user: bool,
+    text: String,
+    time: Option<f64>,
+    model: Option<String>,
+}
+
+// Reads a ChatGPT data export and stores each conversation as a session. Conversations
+// imported before are skipped, so running it twice on the same export is harmless.
+pub async fn import_chatgpt(rag: &RagSystem, path: PathBuf) -> Result<ImportReport, AppError> {
+    let (sessions, unreadable) = tokio::task::spawn_blocking(move || -> Result<_, AppError> {
+        parse_chatgpt(&fs::read_to_string(&path)?)
+    }).await??;
+
+    let mut report = ImportReport { skipped: unreadable, ..Default::default() };
+    let turns: Vec<usize> = sessions.iter().map(|session| session.entries.len()).collect();
+    for (imported, turns) in rag.import_sessions(sessions).await?.into_iter().zip(turns) {
+        if imported {
+            report.imported += 1;
+            report.turns += turns;
+        } else {
+            report.skipped += 1;
+        }
+    }
+    Ok(report)
+}
+
+// The conversations worth importing, plus how many were unreadable or had no complete exchange
+pub fn parse_chatgpt(json: &str) -> Result<(Vec<ImportedSession>, usize), AppError> {
+    let conversations: Vec<Value> = serde_json::from_str(json)
+        .map_err(|e| AppError::Parse(format!("Not a ChatGPT conversations.json export: {}", e)))?;
+
+    let mut sessions = Vec::new();
+    let mut skipped = 0;
+    for value in conversations {
+        let session = serde_json::from_value::<ExportConversation>(value)
+            .map_err(|e| eprintln!("Skipping unreadable conversation: {}", e))
+            .ok()
+            .and_then(|conversation| to_session(&conversation));
+        match session {
+            Some(session) => sessions.push(session),
+            None => skipped += 1,
+        }
+    }
+    Ok((sessions, skipped))
+}
+
+fn to_session(conversation: &ExportConversation) -> Option<ImportedSession> {
+    let created_at = conversation.create_time.and_then(to_local);
+    let turns = turns(&canonical_path(conversation));
+
+    let entries: Vec<ConversationEntry> = turns
+        .windows(2)
+        .filter(|pair| pair[0].is_user && !pair[1].is_user)
+        .map(|pair| {
+            let (prompt, answer) = (&pair[0], &pair[1]);
+            let response_time_ms = match (prompt.time, answer.time) {
+                (Some(asked), Some(answered)) => ((answered - asked) * 1000.0).max(0.0) as i64,
+                _ => 0,
+            };
+            ConversationEntry {
+                id: 0,
+                timestamp: prompt.time.and_then(to_local).or(created_at).unwrap_or_else(Local::now),
+                prompt: prompt.text.clone(),
+                response: answer.text.clone(),
+                model_used: answer.model.clone().unwrap_or_else(|| CHATGPT_MODEL.to_string()),
+                response_time_ms,
+                file_context: None,
+                tags: Vec::new(),
+                status: ConversationStatus::Ok,
+                reasoning: None,
+                first_token_ms: None,
+                starred: false,
+                feedback: 0,
+                backend: None,
+                source: Some(CHATGPT_SOURCE.to_string()),
+            }
+        })
+        .collect();
+
+    let first = entries.first()?;
+    let original_id = conversation.conversation_id.as_ref().or(conversation.id.as_ref());
+    Some(ImportedSession {
+        id: match original_id {
+            Some(id) => format!("chatgpt-{}", id),
+            None => format!("chatgpt-{}", first.timestamp.timestamp_millis()),
+        },
+        title: conversation.title.clone().filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| "Imported from ChatGPT".to_string()),
+        created_at: created_at.unwrap_or(first.timestamp),
+        entries,
+    })
+}
+
+// Messages from the root to `current_node`, so edited prompts and regenerated answers
+// only contribute the version that was kept. Without a current node the newest child
+// is followed from the root instead.
+fn canonical_path(conversation: &ExportConversation) -> Vec<&ExportMessage> {
+    let mapping = &conversation.mapping;
+    let mut ids: Vec<&String> = Vec::new();
+
+    if let Some(leaf) = conversation.current_node.as_ref().filter(|leaf| mapping.contains_key(*leaf)) {
+        let mut next = Some(leaf);
+        while let Some(id) = next {
+            // A malformed export could link nodes in a cycle
+            if ids.len() > mapping.len() {
+                break;
+            }
+            ids.push(id);
+            next = mapping.get(id).and_then(|node| node.parent.as_ref()).filter(|parent| mapping.contains_key(*parent));
+        }
+        ids.reverse();
+    } else {
+        let root = mapping.iter().find(|(_, node)| !node.parent.as_ref().is_some_and(|parent| mapping.contains_key(parent)));
+        let mut next = root.map(|(id, _)| id);
+        while let Some(id) = next {
+            if ids.len() > mapping.len() {
+                break;
+            }
+            ids.push(id);
+            next = mapping.get(id).and_then(|node| node.children.last()).filter(|child| mapping.contains_key(*child));
+        }
+    }
+
+    ids.into_iter()
+        .filter_map(|id| mapping.get(id).and_then(|node| node.message.as_ref()))
+        .collect()
+}
+
+// User and assistant text only: system prompts, tool calls, tool output and hidden
+// messages are dropped
+fn turns(messages: &[&ExportMessage]) -> Vec<Turn> {
+    let mut turns: Vec<Turn> = Vec::new();
+    for message in messages {
+        let is_user = match message.author.role.as_str() {
+            "user" => true,
+            "assistant" => false,
+            _ => continue,
+        };
+        if message.metadata.is_visually_hidden_from_conversation
+            || message.recipient.as_deref().is_some_and(|recipient| recipient != "all")
+        {
+            continue;
+        }
+        let Some(text) = message.content.as_ref().and_then(text_of) else {
+            continue;
+        };
+
+        match turns.last_mut() {
+            Some(turn) if turn.is_user == is_user => {
+                turn.text.push_str("\n\n");
+                turn.text.push_str(&text);
+                if message.metadata.model_slug.is_some() {
+                    turn.model = message.metadata.model_slug.clone();
+                }
+            }
+            _ => turns.push(Turn {
+                is_user,
+                text,
+                time: message.create_time,
+                model: message.metadata.model_slug.clone(),
+            }),
+        }
+    }
+    turns
+}
+
+fn text_of(content: &ExportContent) -> Option<String> {
+    if content.content_type != "text" && content.content_type != "multimodal_text" {
+        return None;
+    }
+    let text = content.parts
+        .iter()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+// Export times are seconds since the epoch with a fractional part
+fn to_local(seconds: f64) -> Option<DateTime<Local>> {
+    DateTime::from_timestamp_millis((seconds * 1000.0) as i64).map(|time| time.with_timezone(&Local))
+}
@@ -0,0 +1,81 @@
+// indexer.rs
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use crate::models::{AppError, IndexProgress};
+use crate::backend::LlmBackend;
+use crate::rag::RagSystem;
+
+// Conversations embedded per batch, which also bounds concurrent embedding requests
+const BATCH_SIZE: usize = 4;
+const BATCH_DELAY: Duration = Duration::from_millis(250);
+const BUSY_POLL: Duration = Duration::from_millis(500);
+
+// Backfills embeddings for conversations that don't have one yet. Progress is derived
+// from the database, so a cancelled or interrupted run simply resumes next time.
+// A conversation that fails to embed goes to `failed` and is retried on the next run.
+pub struct EmbeddingBackfill {
+    pub rag: RagSystem,
+    pub client: Arc<dyn LlmBackend>,
+    pub model: String,
+    pub cancel: Arc<AtomicBool>,
+    pub interactive_busy: Arc<AtomicBool>,
+}
+
+impl EmbeddingBackfill {
+    pub async fn run<F, Fut, E>(self, report: F, failed: E) -> Result<usize, AppError>
+    where
+        F: Fn(IndexProgress) -> Fut,
+        Fut: Future<Output = ()>,
+        E: Fn(AppError),
+    {
+        let total = self.rag.count_missing_embeddings().await?;
+        let mut progress = IndexProgress { indexed: 0, total, finished: false };
+        let mut last_id = 0;
+        report(progress).await;
+
+        while !self.cancel.load(Ordering::Relaxed) {
+            // Stay out of the way while the user is waiting on a response
+            if self.interactive_busy.load(Ordering::Relaxed) {
+                tokio::time::sleep(BUSY_POLL).await;
+                continue;
+            }
+
+            let batch = self.rag.conversations_missing_embeddings(last_id, BATCH_SIZE).await?;
+            let Some((batch_last_id, _)) = batch.last() else {
+                break;
+            };
+            last_id = *batch_last_id;
+
+            let mut tasks = JoinSet::new();
+            for (conversation_id, text) in batch {
+                let client = self.client.clone();
+                let model = self.model.clone();
+                tasks.spawn(async move { (conversation_id, client.embed(&model, &text).await) });
+            }
+
+            let mut embeddings = Vec::with_capacity(BATCH_SIZE);
+            while let Some(joined) = tasks.join_next().await {
+                match joined {
+                    Ok((conversation_id, Ok(vector))) => embeddings.push((conversation_id, vector)),
+                    Ok((conversation_id, Err(e))) => {
+                        failed(AppError::Other(format!("Conversation {}: {}", conversation_id, e)));
+                    }
+                    Err(e) => failed(e.into()),
+                }
+            }
+            progress.indexed += embeddings.len();
+            self.rag.store_embeddings(&self.model, embeddings).await?;
+
+            report(progress).await;
+            tokio::time::sleep(BATCH_DELAY).await;
+        }
+
+        progress.finished = true;
+        report(progress).await;
+
+        Ok(progress.indexed)
+    }
+}
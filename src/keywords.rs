@@ -0,0 +1,80 @@
+// keywords.rs
+use std::collections::HashSet;
+
+pub const STOPWORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "also", "am", "an", "and", "any",
+    "are", "as", "at", "be", "because", "been", "before", "being", "below", "between", "both",
+    "but", "by", "can", "could", "did", "do", "does", "doing", "down", "during", "each", "else",
+    "few", "for", "from", "further", "get", "give", "had", "has", "have", "having", "he", "her",
+    "here", "hers", "herself", "him", "himself", "his", "how", "i", "if", "in", "into", "is",
+    "it", "its", "itself", "just", "know", "let", "like", "make", "me", "might", "more", "most",
+    "must", "my", "myself", "need", "no", "nor", "not", "now", "of", "off", "on", "once", "only",
+    "or", "other", "our", "ours", "ourselves", "out", "over", "own", "please", "same", "shall",
+    "she", "should", "so", "some", "such", "tell", "than", "thanks", "that", "the", "their",
+    "theirs", "them", "themselves", "then", "there", "these", "they", "this", "those", "through",
+    "to", "too", "under", "until", "up", "use", "using", "very", "want", "was", "we", "were",
+    "what", "when", "where", "which", "while", "who", "whom", "why", "will", "with", "would",
+    "you", "your", "yours", "yourself", "yourselves",
+];
+
+pub fn is_stopword(word: &str) -> bool {
+    STOPWORDS.contains(&word)
+}
+
+// Lowercased words with punctuation stripped and stopwords removed
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map(|word| word.to_lowercase())
+        .filter(|word| word.chars().count() > 1 && !is_stopword(word))
+        .collect()
+}
+
+// Ranks the distinct tokens of `text` by length and inverse document frequency
+pub fn extract_keywords<F>(
+    text: &str,
+    total_documents: usize,
+    mut document_frequency: F,
+    max_keywords: usize,
+) -> Vec<(String, f32)>
+where
+    F: FnMut(&str) -> usize,
+{
+    let mut seen = HashSet::new();
+    let mut weighted: Vec<(String, f32)> = tokenize(text)
+        .into_iter()
+        .filter(|word| seen.insert(word.clone()))
+        .map(|word| {
+            let frequency = document_frequency(&word);
+            let idf = ((total_documents as f32 + 1.0) / (frequency as f32 + 1.0)).ln() + 1.0;
+            let length = (word.chars().count().min(12) as f32).sqrt();
+            let weight = idf * length;
+            (word, weight)
+        })
+        .collect();
+
+    weighted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    weighted.truncate(max_keywords);
+    weighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn question_words_and_stopwords_are_dropped() {
+        let keywords: HashSet<String> = extract_keywords("How do I parse JSON in Rust", 10, |_| 1, 10)
+            .into_iter()
+            .map(|(word, _)| word)
+            .collect();
+        let expected: HashSet<String> = ["parse", "json", "rust"].iter().map(|word| word.to_string()).collect();
+        assert_eq!(keywords, expected);
+    }
+
+    #[test]
+    fn rarer_words_weigh_more() {
+        let keywords = extract_keywords("rust tokio", 100, |word| if word == "rust" { 50 } else { 2 }, 10);
+        assert_eq!(keywords[0].0, "tokio");
+        assert!(keywords[0].1 > keywords[1].1);
+    }
+}
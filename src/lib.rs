@@ -0,0 +1,41 @@
+// lib.rs
+// Everything except the window setup lives here, so the storage, HTTP and plugin code
+// can be used and tested without a display.
+pub mod models;
+pub mod backend;
+pub mod ollama;
+pub mod scheduler;
+pub mod openai;
+pub mod rag;
+pub mod analytics;
+pub mod ui;
+pub mod pipeline;
+pub mod history;
+#[cfg(feature = "encryption")]
+pub mod unlock;
+pub mod file_handler;
+pub mod migrations;
+pub mod db;
+pub mod writer;
+pub mod keywords;
+pub mod indexer;
+pub mod titles;
+pub mod followups;
+pub mod plugins;
+pub mod diff;
+pub mod tokens;
+pub mod config;
+pub mod templates;
+pub mod export;
+pub mod obsidian;
+pub mod import;
+pub mod notifier;
+#[cfg(feature = "speech")]
+pub mod speech;
+#[cfg(feature = "voice")]
+pub mod voice;
+pub mod archive;
+pub mod repo;
+pub mod server;
+pub mod webhook;
+pub mod text;
@@ -1,26 +1,113 @@
 // main.rs
 use eframe::egui;
+use std::path::PathBuf;
 
-mod models;
-mod ollama;
-mod rag;
-mod analytics;
-mod ui;
-mod file_handler;
-
-use crate::ui::TouristApp;
+use rustai::config::{self, AppConfig};
+use rustai::db;
+use rustai::import;
+use rustai::rag::RagSystem;
+#[cfg(feature = "encryption")]
+use rustai::unlock::Launcher;
+#[cfg(not(feature = "encryption"))]
+use rustai::ui::TouristApp;
 
 fn main() -> Result<(), eframe::Error> {
+    if let Some(export) = parse_args() {
+        std::process::exit(import_chatgpt(export));
+    }
+    let config = AppConfig::load();
+    #[cfg(not(feature = "encryption"))]
+    if db::is_encrypted_in(config::data_dir()) {
+        eprintln!("The database is encrypted, this build can't open it. Rebuild with --features encryption.");
+        std::process::exit(1);
+    }
+    
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size([1200.0, 800.0])
+        .with_min_inner_size([800.0, 600.0]);
+    if let Some(window) = config.window {
+        // Off-screen positions are clamped once the monitor size is known
+        viewport = viewport
+            .with_inner_size(window.size)
+            .with_position(window.position);
+    }
+    
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1200.0, 800.0])
-            .with_min_inner_size([800.0, 600.0]),
+        viewport,
+        // The app sets its own dark theme once, following the system would replace it
+        follow_system_theme: false,
         ..Default::default()
     };
     
     eframe::run_native(
         "TouristXi9d - Enhanced AI Client with RAG & Analytics",
         options,
-        Box::new(|_cc| Ok(Box::new(TouristApp::default()))),
+        Box::new(|cc| {
+            // Zoom shortcuts are handled by the app so they respect the configured range
+            cc.egui_ctx.options_mut(|options| options.zoom_with_keyboard = false);
+            #[cfg(feature = "encryption")]
+            let app = Launcher::new(config, cc.egui_ctx.clone());
+            #[cfg(not(feature = "encryption"))]
+            let app = TouristApp::new(config, cc.egui_ctx.clone());
+            Ok(Box::new(app))
+        }),
     )
-}
\ No newline at end of file
+}
+
+// `--data-dir <path>` runs against another directory, e.g. to keep a separate profile.
+// `import-chatgpt <conversations.json>` imports without opening a window, its file is returned.
+fn parse_args() -> Option<PathBuf> {
+    let mut export = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let dir = match arg.strip_prefix("--data-dir=") {
+            Some(dir) => Some(dir.to_string()),
+            None if arg == "--data-dir" => args.next(),
+            None if arg == "import-chatgpt" => {
+                let Some(file) = args.next() else {
+                    eprintln!("Usage: rustai [--data-dir <path>] import-chatgpt <conversations.json>");
+                    std::process::exit(2);
+                };
+                export = Some(PathBuf::from(file));
+                continue;
+            }
+            None => {
+                eprintln!("Ignoring unknown argument {}", arg);
+                continue;
+            }
+        };
+        match dir {
+            Some(dir) if !dir.is_empty() => config::set_data_dir(dir.into()),
+            _ => {
+                eprintln!("--data-dir needs a path");
+                std::process::exit(2);
+            }
+        }
+    }
+    export
+}
+
+// Exit code for the import-chatgpt command
+fn import_chatgpt(export: PathBuf) -> i32 {
+    if db::is_encrypted_in(config::data_dir()) {
+        eprintln!("Import failed: the database is encrypted, import from the app instead");
+        return 1;
+    }
+    let result = RagSystem::new().and_then(|rag| {
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(import::import_chatgpt(&rag, export))
+    });
+    match result {
+        Ok(report) => {
+            println!(
+                "Imported {} conversations ({} exchanges), skipped {}",
+                report.imported, report.turns, report.skipped,
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Import failed: {}", e);
+            1
+        }
+    }
+}
@@ -0,0 +1,442 @@
+// migrations.rs
+use rusqlite::Connection;
+use crate::models::AppError;
+
+type Migration = fn(&Connection) -> Result<(), rusqlite::Error>;
+
+// Ordered schema migrations. Append new steps at the end, never edit an applied one.
+const MIGRATIONS: &[(&str, Migration)] = &[
+    ("create base tables", create_base_tables),
+    ("add session_id to conversations", add_session_id),
+    ("create tag tables", create_tag_tables),
+    ("add status to conversations", add_conversation_status),
+    ("create conversation embeddings table", create_embeddings_table),
+    ("create documents table", create_documents_table),
+    ("index conversations by timestamp", create_timestamp_index),
+    ("add token counts to conversations", add_token_counts),
+    ("create errors table", create_errors_table),
+    ("add reasoning to conversations", add_reasoning),
+    ("add superseded_at to conversations", add_superseded_at),
+    ("add first_token_ms to conversations", add_first_token_ms),
+    ("create prompt templates table", create_prompt_templates_table),
+    ("add starred to conversations", add_starred),
+    ("add feedback to conversations", add_feedback),
+    ("create comparisons table", create_comparisons_table),
+    ("create sessions table", create_sessions_table),
+    ("add parent_response_id to conversations", add_parent_response_id),
+    ("create command runs table", create_command_runs_table),
+    ("create plugin requests table", create_plugin_requests_table),
+    ("add revision to documents", add_document_revision),
+    ("add backend to conversations", add_conversation_backend),
+    ("add source to conversations", add_conversation_source),
+    ("create outbox table", create_outbox_table),
+    ("index conversations by model and session", create_model_session_indexes),
+    ("add branch references to sessions and conversations", add_branch_references),
+    ("add tags to outbox", add_outbox_tags),
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.len() as i64
+}
+
+pub fn current_version(connection: &Connection) -> Result<i64, AppError> {
+    let version: Option<i64> = connection.query_row(
+        "SELECT MAX(version) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(version.unwrap_or(0))
+}
+
+pub fn run(connection: &mut Connection) -> Result<(), AppError> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)",
+        [],
+    )?;
+
+    let current = current_version(connection)?;
+
+    for (index, (description, migration)) in MIGRATIONS.iter().enumerate() {
+        let version = index as i64 + 1;
+        if version <= current {
+            continue;
+        }
+
+        let tx = connection.transaction()?;
+        migration(&tx)?;
+        tx.execute("DELETE FROM schema_version", [])?;
+        tx.execute("INSERT INTO schema_version (version) VALUES (?1)", [version])?;
+        tx.commit()?;
+
+        eprintln!("Applied migration {}: {}", version, description);
+    }
+
+    Ok(())
+}
+
+fn create_base_tables(connection: &Connection) -> Result<(), rusqlite::Error> {
+    // Uses IF NOT EXISTS so databases created before versioning adopt version 1 as-is
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS conversations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            response TEXT NOT NULL,
+            model_used TEXT NOT NULL,
+            response_time_ms INTEGER NOT NULL,
+            file_context TEXT
+        )",
+        [],
+    )?;
+
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS embeddings_cache (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            prompt_hash TEXT UNIQUE NOT NULL,
+            prompt TEXT NOT NULL,
+            response TEXT NOT NULL,
+            similarity_score REAL DEFAULT 0.0
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn add_session_id(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute("ALTER TABLE conversations ADD COLUMN session_id TEXT", [])?;
+    Ok(())
+}
+
+fn create_tag_tables(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(
+        "CREATE TABLE tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL
+        );
+        CREATE TABLE conversation_tags (
+            conversation_id INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+            tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (conversation_id, tag_id)
+        );",
+    )
+}
+
+fn add_conversation_status(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(
+        "ALTER TABLE conversations ADD COLUMN status TEXT NOT NULL DEFAULT 'ok';
+        UPDATE conversations SET status = 'error' WHERE response LIKE 'Error making request%';",
+    )
+}
+
+fn create_embeddings_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "CREATE TABLE conversation_embeddings (
+            conversation_id INTEGER PRIMARY KEY REFERENCES conversations(id) ON DELETE CASCADE,
+            model TEXT NOT NULL,
+            vector BLOB NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_documents_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "CREATE TABLE documents (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            added_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_timestamp_index(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "CREATE INDEX IF NOT EXISTS idx_conversations_timestamp ON conversations(timestamp)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_token_counts(connection: &Connection) -> Result<(), rusqlite::Error> {
+    // Left NULL for existing rows, analytics estimates those from their length
+    connection.execute_batch(
+        "ALTER TABLE conversations ADD COLUMN prompt_tokens INTEGER;
+        ALTER TABLE conversations ADD COLUMN response_tokens INTEGER;",
+    )
+}
+
+fn create_errors_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(
+        "CREATE TABLE errors (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            message TEXT NOT NULL,
+            model TEXT NOT NULL,
+            url TEXT NOT NULL
+        );
+        CREATE INDEX idx_errors_timestamp ON errors(timestamp);",
+    )
+}
+
+fn add_reasoning(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute("ALTER TABLE conversations ADD COLUMN reasoning TEXT", [])?;
+    Ok(())
+}
+
+fn add_superseded_at(connection: &Connection) -> Result<(), rusqlite::Error> {
+    // Set when the user edits an earlier prompt and the response is cut from the chat
+    connection.execute("ALTER TABLE conversations ADD COLUMN superseded_at TEXT", [])?;
+    Ok(())
+}
+
+fn add_first_token_ms(connection: &Connection) -> Result<(), rusqlite::Error> {
+    // Only streamed responses have a time to first token, others stay NULL
+    connection.execute("ALTER TABLE conversations ADD COLUMN first_token_ms INTEGER", [])?;
+    Ok(())
+}
+
+fn create_prompt_templates_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "CREATE TABLE prompt_templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            body TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_starred(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute("ALTER TABLE conversations ADD COLUMN starred INTEGER NOT NULL DEFAULT 0", [])?;
+    Ok(())
+}
+
+fn add_feedback(connection: &Connection) -> Result<(), rusqlite::Error> {
+    // -1 thumbs down, 0 no rating, 1 thumbs up
+    connection.execute("ALTER TABLE conversations ADD COLUMN feedback INTEGER NOT NULL DEFAULT 0", [])?;
+    Ok(())
+}
+
+fn create_comparisons_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "CREATE TABLE comparisons (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            left_model TEXT NOT NULL,
+            right_model TEXT NOT NULL,
+            left_conversation_id INTEGER,
+            right_conversation_id INTEGER,
+            verdict TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_sessions_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    // A NULL title means none was generated or given yet
+    connection.execute(
+        "CREATE TABLE sessions (
+            id TEXT PRIMARY KEY,
+            title TEXT,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    connection.execute(
+        "INSERT INTO sessions (id, created_at)
+         SELECT session_id, MIN(timestamp) FROM conversations
+         WHERE session_id IS NOT NULL GROUP BY session_id",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_parent_response_id(connection: &Connection) -> Result<(), rusqlite::Error> {
+    // Set on an answer rerun with another model, pointing at the answer it retried
+    connection.execute("ALTER TABLE conversations ADD COLUMN parent_response_id INTEGER", [])?;
+    Ok(())
+}
+
+fn create_command_runs_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "CREATE TABLE command_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            command TEXT NOT NULL,
+            exit_code INTEGER,
+            timed_out INTEGER NOT NULL DEFAULT 0,
+            duration_ms INTEGER NOT NULL,
+            output TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_plugin_requests_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "CREATE TABLE plugin_requests (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            plugin TEXT NOT NULL,
+            purpose TEXT NOT NULL,
+            model TEXT NOT NULL,
+            response_time_ms INTEGER NOT NULL,
+            success INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_document_revision(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute("ALTER TABLE documents ADD COLUMN revision TEXT", [])?;
+    Ok(())
+}
+
+// Everything saved before this came from Ollama, the only backend there was
+fn add_conversation_backend(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute("ALTER TABLE conversations ADD COLUMN backend TEXT", [])?;
+    connection.execute("UPDATE conversations SET backend = 'ollama'", [])?;
+    Ok(())
+}
+
+// NULL for conversations had in the app, otherwise where they were imported from
+fn add_conversation_source(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute("ALTER TABLE conversations ADD COLUMN source TEXT", [])?;
+    Ok(())
+}
+
+// Prompts written while the server was unreachable, sent in id order once it's back
+fn create_outbox_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "CREATE TABLE outbox (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            created_at TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            model TEXT NOT NULL,
+            session_id TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+// The per-model GROUP BYs and session lookups scanned the whole table. ANALYZE gives the
+// planner row counts so it picks these over the timestamp index where they're better.
+fn create_model_session_indexes(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_conversations_model_used ON conversations(model_used);
+         CREATE INDEX IF NOT EXISTS idx_conversations_session_id ON conversations(session_id);
+         ANALYZE conversations;",
+    )?;
+    Ok(())
+}
+
+// A branch is a session started from part of another. Its conversations are copies that
+// point back at the rows they were copied from.
+fn add_branch_references(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(
+        "ALTER TABLE sessions ADD COLUMN parent_id TEXT;
+         ALTER TABLE conversations ADD COLUMN branched_from INTEGER;",
+    )?;
+    Ok(())
+}
+
+// Tags active when the prompt was queued, comma-separated like GROUP_CONCAT gives them
+fn add_outbox_tags(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute("ALTER TABLE outbox ADD COLUMN tags TEXT NOT NULL DEFAULT ''", [])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn columns(connection: &Connection, table: &str) -> Vec<String> {
+        let mut statement = connection.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        let names = statement.query_map([], |row| row.get::<_, String>(1)).unwrap();
+        names.collect::<Result<_, _>>().unwrap()
+    }
+
+    fn count(connection: &Connection, table: &str) -> i64 {
+        connection.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    // A database from before versioning: only the base tables, no schema_version
+    fn unversioned_database() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        create_base_tables(&connection).unwrap();
+        let mut insert = connection.prepare(
+            "INSERT INTO conversations (timestamp, prompt, response, model_used, response_time_ms, file_context)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        ).unwrap();
+        insert.execute(params!["2024-01-01T10:00:00+00:00", "first", "an answer", "llama3", 120, ""]).unwrap();
+        insert.execute(params!["2024-01-01T10:05:00+00:00", "second", "Error making request: refused", "llama3", 5, ""]).unwrap();
+        insert.execute(params!["2024-01-02T09:00:00+00:00", "third", "another answer", "mistral", 300, "notes.txt"]).unwrap();
+        drop(insert);
+        connection.execute(
+            "INSERT INTO embeddings_cache (prompt_hash, prompt, response) VALUES ('abc', 'first', 'an answer')",
+            [],
+        ).unwrap();
+        connection
+    }
+
+    #[test]
+    fn upgrades_unversioned_database_keeping_rows() {
+        let mut connection = unversioned_database();
+        run(&mut connection).unwrap();
+
+        assert_eq!(current_version(&connection).unwrap(), latest_version());
+        assert_eq!(count(&connection, "conversations"), 3);
+        assert_eq!(count(&connection, "embeddings_cache"), 1);
+
+        let conversation_columns = columns(&connection, "conversations");
+        for column in [
+            "session_id", "status", "prompt_tokens", "response_tokens", "reasoning", "superseded_at",
+            "first_token_ms", "starred", "feedback", "parent_response_id", "backend", "source", "branched_from",
+        ] {
+            assert!(conversation_columns.iter().any(|name| name == column), "missing column {}", column);
+        }
+        assert!(columns(&connection, "sessions").iter().any(|name| name == "parent_id"));
+        for table in ["tags", "conversation_tags", "conversation_embeddings", "documents", "errors", "outbox"] {
+            assert_eq!(count(&connection, table), 0, "table {}", table);
+        }
+
+        // Old error responses are recognised, everything else defaults to ok
+        let statuses: Vec<(String, String)> = connection
+            .prepare("SELECT prompt, status FROM conversations ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(statuses, vec![
+            ("first".to_string(), "ok".to_string()),
+            ("second".to_string(), "error".to_string()),
+            ("third".to_string(), "ok".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn running_twice_changes_nothing() {
+        let mut connection = unversioned_database();
+        run(&mut connection).unwrap();
+        run(&mut connection).unwrap();
+
+        assert_eq!(current_version(&connection).unwrap(), latest_version());
+        assert_eq!(count(&connection, "schema_version"), 1);
+        assert_eq!(count(&connection, "conversations"), 3);
+    }
+}
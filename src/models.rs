@@ -1,12 +1,29 @@
 // models.rs
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
+use crate::plugins::PluginRun;
 
 #[derive(Serialize)]
 pub struct OllamaRequest {
     pub model: String,
     pub prompt: String,
     pub stream: bool,
+    // Base64-encoded images for multimodal models
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<GenerateOptions>,
+    // "json" constrains the output to valid JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GenerateOptions {
+    pub num_predict: u32,
 }
 
 #[derive(Deserialize)]
@@ -14,6 +31,271 @@ pub struct OllamaResponse {
     pub response: String,
 }
 
+// One message of a conversation sent to a chat endpoint, role is "system", "user" or "assistant"
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatTurn {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: "system".to_string(), content: content.into() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".to_string(), content: content.into() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: "assistant".to_string(), content: content.into() }
+    }
+}
+
+#[derive(Serialize)]
+pub struct OllamaChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatTurn>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<GenerateOptions>,
+}
+
+#[derive(Deserialize)]
+pub struct OllamaChatResponse {
+    pub message: ChatTurn,
+}
+
+// One line of a streamed /api/generate response
+#[derive(Deserialize)]
+pub struct OllamaStreamChunk {
+    #[serde(default)]
+    pub response: String,
+    #[serde(default)]
+    pub done: bool,
+    pub error: Option<String>,
+}
+
+// Text produced by a generation request, which the user may have stopped early
+#[derive(Debug, Default)]
+pub struct Generation {
+    pub text: String,
+    pub first_token_ms: Option<i64>,
+    pub cancelled: bool,
+}
+
+#[derive(Serialize)]
+pub struct ShowRequest {
+    pub model: String,
+}
+
+// Only the parameters are read, as a Modelfile-style "name value" list
+#[derive(Deserialize)]
+pub struct ShowResponse {
+    #[serde(default)]
+    pub parameters: String,
+}
+
+// Installed models as listed by /api/tags
+#[derive(Deserialize)]
+pub struct TagsResponse {
+    #[serde(default)]
+    pub models: Vec<ModelTag>,
+}
+
+#[derive(Deserialize)]
+pub struct ModelTag {
+    pub name: String,
+}
+
+// Models loaded in memory as listed by /api/ps
+#[derive(Deserialize)]
+pub struct ProcessResponse {
+    #[serde(default)]
+    pub models: Vec<RunningModel>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RunningModel {
+    pub name: String,
+    // Bytes in memory overall, and how many of those are on the GPU
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub size_vram: u64,
+    // When the server unloads it if unused
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Local>>,
+}
+
+// A generate request with no prompt and keep_alive 0 unloads the model
+#[derive(Serialize)]
+pub struct UnloadRequest {
+    pub model: String,
+    pub keep_alive: i64,
+}
+
+#[derive(Serialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    pub prompt: String,
+}
+
+#[derive(Deserialize)]
+pub struct EmbeddingResponse {
+    pub embedding: Vec<f32>,
+}
+
+// /v1/chat/completions as spoken by LM Studio, llama.cpp's server and vLLM
+#[derive(Serialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatCompletionMessage>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    // {"type": "json_object"} constrains the output to valid JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+}
+
+#[derive(Serialize)]
+pub struct ChatCompletionMessage {
+    pub role: String,
+    pub content: MessageContent,
+}
+
+// Plain text unless there are images, some servers only accept the plain form
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+// Images are sent inline as data: URLs
+#[derive(Serialize)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChatCompletionResponse {
+    #[serde(default)]
+    pub choices: Vec<ChatCompletionChoice>,
+}
+
+#[derive(Deserialize)]
+pub struct ChatCompletionChoice {
+    pub message: ChatCompletionDelta,
+}
+
+// One `data:` event of a streamed completion. Errors arrive as {"error": ...} instead.
+#[derive(Deserialize)]
+pub struct ChatCompletionChunk {
+    #[serde(default)]
+    pub choices: Vec<ChatCompletionChunkChoice>,
+    pub error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+pub struct ChatCompletionChunkChoice {
+    #[serde(default)]
+    pub delta: ChatCompletionDelta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ChatCompletionDelta {
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ModelList {
+    #[serde(default)]
+    pub data: Vec<ModelListEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct ModelListEntry {
+    pub id: String,
+}
+
+#[derive(Serialize)]
+pub struct OpenAiEmbeddingRequest {
+    pub model: String,
+    pub input: String,
+}
+
+#[derive(Deserialize)]
+pub struct OpenAiEmbeddingResponse {
+    #[serde(default)]
+    pub data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Deserialize)]
+pub struct OpenAiEmbedding {
+    pub embedding: Vec<f32>,
+}
+
+// Which kind of server the app talks to, chosen in settings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackendKind {
+    #[default]
+    Ollama,
+    OpenAiCompat,
+}
+
+impl BackendKind {
+    pub const ALL: [BackendKind; 2] = [BackendKind::Ollama, BackendKind::OpenAiCompat];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BackendKind::Ollama => "Ollama",
+            BackendKind::OpenAiCompat => "OpenAI-compatible",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConversationStatus {
+    Ok,
+    Error,
+    Cancelled,
+}
+
+impl ConversationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConversationStatus::Ok => "ok",
+            ConversationStatus::Error => "error",
+            ConversationStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "error" => ConversationStatus::Error,
+            "cancelled" => ConversationStatus::Cancelled,
+            _ => ConversationStatus::Ok,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ConversationEntry {
     pub id: i64,
@@ -23,54 +305,768 @@ pub struct ConversationEntry {
     pub model_used: String,
     pub response_time_ms: i64,
     pub file_context: Option<String>,
+    pub tags: Vec<String>,
+    pub status: ConversationStatus,
+    pub reasoning: Option<String>,
+    pub first_token_ms: Option<i64>,
+    pub starred: bool,
+    pub feedback: i64,
+    // Kind of server that produced it, None for imported exchanges
+    pub backend: Option<String>,
+    // Where an imported exchange came from, e.g. "chatgpt-import"
+    pub source: Option<String>,
+}
+
+// One conversation from another app, stored as a session of its own
+#[derive(Clone, Debug)]
+pub struct ImportedSession {
+    pub id: String,
+    pub title: String,
+    pub created_at: DateTime<Local>,
+    pub entries: Vec<ConversationEntry>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    // Exchanges across all imported conversations
+    pub turns: usize,
+    // Unreadable, empty or already imported
+    pub skipped: usize,
+}
+
+// A model response split into its answer and any <think>...</think> reasoning
+#[derive(Clone, Debug, Default)]
+pub struct ParsedResponse {
+    pub answer: String,
+    pub reasoning: Option<String>,
+    // Notes from observing plugins, shown under the answer
+    pub annotations: Vec<String>,
+}
+
+impl ParsedResponse {
+    pub fn parse(raw: &str) -> Self {
+        const OPEN: &str = "<think>";
+        const CLOSE: &str = "</think>";
+        
+        let mut answer = String::new();
+        let mut reasoning: Vec<&str> = Vec::new();
+        let mut rest = raw;
+        
+        while let Some(start) = rest.find(OPEN) {
+            answer.push_str(&rest[..start]);
+            let after_open = &rest[start + OPEN.len()..];
+            match after_open.find(CLOSE) {
+                Some(end) => {
+                    reasoning.push(after_open[..end].trim());
+                    rest = &after_open[end + CLOSE.len()..];
+                }
+                None => {
+                    // Unterminated block, e.g. a truncated response
+                    reasoning.push(after_open.trim());
+                    rest = "";
+                }
+            }
+        }
+        answer.push_str(rest);
+        
+        let reasoning = reasoning.join("\n\n");
+        Self {
+            answer: answer.trim().to_string(),
+            reasoning: (!reasoning.is_empty()).then_some(reasoning),
+            annotations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub content: String,
+    pub is_user: bool,
+    pub timestamp: DateTime<Local>,
+    #[serde(default)]
+    pub model_used: Option<String>,
+    #[serde(default)]
+    pub response_time: Option<i64>,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    #[serde(default)]
+    pub conversation_id: Option<i64>,
+    #[serde(default)]
+    pub truncated: bool,
+    #[serde(default)]
+    pub starred: bool,
+    #[serde(default)]
+    pub feedback: i64,
+    // Set on the left answer of a side-by-side comparison
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Box<Comparison>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<String>,
+    // (prompt, response) token counts, for answers generated in this session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<(usize, usize)>,
+    // Asked through the HTTP API rather than typed here
+    #[serde(default)]
+    pub external: bool,
+    // Outbox row of a prompt waiting for the server to come back, negative until the row is written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queued: Option<i64>,
+    // Earlier messages left out of the request so the chat fit the context window
+    #[serde(default)]
+    pub history_dropped: usize,
+}
+
+// A prompt waiting in the outbox
+#[derive(Clone, Debug)]
+pub struct OutboxItem {
+    pub id: i64,
+    pub created_at: DateTime<Local>,
+    pub prompt: String,
+    pub model: String,
+    pub session_id: String,
+    // Applied to the answer as if the prompt had been sent when it was queued
+    pub tags: Vec<String>,
+}
+
+// A finished answer with what was true when it was generated, not when the UI got to it
+#[derive(Clone, Debug)]
+pub struct ResponsePayload {
+    pub parsed: ParsedResponse,
+    pub model: String,
+    pub response_time_ms: i64,
+    // Row in the conversations table, None when it wasn't saved
+    pub conversation_id: Option<i64>,
+    pub prompt_tokens: usize,
+    pub response_tokens: usize,
+    pub history_dropped: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ComparisonSide {
+    Left,
+    Right,
+}
+
+impl ComparisonSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComparisonSide::Left => "left",
+            ComparisonSide::Right => "right",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Comparison {
+    pub right: ChatMessage,
+    #[serde(default)]
+    pub verdict: Option<ComparisonSide>,
+}
+
+// One model's answer in a comparison, errors are kept as text
+#[derive(Clone, Debug)]
+pub struct ComparedResponse {
+    pub model: String,
+    pub result: Result<ParsedResponse, String>,
+    pub response_time: i64,
+    pub conversation_id: Option<i64>,
+}
+
+// A chat session as listed in the sidebar
+#[derive(Clone, Debug)]
+pub struct SessionSummary {
+    pub id: String,
+    pub title: Option<String>,
+    pub last_active: DateTime<Local>,
+    pub conversations: usize,
+    // The session this one was branched from
+    pub parent_id: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ComparisonRecord {
+    pub timestamp: DateTime<Local>,
+    pub prompt: String,
+    pub left_model: String,
+    pub right_model: String,
+    pub left_conversation_id: Option<i64>,
+    pub right_conversation_id: Option<i64>,
+    pub verdict: ComparisonSide,
+}
+
+// Settings the chat was held with, informational only on import
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    pub enable_rag: bool,
+    pub rag_hybrid: bool,
+    pub embedding_model: String,
+}
+
+// Envelope written by the JSON export and read back by the import
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatExport {
+    pub version: u32,
+    pub exported_at: DateTime<Local>,
+    pub model: String,
+    #[serde(default)]
+    pub settings: ExportSettings,
+    pub messages: Vec<ChatMessage>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Text,
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Text, ExportFormat::Markdown, ExportFormat::Json];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Text => "Plain text",
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Json => "JSON",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+// Plain-text copies of saved exchanges, written next to the database
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextDump {
+    Off,
+    // A response_*.txt file for every exchange
+    #[default]
+    PerResponse,
+    // Appended to one conversations_YYYY-MM-DD.log.md file per day
+    DailyLog,
+}
+
+impl TextDump {
+    pub const ALL: [TextDump; 3] = [TextDump::Off, TextDump::PerResponse, TextDump::DailyLog];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TextDump::Off => "Off",
+            TextDump::PerResponse => "One file per response",
+            TextDump::DailyLog => "Daily log file",
+        }
+    }
+}
+
+// A saved prompt, `id` is 0 until it has been stored
+#[derive(Clone, Debug, Default)]
+pub struct PromptTemplate {
+    pub id: i64,
+    pub name: String,
+    pub body: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttachmentKind {
+    Text,
+    Image,
+}
+
+// Where an attachment came from. Only files picked or dropped with a path have one on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttachmentOrigin {
+    File,
+    Dropped,
+    Clipboard,
+}
+
+// A file attached to the next message. Image content is base64, ready for the API.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Attachment {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    // Shared, so the copies handed to plugins and tasks on every send don't copy the file
+    pub content: Arc<str>,
+    pub kind: AttachmentKind,
+    pub origin: AttachmentOrigin,
+    // Size of the original file, the content may be only part of it
+    pub len: u64,
+    pub modified: Option<DateTime<Local>>,
+    // Encoding the text was decoded from, None for images. Only shown, so not kept across restarts.
+    #[serde(skip)]
+    pub encoding: Option<&'static str>,
+    // Schema-level view of CSV and JSON files, sent instead of the content by default
+    pub summary: Option<String>,
+    pub include_raw: bool,
+    // Set when the file loaded but couldn't be read the way its type suggests
+    pub warning: Option<String>,
+}
+
+impl Attachment {
+    // What goes into the prompt for this file
+    pub fn prompt_content(&self) -> &str {
+        match &self.summary {
+            Some(summary) if !self.include_raw => summary,
+            _ => &self.content,
+        }
+    }
+}
+
+// The unsaved state of the open chat, written to disk so a crash doesn't lose it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub saved_at: DateTime<Local>,
+    pub messages: Vec<ChatMessage>,
+    pub attachments: Vec<Arc<Attachment>>,
+    pub draft: String,
+    pub model: String,
+    // Restored so answers after a recovery join the same session, empty in older files
+    #[serde(default)]
+    pub session_id: String,
+}
+
+// A text file read from disk along with what the filesystem says about it
+#[derive(Clone, Debug)]
+pub struct LoadedFile {
+    pub path: PathBuf,
+    pub name: String,
+    pub len: u64,
+    pub modified: Option<DateTime<Local>>,
+    // Carries a "(truncated, ...)" line when only part of the file was read
+    pub content: String,
+    pub encoding: &'static str,
+}
+
+// The files in a zip or tar.gz, for the user to pick from before anything is extracted
+#[derive(Clone, Debug)]
+pub struct ArchiveListing {
+    pub path: PathBuf,
+    pub name: String,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ArchiveEntry {
+    // Position in the archive, which is how the entry is found again on extraction
+    pub index: usize,
+    // Relative to the archive root, with forward slashes
+    pub path: String,
+    pub size: u64,
+    pub selected: bool,
+}
+
+// The files in a git repository, for the user to pick from before anything is read
+#[derive(Clone, Debug)]
+pub struct RepoListing {
+    pub root: PathBuf,
+    pub name: String,
+    pub branch: Option<String>,
+    // Short hash of HEAD
+    pub head: Option<String>,
+    // Sorted by path, so each directory's files are contiguous
+    pub files: Vec<RepoFile>,
+}
+
+impl RepoListing {
+    // "main@1a2b3c4", or whichever half git could tell us
+    pub fn revision(&self) -> Option<String> {
+        match (&self.branch, &self.head) {
+            (Some(branch), Some(head)) => Some(format!("{}@{}", branch, head)),
+            (Some(only), None) | (None, Some(only)) => Some(only.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RepoFile {
+    // Relative to the repository root, with forward slashes
+    pub path: String,
+    pub size: u64,
+    pub selected: bool,
+}
+
+// Which end of an over-limit text file to keep
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Truncation {
+    Head,
+    Tail,
+}
+
+// How attachments are read, from the user's settings
+#[derive(Clone, Copy, Debug)]
+pub struct LoadOptions {
+    pub max_text_bytes: u64,
+    // Which end of an over-limit text file to keep, None refuses it
+    pub truncation: Option<Truncation>,
+    // Include cell outputs when rendering notebooks
+    pub notebook_outputs: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContextSource {
+    Conversation,
+    // Knowledge-base document, by name. The entry's prompt is the name and its response the chunk text.
+    // Documents from a git repository carry the branch and commit they were read at.
+    Document { name: String, revision: Option<String> },
+}
+
+#[derive(Clone, Debug)]
+pub struct ScoredEntry {
+    pub entry: ConversationEntry,
+    pub score: f32,
+    pub excluded: bool,
+    pub source: ContextSource,
+}
+
+#[derive(Clone, Debug)]
+pub struct HybridQuery {
+    pub embedding: Vec<f32>,
+    pub keyword_weight: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetrievalOptions {
+    pub filter: ConversationFilter,
+    // Blends in vector similarity when present, otherwise keyword ranking only
+    pub hybrid: Option<HybridQuery>,
+    pub include_conversations: bool,
+    pub include_documents: bool,
+}
+
+impl Default for RetrievalOptions {
+    fn default() -> Self {
+        Self {
+            filter: ConversationFilter::default(),
+            hybrid: None,
+            include_conversations: true,
+            include_documents: true,
+        }
+    }
 }
 
 #[derive(Default, Clone, Debug)]
+pub struct ConversationFilter {
+    pub model: Option<String>,
+    pub after: Option<DateTime<Local>>,
+    pub before: Option<DateTime<Local>>,
+    pub text: Option<String>,
+    pub tag: Option<String>,
+    pub exclude_downvoted: bool,
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
 pub struct Analytics {
     pub total_requests: usize,
     pub avg_response_time: f64,
+    pub avg_streamed_response_time: Option<f64>,
+    pub avg_buffered_response_time: Option<f64>,
+    pub avg_first_token_time: Option<f64>,
+    pub min_response_time: i64,
+    pub max_response_time: i64,
+    pub p50_response_time: i64,
+    pub p90_response_time: i64,
+    pub p99_response_time: i64,
     pub most_used_model: String,
-    pub total_tokens_approx: usize,
+    pub total_tokens: usize,
     pub sessions_today: usize,
+    pub requests_today: usize,
+    pub avg_session_minutes: f64,
+    pub errors_today: usize,
+    pub error_rate: f64,
+    pub recent_errors: Vec<ErrorRecord>,
+    pub feedback_by_model: Vec<ModelFeedback>,
     pub cache_hits: usize,
     pub cache_misses: usize,
+    // Extra generations made by plugins, not counted in total_requests
+    pub plugin_requests: usize,
+    pub plugin_requests_today: usize,
+}
+
+// Thumbs up/down counts for one model
+#[derive(Clone, Debug, Serialize)]
+pub struct ModelFeedback {
+    pub model: String,
+    pub positive: usize,
+    pub negative: usize,
+}
+
+impl ModelFeedback {
+    pub fn positive_rate(&self) -> f64 {
+        let rated = self.positive + self.negative;
+        if rated == 0 { 0.0 } else { self.positive as f64 / rated as f64 }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorRecord {
+    pub timestamp: DateTime<Local>,
+    pub kind: String,
+    pub message: String,
+    pub model: String,
+    pub url: String,
+}
+
+// One approved command run by the commands plugin
+#[derive(Clone, Debug)]
+pub struct CommandRun {
+    pub timestamp: DateTime<Local>,
+    pub command: String,
+    // None when the command couldn't start, was killed or ended by a signal
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub duration_ms: i64,
+    // stdout followed by stderr
+    pub output: String,
+}
+
+// A generation a plugin made on its own, outside the user's request
+#[derive(Clone, Debug)]
+pub struct PluginRequest {
+    pub timestamp: DateTime<Local>,
+    pub plugin: String,
+    // What the request was for, e.g. "translate prompt"
+    pub purpose: String,
+    pub model: String,
+    pub response_time_ms: i64,
+    pub success: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    // Expires on its own
+    Warning,
+    // Stays until dismissed
+    Error,
+}
+
+// An infrastructure problem shown in the banner rather than the chat
+#[derive(Clone, Debug)]
+pub struct UiError {
+    pub message: String,
+    pub timestamp: DateTime<Local>,
+    pub severity: Severity,
+}
+
+impl UiError {
+    pub fn new(message: impl Into<String>, severity: Severity) -> Self {
+        Self {
+            message: message.into(),
+            timestamp: Local::now(),
+            severity,
+        }
+    }
+
+    // "`context`: `error`", as severe as the error's kind
+    pub fn from_error(context: &str, error: &AppError) -> Self {
+        Self::new(format!("{}: {}", context, error), error.severity())
+    }
+}
+
+// One day of usage, days without requests are included with zero counts
+#[derive(Clone, Debug)]
+pub struct DailyUsage {
+    pub date: NaiveDate,
+    pub requests: usize,
+    pub avg_response_ms: f64,
+}
+
+// Prompt size against response time, with a least-squares fit over the sampled points
+#[derive(Clone, Debug, Default)]
+pub struct LatencyCorrelation {
+    pub points: Vec<[f64; 2]>,
+    pub total_rows: usize,
+    pub pearson: Option<f64>,
+    pub slope: f64,
+    pub intercept: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IndexProgress {
+    pub indexed: usize,
+    pub total: usize,
+    pub finished: bool,
 }
 
 #[derive(Debug)]
 pub enum PendingOperation {
-    Response(String),
+    ResponseChunk(String),
+    Response(ResponsePayload),
+    Stopped(ResponsePayload),
+    Comparison(ComparedResponse, ComparedResponse),
     Analytics(Analytics),
-    RagSuggestions(Vec<ConversationEntry>),
+    DailyUsage(Vec<DailyUsage>),
+    TopKeywords(Vec<(String, usize)>),
+    LatencyCorrelation(LatencyCorrelation),
+    RagSuggestions(Vec<ScoredEntry>),
+    RagFailed(UiError),
+    History(Vec<ConversationEntry>),
+    Tags(Vec<String>),
+    IndexProgress(IndexProgress),
+    Documents(Vec<String>),
+    Templates(Vec<PromptTemplate>),
+    PromptHistory(Vec<String>),
+    Starred(Vec<ConversationEntry>),
+    LastSession(Vec<ConversationEntry>),
+    ConversationsDeleted,
+    // num_ctx for a model, None when the model doesn't set one
+    ContextWindow { model: String, tokens: Option<usize> },
+    // Conversation ids for imported messages, by index in the chat
+    ChatImported(Vec<(usize, i64)>),
+    ChatGptImported(ImportReport),
+    // A prompt received by the HTTP API, and later the answer it got
+    ExternalPrompt(String),
+    ExternalResponse(ComparedResponse),
+    Sessions(Vec<SessionSummary>),
+    SessionOpened { session_id: String, entries: Vec<ConversationEntry> },
+    // Suggested next questions for the answer saved as this conversation
+    FollowUps { conversation_id: i64, questions: Vec<String> },
+    Models(Vec<String>),
+    // None when the command couldn't be run at all
+    CommandFinished(Option<CommandRun>),
+    // The pre-prompt plugin chain run over `input` for the send preview
+    PromptPreview { input: String, result: Result<PluginRun, String> },
+    // An answer rerun with another model, to be placed right after the message it retried.
+    // The parent is found again by its timestamp, indexes change as messages are deleted.
+    Retried { parent: DateTime<Local>, parent_id: Option<i64>, response: ComparedResponse },
+    BackupStatus(String),
+    // Result of the settings' test webhook
+    WebhookStatus(String),
+    ObsidianStatus(String),
+    // Whether the server answered the periodic health check
+    ConnectionChecked(bool),
+    Outbox(Vec<OutboxItem>),
+    // A prompt shown with the provisional id was written to the outbox, or couldn't be
+    Queued { provisional: i64, result: Result<OutboxItem, UiError> },
+    // A queued prompt was answered, or failed for a reason other than the server being away
+    OutboxSent { outbox_id: i64, response: ComparedResponse },
+    // The server went away again while a queued prompt was being sent, it stays queued
+    OutboxHeld(i64),
+    // /api/ps, or why it couldn't be read
+    RunningModels(Result<Vec<RunningModel>, String>),
+    // Voice input as text, or why it couldn't be transcribed
+    Transcribed(Result<String, String>),
+    DatabaseRestored,
     LoadingComplete,
-    Error(String),
+    // A failed chat request, carrying the prompt so it can be put back in the input
+    GenerationError { message: String, prompt: String },
+    Error(UiError),
 }
 
-// Custom error type that implements Send
-#[derive(Debug)]
-pub struct AppError(pub String);
+// Lets a value through once it has stayed the same for `delay`, differs from the last value
+// let through, and the work started for that one has finished. Time is passed in by the caller.
+#[derive(Clone, Debug)]
+pub struct Debouncer {
+    delay: Duration,
+    last_input: String,
+    last_input_change: Instant,
+    last_queried_input: String,
+    inflight: bool,
+}
 
-impl std::fmt::Display for AppError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            last_input: String::new(),
+            last_input_change: Instant::now(),
+            last_queried_input: String::new(),
+            inflight: false,
+        }
     }
-}
 
-impl std::error::Error for AppError {}
+    // True when `input` should be queried now, which marks a query as in flight
+    pub fn poll(&mut self, input: &str, now: Instant) -> bool {
+        if input != self.last_input {
+            self.last_input = input.to_string();
+            self.last_input_change = now;
+        }
+        if self.inflight || input == self.last_queried_input || now.duration_since(self.last_input_change) < self.delay {
+            return false;
+        }
+        self.last_queried_input = input.to_string();
+        self.inflight = true;
+        true
+    }
 
-impl From<rusqlite::Error> for AppError {
-    fn from(err: rusqlite::Error) -> Self {
-        AppError(err.to_string())
+    pub fn finish(&mut self) {
+        self.inflight = false;
     }
+
+    // How long until `poll` could let the current input through, for scheduling a repaint
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        if self.inflight || self.last_input == self.last_queried_input {
+            return None;
+        }
+        Some(self.delay.saturating_sub(now.duration_since(self.last_input_change)))
+    }
+}
+
+// Every failure in the app, by kind so callers can tell an unreachable server from a
+// missing model or a locked database. Display is what the UI shows.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("Server returned {status}: {body}")]
+    Http { status: u16, body: String },
+    #[error("Request failed: {0}")]
+    Connection(#[source] reqwest::Error),
+    #[error("{0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Parse(String),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Cancelled")]
+    Cancelled,
+    #[error("Model {0} isn't available on the server, check the name or install it there")]
+    ModelNotFound(String),
+    // The database writer thread is gone, no write will succeed until restart
+    #[error("Database writer stopped")]
+    WriterStopped,
+    #[error("{0}")]
+    Other(String),
 }
 
-impl From<std::io::Error> for AppError {
-    fn from(err: std::io::Error) -> Self {
-        AppError(err.to_string())
+impl AppError {
+    // Storage failures stay on screen until dismissed, they mean work is being lost
+    pub fn severity(&self) -> Severity {
+        match self {
+            AppError::Database(_) | AppError::WriterStopped => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+
+    // SQLITE_BUSY and SQLITE_LOCKED, worth retrying once the other writer is done
+    pub fn is_database_locked(&self) -> bool {
+        matches!(
+            self,
+            AppError::Database(rusqlite::Error::SqliteFailure(error, _))
+                if matches!(error.code, rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+        )
     }
 }
 
 impl From<chrono::ParseError> for AppError {
     fn from(err: chrono::ParseError) -> Self {
-        AppError(err.to_string())
+        AppError::Parse(err.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for AppError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        if err.is_cancelled() {
+            AppError::Cancelled
+        } else {
+            AppError::Other(err.to_string())
+        }
     }
 }
\ No newline at end of file
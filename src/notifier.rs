@@ -0,0 +1,44 @@
+// notifier.rs
+use eframe::egui;
+use notify_rust::Notification;
+use crate::text;
+
+const PREVIEW_CHARS: usize = 80;
+
+// Shown on a blocking thread, the Linux notification daemon is waited on for a click
+pub fn response_finished(ctx: egui::Context, answer: &str, sound: bool) {
+    let preview = text::preview(answer, PREVIEW_CHARS);
+    
+    std::thread::spawn(move || {
+        let mut notification = Notification::new();
+        notification
+            .summary("TouristXi9d: response ready")
+            .body(&preview)
+            .appname("TouristXi9d");
+        if sound {
+            notification.sound_name("message-new-instant");
+        }
+        
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            notification.action("default", "Open");
+            match notification.show() {
+                Ok(handle) => handle.wait_for_action(|action| {
+                    if action == "default" {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                    }
+                }),
+                Err(e) => eprintln!("Notification failed: {}", e),
+            }
+        }
+        
+        // Elsewhere clicking the notification is handled by the OS
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        {
+            let _ = &ctx;
+            if let Err(e) = notification.show() {
+                eprintln!("Notification failed: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,133 @@
+// obsidian.rs
+use chrono::{DateTime, Local};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use crate::models::{AppError, ConversationEntry};
+use crate::rag::RagSystem;
+
+const MAX_TITLE_CHARS: usize = 80;
+// Front matter key that ties a note to its session, so renamed sessions update the same file
+const SESSION_KEY: &str = "session";
+
+// Writes a session to `YYYY-MM-DD – <title>.md` in the vault, replacing the note written for
+// it earlier even if the title has changed since
+pub async fn export_session(rag: &RagSystem, vault: PathBuf, session_id: String) -> Result<PathBuf, AppError> {
+    let entries = rag.session_conversations(session_id.clone()).await?;
+    let Some(first) = entries.first() else {
+        return Err(AppError::Other("The session has no saved conversations".to_string()));
+    };
+    let title = rag.session_title(session_id.clone()).await?.unwrap_or_else(|| "Chat".to_string());
+    let path = vault.join(note_filename(first.timestamp, &title));
+    let note = to_note(&session_id, &title, &entries);
+
+    tokio::task::spawn_blocking(move || -> Result<PathBuf, AppError> {
+        fs::create_dir_all(&vault)?;
+        let previous = find_note(&vault, &session_id)?;
+        fs::write(&path, note)?;
+        if let Some(previous) = previous.filter(|previous| *previous != path) {
+            fs::remove_file(previous)?;
+        }
+        Ok(path)
+    }).await?
+}
+
+pub fn note_filename(date: DateTime<Local>, title: &str) -> String {
+    format!("{} – {}.md", date.format("%Y-%m-%d"), safe_title(title))
+}
+
+pub fn to_note(session_id: &str, title: &str, entries: &[ConversationEntry]) -> String {
+    let models: BTreeSet<&str> = entries.iter().map(|entry| entry.model_used.as_str()).collect();
+    let tags: BTreeSet<String> = entries.iter().flat_map(|entry| &entry.tags).map(|tag| tag_name(tag)).collect();
+    let times: Vec<i64> = entries.iter().map(|entry| entry.response_time_ms).collect();
+
+    let mut note = String::from("---\n");
+    if let Some(first) = entries.first() {
+        note.push_str(&format!("date: {}\n", first.timestamp.format("%Y-%m-%d")));
+    }
+    note.push_str(&format!("{}: {}\n", SESSION_KEY, yaml_string(session_id)));
+    note.push_str(&format!("title: {}\n", yaml_string(title)));
+    note.push_str(&format!("model: [{}]\n", models.iter().map(|model| yaml_string(model)).collect::<Vec<_>>().join(", ")));
+    note.push_str(&format!("tags: [{}]\n", tags.iter().map(|tag| yaml_string(tag)).collect::<Vec<_>>().join(", ")));
+    note.push_str(&format!("conversations: {}\n", entries.len()));
+    if let (Some(min), Some(max)) = (times.iter().min(), times.iter().max()) {
+        let mean = times.iter().sum::<i64>() / times.len() as i64;
+        note.push_str(&format!("response_time_ms:\n  mean: {}\n  min: {}\n  max: {}\n", mean, min, max));
+    }
+    note.push_str("---\n");
+    note.push_str(&format!("\n# {}\n", escape_wiki_links(title)));
+
+    for entry in entries {
+        note.push_str(&format!(
+            "\n## Prompt\n\n_{} · {}_\n\n{}\n",
+            entry.timestamp.format("%H:%M"),
+            entry.model_used,
+            escape_wiki_links(entry.prompt.trim_end()),
+        ));
+        note.push_str(&format!("\n## Response\n\n{}\n", escape_wiki_links(entry.response.trim_end())));
+    }
+    note
+}
+
+// Characters Obsidian won't have in a note name or that break links to it
+fn safe_title(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| if "[]#^|\\/:*?\"<>".contains(c) || c.is_control() { ' ' } else { c })
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let cleaned: String = cleaned.trim_start_matches('.').chars().take(MAX_TITLE_CHARS).collect();
+    match cleaned.trim() {
+        "" => "Chat".to_string(),
+        title => title.to_string(),
+    }
+}
+
+// Tags can't hold spaces and read better lowercase
+fn tag_name(tag: &str) -> String {
+    tag.split_whitespace().collect::<Vec<_>>().join("-").to_lowercase()
+}
+
+fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', " "))
+}
+
+// `[[` outside code would turn model output into links to notes that don't exist
+fn escape_wiki_links(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            escaped.push_str(line);
+        } else if in_fence {
+            escaped.push_str(line);
+        } else {
+            escaped.push_str(&line.replace("[[", "\\[\\[").replace("]]", "\\]\\]"));
+        }
+    }
+    escaped
+}
+
+// The note already exported for a session, found by its front matter
+fn find_note(vault: &Path, session_id: &str) -> Result<Option<PathBuf>, AppError> {
+    let wanted = format!("{}: {}", SESSION_KEY, yaml_string(session_id));
+    for dir_entry in fs::read_dir(vault)? {
+        let path = dir_entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some("md") {
+            continue;
+        }
+        let Ok(file) = fs::File::open(&path) else {
+            continue;
+        };
+        let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+        if lines.next().as_deref() != Some("---") {
+            continue;
+        }
+        if lines.take_while(|line| line != "---").any(|line| line == wanted) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
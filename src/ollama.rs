@@ -1,11 +1,29 @@
 // ollama.rs
+use async_trait::async_trait;
 use reqwest::Client;
-use crate::models::{OllamaRequest, OllamaResponse, AppError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use crate::backend::LlmBackend;
+use crate::scheduler::{Permit, Priority, Scheduler};
+use crate::models::{
+    OllamaRequest, OllamaResponse, OllamaChatRequest, OllamaChatResponse, ChatTurn, GenerateOptions, OllamaStreamChunk, EmbeddingRequest, EmbeddingResponse, Generation, ShowRequest,
+    ShowResponse, TagsResponse, ProcessResponse, RunningModel, UnloadRequest, AppError,
+};
 
+pub const DEFAULT_URL: &str = "http://localhost:11434/api/generate";
+pub const DEFAULT_MAX_CONCURRENT: usize = 2;
+
+// Generations and embeddings go through the scheduler, which a client shares with its
+// background() copies. Model lists and other metadata calls are cheap and skip it.
 #[derive(Clone)]
 pub struct OllamaClient {
     client: Client,
     base_url: String,
+    scheduler: Arc<Scheduler>,
+    priority: Priority,
+    max_concurrent: usize,
+    min_interval: Duration,
 }
 
 impl OllamaClient {
@@ -13,43 +31,390 @@ impl OllamaClient {
         Self {
             client: Client::new(),
             base_url,
+            scheduler: Arc::new(Scheduler::new(DEFAULT_MAX_CONCURRENT, Duration::ZERO)),
+            priority: Priority::Interactive,
+            max_concurrent: DEFAULT_MAX_CONCURRENT,
+            min_interval: Duration::ZERO,
+        }
+    }
+
+    pub fn with_limits(mut self, max_concurrent: usize, min_interval: Duration) -> Self {
+        self.max_concurrent = max_concurrent;
+        self.min_interval = min_interval;
+        self.scheduler = Arc::new(Scheduler::new(max_concurrent, min_interval));
+        self
+    }
+
+    async fn permit(&self) -> Permit {
+        self.scheduler.acquire(self.priority).await
+    }
+
+    async fn generate(&self, request: &OllamaRequest) -> Result<String, AppError> {
+        let _permit = self.permit().await;
+        let response = self
+            .client
+            .post(&self.base_url)
+            .json(request)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(&request.model)).await);
         }
+
+        let ollama_response: OllamaResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Parse(format!("Failed to parse response: {}", e)))?;
+
+        Ok(ollama_response.response)
     }
 
-    pub async fn generate_response(
+    // The configured URL points at /api/generate, other endpoints share its root
+    fn api_url(&self, path: &str) -> String {
+        let root = self.base_url.trim_end_matches('/').trim_end_matches("/api/generate");
+        format!("{}{}", root, path)
+    }
+
+    // Coarse category of an error produced by this client, stored with failure records
+    pub fn failure_kind(error: &AppError) -> &'static str {
+        match error {
+            AppError::Connection(_) => "connection",
+            AppError::Timeout => "timeout",
+            AppError::Http { .. } => "server",
+            AppError::ModelNotFound(_) => "model",
+            AppError::Parse(_) => "parse",
+            _ => "other",
+        }
+    }
+
+    // Another server gets its own queue
+    pub fn update_url(&mut self, new_url: String) {
+        self.base_url = new_url;
+        self.scheduler = Arc::new(Scheduler::new(self.max_concurrent, self.min_interval));
+    }
+
+    // Models currently loaded by the server, with their memory use
+    pub async fn list_running(&self) -> Result<Vec<RunningModel>, AppError> {
+        let response = self
+            .client
+            .get(self.api_url("/api/ps"))
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, None).await);
+        }
+
+        let running: ProcessResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Parse(format!("Failed to parse running models: {}", e)))?;
+
+        Ok(running.models)
+    }
+
+    // Frees the model's memory now instead of when its keep-alive runs out
+    pub async fn unload(&self, model: &str) -> Result<(), AppError> {
+        let request = UnloadRequest {
+            model: model.to_string(),
+            keep_alive: 0,
+        };
+        let response = self
+            .client
+            .post(self.api_url("/api/generate"))
+            .json(&request)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(model)).await);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OllamaClient {
+    async fn generate_response(
         &self,
         model: &str,
         prompt: &str,
+        images: &[Arc<str>],
+        num_predict: Option<u32>,
     ) -> Result<String, AppError> {
         let request = OllamaRequest {
             model: model.to_string(),
             prompt: prompt.to_string(),
             stream: false,
+            images: images.to_vec(),
+            options: num_predict.map(|num_predict| GenerateOptions { num_predict }),
+            format: None,
+        };
+        self.generate(&request).await
+    }
+
+    async fn chat(&self, model: &str, messages: &[ChatTurn], num_predict: Option<u32>) -> Result<String, AppError> {
+        let request = OllamaChatRequest {
+            model: model.to_string(),
+            messages: messages.to_vec(),
+            stream: false,
+            options: num_predict.map(|num_predict| GenerateOptions { num_predict }),
         };
 
+        let _permit = self.permit().await;
         let response = self
             .client
-            .post(&self.base_url)
+            .post(self.api_url("/api/chat"))
             .json(&request)
             .send()
             .await
-            .map_err(|e| AppError(format!("Request failed: {}", e)))?;
+            .map_err(request_error)?;
 
-        let ollama_response: OllamaResponse = response
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(model)).await);
+        }
+
+        let chat: OllamaChatResponse = response
             .json()
             .await
-            .map_err(|e| AppError(format!("Failed to parse response: {}", e)))?;
+            .map_err(|e| AppError::Parse(format!("Failed to parse response: {}", e)))?;
 
-        Ok(ollama_response.response)
+        Ok(chat.message.content)
     }
 
-    pub fn update_url(&mut self, new_url: String) {
-        self.base_url = new_url;
+    async fn generate_json(&self, model: &str, prompt: &str, num_predict: u32) -> Result<String, AppError> {
+        let request = OllamaRequest {
+            model: model.to_string(),
+            prompt: prompt.to_string(),
+            stream: false,
+            images: Vec::new(),
+            options: Some(GenerateOptions { num_predict }),
+            format: Some("json".to_string()),
+        };
+        self.generate(&request).await
+    }
+
+    async fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[Arc<str>],
+        cancel: &Notify,
+        on_chunk: &mut (dyn FnMut(String) + Send),
+    ) -> Result<Generation, AppError> {
+        let request = OllamaRequest {
+            model: model.to_string(),
+            prompt: prompt.to_string(),
+            stream: true,
+            images: images.to_vec(),
+            options: None,
+            format: None,
+        };
+
+        let mut generation = Generation::default();
+        // The slot is held until the stream ends, waiting for it can be cancelled like the rest
+        let _permit = tokio::select! {
+            permit = self.permit() => permit,
+            _ = cancel.notified() => {
+                generation.cancelled = true;
+                return Ok(generation);
+            }
+        };
+        let started = Instant::now();
+
+        let send = self.client.post(&self.base_url).json(&request).send();
+        let mut response = tokio::select! {
+            response = send => response.map_err(request_error)?,
+            _ = cancel.notified() => {
+                generation.cancelled = true;
+                return Ok(generation);
+            }
+        };
+
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(model)).await);
+        }
+
+        // Chunks don't line up with the newline-delimited JSON objects, so buffer bytes
+        let mut buffer: Vec<u8> = Vec::new();
+        loop {
+            let bytes = tokio::select! {
+                bytes = response.chunk() => bytes.map_err(request_error)?,
+                _ = cancel.notified() => {
+                    generation.cancelled = true;
+                    return Ok(generation);
+                }
+            };
+            let Some(bytes) = bytes else {
+                break;
+            };
+            buffer.extend_from_slice(&bytes);
+
+            while let Some(newline) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=newline).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+
+                let chunk: OllamaStreamChunk = serde_json::from_slice(&line)
+                    .map_err(|e| AppError::Parse(format!("Failed to parse response: {}", e)))?;
+                if let Some(error) = chunk.error {
+                    return Err(stream_error(model, error));
+                }
+                if !chunk.response.is_empty() {
+                    generation.first_token_ms.get_or_insert(started.elapsed().as_millis() as i64);
+                    generation.text.push_str(&chunk.response);
+                    on_chunk(chunk.response);
+                }
+                if chunk.done {
+                    return Ok(generation);
+                }
+            }
+        }
+
+        Ok(generation)
+    }
+
+    async fn embed(&self, model: &str, text: &str) -> Result<Vec<f32>, AppError> {
+        let request = EmbeddingRequest {
+            model: model.to_string(),
+            prompt: text.to_string(),
+        };
+
+        let _permit = self.permit().await;
+        let response = self
+            .client
+            .post(self.api_url("/api/embeddings"))
+            .json(&request)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(model)).await);
+        }
+
+        let embedding: EmbeddingResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Parse(format!("Failed to parse embedding: {}", e)))?;
+
+        Ok(embedding.embedding)
+    }
+
+    // The num_ctx the model runs with, if its Modelfile sets one
+    async fn context_window(&self, model: &str) -> Result<Option<usize>, AppError> {
+        let request = ShowRequest {
+            model: model.to_string(),
+        };
+
+        let response = self
+            .client
+            .post(self.api_url("/api/show"))
+            .json(&request)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(model)).await);
+        }
+
+        let show: ShowResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Parse(format!("Failed to parse model info: {}", e)))?;
+
+        Ok(show.parameters.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("num_ctx"), Some(value)) => value.parse().ok(),
+                _ => None,
+            }
+        }))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AppError> {
+        let response = self
+            .client
+            .get(self.api_url("/api/tags"))
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, None).await);
+        }
+
+        let tags: TagsResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Parse(format!("Failed to parse model list: {}", e)))?;
+
+        let mut models: Vec<String> = tags.models.into_iter().map(|model| model.name).collect();
+        models.sort();
+        Ok(models)
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn with_url(&self, url: String) -> Arc<dyn LlmBackend> {
+        let mut client = self.clone();
+        client.update_url(url);
+        Arc::new(client)
+    }
+
+    fn background(&self) -> Arc<dyn LlmBackend> {
+        Arc::new(Self { priority: Priority::Background, ..self.clone() })
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.scheduler.queue_depth()
     }
 }
 
 impl Default for OllamaClient {
     fn default() -> Self {
-        Self::new("http://localhost:11434/api/generate".to_string())
+        Self::new(DEFAULT_URL.to_string())
     }
-}
\ No newline at end of file
+}
+
+// Timeouts and undecodable bodies get their own kinds, anything else means the server wasn't reached
+pub fn request_error(error: reqwest::Error) -> AppError {
+    if error.is_timeout() {
+        AppError::Timeout
+    } else if error.is_decode() {
+        AppError::Parse(format!("Failed to parse response: {}", error))
+    } else {
+        AppError::Connection(error)
+    }
+}
+
+// Ollama answers 404 when the requested model isn't installed
+pub async fn status_error(response: reqwest::Response, model: Option<&str>) -> AppError {
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+    match model {
+        Some(model) if status == 404 => AppError::ModelNotFound(model.to_string()),
+        _ => AppError::Http { status, body: body.trim().to_string() },
+    }
+}
+
+// Errors reported inside a stream that already started with 200
+fn stream_error(model: &str, error: String) -> AppError {
+    if error.contains("not found") {
+        AppError::ModelNotFound(model.to_string())
+    } else {
+        AppError::Other(format!("Server returned error: {}", error))
+    }
+}
@@ -0,0 +1,298 @@
+// openai.rs
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Notify;
+use crate::backend::LlmBackend;
+use crate::models::{
+    AppError, ChatTurn, ChatCompletionChunk, ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, ContentPart, Generation,
+    ImageUrl, MessageContent, ModelList, OpenAiEmbeddingRequest, OpenAiEmbeddingResponse, ResponseFormat,
+};
+use crate::ollama::request_error;
+
+pub const DEFAULT_URL: &str = "http://localhost:1234/v1";
+
+// Servers that speak the OpenAI API: LM Studio, llama.cpp's server, vLLM. `base_url` is the
+// /v1 root, the key is sent as a bearer token when one is set.
+#[derive(Clone)]
+pub struct OpenAiCompatBackend {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiCompatBackend {
+    pub fn new(base_url: String, api_key: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url,
+            api_key: api_key.filter(|key| !key.trim().is_empty()),
+        }
+    }
+
+    fn api_url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key.trim()),
+            None => request,
+        }
+    }
+
+    fn chat_request(model: &str, prompt: &str, images: &[Arc<str>], stream: bool) -> ChatCompletionRequest {
+        let content = if images.is_empty() {
+            MessageContent::Text(prompt.to_string())
+        } else {
+            let mut parts = vec![ContentPart::Text { text: prompt.to_string() }];
+            parts.extend(images.iter().map(|image| ContentPart::ImageUrl {
+                image_url: ImageUrl { url: format!("data:image/png;base64,{}", image) },
+            }));
+            MessageContent::Parts(parts)
+        };
+        ChatCompletionRequest {
+            model: model.to_string(),
+            messages: vec![ChatCompletionMessage { role: "user".to_string(), content }],
+            stream,
+            max_tokens: None,
+            response_format: None,
+        }
+    }
+
+    async fn complete(&self, request: &ChatCompletionRequest) -> Result<String, AppError> {
+        let response = self
+            .authorized(self.client.post(self.api_url("/chat/completions")))
+            .json(request)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(&request.model)).await);
+        }
+
+        let completion: ChatCompletionResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Parse(format!("Failed to parse response: {}", e)))?;
+
+        completion.choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content.unwrap_or_default())
+            .ok_or_else(|| AppError::Parse("Response contained no choices".to_string()))
+    }
+}
+
+// Unlike Ollama these servers also answer 404 for a wrong base URL, so only a body that says
+// the model is unknown makes it ModelNotFound
+async fn status_error(response: reqwest::Response, model: Option<&str>) -> AppError {
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default().trim().to_string();
+    match model {
+        Some(model) if status == 404 && mentions_unknown_model(&body) => AppError::ModelNotFound(model.to_string()),
+        _ => AppError::Http { status, body },
+    }
+}
+
+// OpenAI sends {"error": {"code": "model_not_found", "message": ...}}, other servers put a
+// message in "error" or send plain text
+fn mentions_unknown_model(body: &str) -> bool {
+    let json: Option<serde_json::Value> = serde_json::from_str(body).ok();
+    let error = json.as_ref().and_then(|json| json.get("error"));
+    if error.and_then(|error| error.get("code")).and_then(|code| code.as_str()) == Some("model_not_found") {
+        return true;
+    }
+    let message = error
+        .and_then(|error| error.get("message").or(Some(error)))
+        .and_then(|message| message.as_str())
+        .unwrap_or(body)
+        .to_lowercase();
+    message.contains("model")
+        && ["not found", "does not exist", "not loaded", "unknown", "no such"]
+            .iter()
+            .any(|phrase| message.contains(phrase))
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiCompatBackend {
+    async fn generate_response(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[Arc<str>],
+        num_predict: Option<u32>,
+    ) -> Result<String, AppError> {
+        let mut request = Self::chat_request(model, prompt, images, false);
+        request.max_tokens = num_predict;
+        self.complete(&request).await
+    }
+
+    async fn chat(&self, model: &str, messages: &[ChatTurn], num_predict: Option<u32>) -> Result<String, AppError> {
+        let request = ChatCompletionRequest {
+            model: model.to_string(),
+            messages: messages
+                .iter()
+                .map(|message| ChatCompletionMessage {
+                    role: message.role.clone(),
+                    content: MessageContent::Text(message.content.clone()),
+                })
+                .collect(),
+            stream: false,
+            max_tokens: num_predict,
+            response_format: None,
+        };
+        self.complete(&request).await
+    }
+
+    async fn generate_json(&self, model: &str, prompt: &str, num_predict: u32) -> Result<String, AppError> {
+        let mut request = Self::chat_request(model, prompt, &[], false);
+        request.max_tokens = Some(num_predict);
+        request.response_format = Some(ResponseFormat { kind: "json_object".to_string() });
+        self.complete(&request).await
+    }
+
+    async fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[Arc<str>],
+        cancel: &Notify,
+        on_chunk: &mut (dyn FnMut(String) + Send),
+    ) -> Result<Generation, AppError> {
+        let request = Self::chat_request(model, prompt, images, true);
+        let started = Instant::now();
+        let mut generation = Generation::default();
+
+        let send = self.authorized(self.client.post(self.api_url("/chat/completions"))).json(&request).send();
+        let mut response = tokio::select! {
+            response = send => response.map_err(request_error)?,
+            _ = cancel.notified() => {
+                generation.cancelled = true;
+                return Ok(generation);
+            }
+        };
+
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(model)).await);
+        }
+
+        // Server-sent events: `data: {json}` lines, blank lines between events, `data: [DONE]` at the end
+        let mut buffer: Vec<u8> = Vec::new();
+        loop {
+            let bytes = tokio::select! {
+                bytes = response.chunk() => bytes.map_err(request_error)?,
+                _ = cancel.notified() => {
+                    generation.cancelled = true;
+                    return Ok(generation);
+                }
+            };
+            let Some(bytes) = bytes else {
+                break;
+            };
+            buffer.extend_from_slice(&bytes);
+
+            while let Some(newline) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=newline).collect();
+                let line = String::from_utf8_lossy(&line);
+                let Some(data) = line.trim().strip_prefix("data:") else {
+                    continue;
+                };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    return Ok(generation);
+                }
+
+                let chunk: ChatCompletionChunk = serde_json::from_str(data)
+                    .map_err(|e| AppError::Parse(format!("Failed to parse response: {}", e)))?;
+                if let Some(error) = chunk.error {
+                    let message = error.get("message").and_then(|message| message.as_str()).map(str::to_string)
+                        .unwrap_or_else(|| error.to_string());
+                    return Err(AppError::Other(format!("Server returned error: {}", message)));
+                }
+                for choice in chunk.choices {
+                    if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+                        generation.first_token_ms.get_or_insert(started.elapsed().as_millis() as i64);
+                        generation.text.push_str(&text);
+                        on_chunk(text);
+                    }
+                }
+            }
+        }
+
+        Ok(generation)
+    }
+
+    async fn embed(&self, model: &str, text: &str) -> Result<Vec<f32>, AppError> {
+        let request = OpenAiEmbeddingRequest {
+            model: model.to_string(),
+            input: text.to_string(),
+        };
+
+        let response = self
+            .authorized(self.client.post(self.api_url("/embeddings")))
+            .json(&request)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, Some(model)).await);
+        }
+
+        let embeddings: OpenAiEmbeddingResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Parse(format!("Failed to parse embedding: {}", e)))?;
+
+        embeddings.data
+            .into_iter()
+            .next()
+            .map(|embedding| embedding.embedding)
+            .ok_or_else(|| AppError::Parse("Embedding response was empty".to_string()))
+    }
+
+    // The API has no standard way to ask, so the configured default applies
+    async fn context_window(&self, _model: &str) -> Result<Option<usize>, AppError> {
+        Ok(None)
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AppError> {
+        let response = self
+            .authorized(self.client.get(self.api_url("/models")))
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response, None).await);
+        }
+
+        let list: ModelList = response
+            .json()
+            .await
+            .map_err(|e| AppError::Parse(format!("Failed to parse model list: {}", e)))?;
+
+        let mut models: Vec<String> = list.data.into_iter().map(|model| model.id).collect();
+        models.sort();
+        Ok(models)
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn name(&self) -> &'static str {
+        "openai-compatible"
+    }
+
+    fn with_url(&self, url: String) -> Arc<dyn LlmBackend> {
+        Arc::new(Self { base_url: url, ..self.clone() })
+    }
+
+    fn background(&self) -> Arc<dyn LlmBackend> {
+        Arc::new(self.clone())
+    }
+}
@@ -0,0 +1,264 @@
+// pipeline.rs
+// One prompt through plugins, the model and storage, without any UI. The chat window and the
+// HTTP API both run their non-streamed requests through here, and report back to the window
+// through an OpSender.
+use async_trait::async_trait;
+use chrono::Local;
+use std::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use crate::analytics::AnalyticsEngine;
+use crate::backend::LlmBackend;
+use crate::models::{
+    AppError, Attachment, AttachmentKind, ComparedResponse, ConversationEntry, ConversationStatus, ErrorRecord, ParsedResponse, PendingOperation,
+    RetrievalOptions, Severity, UiError,
+};
+use crate::ollama::OllamaClient;
+use crate::plugins::{PluginContext, PluginManager, Stage};
+use crate::rag::RagSystem;
+use crate::server::{AskHandler, AskRequest, AskResponse};
+use crate::webhook::{Webhook, WebhookPayload};
+
+// Background tasks hand their results to the UI through this. Sending calls `wake`, the UI's
+// repaint, and check_async_updates drains everything sent so far on the next frame.
+#[derive(Clone)]
+pub struct OpSender {
+    tx: mpsc::Sender<PendingOperation>,
+    wake: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl OpSender {
+    pub fn new(tx: mpsc::Sender<PendingOperation>, wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self { tx, wake: Arc::new(wake) }
+    }
+
+    pub fn send(&self, op: PendingOperation) {
+        // Only fails once the app, which owns the receiver, has shut down
+        if self.tx.send(op).is_ok() {
+            (self.wake)();
+        }
+    }
+
+    // For results that arrive some other way than through `send`
+    pub fn wake(&self) {
+        (self.wake)();
+    }
+}
+
+// Everything one non-streamed generation needs, shared by both sides of a comparison
+pub struct GenerationJob {
+    pub backend: Arc<dyn LlmBackend>,
+    pub rag_system: Option<RagSystem>,
+    pub analytics_engine: Option<AnalyticsEngine>,
+    pub prompt: String,
+    pub original_prompt: String,
+    pub images: Vec<Arc<str>>,
+    pub file_context: Option<String>,
+    pub tags: Vec<String>,
+    pub keep_failed: bool,
+    pub session_id: String,
+    pub plugins: Arc<RwLock<PluginManager>>,
+    pub plugin_context: PluginContext,
+    pub webhook: Option<Webhook>,
+    pub pending_ops: OpSender,
+}
+
+impl GenerationJob {
+    // Generates and saves the result the same way a normal send does
+    pub async fn run(&self, model: String) -> ComparedResponse {
+        let start_time = std::time::Instant::now();
+        let plugin_context = self.plugin_context.for_model(model.clone());
+        let result = match run_plugins(&self.plugins, &plugin_context, self.prompt.clone(), &self.pending_ops).await {
+            Ok((prompt, _)) => self.backend.generate_response(&model, &prompt, &self.images, None).await,
+            Err(e) => Err(e),
+        };
+        let response_time = start_time.elapsed().as_millis() as i64;
+        
+        if let (Err(e), Some(analytics)) = (&result, &self.analytics_engine) {
+            let record = ErrorRecord {
+                timestamp: Local::now(),
+                kind: OllamaClient::failure_kind(e).to_string(),
+                message: e.to_string(),
+                model: model.clone(),
+                url: self.backend.base_url().to_string(),
+            };
+            if let Err(e) = analytics.record_error(record).await {
+                self.pending_ops.send(PendingOperation::Error(UiError::from_error("Error recording failure", &e)));
+            }
+        }
+        
+        let result = match result {
+            Ok(text) => {
+                let parsed = ParsedResponse::parse(&text);
+                run_plugins(&self.plugins, &plugin_context.for_stage(Stage::PostResponse), parsed.answer, &self.pending_ops).await
+                    .map(|(answer, annotations)| ParsedResponse { answer, reasoning: parsed.reasoning, annotations })
+            }
+            Err(e) => Err(e),
+        };
+        let mut conversation_id = None;
+        if result.is_ok() || self.keep_failed {
+            if let Some(rag) = &self.rag_system {
+                let (response, reasoning, status) = match &result {
+                    Ok(parsed) => (parsed.answer.clone(), parsed.reasoning.clone(), ConversationStatus::Ok),
+                    Err(e) => (e.to_string(), None, ConversationStatus::Error),
+                };
+                let entry = ConversationEntry {
+                    id: 0,
+                    timestamp: Local::now(),
+                    prompt: self.original_prompt.clone(),
+                    response,
+                    model_used: model.clone(),
+                    response_time_ms: response_time,
+                    file_context: self.file_context.clone(),
+                    tags: self.tags.clone(),
+                    status,
+                    reasoning,
+                    first_token_ms: None,
+                    starred: false,
+                    feedback: 0,
+                    backend: Some(self.backend.name().to_string()),
+                    source: None,
+                };
+                
+                conversation_id = save_in_session(rag, &entry, &self.session_id, &self.pending_ops).await;
+                if let Some(id) = conversation_id {
+                    notify_webhook(self.webhook.as_ref(), || WebhookPayload::new(id, &entry, &self.session_id), &self.pending_ops);
+                }
+            }
+        }
+        
+        ComparedResponse {
+            model,
+            result: result.map_err(|e| e.to_string()),
+            response_time,
+            conversation_id,
+        }
+    }
+}
+
+// What API requests pick up from the app, kept current while the server runs
+pub struct ApiSettings {
+    pub backend: Arc<dyn LlmBackend>,
+    pub model: String,
+    pub session_id: String,
+    pub keep_failed: bool,
+    pub webhook: Option<Webhook>,
+}
+
+// Runs /api/ask prompts the way the send button does and mirrors them into the open chat
+pub struct ApiBridge {
+    pub settings: Arc<std::sync::RwLock<ApiSettings>>,
+    pub rag_system: Option<RagSystem>,
+    pub analytics_engine: Option<AnalyticsEngine>,
+    pub plugins: Arc<RwLock<PluginManager>>,
+    pub pending_ops: OpSender,
+}
+
+#[async_trait]
+impl AskHandler for ApiBridge {
+    async fn ask(&self, request: AskRequest) -> Result<AskResponse, AppError> {
+        let (backend, model, session_id, keep_failed, webhook) = {
+            let settings = self.settings.read().unwrap_or_else(std::sync::PoisonError::into_inner);
+            let model = request.model.clone().unwrap_or_else(|| settings.model.clone());
+            (settings.backend.clone(), model, settings.session_id.clone(), settings.keep_failed, settings.webhook.clone())
+        };
+        self.pending_ops.send(PendingOperation::ExternalPrompt(request.prompt.clone()));
+        
+        // Retrieval with the default filters, the sidebar's are for the chat box
+        let mut prompt = request.prompt.clone();
+        if let (true, Some(rag)) = (request.use_rag, &self.rag_system) {
+            match rag.find_similar_responses(&request.prompt, 3, &RetrievalOptions::default()).await {
+                Ok(suggestions) if !suggestions.is_empty() => prompt = rag.create_rag_context(&suggestions, &prompt),
+                Ok(_) => {}
+                Err(e) => self.pending_ops.send(PendingOperation::Error(UiError::from_error("RAG lookup for API request failed", &e))),
+            }
+        }
+        
+        let job = GenerationJob {
+            backend,
+            rag_system: self.rag_system.clone(),
+            analytics_engine: self.analytics_engine.clone(),
+            prompt,
+            original_prompt: request.prompt,
+            images: Vec::new(),
+            file_context: None,
+            tags: Vec::new(),
+            keep_failed,
+            session_id,
+            plugins: self.plugins.clone(),
+            plugin_context: PluginContext::new(Stage::PrePrompt, model.clone()).with_rag(self.rag_system.clone()),
+            webhook,
+            pending_ops: self.pending_ops.clone(),
+        };
+        let response = job.run(model).await;
+        self.pending_ops.send(PendingOperation::ExternalResponse(response.clone()));
+        
+        let parsed = response.result.map_err(AppError::Other)?;
+        Ok(AskResponse {
+            response: parsed.answer,
+            reasoning: parsed.reasoning,
+            model: response.model,
+            response_time_ms: response.response_time,
+            conversation_id: response.conversation_id,
+        })
+    }
+}
+
+// Failures of optional plugins go to the error banner, only a required one fails the request.
+// Returns the text with the observers' annotations, which only answers have a place for.
+pub async fn run_plugins(
+    plugins: &RwLock<PluginManager>,
+    ctx: &PluginContext,
+    text: String,
+    pending_ops: &OpSender,
+) -> Result<(String, Vec<String>), AppError> {
+    let run = plugins.read().await.process(&text, ctx).await?;
+    for failure in run.failures {
+        pending_ops.send(PendingOperation::Error(UiError::new(failure.to_string(), Severity::Warning)));
+    }
+    let annotations = run.annotations
+        .into_iter()
+        .map(|(name, annotation)| format!("{}: {}", name, annotation))
+        .collect();
+    Ok((run.text, annotations))
+}
+
+// The base64 images among the attachments, sharing their content rather than copying it
+pub fn image_payloads(attachments: &[Arc<Attachment>]) -> Vec<Arc<str>> {
+    attachments.iter()
+        .filter(|attachment| attachment.kind == AttachmentKind::Image)
+        .map(|attachment| attachment.content.clone())
+        .collect()
+}
+
+// Saves a conversation and files it under the chat's session, returning its id.
+// A failed save still shows the answer, the banner says it won't be in the history
+pub async fn save_in_session(rag: &RagSystem, entry: &ConversationEntry, session_id: &str, pending_ops: &OpSender) -> Option<i64> {
+    match rag.save_conversation(entry).await {
+        Ok(id) => {
+            if let Err(e) = rag.assign_session(vec![id], session_id.to_string()).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Error assigning session", &e)));
+            }
+            Some(id)
+        }
+        Err(e) => {
+            pending_ops.send(PendingOperation::Error(UiError::from_error("Error saving conversation", &e)));
+            None
+        }
+    }
+}
+
+// Delivery happens in its own task so a slow endpoint never holds up the answer. The payload
+// copies the whole exchange, so it's only built when there is somewhere to send it.
+pub fn notify_webhook(webhook: Option<&Webhook>, payload: impl FnOnce() -> WebhookPayload, pending_ops: &OpSender) {
+    let Some(webhook) = webhook.cloned() else {
+        return;
+    };
+    let payload = payload();
+    let pending_ops = pending_ops.clone();
+    tokio::spawn(async move {
+        if let Err(e) = webhook.send(&payload).await {
+            pending_ops.send(PendingOperation::Error(UiError::from_error("Webhook error", &e)));
+        }
+    });
+}
@@ -0,0 +1,294 @@
+// plugins/command.rs
+use async_trait::async_trait;
+use chrono::Local;
+use serde_json::{json, Value};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use crate::models::{AppError, CommandRun};
+use crate::text::truncate_chars;
+use super::{Mode, Plugin, PluginContext, SettingField, SettingKind, Stage};
+
+const RUN_FENCE: &str = "```run";
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long the pipes get to close once the command has exited or been killed. Anything it
+// started in the background can hold them open for as long as it likes.
+const READER_GRACE: Duration = Duration::from_secs(2);
+// Output beyond this is cut before it goes back to the model
+const MAX_OUTPUT_CHARS: usize = 8000;
+
+// A command the model asked for, waiting for the user to approve or dismiss it
+#[derive(Clone, Debug)]
+pub struct CommandRequest {
+    pub command: String,
+    // Whether the program is on the allowlist, others can't be run at all
+    pub allowed: bool,
+}
+
+// Commands found in answers, shared between the plugin and the approval dialog
+#[derive(Clone, Default)]
+pub struct CommandQueue {
+    requests: Arc<Mutex<Vec<CommandRequest>>>,
+}
+
+impl CommandQueue {
+    fn push(&self, request: CommandRequest) {
+        self.requests.lock().unwrap_or_else(PoisonError::into_inner).push(request);
+    }
+
+    // Oldest first
+    pub fn pop(&self) -> Option<CommandRequest> {
+        let mut requests = self.requests.lock().unwrap_or_else(PoisonError::into_inner);
+        (!requests.is_empty()).then(|| requests.remove(0))
+    }
+}
+
+// Never runs anything itself, it only queues ```run blocks for the user to approve
+pub struct CommandPlugin {
+    enabled: bool,
+    required: bool,
+    allowed_programs: Vec<String>,
+    queue: CommandQueue,
+}
+
+impl CommandPlugin {
+    pub fn new(queue: CommandQueue) -> Self {
+        Self {
+            enabled: false,
+            required: false,
+            allowed_programs: vec!["cargo".to_string(), "git".to_string(), "ls".to_string()],
+            queue,
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for CommandPlugin {
+    fn name(&self) -> &str {
+        "Commands"
+    }
+
+    fn runs_in(&self, stage: Stage) -> bool {
+        stage == Stage::PostResponse
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Observe
+    }
+
+    async fn process(&self, input: &str, _ctx: &PluginContext) -> Result<String, AppError> {
+        let commands = run_blocks(input);
+        let count = commands.len();
+        for command in commands {
+            let allowed = split_command(&command)
+                .first()
+                .is_some_and(|program| self.allowed_programs.iter().any(|allowed| allowed == program));
+            self.queue.push(CommandRequest { command, allowed });
+        }
+        Ok(match count {
+            0 => String::new(),
+            1 => "1 command waiting for approval".to_string(),
+            count => format!("{} commands waiting for approval", count),
+        })
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn is_required(&self) -> bool {
+        self.required
+    }
+
+    fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    fn config(&self) -> Value {
+        json!({ "allowed_programs": self.allowed_programs.join(", ") })
+    }
+
+    fn set_config(&mut self, config: Value) {
+        if let Some(programs) = config.get("allowed_programs").and_then(Value::as_str) {
+            self.allowed_programs = programs
+                .split(',')
+                .map(str::trim)
+                .filter(|program| !program.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+    }
+
+    fn settings_schema(&self) -> Vec<SettingField> {
+        vec![SettingField { key: "allowed_programs", label: "Allowed programs", kind: SettingKind::Text }]
+    }
+}
+
+// Each non-empty line of a ```run block is one command
+fn run_blocks(text: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut in_block = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if in_block {
+            if trimmed.starts_with("```") {
+                in_block = false;
+            } else if !trimmed.is_empty() {
+                commands.push(trimmed.to_string());
+            }
+        } else if trimmed == RUN_FENCE {
+            in_block = true;
+        }
+    }
+    commands
+}
+
+// Whitespace-separated words, with single or double quotes grouping. No shell is involved,
+// so pipes, redirects and variables are passed through as plain arguments.
+fn split_command(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    let mut in_word = false;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+// Runs an approved command, killing it once it passes the timeout. Blocks, so call it
+// from a blocking task.
+pub fn run_command(command: &str) -> CommandRun {
+    let started = Instant::now();
+    let mut run = CommandRun {
+        timestamp: Local::now(),
+        command: command.to_string(),
+        exit_code: None,
+        timed_out: false,
+        duration_ms: 0,
+        output: String::new(),
+    };
+
+    let words = split_command(command);
+    let Some((program, args)) = words.split_first() else {
+        run.output = "Empty command".to_string();
+        return run;
+    };
+
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            run.output = format!("Failed to start {}: {}", program, e);
+            return run;
+        }
+    };
+
+    // Read both pipes on their own threads so a chatty command can't fill one and stall
+    let stdout = child.stdout.take().map(read_to_end);
+    let stderr = child.stderr.take().map(read_to_end);
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                run.exit_code = status.code();
+                break;
+            }
+            Ok(None) if started.elapsed() > COMMAND_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                run.timed_out = true;
+                break;
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                run.output = format!("Failed to wait for {}: {}", program, e);
+                break;
+            }
+        }
+    }
+
+    let readers_deadline = Instant::now() + READER_GRACE;
+    let stdout = stdout.and_then(|reader| join_until(reader, readers_deadline)).unwrap_or_default();
+    let stderr = stderr.and_then(|reader| join_until(reader, readers_deadline)).unwrap_or_default();
+    run.output.push_str(&stdout);
+    if !stderr.is_empty() {
+        if !run.output.is_empty() && !run.output.ends_with('\n') {
+            run.output.push('\n');
+        }
+        run.output.push_str(&stderr);
+    }
+    if run.output.chars().count() > MAX_OUTPUT_CHARS {
+        run.output = truncate_chars(&run.output, MAX_OUTPUT_CHARS).to_string();
+        run.output.push_str("\n… (output truncated)");
+    }
+    run.duration_ms = started.elapsed().as_millis() as i64;
+    run
+}
+
+// The reader's output, or None if it is still blocked at the deadline. Dropping the handle
+// detaches the thread, it ends by itself when the last process holding the pipe exits.
+fn join_until(reader: std::thread::JoinHandle<String>, deadline: Instant) -> Option<String> {
+    while !reader.is_finished() {
+        if Instant::now() >= deadline {
+            return None;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    reader.join().ok()
+}
+
+pub(super) fn read_to_end<R: Read + Send + 'static>(mut pipe: R) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn background_process_holding_the_pipes_does_not_block() {
+        let started = Instant::now();
+        let run = run_command("sh -c 'echo started; sleep 30 &'");
+
+        assert!(started.elapsed() < READER_GRACE + Duration::from_secs(3), "took {:?}", started.elapsed());
+        assert_eq!(run.exit_code, Some(0));
+        assert!(!run.timed_out);
+    }
+}
@@ -0,0 +1,62 @@
+// plugins/context.rs
+use std::sync::Arc;
+use crate::models::{AppError, Attachment, ChatMessage, RetrievalOptions, ScoredEntry};
+use crate::rag::RagSystem;
+use super::Stage;
+
+// What a plugin can see of the request it runs in. Built once per request and only read.
+#[derive(Clone)]
+pub struct PluginContext {
+    pub stage: Stage,
+    // The chat's latest messages before this request, oldest first
+    pub history: Vec<ChatMessage>,
+    pub attachments: Vec<Arc<Attachment>>,
+    // The model answering this request
+    pub model: String,
+    rag: Option<RagSystem>,
+}
+
+impl PluginContext {
+    pub fn new(stage: Stage, model: String) -> Self {
+        Self {
+            stage,
+            history: Vec::new(),
+            attachments: Vec::new(),
+            model,
+            rag: None,
+        }
+    }
+
+    pub fn with_history(mut self, history: Vec<ChatMessage>) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_attachments(mut self, attachments: Vec<Arc<Attachment>>) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    pub fn with_rag(mut self, rag: Option<RagSystem>) -> Self {
+        self.rag = rag;
+        self
+    }
+
+    // The same request at another stage
+    pub fn for_stage(&self, stage: Stage) -> Self {
+        Self { stage, ..self.clone() }
+    }
+
+    // The same request answered by another model, as in a comparison
+    pub fn for_model(&self, model: String) -> Self {
+        Self { model, ..self.clone() }
+    }
+
+    // Past answers like `query`, empty when the database is unavailable
+    pub async fn find_similar_responses(&self, query: &str, limit: usize) -> Result<Vec<ScoredEntry>, AppError> {
+        match &self.rag {
+            Some(rag) => rag.find_similar_responses(query, limit, &RetrievalOptions::default()).await,
+            None => Ok(Vec::new()),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// plugins/json_extract.rs
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::time::Duration;
+use crate::models::AppError;
+use super::{ModelAccess, Plugin, PluginContext, SettingField, SettingKind, Stage, PLUGIN_TIMEOUT};
+
+// Room for the one retry generation
+const RETRY_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Pulls the JSON out of an answer that was asked to be JSON only, with a badge saying
+// whether it parsed
+pub struct JsonExtractPlugin {
+    enabled: bool,
+    required: bool,
+    // Ask the model once to fix output that isn't valid JSON
+    retry: bool,
+    // Show only the extracted JSON instead of adding it below the answer
+    replace: bool,
+    models: ModelAccess,
+}
+
+impl JsonExtractPlugin {
+    pub fn new(models: ModelAccess) -> Self {
+        Self {
+            enabled: false,
+            required: false,
+            retry: false,
+            replace: false,
+            models,
+        }
+    }
+
+    fn annotate(&self, input: &str, result: Result<String, String>) -> String {
+        let block = match result {
+            Ok(pretty) => format!("✅ Valid JSON\n```json\n{}\n```", pretty),
+            // Nothing to replace the answer with, so it is always kept
+            Err(e) => return format!("{}\n\n❌ No valid JSON: {}", input, e),
+        };
+        if self.replace {
+            block
+        } else {
+            format!("{}\n\n{}", input, block)
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for JsonExtractPlugin {
+    fn name(&self) -> &str {
+        "JSON extractor"
+    }
+
+    fn runs_in(&self, stage: Stage) -> bool {
+        stage == Stage::PostResponse
+    }
+
+    async fn process(&self, input: &str, ctx: &PluginContext) -> Result<String, AppError> {
+        let error = match extract_json(input) {
+            Ok(value) => return Ok(self.annotate(input, Ok(pretty(&value)))),
+            Err(e) => e,
+        };
+        if !self.retry {
+            return Ok(self.annotate(input, Err(error)));
+        }
+
+        let prompt = format!(
+            "Your previous output was not valid JSON ({}), fix it. Reply with the corrected JSON only.\n\n{}",
+            error, input
+        );
+        let fixed = self.models.generate(self.name(), "fix JSON", &ctx.model, &prompt).await?;
+        let result = extract_json(&fixed)
+            .map(|value| pretty(&value))
+            .map_err(|e| format!("{} (also after one retry)", e));
+        Ok(self.annotate(input, result))
+    }
+
+    fn timeout(&self) -> Duration {
+        if self.retry { RETRY_TIMEOUT } else { PLUGIN_TIMEOUT }
+    }
+
+    fn calls_model(&self) -> bool {
+        self.retry
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn is_required(&self) -> bool {
+        self.required
+    }
+
+    fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    fn config(&self) -> Value {
+        json!({ "retry": self.retry, "replace": self.replace })
+    }
+
+    fn set_config(&mut self, config: Value) {
+        if let Some(retry) = config.get("retry").and_then(Value::as_bool) {
+            self.retry = retry;
+        }
+        if let Some(replace) = config.get("replace").and_then(Value::as_bool) {
+            self.replace = replace;
+        }
+    }
+
+    fn settings_schema(&self) -> Vec<SettingField> {
+        vec![
+            SettingField { key: "retry", label: "Ask the model to fix invalid JSON once", kind: SettingKind::Bool },
+            SettingField { key: "replace", label: "Replace the answer with the JSON", kind: SettingKind::Bool },
+        ]
+    }
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+// The first balanced object or array in `text` that parses. When none does, the error
+// is the parse error of the first balanced one.
+fn extract_json(text: &str) -> Result<Value, String> {
+    let mut first_error = None;
+    for (start, c) in text.char_indices() {
+        if c != '{' && c != '[' {
+            continue;
+        }
+        let Some(end) = balanced_end(&text[start..]) else {
+            continue;
+        };
+        match serde_json::from_str::<Value>(&text[start..start + end]) {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                first_error.get_or_insert_with(|| e.to_string());
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| "no JSON object or array found".to_string()))
+}
+
+// Byte length of the bracketed value `text` starts with, skipping brackets inside strings.
+// None when the brackets never close or don't match.
+fn balanced_end(text: &str) -> Option<usize> {
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                if closers.pop() != Some(c) {
+                    return None;
+                }
+                if closers.is_empty() {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,104 @@
+// plugins/language.rs
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex, PoisonError};
+use crate::models::AppError;
+use super::{Plugin, PluginContext, SettingField, SettingKind, Stage};
+
+// What the last prompt was written in, with whatlang's confidence from 0 to 1
+#[derive(Clone, Debug)]
+pub struct Detection {
+    pub language: String,
+    pub confidence: f64,
+}
+
+// The latest detection, shared with the plugins that act on it
+#[derive(Clone, Default)]
+pub struct DetectedLanguage {
+    detection: Arc<Mutex<Option<Detection>>>,
+}
+
+impl DetectedLanguage {
+    fn set(&self, detection: Option<Detection>) {
+        *self.detection.lock().unwrap_or_else(PoisonError::into_inner) = detection;
+    }
+
+    pub fn get(&self) -> Option<Detection> {
+        self.detection.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+// Detects the prompt's language and leaves the text alone. It stays a transformer anyway
+// so the translator after it in the chain sees this prompt's detection.
+pub struct LanguageDetectPlugin {
+    enabled: bool,
+    required: bool,
+    // Prompts shorter than this are too ambiguous to detect
+    min_chars: usize,
+    detected: DetectedLanguage,
+}
+
+impl LanguageDetectPlugin {
+    pub fn new(detected: DetectedLanguage) -> Self {
+        Self {
+            enabled: false,
+            required: false,
+            min_chars: 20,
+            detected,
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for LanguageDetectPlugin {
+    fn name(&self) -> &str {
+        "Language detection"
+    }
+
+    fn runs_in(&self, stage: Stage) -> bool {
+        stage == Stage::PrePrompt
+    }
+
+    async fn process(&self, input: &str, _ctx: &PluginContext) -> Result<String, AppError> {
+        // A stale detection from the previous prompt must not carry over
+        let detection = (input.chars().count() >= self.min_chars)
+            .then(|| whatlang::detect(input))
+            .flatten()
+            .map(|info| Detection {
+                language: info.lang().eng_name().to_string(),
+                confidence: info.confidence(),
+            });
+        self.detected.set(detection);
+        Ok(input.to_string())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn is_required(&self) -> bool {
+        self.required
+    }
+
+    fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    fn config(&self) -> Value {
+        json!({ "min_chars": self.min_chars })
+    }
+
+    fn set_config(&mut self, config: Value) {
+        if let Some(min_chars) = config.get("min_chars").and_then(Value::as_u64) {
+            self.min_chars = min_chars as usize;
+        }
+    }
+
+    fn settings_schema(&self) -> Vec<SettingField> {
+        vec![SettingField { key: "min_chars", label: "Minimum prompt length", kind: SettingKind::Number }]
+    }
+}
@@ -0,0 +1,482 @@
+// plugins/mod.rs
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use futures::future::join_all;
+use std::time::Duration;
+use crate::models::AppError;
+
+mod translator;
+mod summarizer;
+mod regex_rewrite;
+mod command;
+mod language;
+mod model_access;
+mod context;
+mod json_extract;
+mod rustfmt;
+
+pub use translator::TranslatorPlugin;
+pub use summarizer::SummarizerPlugin;
+pub use regex_rewrite::{RegexRewritePlugin, RegexRule, RuleSet};
+pub use command::{run_command, CommandPlugin, CommandQueue, CommandRequest};
+pub use language::{DetectedLanguage, LanguageDetectPlugin};
+pub use model_access::ModelAccess;
+pub use context::PluginContext;
+pub use json_extract::JsonExtractPlugin;
+pub use rustfmt::RustfmtPlugin;
+
+// Longest a single plugin may take before the chain moves on without it
+const PLUGIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Where in a request a plugin runs. Each stage is its own chain, a plugin only
+// ever sees the prompt or the answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stage {
+    // Rewrites the user's prompt before it goes to Ollama
+    PrePrompt,
+    // Rewrites the model's answer before it is shown and saved
+    PostResponse,
+}
+
+// Whether a plugin's output replaces the text or only comments on it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    // Runs in the chain in list order, its output is the text the next plugin sees
+    Transform,
+    // Runs concurrently with the others, its output is a short annotation shown with the
+    // answer and the text is left as it was. Empty output adds nothing.
+    Observe,
+}
+
+#[async_trait]
+pub trait Plugin: Send + Sync {
+    fn name(&self) -> &str;
+    // Most plugins run in one stage, a plugin may take part in both
+    fn runs_in(&self, stage: Stage) -> bool;
+    // `ctx.stage` says which chain this call is part of
+    async fn process(&self, input: &str, ctx: &PluginContext) -> Result<String, AppError>;
+    fn is_enabled(&self) -> bool;
+    fn set_enabled(&mut self, enabled: bool);
+    // A required plugin's failure fails the whole request instead of being skipped
+    fn is_required(&self) -> bool;
+    fn set_required(&mut self, required: bool);
+    fn mode(&self) -> Mode {
+        Mode::Transform
+    }
+    // How long `process` may take before the chain moves on without it
+    fn timeout(&self) -> Duration {
+        PLUGIN_TIMEOUT
+    }
+    // Plugins that make their own generations are left out of the prompt preview
+    fn calls_model(&self) -> bool {
+        false
+    }
+
+    // Settings as a JSON object keyed by `SettingField::key`, saved in the config file
+    fn config(&self) -> Value {
+        Value::Null
+    }
+    // Unknown keys and values of the wrong type are ignored
+    fn set_config(&mut self, _config: Value) {}
+    // Fields for the settings form in the plugins panel
+    fn settings_schema(&self) -> Vec<SettingField> {
+        Vec::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingKind {
+    Text,
+    // A whole, non-negative number
+    Number,
+    Bool,
+}
+
+pub struct SettingField {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub kind: SettingKind,
+}
+
+// The text after one stage, and the optional plugins that failed along the way
+#[derive(Debug)]
+pub struct PluginRun {
+    pub text: String,
+    pub failures: Vec<AppError>,
+    // Each plugin that ran successfully, with the text it produced
+    pub steps: Vec<(String, String)>,
+    // Enabled plugins a preview left out because they call the model
+    pub skipped: Vec<String>,
+    // Observers that had something to say, by plugin name
+    pub annotations: Vec<(String, String)>,
+}
+
+// Plugins run in list order, which starts as registration order and can be rearranged
+pub struct PluginManager {
+    plugins: Vec<Box<dyn Plugin>>,
+    // Name to position in `plugins`, rebuilt whenever the order changes
+    index: HashMap<String, usize>,
+}
+
+impl PluginManager {
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    // The bundled plugins, all off until enabled in the sidebar
+    pub fn with_defaults(rules: RuleSet, commands: CommandQueue, models: ModelAccess) -> Self {
+        let detected = DetectedLanguage::default();
+        let mut manager = Self::new();
+        // Detection has to come before the translator that reads it
+        manager.register_plugin(Box::new(LanguageDetectPlugin::new(detected.clone())));
+        manager.register_plugin(Box::new(TranslatorPlugin::new("English".to_string(), detected, models.clone())));
+        manager.register_plugin(Box::new(SummarizerPlugin::new(500)));
+        manager.register_plugin(Box::new(RegexRewritePlugin::new(rules)));
+        manager.register_plugin(Box::new(JsonExtractPlugin::new(models)));
+        manager.register_plugin(Box::new(RustfmtPlugin::new()));
+        manager.register_plugin(Box::new(CommandPlugin::new(commands)));
+        manager
+    }
+
+    // A plugin with the same name replaces the registered one
+    pub fn register_plugin(&mut self, plugin: Box<dyn Plugin>) {
+        match self.index.get(plugin.name()) {
+            Some(&position) => self.plugins[position] = plugin,
+            None => {
+                self.index.insert(plugin.name().to_string(), self.plugins.len());
+                self.plugins.push(plugin);
+            }
+        }
+    }
+
+    pub fn move_up(&mut self, name: &str) {
+        if let Some(&position) = self.index.get(name).filter(|&&position| position > 0) {
+            self.plugins.swap(position, position - 1);
+            self.reindex();
+        }
+    }
+
+    pub fn move_down(&mut self, name: &str) {
+        if let Some(&position) = self.index.get(name).filter(|&&position| position + 1 < self.plugins.len()) {
+            self.plugins.swap(position, position + 1);
+            self.reindex();
+        }
+    }
+
+    // Named plugins go first in the given order, unknown names are ignored and
+    // plugins not mentioned keep their relative order after them
+    pub fn set_order(&mut self, names: &[String]) {
+        let mut remaining: Vec<Option<Box<dyn Plugin>>> = self.plugins.drain(..).map(Some).collect();
+        let mut ordered = Vec::with_capacity(remaining.len());
+        for name in names {
+            if let Some(plugin) = self.index.get(name).and_then(|&position| remaining[position].take()) {
+                ordered.push(plugin);
+            }
+        }
+        ordered.extend(remaining.into_iter().flatten());
+        self.plugins = ordered;
+        self.reindex();
+    }
+
+    // Restores saved settings, plugins without an entry keep their defaults
+    pub fn apply_configs(&mut self, configs: &BTreeMap<String, Value>) {
+        for plugin in &mut self.plugins {
+            if let Some(config) = configs.get(plugin.name()) {
+                plugin.set_config(config.clone());
+            }
+        }
+    }
+
+    pub fn order(&self) -> Vec<String> {
+        self.plugins.iter().map(|plugin| plugin.name().to_string()).collect()
+    }
+
+    fn reindex(&mut self) {
+        self.index = self.plugins
+            .iter()
+            .enumerate()
+            .map(|(position, plugin)| (plugin.name().to_string(), position))
+            .collect();
+    }
+
+    // Runs the enabled plugins of `ctx.stage` over `input`, each on the previous one's output.
+    // A plugin that fails or times out is skipped with the text unchanged, unless it is required.
+    pub async fn process(&self, input: &str, ctx: &PluginContext) -> Result<PluginRun, AppError> {
+        self.run_chain(input, ctx, true).await
+    }
+
+    // Like `process`, but without plugins that would make their own generations
+    pub async fn preview(&self, input: &str, ctx: &PluginContext) -> Result<PluginRun, AppError> {
+        self.run_chain(input, ctx, false).await
+    }
+
+    async fn run_chain(&self, input: &str, ctx: &PluginContext, model_calls: bool) -> Result<PluginRun, AppError> {
+        let mut run = PluginRun {
+            text: input.to_string(),
+            failures: Vec::new(),
+            steps: Vec::new(),
+            skipped: Vec::new(),
+            annotations: Vec::new(),
+        };
+        
+        let mut transformers = Vec::new();
+        let mut observers = Vec::new();
+        for plugin in &self.plugins {
+            if !plugin.is_enabled() || !plugin.runs_in(ctx.stage) {
+                continue;
+            }
+            if plugin.calls_model() && !model_calls {
+                run.skipped.push(plugin.name().to_string());
+                continue;
+            }
+            match plugin.mode() {
+                Mode::Transform => transformers.push(plugin.as_ref()),
+                Mode::Observe => observers.push(plugin.as_ref()),
+            }
+        }
+        
+        // Observers all see the stage's input and run alongside the transformers, so a slow
+        // one holds the stage up no longer than its own timeout
+        let observing = join_all(observers.iter().map(|plugin| run_plugin(*plugin, input, ctx)));
+        let transforming = async {
+            for plugin in &transformers {
+                match run_plugin(*plugin, &run.text, ctx).await {
+                    Ok(output) => {
+                        run.steps.push((plugin.name().to_string(), output.clone()));
+                        run.text = output;
+                    }
+                    Err(e) if plugin.is_required() => return Err(e),
+                    Err(e) => run.failures.push(e),
+                }
+            }
+            Ok(run)
+        };
+        let (observed, transformed) = tokio::join!(observing, transforming);
+        
+        let mut run = transformed?;
+        for (plugin, result) in observers.iter().zip(observed) {
+            match result {
+                Ok(annotation) if annotation.trim().is_empty() => {}
+                Ok(annotation) => run.annotations.push((plugin.name().to_string(), annotation.trim().to_string())),
+                Err(e) if plugin.is_required() => return Err(e),
+                Err(e) => run.failures.push(e),
+            }
+        }
+        
+        Ok(run)
+    }
+
+    pub fn has_stage(&self, stage: Stage) -> bool {
+        self.plugins.iter().any(|plugin| plugin.is_enabled() && plugin.runs_in(stage))
+    }
+
+    pub fn plugins_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Plugin>> {
+        self.plugins.iter_mut()
+    }
+
+    pub fn plugin_count(&self) -> usize {
+        self.plugins.len()
+    }
+}
+
+impl Default for PluginManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn run_plugin(plugin: &dyn Plugin, input: &str, ctx: &PluginContext) -> Result<String, AppError> {
+    let timeout = plugin.timeout();
+    match tokio::time::timeout(timeout, plugin.process(input, ctx)).await {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(e)) => Err(AppError::Other(format!("Plugin {} failed: {}", plugin.name(), e))),
+        Err(_) => Err(AppError::Other(format!("Plugin {} timed out after {}s", plugin.name(), timeout.as_secs()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex, PoisonError};
+
+    // (plugin, stage, input) for every call, across all plugins of a test
+    type CallLog = Arc<Mutex<Vec<(String, Stage, String)>>>;
+
+    // Appends its name to the text and records what it was given. As an observer it waits
+    // `delay` and then tries to rewrite the text anyway.
+    struct RecordingPlugin {
+        name: String,
+        stage: Stage,
+        log: CallLog,
+        enabled: bool,
+        mode: Mode,
+        delay: Duration,
+        timeout: Duration,
+    }
+
+    impl RecordingPlugin {
+        fn new(name: &str, stage: Stage, log: &CallLog) -> Box<dyn Plugin> {
+            Box::new(Self {
+                name: name.to_string(),
+                stage,
+                log: log.clone(),
+                enabled: true,
+                mode: Mode::Transform,
+                delay: Duration::ZERO,
+                timeout: PLUGIN_TIMEOUT,
+            })
+        }
+
+        fn observer(name: &str, stage: Stage, log: &CallLog, delay: Duration, timeout: Duration) -> Box<dyn Plugin> {
+            Box::new(Self {
+                name: name.to_string(),
+                stage,
+                log: log.clone(),
+                enabled: true,
+                mode: Mode::Observe,
+                delay,
+                timeout,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Plugin for RecordingPlugin {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn runs_in(&self, stage: Stage) -> bool {
+            stage == self.stage
+        }
+
+        async fn process(&self, input: &str, ctx: &PluginContext) -> Result<String, AppError> {
+            self.log.lock().unwrap_or_else(PoisonError::into_inner).push((self.name.clone(), ctx.stage, input.to_string()));
+            tokio::time::sleep(self.delay).await;
+            Ok(format!("{}[{}]", input, self.name))
+        }
+
+        fn is_enabled(&self) -> bool {
+            self.enabled
+        }
+
+        fn set_enabled(&mut self, enabled: bool) {
+            self.enabled = enabled;
+        }
+
+        fn is_required(&self) -> bool {
+            false
+        }
+
+        fn set_required(&mut self, _required: bool) {}
+
+        fn mode(&self) -> Mode {
+            self.mode
+        }
+
+        fn timeout(&self) -> Duration {
+            self.timeout
+        }
+    }
+
+    fn calls(log: &CallLog) -> Vec<(String, Stage, String)> {
+        log.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    fn manager(log: &CallLog) -> PluginManager {
+        let mut manager = PluginManager::new();
+        manager.register_plugin(RecordingPlugin::new("a", Stage::PrePrompt, log));
+        manager.register_plugin(RecordingPlugin::new("x", Stage::PostResponse, log));
+        manager.register_plugin(RecordingPlugin::new("b", Stage::PrePrompt, log));
+        manager.register_plugin(RecordingPlugin::new("y", Stage::PostResponse, log));
+        manager.register_plugin(RecordingPlugin::new("c", Stage::PrePrompt, log));
+        manager
+    }
+
+    #[tokio::test]
+    async fn pre_prompt_plugins_run_in_configured_order() {
+        let log = CallLog::default();
+        let mut manager = manager(&log);
+        manager.set_order(&["c".to_string(), "a".to_string()]);
+
+        let run = manager.process("prompt", &PluginContext::new(Stage::PrePrompt, "m".to_string())).await.unwrap();
+
+        assert_eq!(run.text, "prompt[c][a][b]");
+        let order: Vec<String> = calls(&log).into_iter().map(|(name, _, _)| name).collect();
+        assert_eq!(order, ["c", "a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn pre_prompt_plugins_never_see_the_answer() {
+        let log = CallLog::default();
+        let manager = manager(&log);
+        let ctx = PluginContext::new(Stage::PrePrompt, "m".to_string());
+
+        let prompt = manager.process("prompt", &ctx).await.unwrap();
+        let answer = manager.process("answer", &ctx.for_stage(Stage::PostResponse)).await.unwrap();
+
+        assert_eq!(prompt.text, "prompt[a][b][c]");
+        assert_eq!(answer.text, "answer[x][y]");
+        for (name, stage, input) in calls(&log) {
+            match name.as_str() {
+                "a" | "b" | "c" => {
+                    assert_eq!(stage, Stage::PrePrompt);
+                    assert!(input.starts_with("prompt") && !input.contains("answer"), "{} saw {}", name, input);
+                }
+                _ => {
+                    assert_eq!(stage, Stage::PostResponse);
+                    assert!(input.starts_with("answer") && !input.contains("prompt"), "{} saw {}", name, input);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn post_response_plugins_never_see_the_prompt() {
+        let log = CallLog::default();
+        let manager = manager(&log);
+
+        let run = manager.process("answer", &PluginContext::new(Stage::PostResponse, "m".to_string())).await.unwrap();
+
+        assert_eq!(run.text, "answer[x][y]");
+        let names: Vec<String> = calls(&log).into_iter().map(|(name, _, _)| name).collect();
+        assert_eq!(names, ["x", "y"]);
+    }
+
+    #[tokio::test]
+    async fn observer_output_is_an_annotation_not_the_text() {
+        let log = CallLog::default();
+        let mut manager = PluginManager::new();
+        manager.register_plugin(RecordingPlugin::observer("watcher", Stage::PostResponse, &log, Duration::ZERO, PLUGIN_TIMEOUT));
+        manager.register_plugin(RecordingPlugin::new("x", Stage::PostResponse, &log));
+
+        let run = manager.process("answer", &PluginContext::new(Stage::PostResponse, "m".to_string())).await.unwrap();
+
+        assert_eq!(run.text, "answer[x]");
+        assert_eq!(run.annotations, [("watcher".to_string(), "answer[watcher]".to_string())]);
+        assert!(run.steps.iter().all(|(name, _)| name != "watcher"));
+    }
+
+    #[tokio::test]
+    async fn slow_observer_holds_the_stage_no_longer_than_its_timeout() {
+        let log = CallLog::default();
+        let timeout = Duration::from_millis(100);
+        let mut manager = PluginManager::new();
+        manager.register_plugin(RecordingPlugin::observer("slow", Stage::PostResponse, &log, Duration::from_secs(30), timeout));
+        manager.register_plugin(RecordingPlugin::new("x", Stage::PostResponse, &log));
+
+        let started = std::time::Instant::now();
+        let run = manager.process("answer", &PluginContext::new(Stage::PostResponse, "m".to_string())).await.unwrap();
+        let elapsed = started.elapsed();
+
+        assert_eq!(run.text, "answer[x]");
+        assert!(run.annotations.is_empty());
+        assert_eq!(run.failures.len(), 1);
+        assert!(elapsed < timeout + Duration::from_secs(1), "stage took {:?}", elapsed);
+    }
+}
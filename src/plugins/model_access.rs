@@ -0,0 +1,52 @@
+// plugins/model_access.rs
+use chrono::Local;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Instant;
+use crate::analytics::AnalyticsEngine;
+use crate::models::{AppError, ParsedResponse, PluginRequest};
+use crate::backend::LlmBackend;
+
+// Lets plugins make their own generations against the app's Ollama server. Every
+// request is logged to analytics under the plugin's name.
+#[derive(Clone)]
+pub struct ModelAccess {
+    client: Arc<RwLock<Arc<dyn LlmBackend>>>,
+    analytics: Option<AnalyticsEngine>,
+}
+
+impl ModelAccess {
+    pub fn new(client: Arc<dyn LlmBackend>, analytics: Option<AnalyticsEngine>) -> Self {
+        Self {
+            client: Arc::new(RwLock::new(client)),
+            analytics,
+        }
+    }
+
+    // Follows the server URL set in the sidebar
+    pub fn set_backend(&self, client: Arc<dyn LlmBackend>) {
+        *self.client.write().unwrap_or_else(PoisonError::into_inner) = client;
+    }
+
+    // Returns the answer with any reasoning block removed
+    pub async fn generate(&self, plugin: &str, purpose: &str, model: &str, prompt: &str) -> Result<String, AppError> {
+        let client = self.client.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let started = Instant::now();
+        let result = client.generate_response(model, prompt, &[], None).await;
+
+        if let Some(analytics) = &self.analytics {
+            let request = PluginRequest {
+                timestamp: Local::now(),
+                plugin: plugin.to_string(),
+                purpose: purpose.to_string(),
+                model: model.to_string(),
+                response_time_ms: started.elapsed().as_millis() as i64,
+                success: result.is_ok(),
+            };
+            if let Err(e) = analytics.record_plugin_request(request).await {
+                eprintln!("Error recording plugin request: {}", e);
+            }
+        }
+
+        Ok(ParsedResponse::parse(&result?).answer.trim().to_string())
+    }
+}
@@ -0,0 +1,116 @@
+// plugins/regex_rewrite.rs
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, PoisonError, RwLock};
+use crate::models::AppError;
+use super::{Plugin, PluginContext, Stage};
+
+// One find/replace rule as stored in the config file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegexRule {
+    pub pattern: String,
+    // May refer to capture groups as $1 or ${name}
+    pub replacement: String,
+    pub stage: Stage,
+}
+
+struct CompiledRule {
+    regex: Regex,
+    replacement: String,
+    stage: Stage,
+}
+
+// The compiled rules, shared between the plugin and the rule editor in the sidebar
+#[derive(Clone, Default)]
+pub struct RuleSet {
+    rules: Arc<RwLock<Vec<CompiledRule>>>,
+}
+
+impl RuleSet {
+    // Invalid rules are left out. Returns the compile error of each rule by position.
+    pub fn replace(&self, rules: &[RegexRule]) -> Vec<Option<String>> {
+        let mut compiled = Vec::new();
+        let errors = rules
+            .iter()
+            .map(|rule| match Regex::new(&rule.pattern) {
+                Ok(regex) => {
+                    compiled.push(CompiledRule {
+                        regex,
+                        replacement: rule.replacement.clone(),
+                        stage: rule.stage,
+                    });
+                    None
+                }
+                Err(e) => Some(e.to_string()),
+            })
+            .collect();
+        *self.rules.write().unwrap_or_else(PoisonError::into_inner) = compiled;
+        errors
+    }
+
+    pub fn has_stage(&self, stage: Stage) -> bool {
+        self.rules
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .any(|rule| rule.stage == stage)
+    }
+
+    // Applies the stage's rules in order, each to the previous one's output
+    pub fn apply(&self, stage: Stage, input: &str) -> String {
+        let rules = self.rules.read().unwrap_or_else(PoisonError::into_inner);
+        let mut text = input.to_string();
+        for rule in rules.iter().filter(|rule| rule.stage == stage) {
+            text = rule.regex.replace_all(&text, rule.replacement.as_str()).into_owned();
+        }
+        text
+    }
+}
+
+pub struct RegexRewritePlugin {
+    enabled: bool,
+    required: bool,
+    rules: RuleSet,
+}
+
+impl RegexRewritePlugin {
+    pub fn new(rules: RuleSet) -> Self {
+        Self {
+            enabled: false,
+            required: false,
+            rules,
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for RegexRewritePlugin {
+    fn name(&self) -> &str {
+        "Regex rewrite"
+    }
+
+    fn runs_in(&self, stage: Stage) -> bool {
+        self.rules.has_stage(stage)
+    }
+
+    async fn process(&self, input: &str, ctx: &PluginContext) -> Result<String, AppError> {
+        Ok(self.rules.apply(ctx.stage, input))
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn is_required(&self) -> bool {
+        self.required
+    }
+
+    fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+}
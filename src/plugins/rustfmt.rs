@@ -0,0 +1,193 @@
+// plugins/rustfmt.rs
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use crate::models::AppError;
+use super::command::read_to_end;
+use super::{Plugin, PluginContext, SettingField, SettingKind, Stage};
+
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+// Several blocks are formatted one after another
+const PLUGIN_TIMEOUT: Duration = Duration::from_secs(30);
+const UNFORMATTED_NOTE: &str = "*(unformatted: rustfmt rejected this block)*";
+
+enum Formatted {
+    Code(String),
+    Rejected,
+    // rustfmt isn't installed at the configured path
+    Missing,
+}
+
+// Runs ```rust blocks in answers through rustfmt
+pub struct RustfmtPlugin {
+    enabled: bool,
+    required: bool,
+    rustfmt_path: String,
+}
+
+impl RustfmtPlugin {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            required: false,
+            rustfmt_path: "rustfmt".to_string(),
+        }
+    }
+}
+
+impl Default for RustfmtPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Plugin for RustfmtPlugin {
+    fn name(&self) -> &str {
+        "Rust formatter"
+    }
+
+    fn runs_in(&self, stage: Stage) -> bool {
+        stage == Stage::PostResponse
+    }
+
+    async fn process(&self, input: &str, _ctx: &PluginContext) -> Result<String, AppError> {
+        if !input.lines().any(|line| is_rust_fence(line.trim())) {
+            return Ok(input.to_string());
+        }
+        let path = self.rustfmt_path.clone();
+        let input = input.to_string();
+        // rustfmt blocks while it runs, keep it off the async workers
+        tokio::task::spawn_blocking(move || format_blocks(&path, &input))
+            .await
+            .map_err(|e| AppError::Other(format!("rustfmt task failed: {}", e)))
+    }
+
+    fn timeout(&self) -> Duration {
+        PLUGIN_TIMEOUT
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn is_required(&self) -> bool {
+        self.required
+    }
+
+    fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    fn config(&self) -> Value {
+        json!({ "rustfmt_path": self.rustfmt_path })
+    }
+
+    fn set_config(&mut self, config: Value) {
+        if let Some(path) = config.get("rustfmt_path").and_then(Value::as_str) {
+            self.rustfmt_path = path.to_string();
+        }
+    }
+
+    fn settings_schema(&self) -> Vec<SettingField> {
+        vec![SettingField { key: "rustfmt_path", label: "rustfmt path", kind: SettingKind::Text }]
+    }
+}
+
+fn is_rust_fence(line: &str) -> bool {
+    line == "```rust" || line == "```rs"
+}
+
+// Replaces each ```rust block with its formatted code, everything else is copied as is
+fn format_blocks(rustfmt: &str, text: &str) -> String {
+    let mut output = Vec::new();
+    let mut block: Option<Vec<&str>> = None;
+    for line in text.lines() {
+        match &mut block {
+            None => {
+                output.push(line.to_string());
+                if is_rust_fence(line.trim()) {
+                    block = Some(Vec::new());
+                }
+            }
+            Some(code) if !line.trim().starts_with("```") => code.push(line),
+            Some(code) => {
+                let code = code.join("\n");
+                let formatted = format_code(rustfmt, &code);
+                match &formatted {
+                    Formatted::Code(formatted) => output.push(formatted.trim_end().to_string()),
+                    Formatted::Rejected | Formatted::Missing => output.push(code),
+                }
+                output.push(line.to_string());
+                match formatted {
+                    Formatted::Rejected => output.push(UNFORMATTED_NOTE.to_string()),
+                    // Without rustfmt no other block can be formatted either
+                    Formatted::Missing => return text.to_string(),
+                    Formatted::Code(_) => {}
+                }
+                block = None;
+            }
+        }
+    }
+    // An unclosed block at the end is left alone
+    if let Some(code) = block {
+        output.extend(code.into_iter().map(str::to_string));
+    }
+    output.join("\n")
+}
+
+fn format_code(rustfmt: &str, code: &str) -> Formatted {
+    let mut child = match Command::new(rustfmt)
+        .args(["--edition", "2021", "--emit", "stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Formatted::Missing,
+        Err(e) => {
+            eprintln!("Could not start rustfmt: {}", e);
+            return Formatted::Missing;
+        }
+    };
+
+    // Write on a thread so a large block can't deadlock against a full stdout pipe
+    let stdin = child.stdin.take().map(|mut stdin| {
+        let code = code.to_string();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(code.as_bytes());
+        })
+    });
+    let stdout = child.stdout.take().map(read_to_end);
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if started.elapsed() > FORMAT_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(_) => break None,
+        }
+    };
+
+    if let Some(writer) = stdin {
+        let _ = writer.join();
+    }
+    let formatted = stdout.and_then(|reader| reader.join().ok()).unwrap_or_default();
+    match status {
+        Some(status) if status.success() && !formatted.trim().is_empty() => Formatted::Code(formatted),
+        _ => Formatted::Rejected,
+    }
+}
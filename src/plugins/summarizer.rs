@@ -0,0 +1,71 @@
+// plugins/summarizer.rs
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use crate::models::AppError;
+use super::{Plugin, PluginContext, SettingField, SettingKind, Stage};
+
+pub struct SummarizerPlugin {
+    enabled: bool,
+    required: bool,
+    max_length: usize,
+}
+
+impl SummarizerPlugin {
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            enabled: false,
+            required: false,
+            max_length,
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for SummarizerPlugin {
+    fn name(&self) -> &str {
+        "Summarizer"
+    }
+
+    fn runs_in(&self, stage: Stage) -> bool {
+        stage == Stage::PostResponse
+    }
+
+    async fn process(&self, input: &str, _ctx: &PluginContext) -> Result<String, AppError> {
+        if input.len() <= self.max_length {
+            return Ok(input.to_string());
+        }
+        
+        // Simple truncation - in real implementation, use proper summarization
+        Ok(format!("{}...", &input[..self.max_length]))
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn is_required(&self) -> bool {
+        self.required
+    }
+
+    fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    fn config(&self) -> Value {
+        json!({ "max_length": self.max_length })
+    }
+
+    fn set_config(&mut self, config: Value) {
+        if let Some(max_length) = config.get("max_length").and_then(Value::as_u64) {
+            self.max_length = max_length as usize;
+        }
+    }
+
+    fn settings_schema(&self) -> Vec<SettingField> {
+        vec![SettingField { key: "max_length", label: "Max length", kind: SettingKind::Number }]
+    }
+}
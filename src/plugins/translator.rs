@@ -0,0 +1,147 @@
+// plugins/translator.rs
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use crate::models::AppError;
+use super::{DetectedLanguage, ModelAccess, Plugin, PluginContext, SettingField, SettingKind, Stage};
+
+// Each direction is a full generation, far slower than the other plugins
+const TRANSLATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Sends the prompt to the model in the pivot language and translates the answer back into
+// the language the prompt was written in, as found by the language detection plugin
+pub struct TranslatorPlugin {
+    enabled: bool,
+    required: bool,
+    target_language: String,
+    // Empty uses the model answering the request
+    model: String,
+    // Detections below this percentage leave the prompt and answer untranslated
+    min_confidence: u64,
+    detected: DetectedLanguage,
+    models: ModelAccess,
+    // Language to translate the next answer back into, set by the prompt stage
+    source_language: Mutex<Option<String>>,
+}
+
+impl TranslatorPlugin {
+    pub fn new(target_language: String, detected: DetectedLanguage, models: ModelAccess) -> Self {
+        Self {
+            enabled: false,
+            required: false,
+            target_language,
+            model: String::new(),
+            min_confidence: 50,
+            detected,
+            models,
+            source_language: Mutex::new(None),
+        }
+    }
+
+    async fn translate(&self, ctx: &PluginContext, purpose: &str, language: &str, text: &str) -> Result<String, AppError> {
+        let prompt = format!(
+            "Translate the following text to {}. Keep formatting, code and names unchanged. \
+             Reply with the translation only.\n\n{}",
+            language, text
+        );
+        let model = if self.model.is_empty() { &ctx.model } else { &self.model };
+        let translation = self.models.generate(self.name(), purpose, model, &prompt).await?;
+        if translation.is_empty() {
+            return Err(AppError::Other("The model returned an empty translation".to_string()));
+        }
+        Ok(translation)
+    }
+}
+
+#[async_trait]
+impl Plugin for TranslatorPlugin {
+    fn name(&self) -> &str {
+        "Translator"
+    }
+
+    fn runs_in(&self, _stage: Stage) -> bool {
+        true
+    }
+
+    async fn process(&self, input: &str, ctx: &PluginContext) -> Result<String, AppError> {
+        match ctx.stage {
+            Stage::PrePrompt => {
+                *self.source_language.lock().unwrap_or_else(PoisonError::into_inner) = None;
+                // Without detection the prompt is still translated, there's just nothing to go back to
+                let detection = self.detected.get();
+                if let Some(detection) = &detection {
+                    if detection.confidence * 100.0 < self.min_confidence as f64
+                        || detection.language.eq_ignore_ascii_case(&self.target_language)
+                    {
+                        return Ok(input.to_string());
+                    }
+                }
+                
+                let translation = self.translate(ctx, "translate prompt", &self.target_language, input).await?;
+                *self.source_language.lock().unwrap_or_else(PoisonError::into_inner) =
+                    detection.map(|detection| detection.language);
+                Ok(translation)
+            }
+            Stage::PostResponse => {
+                let source = self.source_language.lock().unwrap_or_else(PoisonError::into_inner).take();
+                match source {
+                    Some(language) => self.translate(ctx, "translate answer", &language, input).await,
+                    None => Ok(input.to_string()),
+                }
+            }
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        TRANSLATION_TIMEOUT
+    }
+
+    fn calls_model(&self) -> bool {
+        true
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn is_required(&self) -> bool {
+        self.required
+    }
+
+    fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    fn config(&self) -> Value {
+        json!({
+            "target_language": self.target_language,
+            "model": self.model,
+            "min_confidence": self.min_confidence,
+        })
+    }
+
+    fn set_config(&mut self, config: Value) {
+        if let Some(language) = config.get("target_language").and_then(Value::as_str) {
+            self.target_language = language.to_string();
+        }
+        if let Some(model) = config.get("model").and_then(Value::as_str) {
+            self.model = model.to_string();
+        }
+        if let Some(confidence) = config.get("min_confidence").and_then(Value::as_u64) {
+            self.min_confidence = confidence.min(100);
+        }
+    }
+
+    fn settings_schema(&self) -> Vec<SettingField> {
+        vec![
+            SettingField { key: "target_language", label: "Pivot language", kind: SettingKind::Text },
+            SettingField { key: "model", label: "Model (blank for the chat's)", kind: SettingKind::Text },
+            SettingField { key: "min_confidence", label: "Minimum confidence (%)", kind: SettingKind::Number },
+        ]
+    }
+}
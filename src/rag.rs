@@ -1,96 +1,317 @@
 // rag.rs
-use rusqlite::{Connection, params};
-use std::path::PathBuf;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, Transaction};
+use rusqlite::types::Value;
+use std::any::Any;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::fs;
-use chrono::{DateTime, Local};
-use crate::models::{ConversationEntry, AppError};
+use std::sync::{Arc, PoisonError, RwLock};
+use chrono::{DateTime, Duration, Local};
+use crate::models::{
+    ConversationEntry, ConversationFilter, ConversationStatus, ContextSource, RetrievalOptions, ScoredEntry,
+    ImportedSession, OutboxItem, SessionSummary, TextDump, AppError,
+};
+use crate::db::Database;
+use crate::writer::{DbCommand, TransactionJob};
+use crate::keywords;
+use crate::config;
+use crate::analytics::SESSION_GAP_MINUTES;
+
+const MAX_KEYWORDS: usize = 5;
+const DOCUMENT_CHUNK_CHARS: usize = 1500;
+// Upper bound on how far back the last session is looked for
+const LAST_SESSION_LIMIT: usize = 200;
+// Score multiplier for conversations the user starred
+const STARRED_BOOST: f32 = 1.25;
+
+// Column list matching `RagSystem::row_to_entry`
+const CONVERSATION_COLUMNS: &str =
+    "id, timestamp, prompt, response, model_used, response_time_ms, file_context,
+     (SELECT GROUP_CONCAT(t.name, ',') FROM conversation_tags ct
+      JOIN tags t ON t.id = ct.tag_id WHERE ct.conversation_id = conversations.id) AS tags,
+     status, reasoning, first_token_ms, starred, feedback, backend, source";
+
+const TAG_CONDITION: &str =
+    "id IN (SELECT ct.conversation_id FROM conversation_tags ct
+            JOIN tags t ON t.id = ct.tag_id WHERE t.name = ?)";
 
 #[derive(Clone)]
 pub struct RagSystem {
-    db_path: PathBuf,
-    pub save_directory: PathBuf,
+    db: Database,
+    pub save_directory: Arc<Path>,
+    // Shared by every clone so a settings change reaches requests already running
+    text_dump: Arc<RwLock<TextDump>>,
 }
 
 impl RagSystem {
+    // In the data directory chosen at startup, ./tourist_data unless --data-dir says otherwise
     pub fn new() -> Result<Self, AppError> {
-        let save_dir = PathBuf::from("./tourist_data");
-        fs::create_dir_all(&save_dir)?;
-        
-        let db_path = save_dir.join("conversations.db");
-        
-        // Initialize database
-        Self::init_database(&db_path)?;
-        
-        Ok(Self {
-            db_path,
-            save_directory: save_dir,
-        })
+        Self::new_in(config::data_dir())
     }
 
-    fn init_database(db_path: &PathBuf) -> Result<(), AppError> {
-        let connection = Connection::open(db_path)?;
-        
-        connection.execute(
-            "CREATE TABLE IF NOT EXISTS conversations (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                timestamp TEXT NOT NULL,
-                prompt TEXT NOT NULL,
-                response TEXT NOT NULL,
-                model_used TEXT NOT NULL,
-                response_time_ms INTEGER NOT NULL,
-                file_context TEXT
-            )",
-            [],
-        )?;
+    pub fn new_in(dir: impl Into<PathBuf>) -> Result<Self, AppError> {
+        let save_dir = dir.into();
         
-        connection.execute(
-            "CREATE TABLE IF NOT EXISTS embeddings_cache (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                prompt_hash TEXT UNIQUE NOT NULL,
-                prompt TEXT NOT NULL,
-                response TEXT NOT NULL,
-                similarity_score REAL DEFAULT 0.0
-            )",
-            [],
-        )?;
+        // Opening the database also applies pending migrations
+        let db = Database::open_in(&save_dir)?;
+        Ok(Self::with_database(db, save_dir))
+    }
 
-        Ok(())
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted_in(dir: impl Into<PathBuf>, key: &str) -> Result<Self, AppError> {
+        let save_dir = dir.into();
+        let db = Database::open_encrypted_in(&save_dir, key)?;
+        Ok(Self::with_database(db, save_dir))
+    }
+
+    fn with_database(db: Database, save_dir: PathBuf) -> Self {
+        Self {
+            db,
+            save_directory: save_dir.into(),
+            text_dump: Arc::new(RwLock::new(TextDump::default())),
+        }
+    }
+
+    // Plain text copies would undo the encryption, so an encrypted database never writes them
+    pub fn set_text_dump(&self, mode: TextDump) {
+        let mode = if self.db.is_encrypted() { TextDump::Off } else { mode };
+        *self.text_dump.write().unwrap_or_else(PoisonError::into_inner) = mode;
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.db.is_encrypted()
+    }
+
+    pub fn database(&self) -> Database {
+        self.db.clone()
+    }
+
+    pub async fn backup_to(&self, path: PathBuf) -> Result<(), AppError> {
+        self.db.backup_to(path).await
+    }
+
+    pub async fn restore_from(&self, path: PathBuf) -> Result<(), AppError> {
+        self.db.restore_from(path).await
     }
     
-    pub async fn save_conversation(&self, entry: &ConversationEntry) -> Result<(), AppError> {
-        let db_path = self.db_path.clone();
+    // Runs `f` in one transaction, committed if it returns Ok and rolled back otherwise. For
+    // writing many rows at once, with statements from prepare_cached so each is parsed once.
+    pub async fn with_transaction<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Transaction) -> Result<T, AppError> + Send + 'static,
+    {
+        let job: TransactionJob = Box::new(move |tx: &Transaction| f(tx).map(|value| Box::new(value) as Box<dyn Any + Send>));
+        let value = self.db.send(|reply| DbCommand::Transaction { job, reply }).await?;
+        value
+            .downcast::<T>()
+            .map(|value| *value)
+            .map_err(|_| AppError::Other("Transaction returned an unexpected type".to_string()))
+    }
+
+    // Returns the id of the new conversation row
+    pub async fn save_conversation(&self, entry: &ConversationEntry) -> Result<i64, AppError> {
+        let conversation_id = self.db.send(|reply| DbCommand::InsertConversation { entry: entry.clone(), reply }).await?;
+        
+        let text_dump = *self.text_dump.read().unwrap_or_else(PoisonError::into_inner);
+        if text_dump == TextDump::Off {
+            return Ok(conversation_id);
+        }
         let entry = entry.clone();
         let save_dir = self.save_directory.clone();
+        let dumped = tokio::task::spawn_blocking(move || match text_dump {
+            TextDump::PerResponse => Self::save_as_text_file(&save_dir, &entry, conversation_id),
+            _ => Self::append_to_daily_log(&save_dir, &entry),
+        }).await;
         
-        tokio::task::spawn_blocking(move || -> Result<(), AppError> {
-            let connection = Connection::open(&db_path)?;
-            
-            connection.execute(
-                "INSERT INTO conversations (timestamp, prompt, response, model_used, response_time_ms, file_context)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                params![
-                    entry.timestamp.to_rfc3339(),
-                    entry.prompt,
-                    entry.response,
-                    entry.model_used,
-                    entry.response_time_ms,
-                    entry.file_context.as_deref().unwrap_or("")
-                ],
+        // The row is saved by now, a failed copy must not turn that into an error
+        if let Err(e) = dumped.map_err(AppError::from).and_then(|dumped| dumped) {
+            eprintln!("Error writing text copy of conversation {}: {}", conversation_id, e);
+        }
+        Ok(conversation_id)
+    }
+
+    // Stores imported conversations, each under its own session with its title and start time,
+    // all in one transaction. A session imported before is left alone and reported as false.
+    pub async fn import_sessions(&self, sessions: Vec<ImportedSession>) -> Result<Vec<bool>, AppError> {
+        self.db.send(|reply| DbCommand::ImportSessions { sessions, reply }).await
+    }
+
+    pub async fn assign_session(&self, conversation_ids: Vec<i64>, session_id: String) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::AssignSession { conversation_ids, session_id, reply }).await
+    }
+
+    pub async fn rename_session(&self, session_id: String, title: String) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::RenameSession { session_id, title, reply }).await
+    }
+
+    pub async fn session_title(&self, session_id: String) -> Result<Option<String>, AppError> {
+        self.db.read(move |connection| {
+            let title = connection.query_row(
+                "SELECT title FROM sessions WHERE id = ?1",
+                [session_id],
+                |row| row.get(0),
+            ).optional()?;
+            Ok(title.flatten())
+        }).await
+    }
+
+    // Sessions with at least one conversation still in the chat, most recently used first
+    pub async fn list_sessions(&self, limit: usize) -> Result<Vec<SessionSummary>, AppError> {
+        self.db.read(move |connection| {
+            let mut stmt = connection.prepare(
+                "SELECT s.id, s.title, MAX(c.timestamp) AS last_active, COUNT(c.id), s.parent_id
+                 FROM sessions s JOIN conversations c ON c.session_id = s.id
+                 WHERE c.superseded_at IS NULL
+                 GROUP BY s.id ORDER BY last_active DESC LIMIT ?1",
             )?;
+            let sessions = stmt
+                .query_map([limit as i64], |row| {
+                    let last_active: String = row.get(2)?;
+                    let last_active = DateTime::parse_from_rfc3339(&last_active)
+                        .map(|timestamp| timestamp.with_timezone(&Local))
+                        .unwrap_or_else(|_| Local::now());
+                    let conversations: i64 = row.get(3)?;
+                    Ok(SessionSummary {
+                        id: row.get(0)?,
+                        title: row.get(1)?,
+                        last_active,
+                        conversations: conversations as usize,
+                        parent_id: row.get(4)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(sessions)
+        }).await
+    }
+
+    // Starts `branch_id` from the given conversations of `parent_id`. They're copied, with their
+    // timestamps and tags, so either session can carry on without changing the other.
+    pub async fn branch_session(&self, parent_id: String, branch_id: String, conversation_ids: Vec<i64>) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::BranchSession { parent_id, branch_id, conversation_ids, reply }).await
+    }
+
+    // A session's conversations as they appeared in the chat, oldest first
+    pub async fn session_conversations(&self, session_id: String) -> Result<Vec<ConversationEntry>, AppError> {
+        self.db.read(move |connection| {
+            let query = format!(
+                "SELECT {} FROM conversations
+                 WHERE session_id = ? AND superseded_at IS NULL AND status != 'error'
+                 ORDER BY timestamp ASC",
+                CONVERSATION_COLUMNS
+            );
+            let mut stmt = connection.prepare(&query)?;
+            let entries = stmt
+                .query_map([session_id], Self::row_to_entry)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        }).await
+    }
+
+    pub async fn enqueue_outbox(&self, prompt: String, model: String, session_id: String, tags: Vec<String>) -> Result<i64, AppError> {
+        self.db.send(|reply| DbCommand::EnqueueOutbox { prompt, model, session_id, tags, reply }).await
+    }
+
+    // Oldest first, the order they are sent in
+    pub async fn list_outbox(&self) -> Result<Vec<OutboxItem>, AppError> {
+        self.db.read(|connection| {
+            let mut stmt = connection.prepare(
+                "SELECT id, created_at, prompt, model, session_id, tags FROM outbox ORDER BY id ASC",
+            )?;
+            let items = stmt
+                .query_map([], |row| {
+                    let created_at: String = row.get(1)?;
+                    let tags: String = row.get(5)?;
+                    Ok(OutboxItem {
+                        id: row.get(0)?,
+                        created_at: DateTime::parse_from_rfc3339(&created_at)
+                            .map(|timestamp| timestamp.with_timezone(&Local))
+                            .unwrap_or_else(|_| Local::now()),
+                        prompt: row.get(2)?,
+                        model: row.get(3)?,
+                        session_id: row.get(4)?,
+                        tags: tags.split(',').filter(|tag| !tag.is_empty()).map(str::to_string).collect(),
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(items)
+        }).await
+    }
+
+    pub async fn remove_outbox(&self, id: i64) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::RemoveOutbox { id, reply }).await
+    }
+
+    pub async fn set_parent_response(&self, conversation_id: i64, parent_id: i64) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::SetParentResponse { conversation_id, parent_id, reply }).await
+    }
+
+    pub async fn set_starred(&self, conversation_id: i64, starred: bool) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::SetStarred { conversation_id, starred, reply }).await
+    }
+
+    // `value` is -1, 0 or 1
+    pub async fn set_feedback(&self, conversation_id: i64, value: i64) -> Result<(), AppError> {
+        let value = value.clamp(-1, 1);
+        self.db.send(|reply| DbCommand::SetFeedback { conversation_id, value, reply }).await
+    }
+
+    // The most recent run of conversations without a long break, oldest first
+    pub async fn last_session(&self) -> Result<Vec<ConversationEntry>, AppError> {
+        self.db.read(|connection| {
+            let query = format!(
+                "SELECT {} FROM conversations
+                 WHERE superseded_at IS NULL AND status != 'error'
+                 ORDER BY timestamp DESC LIMIT ?",
+                CONVERSATION_COLUMNS
+            );
+            let mut stmt = connection.prepare(&query)?;
+            let entries = stmt
+                .query_map([LAST_SESSION_LIMIT as i64], Self::row_to_entry)?
+                .collect::<Result<Vec<_>, _>>()?;
             
-            // Save as individual text file
-            Self::save_as_text_file(&save_dir, &entry)?;
-            
-            Ok(())
-        }).await.map_err(|e| AppError(e.to_string()))??;
-        
-        Ok(())
+            let mut session: Vec<ConversationEntry> = Vec::new();
+            for entry in entries {
+                if let Some(newer) = session.last() {
+                    if newer.timestamp - entry.timestamp > Duration::minutes(SESSION_GAP_MINUTES) {
+                        break;
+                    }
+                }
+                session.push(entry);
+            }
+            session.reverse();
+            Ok(session)
+        }).await
+    }
+
+    pub async fn list_starred(&self, limit: usize) -> Result<Vec<ConversationEntry>, AppError> {
+        self.db.read(move |connection| {
+            let query = format!(
+                "SELECT {} FROM conversations WHERE starred = 1 ORDER BY timestamp DESC LIMIT ?",
+                CONVERSATION_COLUMNS
+            );
+            let mut stmt = connection.prepare(&query)?;
+            let entries = stmt
+                .query_map([limit as i64], Self::row_to_entry)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        }).await
+    }
+
+    // Tags and embeddings go with it through ON DELETE CASCADE
+    pub async fn delete_conversation(&self, conversation_id: i64) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::DeleteConversation { conversation_id, reply }).await
+    }
+
+    pub async fn mark_superseded(&self, conversation_ids: Vec<i64>) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::MarkSuperseded { conversation_ids, reply }).await
     }
 
-    fn save_as_text_file(save_dir: &PathBuf, entry: &ConversationEntry) -> Result<(), AppError> {
-        let filename = format!("response_{}.txt", entry.timestamp.format("%Y%m%d_%H%M%S"));
+    fn save_as_text_file(save_dir: &Path, entry: &ConversationEntry, conversation_id: i64) -> Result<(), AppError> {
+        // The row id keeps two answers saved within the same second apart
+        let filename = format!("response_{}_{}.txt", entry.timestamp.format("%Y%m%d_%H%M%S"), conversation_id);
         let file_path = save_dir.join(filename);
-        let content = format!(
+        let mut content = format!(
             "Timestamp: {}\nModel: {}\nResponse Time: {}ms\n\nPrompt:\n{}\n\nResponse:\n{}\n",
             entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
             entry.model_used,
@@ -98,76 +319,665 @@ impl RagSystem {
             entry.prompt,
             entry.response
         );
+        if let Some(reasoning) = &entry.reasoning {
+            content.push_str(&format!("\nReasoning:\n{}\n", reasoning));
+        }
         std::fs::write(file_path, content)?;
         Ok(())
     }
+
+    fn append_to_daily_log(save_dir: &Path, entry: &ConversationEntry) -> Result<(), AppError> {
+        let filename = format!("conversations_{}.log.md", entry.timestamp.format("%Y-%m-%d"));
+        let mut content = format!(
+            "## {} · {} · {}ms\n\n**Prompt**\n\n{}\n\n**Response**\n\n{}\n\n",
+            entry.timestamp.format("%H:%M:%S"),
+            entry.model_used,
+            entry.response_time_ms,
+            entry.prompt,
+            entry.response
+        );
+        if let Some(reasoning) = &entry.reasoning {
+            content.push_str(&format!("**Reasoning**\n\n{}\n\n", reasoning));
+        }
+        content.push_str("---\n\n");
+        
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(save_dir.join(filename))?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    }
     
-    pub async fn find_similar_responses(&self, prompt: &str, limit: usize) -> Result<Vec<ConversationEntry>, AppError> {
-        let db_path = self.db_path.clone();
+    pub async fn find_similar_responses(
+        &self,
+        prompt: &str,
+        limit: usize,
+        options: &RetrievalOptions,
+    ) -> Result<Vec<ScoredEntry>, AppError> {
         let prompt = prompt.to_string();
+        let options = options.clone();
         
-        let results = tokio::task::spawn_blocking(move || -> Result<Vec<ConversationEntry>, AppError> {
-            let connection = Connection::open(&db_path)?;
+        self.db.read(move |connection| {
+            let filter = &options.filter;
             let mut results = Vec::new();
             
-            // Simple similarity search using LIKE
-            let keywords: Vec<&str> = prompt.split_whitespace().take(3).collect();
-            let like_conditions: Vec<String> = keywords.iter()
-                .map(|word| format!("(prompt LIKE '%{}%' OR response LIKE '%{}%')", word, word))
-                .collect();
+            if options.include_conversations {
+                let conversations = match &options.hybrid {
+                    None => Self::keyword_search(connection, &prompt, limit, filter)?,
+                    Some(hybrid) => {
+                        // Over-fetch from both rankers so the merge has candidates to reorder
+                        let candidates = limit * 3;
+                        let keyword_results = Self::keyword_search(connection, &prompt, candidates, filter)?;
+                        let vector_results = Self::vector_search(connection, &hybrid.embedding, candidates, filter)?;
+                        Self::merge_hybrid(keyword_results, vector_results, hybrid.keyword_weight, limit)
+                    }
+                };
+                results.extend(conversations);
+            }
             
-            let query = format!(
-                "SELECT id, timestamp, prompt, response, model_used, response_time_ms, file_context 
-                 FROM conversations 
-                 WHERE {} 
-                 ORDER BY timestamp DESC 
-                 LIMIT {}",
-                like_conditions.join(" OR "),
-                limit
-            );
+            for result in &mut results {
+                if result.entry.starred {
+                    result.score *= STARRED_BOOST;
+                }
+            }
             
-            let mut stmt = connection.prepare(&query)?;
-            let conversation_iter = stmt.query_map([], |row| {
-                let timestamp_str: String = row.get(1)?;
-                let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
-                    .map_err(|_| rusqlite::Error::InvalidColumnType(1, "timestamp".to_string(), rusqlite::types::Type::Text))?
-                    .with_timezone(&Local);
-                
-                let file_context: String = row.get(6)?;
+            if options.include_documents {
+                results.extend(Self::document_search(connection, &prompt, limit)?);
+            }
+            
+            results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+            results.truncate(limit);
+            
+            Ok(results)
+        }).await
+    }
+
+    fn keyword_search(
+        connection: &Connection,
+        prompt: &str,
+        limit: usize,
+        filter: &ConversationFilter,
+    ) -> Result<Vec<ScoredEntry>, AppError> {
+        let keywords = Self::weighted_keywords(connection, prompt)?;
+        if keywords.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        // Rank by the summed weight of matched keywords, newest first on ties
+        let mut score_terms = Vec::new();
+        let mut match_conditions = Vec::new();
+        let mut score_values = Vec::new();
+        let mut where_values = Vec::new();
+        
+        for (word, weight) in &keywords {
+            let pattern = like_pattern(word);
+            score_terms.push(format!("(CASE WHEN prompt LIKE ? ESCAPE '\\' OR response LIKE ? ESCAPE '\\' THEN {} ELSE 0 END)", weight));
+            score_values.push(Value::Text(pattern.clone()));
+            score_values.push(Value::Text(pattern.clone()));
+            
+            match_conditions.push("prompt LIKE ? ESCAPE '\\' OR response LIKE ? ESCAPE '\\'");
+            where_values.push(Value::Text(pattern.clone()));
+            where_values.push(Value::Text(pattern));
+        }
+        
+        // Failed or cancelled generations are never used as context, and a branch's copies
+        // would only repeat their originals
+        let mut conditions = vec![
+            format!("({})", match_conditions.join(" OR ")),
+            "status = 'ok'".to_string(),
+            "branched_from IS NULL".to_string(),
+        ];
+        
+        // Tag, model and date restrictions
+        let (filter_conditions, filter_values) = Self::filter_conditions(filter);
+        conditions.extend(filter_conditions);
+        where_values.extend(filter_values);
+        
+        let query = format!(
+            "SELECT {}, {} AS match_score
+             FROM conversations 
+             {} 
+             ORDER BY match_score DESC, timestamp DESC 
+             LIMIT {}",
+            CONVERSATION_COLUMNS,
+            score_terms.join(" + "),
+            Self::where_clause(&conditions),
+            limit
+        );
+        
+        let total_weight: f32 = keywords.iter().map(|(_, weight)| weight).sum();
+        let values = score_values.into_iter().chain(where_values);
+        
+        let mut stmt = connection.prepare(&query)?;
+        let results = stmt
+            .query_map(params_from_iter(values), |row| {
+                let match_score: f64 = row.get(15)?;
+                Ok(ScoredEntry {
+                    entry: Self::row_to_entry(row)?,
+                    score: match_score as f32 / total_weight,
+                    excluded: false,
+                    source: ContextSource::Conversation,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(results)
+    }
+
+    fn document_search(connection: &Connection, prompt: &str, limit: usize) -> Result<Vec<ScoredEntry>, AppError> {
+        let keywords = Self::weighted_keywords(connection, prompt)?;
+        if keywords.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        let mut score_terms = Vec::new();
+        let mut match_conditions = Vec::new();
+        let mut score_values = Vec::new();
+        let mut where_values = Vec::new();
+        
+        for (word, weight) in &keywords {
+            let pattern = like_pattern(word);
+            score_terms.push(format!("(CASE WHEN content LIKE ? ESCAPE '\\' OR name LIKE ? ESCAPE '\\' THEN {} ELSE 0 END)", weight));
+            score_values.push(Value::Text(pattern.clone()));
+            score_values.push(Value::Text(pattern.clone()));
+            
+            match_conditions.push("content LIKE ? ESCAPE '\\' OR name LIKE ? ESCAPE '\\'");
+            where_values.push(Value::Text(pattern.clone()));
+            where_values.push(Value::Text(pattern));
+        }
+        
+        let query = format!(
+            "SELECT id, name, content, added_at, revision, {} AS match_score
+             FROM documents
+             WHERE {}
+             ORDER BY match_score DESC, id
+             LIMIT {}",
+            score_terms.join(" + "),
+            match_conditions.join(" OR "),
+            limit
+        );
+        
+        let total_weight: f32 = keywords.iter().map(|(_, weight)| weight).sum();
+        let values = score_values.into_iter().chain(where_values);
+        
+        let mut stmt = connection.prepare(&query)?;
+        let results = stmt
+            .query_map(params_from_iter(values), |row| {
+                let name: String = row.get(1)?;
+                let added_at: String = row.get(3)?;
+                let match_score: f64 = row.get(5)?;
+                let timestamp = DateTime::parse_from_rfc3339(&added_at)
+                    .map(|timestamp| timestamp.with_timezone(&Local))
+                    .unwrap_or_else(|_| Local::now());
                 
-                Ok(ConversationEntry {
-                    id: row.get(0)?,
-                    timestamp,
-                    prompt: row.get(2)?,
-                    response: row.get(3)?,
-                    model_used: row.get(4)?,
-                    response_time_ms: row.get(5)?,
-                    file_context: if file_context.is_empty() { None } else { Some(file_context) },
+                Ok(ScoredEntry {
+                    entry: ConversationEntry {
+                        id: row.get(0)?,
+                        timestamp,
+                        prompt: name.clone(),
+                        response: row.get(2)?,
+                        model_used: String::new(),
+                        response_time_ms: 0,
+                        file_context: None,
+                        tags: Vec::new(),
+                        status: ConversationStatus::Ok,
+                        reasoning: None,
+                        first_token_ms: None,
+                        starred: false,
+                        feedback: 0,
+                        backend: None,
+                        source: None,
+                    },
+                    score: match_score as f32 / total_weight,
+                    excluded: false,
+                    source: ContextSource::Document { name, revision: row.get(4)? },
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(results)
+    }
+
+    fn vector_search(
+        connection: &Connection,
+        embedding: &[f32],
+        limit: usize,
+        filter: &ConversationFilter,
+    ) -> Result<Vec<ScoredEntry>, AppError> {
+        let (mut conditions, values) = Self::filter_conditions(filter);
+        conditions.push("status = 'ok'".to_string());
+        conditions.push("branched_from IS NULL".to_string());
+        
+        let query = format!(
+            "SELECT e.conversation_id, e.vector FROM conversation_embeddings e
+             JOIN conversations ON conversations.id = e.conversation_id
+             {}",
+            Self::where_clause(&conditions)
+        );
+        
+        let mut stmt = connection.prepare(&query)?;
+        let mut scored = stmt
+            .query_map(params_from_iter(values), |row| {
+                let id: i64 = row.get(0)?;
+                let bytes: Vec<u8> = row.get(1)?;
+                Ok((id, decode_vector(&bytes)))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(id, vector)| (id, cosine_similarity(embedding, &vector)))
+            .collect::<Vec<_>>();
+        
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scored.truncate(limit);
+        
+        let entries = Self::entries_by_id(connection, &scored.iter().map(|(id, _)| *id).collect::<Vec<_>>())?;
+        
+        Ok(scored
+            .into_iter()
+            .filter_map(|(id, score)| {
+                entries.iter().find(|entry| entry.id == id).map(|entry| ScoredEntry {
+                    entry: entry.clone(),
+                    score,
+                    excluded: false,
+                    source: ContextSource::Conversation,
                 })
-            })?;
+            })
+            .collect())
+    }
+
+    fn entries_by_id(connection: &Connection, ids: &[i64]) -> Result<Vec<ConversationEntry>, AppError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let query = format!(
+            "SELECT {} FROM conversations WHERE id IN ({})",
+            CONVERSATION_COLUMNS, placeholders
+        );
+        
+        let mut stmt = connection.prepare(&query)?;
+        let entries = stmt
+            .query_map(params_from_iter(ids), Self::row_to_entry)?
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(entries)
+    }
+
+    // Normalizes each ranking to 0..1 and blends them, keeping one result per conversation
+    fn merge_hybrid(
+        keyword_results: Vec<ScoredEntry>,
+        vector_results: Vec<ScoredEntry>,
+        keyword_weight: f32,
+        limit: usize,
+    ) -> Vec<ScoredEntry> {
+        let keyword_weight = keyword_weight.clamp(0.0, 1.0);
+        let max_score = |results: &[ScoredEntry]| {
+            results.iter().map(|result| result.score).fold(0.0_f32, f32::max)
+        };
+        let keyword_max = max_score(&keyword_results[..]);
+        let vector_max = max_score(&vector_results[..]);
+        
+        let mut merged: Vec<ScoredEntry> = Vec::new();
+        let weighted = keyword_results
+            .into_iter()
+            .map(|result| (result, keyword_weight, keyword_max))
+            .chain(vector_results.into_iter().map(|result| (result, 1.0 - keyword_weight, vector_max)));
+        
+        for (mut result, weight, max) in weighted {
+            let normalized = if max > 0.0 { result.score.max(0.0) / max } else { 0.0 };
+            let contribution = normalized * weight;
             
-            for conversation in conversation_iter {
-                results.push(conversation?);
+            match merged.iter_mut().find(|existing| {
+                existing.entry.id == result.entry.id && existing.source == result.source
+            }) {
+                Some(existing) => existing.score += contribution,
+                None => {
+                    result.score = contribution;
+                    merged.push(result);
+                }
             }
+        }
+        
+        merged.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        merged.truncate(limit);
+        merged
+    }
+
+    fn weighted_keywords(connection: &Connection, prompt: &str) -> Result<Vec<(String, f32)>, AppError> {
+        let total: i64 = connection.query_row("SELECT COUNT(*) FROM conversations WHERE branched_from IS NULL", [], |row| row.get(0))?;
+        let mut stmt = connection.prepare(
+            "SELECT COUNT(*) FROM conversations WHERE branched_from IS NULL AND (prompt LIKE ?1 ESCAPE '\\' OR response LIKE ?1 ESCAPE '\\')"
+        )?;
+        
+        // Rarer words get a higher weight, lookup failures count as common
+        let keywords = keywords::extract_keywords(prompt, total as usize, |word| {
+            stmt.query_row([like_pattern(word)], |row| row.get::<_, i64>(0))
+                .map(|count| count as usize)
+                .unwrap_or(total as usize)
+        }, MAX_KEYWORDS);
+        
+        Ok(keywords)
+    }
+
+    pub async fn list_conversations(
+        &self,
+        offset: usize,
+        limit: usize,
+        filter: &ConversationFilter,
+    ) -> Result<Vec<ConversationEntry>, AppError> {
+        let filter = filter.clone();
+        
+        self.db.read(move |connection| {
+            let (conditions, mut values) = Self::filter_conditions(&filter);
+            values.push(Value::Integer(limit as i64));
+            values.push(Value::Integer(offset as i64));
             
-            Ok(results)
-        }).await.map_err(|e| AppError(e.to_string()))??;
+            // Only one page is ever read from the table
+            let query = format!(
+                "SELECT {} FROM conversations {} ORDER BY timestamp DESC LIMIT ? OFFSET ?",
+                CONVERSATION_COLUMNS,
+                Self::where_clause(&conditions)
+            );
+            
+            let mut stmt = connection.prepare(&query)?;
+            let entries = stmt
+                .query_map(params_from_iter(values), Self::row_to_entry)?
+                .collect::<Result<Vec<_>, _>>()?;
+            
+            Ok(entries)
+        }).await
+    }
+
+    fn filter_conditions(filter: &ConversationFilter) -> (Vec<String>, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
         
-        Ok(results)
+        if let Some(model) = &filter.model {
+            conditions.push("model_used = ?".to_string());
+            values.push(Value::Text(model.clone()));
+        }
+        
+        // Rows keep the offset they were written with, so the strings only sort correctly
+        // within one offset. julianday() compares the instants.
+        if let Some(after) = filter.after {
+            conditions.push("julianday(timestamp) >= julianday(?)".to_string());
+            values.push(Value::Text(after.to_rfc3339()));
+        }
+        
+        if let Some(before) = filter.before {
+            conditions.push("julianday(timestamp) <= julianday(?)".to_string());
+            values.push(Value::Text(before.to_rfc3339()));
+        }
+        
+        if let Some(text) = &filter.text {
+            let pattern = like_pattern(text);
+            conditions.push("(prompt LIKE ? ESCAPE '\\' OR response LIKE ? ESCAPE '\\')".to_string());
+            values.push(Value::Text(pattern.clone()));
+            values.push(Value::Text(pattern));
+        }
+        
+        if let Some(tag) = &filter.tag {
+            conditions.push(TAG_CONDITION.to_string());
+            values.push(Value::Text(Self::normalize_tag(tag)));
+        }
+        
+        if filter.exclude_downvoted {
+            conditions.push("feedback >= 0".to_string());
+        }
+        
+        (conditions, values)
+    }
+
+    fn where_clause(conditions: &[String]) -> String {
+        if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        }
     }
 
-    pub fn create_rag_context(&self, suggestions: &[ConversationEntry], current_prompt: &str) -> String {
-        if suggestions.is_empty() {
+    fn row_to_entry(row: &Row) -> Result<ConversationEntry, rusqlite::Error> {
+        let timestamp_str: String = row.get(1)?;
+        let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(1, "timestamp".to_string(), rusqlite::types::Type::Text))?
+            .with_timezone(&Local);
+        
+        let file_context: Option<String> = row.get(6)?;
+        let tags: Option<String> = row.get(7)?;
+        let status: String = row.get(8)?;
+        
+        Ok(ConversationEntry {
+            id: row.get(0)?,
+            timestamp,
+            prompt: row.get(2)?,
+            response: row.get(3)?,
+            model_used: row.get(4)?,
+            response_time_ms: row.get(5)?,
+            file_context: file_context.filter(|context| !context.is_empty()),
+            tags: tags
+                .map(|tags| tags.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+            status: ConversationStatus::parse(&status),
+            reasoning: row.get(9)?,
+            first_token_ms: row.get(10)?,
+            starred: row.get(11)?,
+            feedback: row.get(12)?,
+            backend: row.get(13)?,
+            source: row.get(14)?,
+        })
+    }
+
+    pub async fn count_missing_embeddings(&self) -> Result<usize, AppError> {
+        self.db.read(|connection| {
+            let count: i64 = connection.query_row(
+                "SELECT COUNT(*) FROM conversations c
+                 LEFT JOIN conversation_embeddings e ON e.conversation_id = c.id
+                 WHERE e.conversation_id IS NULL AND c.status = 'ok' AND c.branched_from IS NULL",
+                [],
+                |row| row.get(0),
+            )?;
+            Ok(count as usize)
+        }).await
+    }
+
+    // Next conversations without an embedding, ordered by id so a run can page past failures
+    pub async fn conversations_missing_embeddings(
+        &self,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<(i64, String)>, AppError> {
+        self.db.read(move |connection| {
+            let mut stmt = connection.prepare_cached(
+                "SELECT c.id, c.prompt, c.response FROM conversations c
+                 LEFT JOIN conversation_embeddings e ON e.conversation_id = c.id
+                 WHERE e.conversation_id IS NULL AND c.status = 'ok' AND c.branched_from IS NULL AND c.id > ?1
+                 ORDER BY c.id
+                 LIMIT ?2",
+            )?;
+            let rows = stmt
+                .query_map(params![after_id, limit as i64], |row| {
+                    let prompt: String = row.get(1)?;
+                    let response: String = row.get(2)?;
+                    Ok((row.get(0)?, format!("{}\n\n{}", prompt, response)))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        }).await
+    }
+
+    // (conversation id, vector) pairs, written in one transaction
+    pub async fn store_embeddings(&self, model: &str, embeddings: Vec<(i64, Vec<f32>)>) -> Result<(), AppError> {
+        let model = model.to_string();
+        self.db.send(|reply| DbCommand::StoreEmbeddings { model, embeddings, reply }).await
+    }
+
+    pub async fn add_document(&self, name: &str, content: &str) -> Result<usize, AppError> {
+        self.add_documents(vec![(name.to_string(), content.to_string())], None).await
+    }
+
+    // Adds (name, content) documents in one transaction, all tagged with `revision` if given
+    pub async fn add_documents(&self, documents: Vec<(String, String)>, revision: Option<String>) -> Result<usize, AppError> {
+        let documents: Vec<(String, Vec<String>)> = documents
+            .into_iter()
+            .map(|(name, content)| (name, chunk_text(&content, DOCUMENT_CHUNK_CHARS)))
+            .collect();
+        
+        self.db.send(|reply| DbCommand::AddDocuments { documents, revision, reply }).await
+    }
+
+    pub async fn remove_document(&self, name: &str) -> Result<(), AppError> {
+        let name = name.to_string();
+        self.db.send(|reply| DbCommand::RemoveDocument { name, reply }).await
+    }
+
+    // Most recent prompts, oldest first
+    pub async fn recent_prompts(&self, limit: usize) -> Result<Vec<String>, AppError> {
+        self.db.read(move |connection| {
+            let mut stmt = connection.prepare_cached("SELECT prompt FROM conversations ORDER BY id DESC LIMIT ?1")?;
+            let mut prompts = stmt
+                .query_map([limit as i64], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            prompts.reverse();
+            Ok(prompts)
+        }).await
+    }
+
+    pub async fn list_documents(&self) -> Result<Vec<String>, AppError> {
+        self.db.read(|connection| {
+            let mut stmt = connection.prepare("SELECT DISTINCT name FROM documents ORDER BY name")?;
+            let names = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(names)
+        }).await
+    }
+
+    pub fn normalize_tag(tag: &str) -> String {
+        // Commas are the GROUP_CONCAT separator, so they can't appear inside a tag
+        tag.trim().replace(',', " ").to_lowercase()
+    }
+
+    pub async fn add_tag(&self, conversation_id: i64, tag: &str) -> Result<(), AppError> {
+        let tag = Self::normalize_tag(tag);
+        if tag.is_empty() {
+            return Ok(());
+        }
+        
+        self.db.send(|reply| DbCommand::AddTag { conversation_id, tag, reply }).await
+    }
+
+    pub async fn remove_tag(&self, conversation_id: i64, tag: &str) -> Result<(), AppError> {
+        let tag = Self::normalize_tag(tag);
+        
+        self.db.send(|reply| DbCommand::RemoveTag { conversation_id, tag, reply }).await
+    }
+
+    pub async fn list_tags(&self) -> Result<Vec<String>, AppError> {
+        self.db.read(|connection| {
+            let mut stmt = connection.prepare("SELECT name FROM tags ORDER BY name")?;
+            let tags = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(tags)
+        }).await
+    }
+
+    pub fn create_rag_context(&self, suggestions: &[ScoredEntry], current_prompt: &str) -> String {
+        let included: Vec<&ScoredEntry> = suggestions
+            .iter()
+            .filter(|suggestion| !suggestion.excluded)
+            .collect();
+        
+        if included.is_empty() {
             return current_prompt.to_string();
         }
 
-        let context = suggestions
+        let context = included
             .iter()
             .take(2)
-            .map(|entry| format!("Previous context:\nQ: {}\nA: {}\n", entry.prompt, entry.response))
+            .map(|suggestion| match &suggestion.source {
+                ContextSource::Conversation => format!(
+                    "From a previous conversation:\nQ: {}\nA: {}\n",
+                    suggestion.entry.prompt, suggestion.entry.response
+                ),
+                ContextSource::Document { name, revision } => format!(
+                    "From your documents ({}):\n{}\n",
+                    document_label(name, revision.as_deref()), suggestion.entry.response
+                ),
+            })
             .collect::<Vec<_>>()
             .join("\n");
         
         format!("{}\n\nCurrent question: {}", context, current_prompt)
     }
-}
\ No newline at end of file
+}
+
+// `text` anywhere in a `LIKE ? ESCAPE '\'` match, its own % and _ taken literally
+fn like_pattern(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+// A document's name, with the branch and commit for documents read from a git repository
+pub fn document_label(name: &str, revision: Option<&str>) -> String {
+    match revision {
+        Some(revision) => format!("{} at {}", name, revision),
+        None => name.to_string(),
+    }
+}
+
+// Splits on paragraph boundaries into chunks of roughly `max_chars` characters
+fn chunk_text(content: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    
+    for paragraph in content.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.chars().count() + paragraph.chars().count() > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        
+        if paragraph.chars().count() > max_chars {
+            let chars: Vec<char> = paragraph.chars().collect();
+            for piece in chars.chunks(max_chars) {
+                chunks.push(piece.iter().collect());
+            }
+            continue;
+        }
+        
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+    
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    
+    chunks
+}
+
+fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
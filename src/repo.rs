@@ -0,0 +1,89 @@
+// repo.rs
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use ignore::WalkBuilder;
+use crate::file_handler::FileHandler;
+use crate::models::{AppError, RepoFile, RepoListing};
+
+// Walks stop here so a monorepo doesn't freeze the picker
+const MAX_FILES: usize = 5000;
+// Committed but rarely worth the tokens, so they start unticked
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock", "package-lock.json", "yarn.lock", "pnpm-lock.yaml", "poetry.lock",
+    "Pipfile.lock", "Gemfile.lock", "composer.lock", "go.sum", "uv.lock",
+];
+
+// Files in the repository that .gitignore, .ignore and git's exclude file don't rule out,
+// with their sizes. Nothing is read yet. Lockfiles and files over `max_text_bytes` start unticked.
+pub fn list(root: &Path, max_text_bytes: u64) -> Result<RepoListing, AppError> {
+    if !root.join(".git").exists() {
+        return Err(AppError::Other(format!("{} is not a git repository", root.display())));
+    }
+    let name = root.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+
+    let mut files = Vec::new();
+    for entry in WalkBuilder::new(root).require_git(true).build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Skipping part of {}: {}", root.display(), e);
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+            continue;
+        }
+        if files.len() >= MAX_FILES {
+            return Err(AppError::Other(format!("{} has more than {} files, attach a subdirectory instead", name, MAX_FILES)));
+        }
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        let file_name = entry.file_name().to_string_lossy();
+        files.push(RepoFile {
+            path: relative.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"),
+            size,
+            selected: size <= max_text_bytes && !LOCKFILES.contains(&file_name.as_ref()),
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(RepoListing {
+        root: root.to_path_buf(),
+        name,
+        branch: git(root, &["rev-parse", "--abbrev-ref", "HEAD"]),
+        head: git(root, &["rev-parse", "--short", "HEAD"]),
+        files,
+    })
+}
+
+// Reads the ticked files as (repo-relative path, text). Binary and unreadable files come
+// back as errors alongside the rest.
+pub fn read_selected(listing: &RepoListing, max_text_bytes: u64) -> Vec<Result<(String, String), AppError>> {
+    listing.files
+        .iter()
+        .filter(|file| file.selected)
+        .map(|file| {
+            let path: PathBuf = listing.root.join(&file.path);
+            let loaded = FileHandler::load_text_file(&path, max_text_bytes, None)
+                .map_err(|e| AppError::Other(format!("{}: {}", file.path, e)))?;
+            Ok((file.path.clone(), loaded.content))
+        })
+        .collect()
+}
+
+// Same chars / 4 heuristic as tokens::approximate, from the size on disk
+pub fn estimated_tokens(bytes: u64) -> u64 {
+    bytes / 4
+}
+
+// Trimmed stdout of a git command, None if git is missing or the command fails
+fn git(root: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).current_dir(root).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
@@ -0,0 +1,131 @@
+// scheduler.rs
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    // Someone is waiting on the answer
+    Interactive,
+    // Titles, follow-ups and embeddings, only started while no interactive request waits
+    Background,
+}
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    waiting_interactive: usize,
+    waiting_background: usize,
+    last_start: Option<Instant>,
+}
+
+// Limits how hard one client leans on a shared server: at most `max_concurrent` requests at
+// once, each started at least `min_interval` after the one before
+pub struct Scheduler {
+    state: Mutex<State>,
+    changed: Notify,
+    max_concurrent: usize,
+    min_interval: Duration,
+}
+
+// Holds a slot until dropped
+pub struct Permit {
+    scheduler: Arc<Scheduler>,
+}
+
+// Counts a request as queued for as long as it waits, including when the wait is cancelled
+struct Waiting<'a> {
+    scheduler: &'a Scheduler,
+    priority: Priority,
+}
+
+impl Scheduler {
+    pub fn new(max_concurrent: usize, min_interval: Duration) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            changed: Notify::new(),
+            max_concurrent: max_concurrent.max(1),
+            min_interval,
+        }
+    }
+
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Permit {
+        let _waiting = Waiting::new(self, priority);
+        loop {
+            // Registered before checking, so a slot freed in between still wakes us
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let delay = {
+                let mut state = self.lock();
+                let blocked = state.running >= self.max_concurrent
+                    || (priority == Priority::Background && state.waiting_interactive > 0);
+                if blocked {
+                    None
+                } else {
+                    let now = Instant::now();
+                    let ready = state.last_start.map_or(now, |last| last + self.min_interval);
+                    if ready <= now {
+                        state.running += 1;
+                        state.last_start = Some(now);
+                        return Permit { scheduler: self.clone() };
+                    }
+                    Some(ready - now)
+                }
+            };
+
+            match delay {
+                Some(delay) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = &mut changed => {}
+                    }
+                }
+                None => changed.await,
+            }
+        }
+    }
+
+    // Requests waiting for a slot
+    pub fn queue_depth(&self) -> usize {
+        let state = self.lock();
+        state.waiting_interactive + state.waiting_background
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.lock().running -= 1;
+        self.scheduler.changed.notify_waiters();
+    }
+}
+
+impl<'a> Waiting<'a> {
+    fn new(scheduler: &'a Scheduler, priority: Priority) -> Self {
+        let mut state = scheduler.lock();
+        match priority {
+            Priority::Interactive => state.waiting_interactive += 1,
+            Priority::Background => state.waiting_background += 1,
+        }
+        drop(state);
+        Self { scheduler, priority }
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.lock();
+        match self.priority {
+            Priority::Interactive => state.waiting_interactive -= 1,
+            Priority::Background => state.waiting_background -= 1,
+        }
+        drop(state);
+        // Background requests held back by this one may go now
+        self.scheduler.changed.notify_waiters();
+    }
+}
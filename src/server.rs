@@ -0,0 +1,224 @@
+// server.rs
+use async_trait::async_trait;
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use crate::analytics::AnalyticsEngine;
+use crate::models::{Analytics, AppError, ConversationEntry, ConversationFilter};
+use crate::rag::RagSystem;
+
+const DEFAULT_CONVERSATION_LIMIT: usize = 20;
+const MAX_CONVERSATION_LIMIT: usize = 200;
+
+#[derive(Deserialize)]
+pub struct AskRequest {
+    pub prompt: String,
+    // The model selected in the app when left out
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default = "default_use_rag")]
+    pub use_rag: bool,
+}
+
+fn default_use_rag() -> bool {
+    true
+}
+
+#[derive(Serialize)]
+pub struct AskResponse {
+    pub response: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    pub model: String,
+    pub response_time_ms: i64,
+    // None if the database is unavailable
+    pub conversation_id: Option<i64>,
+}
+
+// Answers /api/ask. The app supplies it so API prompts go through the same pipeline,
+// and end up in the same history, as prompts typed into the chat.
+#[async_trait]
+pub trait AskHandler: Send + Sync {
+    async fn ask(&self, request: AskRequest) -> Result<AskResponse, AppError>;
+}
+
+#[derive(Deserialize)]
+struct ConversationQuery {
+    query: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ConversationSummary {
+    id: i64,
+    timestamp: DateTime<Local>,
+    prompt: String,
+    response: String,
+    model: String,
+    response_time_ms: i64,
+    tags: Vec<String>,
+}
+
+impl From<ConversationEntry> for ConversationSummary {
+    fn from(entry: ConversationEntry) -> Self {
+        Self {
+            id: entry.id,
+            timestamp: entry.timestamp,
+            prompt: entry.prompt,
+            response: entry.response,
+            model: entry.model_used,
+            response_time_ms: entry.response_time_ms,
+            tags: entry.tags,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    handler: Arc<dyn AskHandler>,
+    rag: Option<RagSystem>,
+    analytics: Option<AnalyticsEngine>,
+    token: Option<String>,
+}
+
+// Failures as a status code and {"error": "..."}
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<AppError> for ApiError {
+    fn from(error: AppError) -> Self {
+        let status = match &error {
+            AppError::ModelNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Connection(_) | AppError::Http { .. } | AppError::Parse(_) => StatusCode::BAD_GATEWAY,
+            AppError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, error.to_string())
+    }
+}
+
+// Stops listening when dropped
+pub struct ApiServer {
+    pub address: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+// Binds `bind` and serves in the background until the returned server is dropped. Without a
+// token only loopback addresses are allowed, anything else would be open to the network.
+// `stopped_with` gets the error if serving fails after startup.
+pub async fn start(
+    bind: &str,
+    token: &str,
+    handler: Arc<dyn AskHandler>,
+    rag: Option<RagSystem>,
+    analytics: Option<AnalyticsEngine>,
+    stopped_with: impl FnOnce(AppError) + Send + 'static,
+) -> Result<ApiServer, AppError> {
+    let address: SocketAddr = bind
+        .trim()
+        .parse()
+        .map_err(|e| AppError::Other(format!("Invalid address {}: {}", bind, e)))?;
+    let token = Some(token.trim().to_string()).filter(|token| !token.is_empty());
+    if token.is_none() && !address.ip().is_loopback() {
+        return Err(AppError::Other(format!("Set a token before serving on {}", address)));
+    }
+
+    let listener = TcpListener::bind(address).await?;
+    let address = listener.local_addr()?;
+    let state = ApiState { handler, rag, analytics, token };
+    let router = Router::new()
+        .route("/api/ask", post(ask))
+        .route("/api/conversations", get(conversations))
+        .route("/api/analytics", get(analytics_summary))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state);
+
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let server = axum::serve(listener, router).with_graceful_shutdown(async {
+            let _ = stopped.await;
+        });
+        if let Err(e) = server.await {
+            stopped_with(e.into());
+        }
+    });
+
+    Ok(ApiServer { address, shutdown: Some(shutdown) })
+}
+
+// Runs before any extractor, so an unauthorized request never has its body read or parsed
+async fn require_token(State(state): State<ApiState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    authorize(&state, request.headers())?;
+    Ok(next.run(request).await)
+}
+
+fn authorize(state: &ApiState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(token) = &state.token else {
+        return Ok(());
+    };
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Compared in constant time so response timing doesn't give the token away byte by byte
+    let matches = given.is_some_and(|given| bool::from(given.trim().as_bytes().ct_eq(token.as_bytes())));
+    if matches {
+        Ok(())
+    } else {
+        Err(ApiError(StatusCode::UNAUTHORIZED, "Missing or wrong bearer token".to_string()))
+    }
+}
+
+async fn ask(State(state): State<ApiState>, Json(request): Json<AskRequest>) -> Result<Json<AskResponse>, ApiError> {
+    if request.prompt.trim().is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "The prompt is empty".to_string()));
+    }
+    Ok(Json(state.handler.ask(request).await?))
+}
+
+// Newest first, `query` matches prompt or response text like the history search
+async fn conversations(
+    State(state): State<ApiState>,
+    Query(query): Query<ConversationQuery>,
+) -> Result<Json<Vec<ConversationSummary>>, ApiError> {
+    let rag = state.rag.as_ref().ok_or_else(database_unavailable)?;
+    let filter = ConversationFilter {
+        text: query.query.filter(|text| !text.trim().is_empty()),
+        ..Default::default()
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_CONVERSATION_LIMIT).min(MAX_CONVERSATION_LIMIT);
+    let entries = rag.list_conversations(0, limit, &filter).await?;
+    Ok(Json(entries.into_iter().map(ConversationSummary::from).collect()))
+}
+
+async fn analytics_summary(State(state): State<ApiState>) -> Result<Json<Analytics>, ApiError> {
+    let analytics = state.analytics.as_ref().ok_or_else(database_unavailable)?;
+    Ok(Json(analytics.get_analytics().await?))
+}
+
+fn database_unavailable() -> ApiError {
+    ApiError(StatusCode::SERVICE_UNAVAILABLE, "Database unavailable".to_string())
+}
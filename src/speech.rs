@@ -0,0 +1,158 @@
+// speech.rs
+use regex::Regex;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+use tts::Tts;
+use crate::models::AppError;
+
+// How often the speech thread checks whether playback has ended on its own
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Debug)]
+pub struct VoiceInfo {
+    pub id: String,
+    pub name: String,
+}
+
+enum Command {
+    Speak { key: i64, text: String, voice: String, rate: f32 },
+    Stop,
+}
+
+// Plays text with the platform's voices on a thread of its own, one utterance at a time.
+// Starting a new one stops the old one, dropping the speaker stops playback.
+pub struct Speaker {
+    commands: Sender<Command>,
+    playing: Arc<Mutex<Option<i64>>>,
+    voices: Vec<VoiceInfo>,
+}
+
+impl Speaker {
+    // `on_finished` runs on the speech thread whenever playback ends without being asked to
+    pub fn start(on_finished: impl Fn() + Send + 'static) -> Result<Self, AppError> {
+        let (commands, received) = mpsc::channel::<Command>();
+        let (ready, started) = mpsc::channel::<Result<Vec<VoiceInfo>, AppError>>();
+        let playing = Arc::new(Mutex::new(None));
+        let thread_playing = playing.clone();
+
+        std::thread::spawn(move || {
+            let mut tts = match Tts::default() {
+                Ok(tts) => tts,
+                Err(e) => {
+                    let _ = ready.send(Err(AppError::Other(format!("Text-to-speech unavailable: {}", e))));
+                    return;
+                }
+            };
+            let voices = tts.voices().unwrap_or_default();
+            let _ = ready.send(Ok(voices.iter().map(|voice| VoiceInfo { id: voice.id(), name: voice.name() }).collect()));
+            let can_poll = tts.supported_features().is_speaking;
+
+            loop {
+                match received.recv_timeout(POLL_INTERVAL) {
+                    Ok(Command::Speak { key, text, voice, rate }) => {
+                        let _ = tts.stop();
+                        if let Some(voice) = voices.iter().find(|candidate| candidate.id() == voice) {
+                            if let Err(e) = tts.set_voice(voice) {
+                                eprintln!("Could not set voice: {}", e);
+                            }
+                        }
+                        let rate = (tts.normal_rate() * rate).clamp(tts.min_rate(), tts.max_rate());
+                        if let Err(e) = tts.set_rate(rate) {
+                            eprintln!("Could not set speech rate: {}", e);
+                        }
+                        let spoken = tts.speak(text, true);
+                        if let Err(e) = &spoken {
+                            eprintln!("Speech failed: {}", e);
+                        }
+                        *thread_playing.lock().unwrap_or_else(PoisonError::into_inner) = spoken.is_ok().then_some(key);
+                    }
+                    Ok(Command::Stop) => {
+                        let _ = tts.stop();
+                        *thread_playing.lock().unwrap_or_else(PoisonError::into_inner) = None;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        // Without is_speaking the button stays on stop until it is pressed
+                        if !can_poll || tts.is_speaking().unwrap_or(true) {
+                            continue;
+                        }
+                        let finished = thread_playing.lock().unwrap_or_else(PoisonError::into_inner).take().is_some();
+                        if finished {
+                            on_finished();
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        let _ = tts.stop();
+                        return;
+                    }
+                }
+            }
+        });
+
+        let voices = started
+            .recv()
+            .map_err(|_| AppError::Other("Text-to-speech thread stopped".to_string()))??;
+        Ok(Self { commands, playing, voices })
+    }
+
+    // `voice` is a VoiceInfo id, or empty for the system default. `rate` scales the normal speed.
+    pub fn speak(&self, key: i64, text: &str, voice: &str, rate: f32) {
+        // Set right away so the button flips before the thread gets to it
+        *self.playing.lock().unwrap_or_else(PoisonError::into_inner) = Some(key);
+        let _ = self.commands.send(Command::Speak {
+            key,
+            text: speakable(text),
+            voice: voice.to_string(),
+            rate,
+        });
+    }
+
+    pub fn stop(&self) {
+        *self.playing.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        let _ = self.commands.send(Command::Stop);
+    }
+
+    // Key of the message being read out
+    pub fn playing(&self) -> Option<i64> {
+        *self.playing.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn voices(&self) -> &[VoiceInfo] {
+        &self.voices
+    }
+}
+
+impl Drop for Speaker {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Stop);
+    }
+}
+
+// Markdown reduced to the words a listener wants: code blocks are skipped with a short
+// note, links keep their text, and formatting characters are dropped
+pub fn speakable(text: &str) -> String {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            (r"(?s)```.*?(```|$)", " (code block omitted) "),
+            (r"!\[[^\]]*\]\([^)]*\)", ""),
+            (r"\[([^\]]*)\]\([^)]*\)", "$1"),
+            (r"`([^`]*)`", "$1"),
+            (r"(?m)^\s{0,3}#{1,6}\s*", ""),
+            (r"(?m)^\s*>\s?", ""),
+            (r"(?m)^\s*([-*+]|\d+[.)])\s+", ""),
+            (r"(?m)^\s*\|?[\s:|-]*-{3,}[\s:|-]*$", ""),
+            (r"\|", " "),
+            (r"(\*{1,3}|_{2,3}|~~)", ""),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| (Regex::new(pattern).expect("valid pattern"), replacement))
+        .collect()
+    });
+
+    let mut text = text.to_string();
+    for (pattern, replacement) in patterns {
+        text = pattern.replace_all(&text, *replacement).into_owned();
+    }
+    text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n")
+}
@@ -0,0 +1,58 @@
+// templates.rs
+use crate::models::{AppError, PromptTemplate};
+use crate::db::Database;
+use crate::writer::DbCommand;
+
+// Saved prompts with {{placeholder}} slots
+#[derive(Clone)]
+pub struct TemplateLibrary {
+    db: Database,
+}
+
+impl TemplateLibrary {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub async fn list(&self) -> Result<Vec<PromptTemplate>, AppError> {
+        self.db.read(|connection| {
+            let mut stmt = connection.prepare("SELECT id, name, body FROM prompt_templates ORDER BY name")?;
+            let templates = stmt
+                .query_map([], |row| {
+                    Ok(PromptTemplate {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        body: row.get(2)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(templates)
+        }).await
+    }
+
+    // Inserts when the template has no id yet, otherwise updates it in place
+    pub async fn save(&self, template: PromptTemplate) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::SaveTemplate { template, reply }).await
+    }
+
+    pub async fn delete(&self, id: i64) -> Result<(), AppError> {
+        self.db.send(|reply| DbCommand::DeleteTemplate { id, reply }).await
+    }
+
+    // Fills known placeholders and returns the text plus the char range of the first one left
+    pub fn expand(body: &str, input: &str, attachment: Option<&str>) -> (String, Option<(usize, usize)>) {
+        let mut text = body.to_string();
+        if !input.trim().is_empty() {
+            text = text.replace("{{input}}", input.trim());
+        }
+        if let Some(attachment) = attachment {
+            text = text.replace("{{file}}", attachment);
+        }
+        
+        let placeholder = text.find("{{").and_then(|start| {
+            let end = start + text[start..].find("}}")? + 2;
+            Some((text[..start].chars().count(), text[..end].chars().count()))
+        });
+        (text, placeholder)
+    }
+}
@@ -0,0 +1,64 @@
+// text.rs
+
+// The first `max_chars` characters of `text`. Byte slicing at a fixed index panics when
+// it lands inside a multi-byte character, so the cut is made on a char boundary.
+pub fn truncate_chars(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+// Like `truncate_chars`, with an ellipsis when anything was cut
+pub fn preview(text: &str, max_chars: usize) -> String {
+    let cut = truncate_chars(text, max_chars);
+    if cut.len() < text.len() {
+        format!("{}…", cut)
+    } else {
+        cut.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emoji_are_never_split() {
+        let text = "ok 👍🏽 done";
+        assert_eq!(truncate_chars(text, 4), "ok 👍");
+        assert_eq!(truncate_chars(text, 5), "ok 👍🏽");
+        assert_eq!(preview("🦀🦀🦀", 2), "🦀🦀…");
+    }
+
+    #[test]
+    fn combining_marks_count_as_their_own_chars() {
+        // "é" written as e + U+0301
+        let text = "cafe\u{301} au lait";
+        assert_eq!(truncate_chars(text, 4), "cafe");
+        assert_eq!(truncate_chars(text, 5), "cafe\u{301}");
+        assert_eq!(preview(text, 5), "cafe\u{301}…");
+    }
+
+    #[test]
+    fn cjk_is_cut_by_character_not_byte() {
+        let text = "日本語のテキスト";
+        assert_eq!(truncate_chars(text, 3), "日本語");
+        assert_eq!(preview(text, 3), "日本語…");
+    }
+
+    #[test]
+    fn zero_keeps_nothing() {
+        assert_eq!(truncate_chars("hello", 0), "");
+        assert_eq!(preview("hello", 0), "…");
+        assert_eq!(preview("", 0), "");
+    }
+
+    #[test]
+    fn exact_length_is_left_alone() {
+        assert_eq!(truncate_chars("héllo", 5), "héllo");
+        assert_eq!(preview("héllo", 5), "héllo");
+        assert_eq!(preview("héllo", 4), "héll…");
+        assert_eq!(preview("日本", 10), "日本");
+    }
+}
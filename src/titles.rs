@@ -0,0 +1,83 @@
+// titles.rs
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use crate::models::{AppError, ParsedResponse};
+use crate::backend::LlmBackend;
+use crate::rag::RagSystem;
+use crate::text::truncate_chars;
+
+const TITLE_NUM_PREDICT: u32 = 24;
+const TITLE_MAX_CHARS: usize = 60;
+// Only the start of the exchange is sent, a title doesn't need more
+const EXCERPT_CHARS: usize = 1000;
+const BUSY_POLL: Duration = Duration::from_millis(500);
+
+// Names a session after its first exchange. Runs only while no interactive request is
+// in flight and gives way as soon as one starts, retrying once it's finished.
+pub struct SessionTitler {
+    pub rag: RagSystem,
+    pub client: Arc<dyn LlmBackend>,
+    pub model: String,
+    pub session_id: String,
+    pub prompt: String,
+    pub answer: String,
+    pub interactive_busy: Arc<AtomicBool>,
+}
+
+impl SessionTitler {
+    // Returns the stored title, or None when the session was named some other way meanwhile
+    pub async fn run(self) -> Result<Option<String>, AppError> {
+        let prompt = format!(
+            "Summarize this exchange as a 4–6 word title. Reply with the title only.\n\nUser: {}\n\nAssistant: {}",
+            excerpt(&self.prompt),
+            excerpt(&self.answer),
+        );
+
+        let text = loop {
+            while self.interactive_busy.load(Ordering::Relaxed) {
+                tokio::time::sleep(BUSY_POLL).await;
+            }
+
+            // Dropping the request when the user sends something lets the server move on
+            tokio::select! {
+                result = self.client.generate_response(&self.model, &prompt, &[], Some(TITLE_NUM_PREDICT)) => break result?,
+                _ = wait_until_busy(&self.interactive_busy) => continue,
+            }
+        };
+
+        let Some(title) = clean_title(&ParsedResponse::parse(&text).answer) else {
+            return Ok(None);
+        };
+        if self.rag.session_title(self.session_id.clone()).await?.is_some() {
+            return Ok(None);
+        }
+        self.rag.rename_session(self.session_id, title.clone()).await?;
+        Ok(Some(title))
+    }
+}
+
+async fn wait_until_busy(busy: &AtomicBool) {
+    while !busy.load(Ordering::Relaxed) {
+        tokio::time::sleep(BUSY_POLL).await;
+    }
+}
+
+fn excerpt(text: &str) -> String {
+    truncate_chars(text, EXCERPT_CHARS).to_string()
+}
+
+// Models like to wrap titles in quotes or prefix them, keep just the words
+fn clean_title(text: &str) -> Option<String> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line.strip_prefix("Title:").unwrap_or(line);
+    let title: String = line
+        .trim()
+        .trim_matches(|c: char| c == '"' || c == '\'' || c == '*' || c == '#')
+        .trim_end_matches('.')
+        .trim()
+        .chars()
+        .take(TITLE_MAX_CHARS)
+        .collect();
+    (!title.is_empty()).then_some(title)
+}
@@ -0,0 +1,38 @@
+// tokens.rs
+use std::sync::{Arc, OnceLock};
+use tiktoken_rs::CoreBPE;
+
+// Counts tokens with the cl100k BPE vocabulary bundled in tiktoken-rs. Local models ship
+// their own tokenizers, so this is a close estimate rather than an exact per-model count.
+#[derive(Clone)]
+pub struct TokenCounter {
+    bpe: Option<Arc<CoreBPE>>,
+}
+
+static SHARED_BPE: OnceLock<Option<Arc<CoreBPE>>> = OnceLock::new();
+
+impl TokenCounter {
+    // Loading the vocabulary is expensive, so every counter shares one instance
+    pub fn shared() -> Self {
+        let bpe = SHARED_BPE.get_or_init(|| match tiktoken_rs::cl100k_base() {
+            Ok(bpe) => Some(Arc::new(bpe)),
+            Err(e) => {
+                eprintln!("Failed to load tokenizer, falling back to estimates: {}", e);
+                None
+            }
+        });
+        Self { bpe: bpe.clone() }
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match &self.bpe {
+            Some(bpe) => bpe.encode_with_special_tokens(text).len(),
+            None => approximate(text),
+        }
+    }
+}
+
+// The old chars / 4 heuristic, still used for rows saved before token counts were stored
+pub fn approximate(text: &str) -> usize {
+    text.chars().count() / 4
+}
@@ -1,65 +1,418 @@
 use eframe::egui;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{Notify, RwLock};
 use chrono::Local;
+use egui_plot::{Bar, BarChart, Legend, Line, Plot, PlotPoints, Points};
 
-use crate::models::{ConversationEntry, Analytics, PendingOperation};
-use crate::ollama::OllamaClient;
-use crate::rag::RagSystem;
+use crate::models::{
+    AppError, Attachment, ChatMessage, ChatTurn, OutboxItem, RunningModel, CommandRun, Comparison, ComparedResponse, ComparisonRecord, ComparisonSide, ExportFormat, ExportSettings, Severity, UiError, AttachmentKind, PromptTemplate, ConversationEntry, ConversationFilter, ErrorRecord, Generation, ParsedResponse, ConversationStatus, ContextSource, ScoredEntry, Analytics, Truncation, TextDump, AttachmentOrigin, LoadOptions, ArchiveListing, RepoFile, RepoListing, SessionSnapshot, BackendKind,
+    Debouncer, ResponsePayload, SessionSummary, DailyUsage, LatencyCorrelation, IndexProgress, HybridQuery, RetrievalOptions, PendingOperation,
+};
+use crate::backend::LlmBackend;
+use crate::ollama::{self, OllamaClient};
+use crate::openai::OpenAiCompatBackend;
+use crate::rag::{self, RagSystem};
 use crate::analytics::AnalyticsEngine;
+use crate::templates::TemplateLibrary;
 use crate::file_handler::FileHandler;
+use crate::archive;
+use crate::repo;
+use crate::text;
+use crate::export::{self, ExportOptions};
+use crate::import;
+use crate::obsidian;
+use crate::server::{self, ApiServer};
+use crate::webhook::{Webhook, WebhookPayload};
+use crate::history;
+use crate::pipeline::{self, ApiBridge, ApiSettings, GenerationJob, OpSender, notify_webhook, run_plugins, save_in_session};
+use crate::notifier;
+#[cfg(feature = "speech")]
+use crate::speech::Speaker;
+#[cfg(feature = "voice")]
+use crate::voice::{self, Recorder};
+use crate::indexer::EmbeddingBackfill;
+use crate::titles::SessionTitler;
+use crate::followups::FollowUpSuggester;
+use crate::plugins::{self, CommandQueue, CommandRequest, ModelAccess, Plugin, PluginContext, PluginManager, PluginRun, RegexRule, RuleSet, SettingKind, Stage};
+use crate::diff::{self, DiffKind};
+use crate::tokens::TokenCounter;
+use crate::config::{self, AppConfig, WindowGeometry, MIN_ZOOM, MAX_ZOOM};
 
-#[derive(Clone)]
-pub struct ChatMessage {
-    pub content: String,
-    pub is_user: bool,
-    pub timestamp: chrono::DateTime<Local>,
-    pub model_used: Option<String>,
-    pub response_time: Option<i64>,
+const HISTORY_PAGE_SIZE: usize = 20;
+const USAGE_RANGES: [u32; 3] = [7, 30, 90];
+const TOPIC_RANGES: [Option<u32>; 4] = [Some(7), Some(30), Some(90), None];
+const TOP_KEYWORD_COUNT: usize = 15;
+const STARRED_LIST_SIZE: usize = 50;
+const SESSION_LIST_SIZE: usize = 30;
+const ZOOM_STEP: f32 = 0.1;
+const CHAT_INPUT_ID: &str = "chat_input";
+const PROMPT_HISTORY_SIZE: usize = 100;
+// Chat messages plugins get to see before each request
+const PLUGIN_HISTORY_SIZE: usize = 20;
+// Pasted text longer than this is offered as an attachment instead
+const LONG_PASTE_LINES: usize = 50;
+const COPIED_TOAST_ID: &str = "copied_toast";
+const COPIED_TOAST_SECONDS: f64 = 1.5;
+// Messages this far outside the visible area are still laid out
+const RENDER_MARGIN: f32 = 400.0;
+const WARNING_LIFETIME_SECONDS: i64 = 10;
+// Code blocks longer than this start folded
+const CODE_FOLD_LINES: usize = 15;
+const FADE_HEIGHT: f32 = 32.0;
+const QUOTE_CHARS: usize = 300;
+// How close to the end still counts as being at the bottom of the chat
+const BOTTOM_SLACK: f32 = 24.0;
+const UNDO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+const DRAFT_SAVE_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+const RAG_DEBOUNCE_DELAY: std::time::Duration = std::time::Duration::from_millis(600);
+const PROMPT_PREVIEW_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+const CONFIG_SAVE_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+const ANALYTICS_REFRESH_DELAY: std::time::Duration = std::time::Duration::from_millis(1500);
+const RUNNING_MODELS_POLL: std::time::Duration = std::time::Duration::from_secs(10);
+const HEALTH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+// The elapsed time under a pending response is the only thing that changes without an event
+const ELAPSED_REFRESH: std::time::Duration = std::time::Duration::from_millis(250);
+// Closing waits this long for answers to be saved before giving up on them
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+const SHUTDOWN_OVERLAY_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+
+// Something removed that can still be brought back with Ctrl+Z
+enum UndoItem {
+    // The cleared messages and the session they belonged to
+    Chat(Vec<ChatMessage>, String),
+    Attachment(usize, Arc<Attachment>),
+    // Deleted messages with their former positions, their rows are removed once this expires
+    Messages(Vec<(usize, ChatMessage)>),
+}
+
+impl UndoItem {
+    fn conversation_ids(&self) -> Vec<i64> {
+        match self {
+            UndoItem::Messages(messages) => messages.iter()
+                .flat_map(|(_, message)| {
+                    let right = message.comparison.as_ref().and_then(|comparison| comparison.right.conversation_id);
+                    message.conversation_id.into_iter().chain(right)
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScrollJump {
+    Top,
+    Bottom,
+}
+
+enum WelcomeAction {
+    Prompt(String),
+    ReopenLastSession,
+}
+
+enum MessageAction {
+    ToggleStar,
+    Feedback(i64),
+    Verdict(ComparisonSide),
+    Edit,
+    Quote,
+    Regenerate,
+    RetryWith(String),
+    ViewInHistory,
+    Delete,
+    // Start or stop reading the message aloud
+    #[cfg(feature = "speech")]
+    Speak,
+    // Drop a prompt from the outbox before it's sent
+    CancelQueued,
+    // Carry on from this message in a new session
+    Branch,
+}
+
+enum TagAction {
+    Add(i64, String),
+    Remove(i64, String),
 }
 
 pub struct TouristApp {
     // Core components
-    ollama_client: OllamaClient,
+    backend: Arc<dyn LlmBackend>,
     rag_system: Option<RagSystem>,
     analytics_engine: Option<AnalyticsEngine>,
+    template_library: Option<TemplateLibrary>,
     
     // Chat State
     input_text: String,
+    token_counter: TokenCounter,
+    counted_input: String,
+    input_tokens: usize,
+    // Tokens the attachments and RAG context add, recounted when either changes
+    counted_context: (Vec<bool>, Vec<i64>, bool),
+    context_tokens: usize,
+    context_window: Option<usize>,
+    allow_context_overflow: bool,
+    // Sent prompts, oldest first, and the position while recalling with Up/Down
+    prompt_history: Vec<String>,
+    recall_index: Option<usize>,
+    // Autosaved copy of the input, written once typing pauses
+    saved_draft: String,
+    draft_changed_at: Option<std::time::Instant>,
+    draft_restored: bool,
+    // Left by a session that didn't exit cleanly, until the user restores or discards it
+    pending_recovery: Option<SessionSnapshot>,
+    // When the recovery file was last written, and how many messages it held
+    recovery_saved_at: std::time::Instant,
+    recovery_saved_len: usize,
     chat_messages: Vec<ChatMessage>,
+    // Measured height of each message, reset when the chat width changes
+    message_heights: Vec<f32>,
+    message_heights_width: f32,
+    // Follow new output only while the view is already at the bottom
+    chat_at_bottom: bool,
+    chat_unseen: bool,
+    seen_content_len: usize,
+    chat_scroll_jump: Option<ScrollJump>,
     is_loading: bool,
     
     // Enhanced Features
-    file_content: String,
-    file_name: Option<String>,
+    // Shared with the plugin contexts and snapshots made from them, so sending copies no files
+    attachments: Vec<Arc<Attachment>>,
     
     // Configuration
     model_name: String,
     ollama_url: String,
     enable_rag: bool,
+    // Suggestions are looked up once the input stops changing
+    rag_debounce: Debouncer,
+    active_tag: String,
+    rag_filter_by_tag: bool,
+    embedding_model: String,
+    rag_use_history: bool,
+    rag_use_documents: bool,
+    rag_max_age_days: Option<i64>,
+    rag_current_model_only: bool,
+    rag_exclude_downvoted: bool,
+    compare_mode: bool,
+    compare_model: String,
     
     // UI State
     show_sidebar: bool,
     show_settings: bool,
+    show_history: bool,
+    
+    // History browser
+    history_entries: Vec<ConversationEntry>,
+    history_page: usize,
+    history_filter_text: String,
+    history_model_filter: String,
+    history_days: Option<i64>,
+    history_tag_editing: Option<(i64, String)>,
+    known_tags: Vec<String>,
+    known_documents: Vec<String>,
+    starred_entries: Vec<ConversationEntry>,
+    // Conversations are saved under the current session; the first answer in one names it
+    session_id: String,
+    session_title_requested: bool,
+    sessions: Vec<SessionSummary>,
+    renaming_session: Option<(String, String)>,
+    // Suggested next questions, shown under the last answer while it is still the last
+    follow_ups: Option<(i64, Vec<String>)>,
+    // Installed models, for picking one to retry an answer with
+    available_models: Vec<String>,
+    // Locked briefly by each generation, the sidebar only ever tries the lock
+    plugin_manager: Arc<RwLock<PluginManager>>,
+    // The plugins' own Ollama client, kept on the sidebar's server URL
+    plugin_models: ModelAccess,
+    // Compiled copy of the configured regex rules, with each rule's compile error
+    regex_rules: RuleSet,
+    regex_rule_errors: Vec<Option<String>>,
+    regex_sample: String,
+    // Commands from answers wait here for approval, one is shown at a time
+    command_queue: CommandQueue,
+    pending_command: Option<CommandRequest>,
+    // Text files over the size limit, waiting for the user to pick which part to attach
+    oversized_files: Vec<(std::path::PathBuf, u64)>,
+    // An opened archive whose entries the user is choosing from
+    archive_listing: Option<ArchiveListing>,
+    // A git repository whose files the user is choosing from
+    repo_listing: Option<RepoListing>,
+    // Long pasted text held back from the input until the user says where it goes
+    long_paste: Option<String>,
+    command_running: bool,
+    // What the pre-prompt plugins make of the input, rerun once typing pauses
+    prompt_preview: Option<(String, Result<PluginRun, String>)>,
+    prompt_preview_typed: Option<(String, std::time::Instant)>,
+    prompt_preview_sent: Option<String>,
+    
+    // Banner for background failures
+    ui_errors: Vec<UiError>,
+    
+    // Find in chat
+    chat_search_open: bool,
+    chat_search_query: String,
+    chat_search_current: usize,
+    chat_search_scroll: bool,
+    focus_chat_search: bool,
+    focus_history_search: bool,
+    
+    // Prompt templates
+    templates: Vec<PromptTemplate>,
+    template_draft: Option<PromptTemplate>,
+    
+    // New chat confirmation and undo
+    confirm_clear_chat: bool,
+    // User message waiting for the "delete its answer too?" choice
+    confirm_delete: Option<usize>,
+    undo_stack: Vec<(UndoItem, std::time::Instant)>,
+    
+    // Editing a previous prompt
+    editing_message: Option<usize>,
+    edit_as_branch: bool,
+    
+    // Export
+    export_format: ExportFormat,
+    export_include_reasoning: bool,
+    export_include_response_times: bool,
+    import_to_history: bool,
+    
+    // Backup / restore
+    pending_restore: Option<std::path::PathBuf>,
+    backup_status: Option<String>,
+    
+    // Persisted preferences, written shortly after they stop changing
+    config: AppConfig,
+    saved_config: AppConfig,
+    config_dirty_since: Option<std::time::Instant>,
+    window_clamped: bool,
     
     // Data
     analytics: Analytics,
-    rag_suggestions: Vec<ConversationEntry>,
+    daily_usage: Vec<DailyUsage>,
+    usage_days: u32,
+    top_keywords: Vec<(String, usize)>,
+    latency_correlation: LatencyCorrelation,
+    topic_days: Option<u32>,
+    analytics_updated_at: Option<chrono::DateTime<Local>>,
+    analytics_refresh_due: Option<std::time::Instant>,
+    rag_suggestions: Vec<ScoredEntry>,
+    expanded_suggestion: Option<usize>,
     
     // Async handling
     rt: Arc<tokio::runtime::Runtime>,
-    pending_operations: Arc<Mutex<Vec<PendingOperation>>>,
+    // Background tasks report back through the sender, the receiver is drained every frame
+    pending_operations: OpSender,
+    pending_receiver: std::sync::mpsc::Receiver<PendingOperation>,
     last_response_time: Option<std::time::Instant>,
+    stream_responses: bool,
+    streaming_text: String,
+    generation_cancel: Option<Arc<Notify>>,
+    interactive_busy: Arc<AtomicBool>,
+    // Generations that end by saving a conversation, waited on when the window closes
+    save_tasks: tokio::task::JoinSet<()>,
+    closing_since: Option<std::time::Instant>,
+    // Set when closing gave up on a save, so the recovery snapshot outlives the exit
+    keep_recovery: bool,
+    
+    // Embedding backfill
+    index_progress: Option<IndexProgress>,
+    index_cancel: Option<Arc<AtomicBool>>,
+    
+    // Local HTTP API
+    api_server: Option<ApiServer>,
+    api_settings: Arc<std::sync::RwLock<ApiSettings>>,
+    api_status: Option<String>,
+    
+    // Completed-response webhook, None when no URL is set
+    webhook: Option<Webhook>,
+    webhook_status: Option<String>,
+    
+    obsidian_status: Option<String>,
+    
+    // Started the first time something is read aloud
+    #[cfg(feature = "speech")]
+    speaker: Option<Speaker>,
+    #[cfg(feature = "speech")]
+    speaker_error: Option<String>,
+    
+    // None until the first health check has answered
+    connected: Option<bool>,
+    connection_checked: Option<std::time::Instant>,
+    connection_inflight: bool,
+    // Prompts written while disconnected, oldest first, mirroring the outbox table
+    outbox: Vec<OutboxItem>,
+    outbox_sending: Option<i64>,
+    // Last provisional id handed to a prompt whose outbox row is still being written
+    next_provisional: i64,
+    // Send the next queued prompt once the current one has finished loading
+    outbox_continue: bool,
+    
+    // Ollama's /api/ps, polled while the analytics panel is open
+    running_models: Option<Vec<RunningModel>>,
+    running_models_error: Option<String>,
+    running_models_polled: Option<std::time::Instant>,
+    running_models_inflight: bool,
+    
+    // Push-to-talk, recording while the mic button is held
+    #[cfg(feature = "voice")]
+    recorder: Option<Recorder>,
+    transcribing: bool,
     
     // Display
     save_directory_display: String,
 }
 
-impl Default for TouristApp {
-    fn default() -> Self {
-        let rag_system = RagSystem::new().ok();
+impl TouristApp {
+    pub fn new(config: AppConfig, ctx: egui::Context) -> Self {
+        let backend = backend_from_config(&config, ollama::DEFAULT_URL);
+        Self::with_backend(config, ctx, backend)
+    }
+
+    // Any model server works, e.g. a MockBackend to run the app without Ollama
+    pub fn with_backend(config: AppConfig, ctx: egui::Context, backend: Box<dyn LlmBackend>) -> Self {
+        Self::with_database(config, ctx, backend, RagSystem::new())
+    }
+
+    // On a database opened beforehand, e.g. one unlocked with a passphrase
+    pub fn with_rag(config: AppConfig, ctx: egui::Context, rag_system: RagSystem) -> Self {
+        let backend = backend_from_config(&config, ollama::DEFAULT_URL);
+        Self::with_database(config, ctx, backend, Ok(rag_system))
+    }
+
+    fn with_database(
+        config: AppConfig,
+        ctx: egui::Context,
+        backend: Box<dyn LlmBackend>,
+        rag_system: Result<RagSystem, AppError>,
+    ) -> Self {
+        // Set once, nothing changes the theme afterwards
+        Self::set_modern_theme(&ctx);
+        let backend: Arc<dyn LlmBackend> = Arc::from(backend);
+        let mut ui_errors = Vec::new();
+        let rag_system = match rag_system {
+            Ok(rag_system) => {
+                rag_system.set_text_dump(config.text_dump);
+                Some(rag_system)
+            }
+            Err(e) => {
+                ui_errors.push(UiError::new(format!("Database unavailable, history and RAG are disabled: {}", e), Severity::Error));
+                None
+            }
+        };
         let analytics_engine = rag_system.as_ref()
-            .map(|rag| AnalyticsEngine::new(rag.save_directory.join("conversations.db")));
+            .map(|rag| AnalyticsEngine::new(rag.database()));
+        let template_library = rag_system.as_ref()
+            .map(|rag| TemplateLibrary::new(rag.database()));
+        let draft = config::load_draft();
+        let (pending_sender, pending_receiver) = std::sync::mpsc::channel();
+        let recovery = config::load_recovery();
+        let regex_rules = RuleSet::default();
+        let regex_rule_errors = regex_rules.replace(&config.regex_rules);
+        let command_queue = CommandQueue::default();
+        let plugin_models = ModelAccess::new(backend.clone(), analytics_engine.clone());
+        let mut plugin_manager = PluginManager::with_defaults(regex_rules.clone(), command_queue.clone(), plugin_models.clone());
+        plugin_manager.set_order(&config.plugin_order);
+        plugin_manager.apply_configs(&config.plugin_settings);
         
         let save_dir = if let Some(ref rag) = rag_system {
             rag.save_directory.display().to_string()
@@ -67,34 +420,199 @@ impl Default for TouristApp {
             "Failed to initialize".to_string()
         };
         
-        Self {
-            ollama_client: OllamaClient::default(),
+        let mut app = Self {
+            ollama_url: ollama::DEFAULT_URL.to_string(),
+            backend: backend.clone(),
             rag_system,
             analytics_engine,
+            template_library,
             
-            input_text: String::new(),
+            input_text: draft.clone().unwrap_or_default(),
+            token_counter: TokenCounter::shared(),
+            counted_input: String::new(),
+            input_tokens: 0,
+            counted_context: (Vec::new(), Vec::new(), false),
+            context_tokens: 0,
+            context_window: None,
+            allow_context_overflow: false,
+            prompt_history: Vec::new(),
+            recall_index: None,
+            saved_draft: draft.clone().unwrap_or_default(),
+            draft_changed_at: None,
+            draft_restored: draft.is_some(),
+            pending_recovery: recovery,
+            recovery_saved_at: std::time::Instant::now(),
+            recovery_saved_len: 0,
             chat_messages: Vec::new(),
+            message_heights: Vec::new(),
+            message_heights_width: 0.0,
+            chat_at_bottom: true,
+            chat_unseen: false,
+            seen_content_len: 0,
+            chat_scroll_jump: None,
             is_loading: false,
             
-            file_content: String::new(),
-            file_name: None,
+            attachments: Vec::new(),
             
             model_name: "deepseek-r1:7b".to_string(),
-            ollama_url: "http://localhost:11434/api/generate".to_string(),
             enable_rag: true,
+            rag_debounce: Debouncer::new(RAG_DEBOUNCE_DELAY),
+            active_tag: String::new(),
+            rag_filter_by_tag: false,
+            embedding_model: "nomic-embed-text".to_string(),
+            rag_use_history: true,
+            rag_use_documents: true,
+            rag_max_age_days: None,
+            rag_current_model_only: false,
+            rag_exclude_downvoted: true,
+            compare_mode: false,
+            compare_model: String::new(),
             
-            show_sidebar: false,
+            show_sidebar: config.show_sidebar,
             show_settings: false,
+            show_history: config.show_history,
+            
+            history_entries: Vec::new(),
+            history_page: 0,
+            history_filter_text: String::new(),
+            history_model_filter: String::new(),
+            history_days: None,
+            history_tag_editing: None,
+            known_tags: Vec::new(),
+            known_documents: Vec::new(),
+            starred_entries: Vec::new(),
+            session_id: new_session_id(),
+            session_title_requested: false,
+            sessions: Vec::new(),
+            renaming_session: None,
+            follow_ups: None,
+            available_models: Vec::new(),
+            plugin_manager: Arc::new(RwLock::new(plugin_manager)),
+            plugin_models,
+            regex_rules,
+            regex_rule_errors,
+            regex_sample: String::new(),
+            command_queue,
+            pending_command: None,
+            oversized_files: Vec::new(),
+            archive_listing: None,
+            repo_listing: None,
+            long_paste: None,
+            command_running: false,
+            prompt_preview: None,
+            prompt_preview_typed: None,
+            prompt_preview_sent: None,
+            
+            ui_errors,
+            chat_search_open: false,
+            chat_search_query: String::new(),
+            chat_search_current: 0,
+            chat_search_scroll: false,
+            focus_chat_search: false,
+            focus_history_search: false,
+            templates: Vec::new(),
+            template_draft: None,
+            confirm_clear_chat: false,
+            confirm_delete: None,
+            undo_stack: Vec::new(),
+            editing_message: None,
+            edit_as_branch: false,
+            export_format: ExportFormat::Markdown,
+            export_include_reasoning: false,
+            export_include_response_times: false,
+            import_to_history: false,
+            pending_restore: None,
+            backup_status: None,
             
             analytics: Analytics::default(),
+            daily_usage: Vec::new(),
+            usage_days: 30,
+            top_keywords: Vec::new(),
+            latency_correlation: LatencyCorrelation::default(),
+            topic_days: Some(30),
+            analytics_updated_at: None,
+            analytics_refresh_due: None,
             rag_suggestions: Vec::new(),
+            expanded_suggestion: None,
             
             rt: Arc::new(tokio::runtime::Runtime::new().unwrap()),
-            pending_operations: Arc::new(Mutex::new(Vec::new())),
+            pending_operations: OpSender::new(pending_sender, move || ctx.request_repaint()),
+            pending_receiver,
             last_response_time: None,
+            stream_responses: true,
+            streaming_text: String::new(),
+            generation_cancel: None,
+            interactive_busy: Arc::new(AtomicBool::new(false)),
+            save_tasks: tokio::task::JoinSet::new(),
+            closing_since: None,
+            keep_recovery: false,
+            
+            index_progress: None,
+            index_cancel: None,
+            
+            api_server: None,
+            api_settings: Arc::new(std::sync::RwLock::new(ApiSettings {
+                backend,
+                model: String::new(),
+                session_id: String::new(),
+                keep_failed: config.keep_failed_generations,
+                webhook: None,
+            })),
+            api_status: None,
+            
+            webhook: Webhook::new(&config.webhook_url, &config.webhook_secret),
+            webhook_status: None,
+            
+            obsidian_status: None,
+            
+            #[cfg(feature = "speech")]
+            speaker: None,
+            #[cfg(feature = "speech")]
+            speaker_error: None,
+            
+            connected: None,
+            connection_checked: None,
+            connection_inflight: false,
+            outbox: Vec::new(),
+            outbox_sending: None,
+            next_provisional: 0,
+            outbox_continue: false,
+            
+            running_models: None,
+            running_models_error: None,
+            running_models_polled: None,
+            running_models_inflight: false,
+            
+            #[cfg(feature = "voice")]
+            recorder: None,
+            transcribing: false,
             
             save_directory_display: save_dir,
+            
+            config: config.clone(),
+            saved_config: config,
+            config_dirty_since: None,
+            window_clamped: false,
+        };
+        
+        // The quick-insert menu needs templates before the sidebar is ever opened
+        app.refresh_templates();
+        app.refresh_starred();
+        app.refresh_sessions();
+        app.refresh_context_window();
+        app.refresh_models();
+        app.load_prompt_history();
+        app.load_outbox();
+        if app.config.api_enabled {
+            app.restart_api_server();
         }
+        app
+    }
+}
+
+impl Default for TouristApp {
+    fn default() -> Self {
+        Self::new(AppConfig::load(), egui::Context::default())
     }
 }
 
@@ -103,258 +621,3618 @@ impl TouristApp {
         if self.is_loading || self.input_text.trim().is_empty() {
             return;
         }
+        if self.over_context_window() && !self.allow_context_overflow {
+            return;
+        }
+        self.allow_context_overflow = false;
+
+        // Editing without branching replaces the edited turn and everything after it
+        if let Some(index) = self.editing_message.take() {
+            if !self.edit_as_branch && index < self.chat_messages.len() {
+                self.truncate_chat(index);
+            }
+        }
+
+        self.recall_index = None;
+        self.draft_restored = false;
+        if self.prompt_history.last() != Some(&self.input_text) {
+            self.prompt_history.push(self.input_text.clone());
+        }
 
         // Add user message to chat
-        let user_message = ChatMessage {
+        let mut user_message = ChatMessage {
             content: self.input_text.clone(),
             is_user: true,
             timestamp: Local::now(),
             model_used: None,
             response_time: None,
+            reasoning: None,
+            conversation_id: None,
+            truncated: false,
+            starred: false,
+            feedback: 0,
+            comparison: None,
+            annotations: Vec::new(),
+            tokens: None,
+            external: false,
+            queued: None,
+            history_dropped: 0,
         };
+        if let Some(id) = self.queue_if_offline(&user_message.content) {
+            user_message.queued = Some(id);
+            self.chat_messages.push(user_message);
+            self.input_text.clear();
+            self.draft_changed_at = Some(std::time::Instant::now());
+            return;
+        }
         self.chat_messages.push(user_message);
 
         let final_prompt = self.build_final_prompt();
         self.start_generation();
         
-        let ollama_client = self.ollama_client.clone();
+        let backend = self.backend.clone();
         let model_name = self.model_name.clone();
         let ctx_clone = ctx.clone();
         let rag_system = self.rag_system.clone();
-        let original_prompt = self.input_text.clone();
-        let file_context = self.file_name.clone();
+        let analytics_engine = self.analytics_engine.clone();
+        let file_context = (!self.attachments.is_empty()).then(|| {
+            self.attachments.iter().map(|attachment| attachment.name.as_str()).collect::<Vec<_>>().join(", ")
+        });
+        let images = pipeline::image_payloads(&self.attachments);
+        let tags = self.active_tags();
+        let keep_failed = self.config.keep_failed_generations;
+        let session_id = self.session_id.clone();
+        let plugins = self.plugin_manager.clone();
+        let plugin_context = self.plugin_context(self.chat_messages.len() - 1, &self.model_name)
+            .with_attachments(self.attachments.clone());
+        let webhook = self.webhook.clone();
+        let stream = self.stream_responses;
+        // Chat turns carry no images, so a message with any goes out on its own
+        let mut chat_history = (self.config.chat_history && images.is_empty())
+            .then(|| self.chat_turns(self.chat_messages.len() - 1));
+        let system_prompt = self.config.system_prompt.clone();
+        let keep_turns = self.config.history_keep_turns;
+        // A quarter of the window is left for the answer
+        let history_budget = self.context_limit() * 3 / 4;
         let start_time = std::time::Instant::now();
         let pending_ops = self.pending_operations.clone();
         let rt = self.rt.clone();
+        
+        let compare_model = Some(self.compare_model.trim().to_string())
+            .filter(|model| self.compare_mode && !model.is_empty());
+        
+        let cancel = Arc::new(Notify::new());
+        self.generation_cancel = Some(cancel.clone());
 
-        // Clear input immediately
-        self.input_text.clear();
+        // Clear input immediately, taking the prompt instead of copying it
+        let original_prompt = std::mem::take(&mut self.input_text);
 
-        rt.spawn(async move {
-            let result = ollama_client.generate_response(&model_name, &final_prompt).await;
+        // Comparisons send the same prompt to both models at once, without streaming
+        if let Some(compare_model) = compare_model {
+            let job = GenerationJob {
+                backend,
+                rag_system,
+                analytics_engine,
+                prompt: final_prompt,
+                original_prompt,
+                images,
+                file_context,
+                tags,
+                keep_failed,
+                session_id,
+                plugins,
+                plugin_context,
+                webhook,
+                pending_ops: pending_ops.clone(),
+            };
             
-            match result {
-                Ok(response) => {
-                    let response_time = start_time.elapsed().as_millis() as i64;
-                    
-                    // Save to RAG system
-                    if let Some(rag) = &rag_system {
-                        let entry = ConversationEntry {
-                            id: 0,
-                            timestamp: Local::now(),
-                            prompt: original_prompt,
-                            response: response.clone(),
-                            model_used: model_name.clone(),
-                            response_time_ms: response_time,
-                            file_context,
-                        };
-                        
-                        if let Err(e) = rag.save_conversation(&entry).await {
-                            eprintln!("Error saving conversation: {}", e);
+            self.save_tasks.spawn_on(async move {
+                tokio::select! {
+                    (left, right) = async { tokio::join!(job.run(model_name), job.run(compare_model)) } => {
+                        pending_ops.send(PendingOperation::Comparison(left, right));
+                        pending_ops.send(PendingOperation::LoadingComplete);
+                    }
+                    _ = cancel.notified() => {
+                        pending_ops.send(PendingOperation::LoadingComplete);
+                    }
+                }
+                ctx_clone.request_repaint();
+            }, rt.handle());
+            return;
+        }
+
+        self.save_tasks.spawn_on(async move {
+            // A required plugin failing stops the prompt from being sent at all
+            let mut history_dropped = 0;
+            let result = match run_plugins(&plugins, &plugin_context, final_prompt, &pending_ops).await {
+                Err(e) => Err(e),
+                Ok((final_prompt, _)) if chat_history.is_some() => {
+                    let mut turns = chat_history.take().unwrap_or_default();
+                    turns.push(ChatTurn::user(final_prompt));
+                    let counter = TokenCounter::shared();
+                    let fitted = history::fit_history(&system_prompt, &turns, keep_turns, history_budget, |text| counter.count(text));
+                    history_dropped = fitted.dropped;
+                    tokio::select! {
+                        result = backend.chat(&model_name, &fitted.turns, None) => {
+                            result.map(|text| Generation { text, ..Default::default() })
                         }
+                        _ = cancel.notified() => Ok(Generation { cancelled: true, ..Default::default() }),
+                    }
+                }
+                Ok((final_prompt, _)) if stream => {
+                    let chunk_ops = pending_ops.clone();
+                    backend.generate_stream(&model_name, &final_prompt, &images, &cancel, &mut move |chunk| {
+                        chunk_ops.send(PendingOperation::ResponseChunk(chunk));
+                    }).await
+                }
+                Ok((final_prompt, _)) => tokio::select! {
+                    result = backend.generate_response(&model_name, &final_prompt, &images, None) => {
+                        result.map(|text| Generation { text, ..Default::default() })
                     }
+                    _ = cancel.notified() => Ok(Generation { cancelled: true, ..Default::default() }),
+                },
+            };
+            let response_time = start_time.elapsed().as_millis() as i64;
+            
+            // Stopped before anything arrived, there is nothing to keep
+            if matches!(&result, Ok(generation) if generation.cancelled && generation.text.trim().is_empty()) {
+                pending_ops.send(PendingOperation::LoadingComplete);
+                ctx_clone.request_repaint();
+                return;
+            }
+            
+            let cancelled = matches!(&result, Ok(generation) if generation.cancelled);
+            let first_token_ms = result.as_ref().ok().and_then(|generation| generation.first_token_ms);
+            let result = match result {
+                Ok(generation) => {
+                    let parsed = ParsedResponse::parse(&generation.text);
+                    run_plugins(&plugins, &plugin_context.for_stage(Stage::PostResponse), parsed.answer, &pending_ops).await
+                        .map(|(answer, annotations)| ParsedResponse { answer, reasoning: parsed.reasoning, annotations })
+                }
+                Err(e) => Err(e),
+            };
+            
+            // Only successful generations are persisted unless debugging failures
+            let (response_text, reasoning, status) = match &result {
+                Ok(parsed) if cancelled => (parsed.answer.clone(), parsed.reasoning.clone(), ConversationStatus::Cancelled),
+                Ok(parsed) => (parsed.answer.clone(), parsed.reasoning.clone(), ConversationStatus::Ok),
+                Err(e) => (e.to_string(), None, ConversationStatus::Error),
+            };
+            
+            if let (Err(e), Some(analytics)) = (&result, &analytics_engine) {
+                let record = ErrorRecord {
+                    timestamp: Local::now(),
+                    kind: OllamaClient::failure_kind(e).to_string(),
+                    message: e.to_string(),
+                    model: model_name.clone(),
+                    url: backend.base_url().to_string(),
+                };
+                if let Err(e) = analytics.record_error(record).await {
+                    pending_ops.send(PendingOperation::Error(UiError::from_error("Error recording failure", &e)));
+                }
+            }
+            
+            let counter = TokenCounter::shared();
+            let prompt_tokens = counter.count(&original_prompt);
+            let response_tokens = match &result {
+                Ok(parsed) => counter.count(&parsed.answer) + parsed.reasoning.as_deref().map_or(0, |reasoning| counter.count(reasoning)),
+                Err(_) => 0,
+            };
+            
+            // Only a failure needs the prompt past saving, to put it back in the input
+            let failed_prompt = result.is_err().then(|| original_prompt.clone());
+            let mut conversation_id = None;
+            if status != ConversationStatus::Error || keep_failed {
+                if let Some(rag) = &rag_system {
+                    let entry = ConversationEntry {
+                        id: 0,
+                        timestamp: Local::now(),
+                        prompt: original_prompt,
+                        response: response_text,
+                        model_used: model_name.clone(),
+                        response_time_ms: response_time,
+                        file_context,
+                        tags,
+                        status,
+                        reasoning,
+                        first_token_ms,
+                        starred: false,
+                        feedback: 0,
+                        backend: Some(backend.name().to_string()),
+                        source: None,
+                    };
                     
-                    let mut ops = pending_ops.lock().await;
-                    ops.push(PendingOperation::Response(response));
-                    ops.push(PendingOperation::LoadingComplete);
+                    conversation_id = save_in_session(rag, &entry, &session_id, &pending_ops).await;
+                    if let Some(id) = conversation_id {
+                        notify_webhook(webhook.as_ref(), || WebhookPayload::new(id, &entry, &session_id), &pending_ops);
+                    }
+                }
+            }
+            
+            let payload = |parsed| ResponsePayload {
+                parsed,
+                model: model_name.clone(),
+                response_time_ms: response_time,
+                conversation_id,
+                prompt_tokens,
+                response_tokens,
+                history_dropped,
+            };
+            match result {
+                Ok(parsed) if cancelled => {
+                    pending_ops.send(PendingOperation::Stopped(payload(parsed)));
+                    pending_ops.send(PendingOperation::LoadingComplete);
+                }
+                Ok(parsed) => {
+                    pending_ops.send(PendingOperation::Response(payload(parsed)));
+                    pending_ops.send(PendingOperation::LoadingComplete);
                 }
                 Err(e) => {
-                    let mut ops = pending_ops.lock().await;
-                    ops.push(PendingOperation::Error(e.to_string()));
-                    ops.push(PendingOperation::LoadingComplete);
+                    pending_ops.send(PendingOperation::GenerationError { message: e.to_string(), prompt: failed_prompt.unwrap_or_default() });
+                    pending_ops.send(PendingOperation::LoadingComplete);
                 }
             }
             
             ctx_clone.request_repaint();
-        });
+        }, rt.handle());
     }
 
-    fn build_final_prompt(&self) -> String {
-        let mut final_prompt = if !self.file_content.is_empty() {
-            format!("File context:\n{}\n\nUser message: {}", self.file_content, self.input_text)
-        } else {
-            self.input_text.clone()
-        };
-
-        // Add RAG context if enabled
-        if self.enable_rag && !self.rag_suggestions.is_empty() {
-            if let Some(rag_system) = &self.rag_system {
-                final_prompt = rag_system.create_rag_context(&self.rag_suggestions, &final_prompt);
+    // False if the platform has no speech engine, the reason is kept for the settings
+    #[cfg(feature = "speech")]
+    fn start_speaker(&mut self) -> bool {
+        if self.speaker.is_some() {
+            return true;
+        }
+        if self.speaker_error.is_some() {
+            return false;
+        }
+        let pending_ops = self.pending_operations.clone();
+        match Speaker::start(move || pending_ops.wake()) {
+            Ok(speaker) => {
+                self.speaker = Some(speaker);
+                true
+            }
+            Err(e) => {
+                self.speaker_error = Some(e.to_string());
+                false
             }
         }
-
-        final_prompt
     }
 
-    fn start_generation(&mut self) {
-        self.is_loading = true;
-        self.last_response_time = Some(std::time::Instant::now());
+    // Reads the message aloud, or stops it if it is the one playing
+    #[cfg(feature = "speech")]
+    fn toggle_speech(&mut self, index: usize) {
+        let Some(key) = self.chat_messages.get(index).and_then(|message| message.timestamp.timestamp_nanos_opt()) else {
+            return;
+        };
+        if !self.start_speaker() {
+            if let Some(error) = &self.speaker_error {
+                self.ui_errors.push(UiError::new(error.clone(), Severity::Warning));
+            }
+            return;
+        }
+        let Some(speaker) = &self.speaker else {
+            return;
+        };
+        if speaker.playing() == Some(key) {
+            speaker.stop();
+        } else {
+            speaker.speak(key, &self.chat_messages[index].content, &self.config.tts_voice, self.config.tts_rate);
+        }
     }
 
-    fn update_rag_suggestions(&mut self) {
-        if !self.enable_rag || self.input_text.trim().is_empty() || self.input_text.len() <= 10 {
+    #[cfg(feature = "voice")]
+    fn start_recording(&mut self) {
+        if self.config.whisper_model.trim().is_empty() {
+            self.ui_errors.push(UiError::new("Set a whisper model in the 🎤 Voice input settings first", Severity::Warning));
             return;
         }
+        match Recorder::start() {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(e) => self.ui_errors.push(UiError::new(e.to_string(), Severity::Error)),
+        }
+    }
 
-        if let Some(rag_system) = &self.rag_system {
-            let rag_system = rag_system.clone();
-            let prompt = self.input_text.clone();
-            let pending_ops = self.pending_operations.clone();
-            let rt = self.rt.clone();
+    #[cfg(feature = "voice")]
+    fn finish_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        let recording = recorder.stop();
+        let model_path = std::path::PathBuf::from(self.config.whisper_model.trim());
+        self.transcribing = true;
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = tokio::task::spawn_blocking(move || voice::transcribe(&model_path, recording))
+                .await
+                .map_err(AppError::from)
+                .and_then(|result| result);
+            pending_ops.send(PendingOperation::Transcribed(result.map_err(|e| e.to_string())));
+        });
+    }
 
-            rt.spawn(async move {
-                match rag_system.find_similar_responses(&prompt, 3).await {
-                    Ok(suggestions) => {
-                        let mut ops = pending_ops.lock().await;
-                        ops.push(PendingOperation::RagSuggestions(suggestions));
-                    }
-                    Err(e) => {
-                        let mut ops = pending_ops.lock().await;
-                        ops.push(PendingOperation::Error(format!("RAG error: {}", e)));
-                    }
-                }
-            });
+    // Records while held, transcribes on release
+    #[cfg(feature = "voice")]
+    fn render_mic_button(&mut self, ui: &mut egui::Ui) {
+        let mic_icon = if self.recorder.is_some() { "🔴" } else { "🎤" };
+        let mic = ui.add_enabled(!self.transcribing, egui::Button::new(mic_icon).small())
+            .on_hover_text("Hold to talk");
+        if mic.is_pointer_button_down_on() {
+            if self.recorder.is_none() {
+                self.start_recording();
+            }
+        } else if self.recorder.is_some() {
+            self.finish_recording();
         }
     }
 
-    fn update_analytics(&mut self) {
-        if let Some(analytics_engine) = &self.analytics_engine {
-            let analytics_engine = analytics_engine.clone();
-            let pending_ops = self.pending_operations.clone();
-            let rt = self.rt.clone();
-            
-            rt.spawn(async move {
-                match analytics_engine.get_analytics().await {
-                    Ok(analytics) => {
-                        let mut ops = pending_ops.lock().await;
-                        ops.push(PendingOperation::Analytics(analytics));
-                    }
-                    Err(e) => {
-                        let mut ops = pending_ops.lock().await;
-                        ops.push(PendingOperation::Error(format!("Analytics error: {}", e)));
-                    }
+    #[cfg(feature = "speech")]
+    fn render_speech_settings(&mut self, ui: &mut egui::Ui) {
+        let openness = egui::CollapsingHeader::new("🔊 Speech")
+            .default_open(self.section_open("🔊 Speech"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                // Listing voices needs the speech engine, so opening this starts it
+                if self.start_speaker() {
+                    let voices = self.speaker.as_ref().map(|speaker| speaker.voices().to_vec()).unwrap_or_default();
+                    let selected = voices.iter()
+                        .find(|voice| voice.id == self.config.tts_voice)
+                        .map_or("System default", |voice| voice.name.as_str());
+                    ui.label("Voice:");
+                    egui::ComboBox::from_id_source("tts_voice")
+                        .selected_text(selected)
+                        .width(ui.available_width())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.config.tts_voice, String::new(), "System default");
+                            for voice in &voices {
+                                ui.selectable_value(&mut self.config.tts_voice, voice.id.clone(), &voice.name);
+                            }
+                        });
+                    ui.label("Rate:");
+                    ui.add(egui::Slider::new(&mut self.config.tts_rate, 0.5..=2.0).suffix("×"));
+                } else if let Some(error) = &self.speaker_error {
+                    ui.label(egui::RichText::new(error).size(11.0).color(egui::Color32::GRAY));
                 }
-            });
-        }
+                ui.add_space(8.0);
+            }).openness;
+        self.record_section("🔊 Speech", openness > 0.5);
+
+        ui.add_space(12.0);
     }
 
-    fn check_async_updates(&mut self) {
-        if let Ok(mut ops) = self.pending_operations.try_lock() {
-            for op in ops.drain(..) {
-                match op {
-                    PendingOperation::Response(result) => {
-                        let ai_message = ChatMessage {
-                            content: result,
-                            is_user: false,
-                            timestamp: Local::now(),
-                            model_used: Some(self.model_name.clone()),
-                            response_time: self.last_response_time
-                                .map(|t| t.elapsed().as_millis() as i64),
-                        };
-                        self.chat_messages.push(ai_message);
-                    }
-                    PendingOperation::Analytics(analytics) => {
-                        self.analytics = analytics;
-                    }
-                    PendingOperation::RagSuggestions(suggestions) => {
-                        self.rag_suggestions = suggestions;
-                    }
-                    PendingOperation::LoadingComplete => {
-                        self.is_loading = false;
-                    }
-                    PendingOperation::Error(error) => {
-                        eprintln!("Background error: {}", error);
-                        let error_message = ChatMessage {
-                            content: format!("Error: {}", error),
-                            is_user: false,
-                            timestamp: Local::now(),
-                            model_used: Some("Error".to_string()),
-                            response_time: None,
-                        };
-                        self.chat_messages.push(error_message);
-                        self.is_loading = false;
+    #[cfg(feature = "voice")]
+    fn render_voice_settings(&mut self, ui: &mut egui::Ui) {
+        let openness = egui::CollapsingHeader::new("🎤 Voice input")
+            .default_open(self.section_open("🎤 Voice input"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                ui.label("Whisper model:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.config.whisper_model)
+                        .desired_width(ui.available_width() - 40.0)
+                        .hint_text("ggml-base.en.bin"));
+                    if ui.button("📂").on_hover_text("Choose model file").clicked() {
+                        if let Some(path) = FileHandler::pick_whisper_model() {
+                            self.config.whisper_model = path.display().to_string();
+                        }
                     }
-                }
-            }
-        }
+                });
+                ui.checkbox(&mut self.config.voice_auto_send, "Send after transcribing");
+                ui.add_space(8.0);
+            }).openness;
+        self.record_section("🎤 Voice input", openness > 0.5);
 
-        // Simple debounced RAG suggestions update
-        self.debounced_rag_update();
+        ui.add_space(12.0);
     }
 
-    fn debounced_rag_update(&mut self) {
-        static mut LAST_INPUT: Option<String> = None;
-        static mut UPDATE_COUNTER: u32 = 0;
-        
-        unsafe {
-            if LAST_INPUT.as_ref() != Some(&self.input_text) {
-                LAST_INPUT = Some(self.input_text.clone());
-                UPDATE_COUNTER += 1;
-                
-                if UPDATE_COUNTER % 5 == 0 {
-                    self.update_rag_suggestions();
-                }
-            }
-        }
+    fn quote_in_reply(&mut self, index: usize) {
+        let Some(message) = self.chat_messages.get(index) else {
+            return;
+        };
+        let excerpt = text::preview(&message.content, QUOTE_CHARS);
+        let quote: Vec<String> = excerpt.lines().map(|line| format!("> {}", line).trim_end().to_string()).collect();
+        self.input_text = format!("{}\n\n{}", quote.join("\n"), self.input_text);
     }
 
-    fn handle_url_change(&mut self) {
-        self.ollama_client.update_url(self.ollama_url.clone());
+    // Resends the prompt that produced an answer, replacing the answer and everything after it
+    fn regenerate(&mut self, index: usize, ctx: &egui::Context) {
+        if self.is_loading {
+            return;
+        }
+        let Some(prompt_index) = index.checked_sub(1)
+            .filter(|&previous| self.chat_messages.get(previous).is_some_and(|message| message.is_user))
+        else {
+            return;
+        };
+        
+        let prompt = self.chat_messages[prompt_index].content.clone();
+        let draft = std::mem::replace(&mut self.input_text, prompt);
+        self.editing_message = None;
+        self.truncate_chat(prompt_index);
+        self.send_message(ctx);
+        self.input_text = draft;
     }
 
-    fn load_file(&mut self) {
-        if let Some(content) = FileHandler::load_text_file() {
-            self.file_content = content;
-            self.file_name = Some("uploaded_file.txt".to_string()); // You could enhance this to get actual filename
+    // Reruns the prompt behind an answer with another model, keeping both answers
+    fn retry_with_model(&mut self, index: usize, model: String, ctx: &egui::Context) {
+        if self.is_loading {
+            return;
         }
+        let Some(prompt) = index.checked_sub(1)
+            .and_then(|previous| self.chat_messages.get(previous))
+            .filter(|message| message.is_user)
+        else {
+            return;
+        };
+        let parent_id = self.chat_messages[index].conversation_id;
+        let parent = self.chat_messages[index].timestamp;
+        
+        let job = GenerationJob {
+            backend: self.backend.clone(),
+            rag_system: self.rag_system.clone(),
+            analytics_engine: self.analytics_engine.clone(),
+            prompt: prompt.content.clone(),
+            original_prompt: prompt.content.clone(),
+            images: Vec::new(),
+            file_context: None,
+            tags: self.active_tags(),
+            keep_failed: self.config.keep_failed_generations,
+            session_id: self.session_id.clone(),
+            plugins: self.plugin_manager.clone(),
+            plugin_context: self.plugin_context(index - 1, &model),
+            webhook: self.webhook.clone(),
+            pending_ops: self.pending_operations.clone(),
+        };
+        self.start_generation();
+        let cancel = Arc::new(Notify::new());
+        self.generation_cancel = Some(cancel.clone());
+        let pending_ops = self.pending_operations.clone();
+        let ctx = ctx.clone();
+        let rt = self.rt.clone();
+        
+        self.save_tasks.spawn_on(async move {
+            tokio::select! {
+                response = job.run(model) => {
+                    if let (Some(rag), Some(id), Some(parent_id)) = (&job.rag_system, response.conversation_id, parent_id) {
+                        if let Err(e) = rag.set_parent_response(id, parent_id).await {
+                            pending_ops.send(PendingOperation::Error(UiError::from_error("Error linking retried answer", &e)));
+                        }
+                    }
+                    pending_ops.send(PendingOperation::Retried { parent, parent_id, response });
+                    pending_ops.send(PendingOperation::LoadingComplete);
+                }
+                _ = cancel.notified() => {
+                    pending_ops.send(PendingOperation::LoadingComplete);
+                }
+            }
+            ctx.request_repaint();
+        }, rt.handle());
     }
 
-    fn clear_chat(&mut self) {
-        self.chat_messages.clear();
+    // Opens the history panel filtered to this conversation's prompt
+    fn view_in_history(&mut self, index: usize) {
+        let prompt = if self.chat_messages.get(index).is_some_and(|message| message.is_user) {
+            self.chat_messages.get(index)
+        } else {
+            index.checked_sub(1).and_then(|previous| self.chat_messages.get(previous))
+        };
+        let Some(prompt) = prompt else {
+            return;
+        };
+        
+        self.history_filter_text = text::truncate_chars(prompt.content.lines().next().unwrap_or_default(), 60).to_string();
+        self.history_page = 0;
+        self.show_history = true;
+        self.refresh_history();
     }
 
-    fn export_chat(&self) {
-        let chat_content = self.chat_messages
+    // Opens a new session holding the chat up to this message. Branching from a prompt keeps
+    // the exchanges before it and puts the prompt back in the input.
+    fn branch_from(&mut self, index: usize) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let Some(message) = self.chat_messages.get(index) else {
+            return;
+        };
+        let conversation_ids: Vec<i64> = self.chat_messages[..=index]
             .iter()
-            .map(|msg| {
-                let role = if msg.is_user { "User" } else { "Assistant" };
-                let timestamp = msg.timestamp.format("%Y-%m-%d %H:%M:%S");
-                format!("[{}] {}: {}\n", timestamp, role, msg.content)
-            })
-            .collect::<String>();
+            .filter(|message| !message.is_user)
+            .filter_map(|message| message.conversation_id)
+            .collect();
+        if conversation_ids.is_empty() {
+            self.ui_errors.push(UiError::new("Nothing before this message has been saved to branch from".to_string(), Severity::Warning));
+            return;
+        }
+        if message.is_user {
+            self.input_text = message.content.clone();
+        }
+        
+        let parent_id = self.session_id.clone();
+        let branch_id = new_session_id();
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = match rag_system.branch_session(parent_id, branch_id.clone(), conversation_ids).await {
+                Ok(()) => rag_system.session_conversations(branch_id.clone()).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(entries) => {
+                    pending_ops.send(PendingOperation::SessionOpened { session_id: branch_id, entries });
+                    match rag_system.list_sessions(SESSION_LIST_SIZE).await {
+                        Ok(sessions) => pending_ops.send(PendingOperation::Sessions(sessions)),
+                        Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Sessions error", &e))),
+                    }
+                }
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Branch error", &e))),
+            }
+        });
+    }
 
-        if let Err(e) = FileHandler::save_text_file(&chat_content, "chat_export.txt") {
-            eprintln!("Error saving chat: {}", e);
+    fn begin_edit(&mut self, index: usize) {
+        if let Some(message) = self.chat_messages.get(index) {
+            self.input_text = message.content.clone();
+            self.editing_message = Some(index);
         }
     }
-}
 
-impl eframe::App for TouristApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.set_modern_theme(ctx);
-        self.check_async_updates();
+    fn truncate_chat(&mut self, index: usize) {
+        let removed: Vec<i64> = self.chat_messages
+            .drain(index..)
+            .filter_map(|message| message.conversation_id)
+            .collect();
         
-        if self.is_loading {
-            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        if removed.is_empty() {
+            return;
         }
-
-        // Sidebar
-        egui::SidePanel::left("sidebar")
-            .resizable(false)
-            .exact_width(280.0)
-            .show_animated(ctx, self.show_sidebar, |ui| {
-                self.render_sidebar(ui);
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            if let Err(e) = rag_system.mark_superseded(removed).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("History error", &e)));
+            }
+        });
+    }
+
+    // What plugins see of a request made after the first `end` chat messages
+    fn plugin_context(&self, end: usize, model: &str) -> PluginContext {
+        let start = end.saturating_sub(PLUGIN_HISTORY_SIZE);
+        PluginContext::new(Stage::PrePrompt, model.to_string())
+            .with_history(self.chat_messages[start..end].to_vec())
+            .with_rag(self.rag_system.clone())
+    }
+
+    // The first `end` chat messages as chat turns. Prompts still in the outbox were never answered.
+    fn chat_turns(&self, end: usize) -> Vec<ChatTurn> {
+        self.chat_messages[..end].iter()
+            .filter(|message| message.queued.is_none())
+            .map(|message| if message.is_user { ChatTurn::user(&message.content) } else { ChatTurn::assistant(&message.content) })
+            .collect()
+    }
+
+    fn build_final_prompt(&self) -> String {
+        let mut final_prompt = FileHandler::create_prompt_with_file_context(&self.attachments, &self.input_text);
+
+        // Add RAG context if enabled
+        if self.enable_rag && !self.rag_suggestions.is_empty() {
+            if let Some(rag_system) = &self.rag_system {
+                final_prompt = rag_system.create_rag_context(&self.rag_suggestions, &final_prompt);
+            }
+        }
+
+        final_prompt
+    }
+
+    fn push_assistant_message(&mut self, payload: ResponsePayload, truncated: bool) {
+        self.streaming_text.clear();
+        self.chat_messages.push(ChatMessage {
+            content: payload.parsed.answer,
+            is_user: false,
+            timestamp: Local::now(),
+            model_used: Some(payload.model),
+            response_time: Some(payload.response_time_ms),
+            reasoning: payload.parsed.reasoning,
+            conversation_id: payload.conversation_id,
+            truncated,
+            starred: false,
+            feedback: 0,
+            comparison: None,
+            annotations: payload.parsed.annotations,
+            tokens: Some((payload.prompt_tokens, payload.response_tokens)),
+            external: false,
+            queued: None,
+            history_dropped: payload.history_dropped,
+        });
+    }
+
+    // Puts the prompt in the outbox when the server is known to be down, returning the
+    // provisional id its bubble carries until the row is written. Attachments can't wait in
+    // the outbox, so prompts with any are sent, and fail, as usual.
+    fn queue_if_offline(&mut self, prompt: &str) -> Option<i64> {
+        if self.connected != Some(false) || !self.attachments.is_empty() {
+            return None;
+        }
+        let rag_system = self.rag_system.clone()?;
+        self.next_provisional -= 1;
+        let provisional = self.next_provisional;
+        let prompt = prompt.to_string();
+        let model = self.model_name.clone();
+        let session_id = self.session_id.clone();
+        let tags = self.active_tags();
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = rag_system
+                .enqueue_outbox(prompt.clone(), model.clone(), session_id.clone(), tags.clone())
+                .await
+                .map(|id| OutboxItem { id, created_at: Local::now(), prompt, model, session_id, tags })
+                .map_err(|e| UiError::from_error("Could not queue the prompt", &e));
+            pending_ops.send(PendingOperation::Queued { provisional, result });
+        });
+        Some(provisional)
+    }
+
+    fn load_outbox(&mut self) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            match rag_system.list_outbox().await {
+                Ok(items) => pending_ops.send(PendingOperation::Outbox(items)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Outbox error", &e))),
+            }
+        });
+    }
+
+    // Asks the server for its models every so often, the answer doesn't matter, only that there is one
+    fn poll_connection(&mut self, ctx: &egui::Context) {
+        let now = std::time::Instant::now();
+        let due = self.connection_checked.map_or(now, |checked| checked + HEALTH_CHECK_INTERVAL);
+        ctx.request_repaint_after(due.saturating_duration_since(now).max(std::time::Duration::from_secs(1)));
+        if now < due || self.connection_inflight {
+            return;
+        }
+        self.connection_checked = Some(now);
+        self.connection_inflight = true;
+        let backend = self.backend.clone();
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let reachable = backend.list_models().await.is_ok();
+            pending_ops.send(PendingOperation::ConnectionChecked(reachable));
+        });
+    }
+
+    // Sends the oldest queued prompt, one at a time like any other request. Each answer
+    // goes right after its own prompt, wherever that is in the chat.
+    fn drain_outbox(&mut self) {
+        if self.is_loading || self.connected != Some(true) {
+            return;
+        }
+        let Some(item) = self.outbox.first().cloned() else {
+            return;
+        };
+        
+        let job = GenerationJob {
+            backend: self.backend.clone(),
+            rag_system: self.rag_system.clone(),
+            analytics_engine: self.analytics_engine.clone(),
+            prompt: item.prompt.clone(),
+            original_prompt: item.prompt.clone(),
+            images: Vec::new(),
+            file_context: None,
+            tags: item.tags.clone(),
+            keep_failed: self.config.keep_failed_generations,
+            session_id: item.session_id.clone(),
+            plugins: self.plugin_manager.clone(),
+            plugin_context: PluginContext::new(Stage::PrePrompt, item.model.clone()).with_rag(self.rag_system.clone()),
+            webhook: self.webhook.clone(),
+            pending_ops: self.pending_operations.clone(),
+        };
+        self.start_generation();
+        self.outbox_sending = Some(item.id);
+        let cancel = Arc::new(Notify::new());
+        self.generation_cancel = Some(cancel.clone());
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        self.save_tasks.spawn_on(async move {
+            tokio::select! {
+                response = job.run(item.model.clone()) => {
+                    if response.result.is_err() && job.backend.list_models().await.is_err() {
+                        pending_ops.send(PendingOperation::OutboxHeld(item.id));
+                    } else {
+                        if let Some(rag) = &job.rag_system {
+                            if let Err(e) = rag.remove_outbox(item.id).await {
+                                pending_ops.send(PendingOperation::Error(UiError::from_error("Error removing sent prompt from the outbox", &e)));
+                            }
+                        }
+                        pending_ops.send(PendingOperation::OutboxSent { outbox_id: item.id, response });
+                    }
+                    pending_ops.send(PendingOperation::LoadingComplete);
+                }
+                _ = cancel.notified() => {
+                    pending_ops.send(PendingOperation::LoadingComplete);
+                }
+            }
+        }, rt.handle());
+    }
+
+    fn cancel_queued(&mut self, index: usize) {
+        let Some(id) = self.chat_messages.get(index).and_then(|message| message.queued) else {
+            return;
+        };
+        if self.outbox_sending == Some(id) {
+            return;
+        }
+        self.chat_messages.remove(index);
+        self.message_heights.clear();
+        self.outbox.retain(|item| item.id != id);
+        // Not written yet, the row is removed when the write reports back
+        if id < 0 {
+            return;
+        }
+        
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            if let Err(e) = rag_system.remove_outbox(id).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Outbox error", &e)));
+            }
+        });
+    }
+
+    fn stop_generation(&mut self) {
+        if let Some(cancel) = &self.generation_cancel {
+            cancel.notify_one();
+        }
+    }
+
+    fn start_generation(&mut self) {
+        self.is_loading = true;
+        self.streaming_text.clear();
+        self.last_response_time = Some(std::time::Instant::now());
+        self.interactive_busy.store(true, Ordering::Relaxed);
+    }
+
+    fn start_indexing(&mut self, ctx: &egui::Context) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        
+        let cancel = Arc::new(AtomicBool::new(false));
+        let backfill = EmbeddingBackfill {
+            rag: rag_system,
+            client: self.backend.background(),
+            model: self.embedding_model.clone(),
+            cancel: cancel.clone(),
+            interactive_busy: self.interactive_busy.clone(),
+        };
+        
+        self.index_cancel = Some(cancel);
+        self.index_progress = Some(IndexProgress::default());
+        
+        let pending_ops = self.pending_operations.clone();
+        let ctx = ctx.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let report = |progress: IndexProgress| {
+                let pending_ops = pending_ops.clone();
+                let ctx = ctx.clone();
+                async move {
+                    pending_ops.send(PendingOperation::IndexProgress(progress));
+                    ctx.request_repaint();
+                }
+            };
+            
+            let failed = |e: AppError| {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Embedding failed", &e)));
+            };
+            
+            if let Err(e) = backfill.run(report, failed).await {
+                pending_ops.send(PendingOperation::IndexProgress(IndexProgress { finished: true, ..Default::default() }));
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Indexing error", &e)));
+            }
+        });
+    }
+
+    // False when no lookup was started
+    fn update_rag_suggestions(&mut self) -> bool {
+        if !self.enable_rag || self.input_text.trim().is_empty() || self.input_text.len() <= 10 {
+            return false;
+        }
+        let Some(rag_system) = self.rag_system.clone() else {
+            return false;
+        };
+        
+        let prompt = self.input_text.clone();
+        let filter = self.rag_filter();
+        let hybrid = self.config.rag_hybrid;
+        let keyword_weight = self.config.hybrid_keyword_weight;
+        let include_conversations = self.rag_use_history;
+        let include_documents = self.rag_use_documents;
+        let backend = self.backend.clone();
+        let embedding_model = self.embedding_model.clone();
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+
+        rt.spawn(async move {
+            // Falls back to keyword-only ranking if the prompt can't be embedded
+            let hybrid = if hybrid {
+                backend.embed(&embedding_model, &prompt).await.ok()
+                    .map(|embedding| HybridQuery { embedding, keyword_weight })
+            } else {
+                None
+            };
+            let options = RetrievalOptions {
+                filter,
+                hybrid,
+                include_conversations,
+                include_documents,
+            };
+            
+            match rag_system.find_similar_responses(&prompt, 3, &options).await {
+                Ok(suggestions) => {
+                    pending_ops.send(PendingOperation::RagSuggestions(suggestions));
+                }
+                Err(e) => {
+                    pending_ops.send(PendingOperation::RagFailed(UiError::from_error("RAG error", &e)));
+                }
+            }
+        });
+        true
+    }
+
+    fn update_analytics(&mut self) {
+        if let Some(analytics_engine) = &self.analytics_engine {
+            let analytics_engine = analytics_engine.clone();
+            let pending_ops = self.pending_operations.clone();
+            let rt = self.rt.clone();
+            let usage_days = self.usage_days;
+            let topic_days = self.topic_days;
+            
+            rt.spawn(async move {
+                match analytics_engine.get_analytics().await {
+                    Ok(analytics) => {
+                        pending_ops.send(PendingOperation::Analytics(analytics));
+                    }
+                    Err(e) => {
+                        pending_ops.send(PendingOperation::Error(UiError::from_error("Analytics error", &e)));
+                    }
+                }
+                
+                match analytics_engine.get_daily_counts(usage_days).await {
+                    Ok(daily_usage) => {
+                        pending_ops.send(PendingOperation::DailyUsage(daily_usage));
+                    }
+                    Err(e) => {
+                        pending_ops.send(PendingOperation::Error(UiError::from_error("Analytics error", &e)));
+                    }
+                }
+                
+                match analytics_engine.get_latency_correlation(usage_days).await {
+                    Ok(correlation) => {
+                        pending_ops.send(PendingOperation::LatencyCorrelation(correlation));
+                    }
+                    Err(e) => {
+                        pending_ops.send(PendingOperation::Error(UiError::from_error("Analytics error", &e)));
+                    }
+                }
+                
+                match analytics_engine.top_keywords(TOP_KEYWORD_COUNT, topic_days).await {
+                    Ok(keywords) => {
+                        pending_ops.send(PendingOperation::TopKeywords(keywords));
+                    }
+                    Err(e) => {
+                        pending_ops.send(PendingOperation::Error(UiError::from_error("Analytics error", &e)));
+                    }
+                }
+            });
+        }
+    }
+
+    fn history_filter(&self) -> ConversationFilter {
+        let text = self.history_filter_text.trim();
+        let model = self.history_model_filter.trim();
+        
+        ConversationFilter {
+            model: if model.is_empty() { None } else { Some(model.to_string()) },
+            after: self.history_days.map(|days| Local::now() - chrono::Duration::days(days)),
+            before: None,
+            text: if text.is_empty() { None } else { Some(text.to_string()) },
+            tag: None,
+            exclude_downvoted: false,
+        }
+    }
+
+    fn rag_filter(&self) -> ConversationFilter {
+        ConversationFilter {
+            model: if self.rag_current_model_only { Some(self.model_name.clone()) } else { None },
+            after: self.rag_max_age_days.map(|days| Local::now() - chrono::Duration::days(days)),
+            before: None,
+            text: None,
+            tag: self.active_tags().into_iter().next().filter(|_| self.rag_filter_by_tag),
+            exclude_downvoted: self.rag_exclude_downvoted,
+        }
+    }
+
+    fn active_tags(&self) -> Vec<String> {
+        let tag = RagSystem::normalize_tag(&self.active_tag);
+        if tag.is_empty() { Vec::new() } else { vec![tag] }
+    }
+
+    fn refresh_tags(&mut self) {
+        if let Some(rag_system) = &self.rag_system {
+            let rag_system = rag_system.clone();
+            let pending_ops = self.pending_operations.clone();
+            let rt = self.rt.clone();
+
+            rt.spawn(async move {
+                match rag_system.list_tags().await {
+                    Ok(tags) => {
+                        pending_ops.send(PendingOperation::Tags(tags));
+                    }
+                    Err(e) => {
+                        pending_ops.send(PendingOperation::Error(UiError::from_error("Tag error", &e)));
+                    }
+                }
+            });
+        }
+    }
+
+    fn refresh_documents(&mut self) {
+        if let Some(rag_system) = &self.rag_system {
+            let rag_system = rag_system.clone();
+            let pending_ops = self.pending_operations.clone();
+            let rt = self.rt.clone();
+
+            rt.spawn(async move {
+                match rag_system.list_documents().await {
+                    Ok(documents) => {
+                        pending_ops.send(PendingOperation::Documents(documents));
+                    }
+                    Err(e) => {
+                        pending_ops.send(PendingOperation::Error(UiError::from_error("Document error", &e)));
+                    }
+                }
+            });
+        }
+    }
+
+    fn load_prompt_history(&mut self) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = rag_system.recent_prompts(PROMPT_HISTORY_SIZE).await;
+            match result {
+                Ok(prompts) => pending_ops.send(PendingOperation::PromptHistory(prompts)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("History error", &e))),
+            }
+        });
+    }
+
+    // Up/Down in an empty input (or while still showing a recalled prompt) walks the history
+    // Long pasted text is held back to be offered as an attachment. Pasting with nothing
+    // but an image on the clipboard attaches the image, where the platform reports the keys.
+    fn handle_paste(&mut self, ctx: &egui::Context) {
+        let id = egui::Id::new(CHAT_INPUT_ID);
+        if !ctx.memory(|memory| memory.has_focus(id)) {
+            return;
+        }
+        
+        let (text, paste_key) = ctx.input(|i| {
+            let text = i.events.iter().find_map(|event| match event {
+                egui::Event::Paste(text) => Some(text.clone()),
+                _ => None,
+            });
+            let paste_key = i.events.iter().any(|event| matches!(
+                event,
+                egui::Event::Key { key: egui::Key::V, pressed: true, modifiers, .. } if modifiers.command
+            ));
+            (text, paste_key)
+        });
+        
+        match text {
+            Some(text) if text.lines().count() > LONG_PASTE_LINES => {
+                ctx.input_mut(|i| i.events.retain(|event| !matches!(event, egui::Event::Paste(_))));
+                self.long_paste = Some(text);
+            }
+            Some(_) => {}
+            None if paste_key => self.paste_clipboard_image(),
+            None => {}
+        }
+    }
+
+    fn paste_clipboard_image(&mut self) {
+        match FileHandler::clipboard_image() {
+            Ok(Some(attachment)) => self.attachments.push(Arc::new(attachment)),
+            Ok(None) => self.ui_errors.push(UiError::new("There is no image on the clipboard", Severity::Warning)),
+            Err(e) => self.add_attachment(Err(e)),
+        }
+    }
+
+    fn render_long_paste(&mut self, ui: &mut egui::Ui) {
+        let Some(lines) = self.long_paste.as_ref().map(|text| text.lines().count()) else {
+            return;
+        };
+        
+        // Some(true) attaches, Some(false) pastes into the input, None drops it
+        let mut choice = Option::<Option<bool>>::None;
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new(format!("Pasted {} lines", lines))
+                .size(12.0)
+                .color(egui::Color32::from_rgb(147, 197, 253)));
+            if ui.small_button("📎 Paste as attachment").clicked() {
+                choice = Some(Some(true));
+            }
+            if ui.small_button("Paste into message").clicked() {
+                choice = Some(Some(false));
+            }
+            if ui.small_button("Discard").clicked() {
+                choice = Some(None);
+            }
+        });
+        ui.add_space(4.0);
+        
+        let Some(choice) = choice else {
+            return;
+        };
+        let Some(text) = self.long_paste.take() else {
+            return;
+        };
+        match choice {
+            Some(true) => self.attachments.push(Arc::new(FileHandler::attachment_from_text(text))),
+            Some(false) => {
+                self.input_text.push_str(&text);
+                self.draft_changed_at = Some(std::time::Instant::now());
+            }
+            None => {}
+        }
+    }
+
+    fn handle_prompt_recall(&mut self, ctx: &egui::Context) {
+        let id = egui::Id::new(CHAT_INPUT_ID);
+        if !ctx.memory(|memory| memory.has_focus(id)) || self.prompt_history.is_empty() {
+            return;
+        }
+        
+        // Any edit to a recalled prompt leaves recall mode and keeps the edit
+        if let Some(index) = self.recall_index {
+            if self.prompt_history.get(index) != Some(&self.input_text) {
+                self.recall_index = None;
+            }
+        }
+        if self.recall_index.is_none() && !self.input_text.is_empty() {
+            return;
+        }
+        
+        let (up, down) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            )
+        });
+        
+        let next = match (up, down, self.recall_index) {
+            (true, _, None) => Some(self.prompt_history.len() - 1),
+            (true, _, Some(index)) => Some(index.saturating_sub(1)),
+            (false, true, Some(index)) if index + 1 < self.prompt_history.len() => Some(index + 1),
+            (false, true, Some(_)) => {
+                self.recall_index = None;
+                self.input_text.clear();
+                return;
+            }
+            _ => return,
+        };
+        
+        if let Some(index) = next {
+            self.recall_index = Some(index);
+            self.input_text = self.prompt_history[index].clone();
+            
+            let mut state = egui::text_edit::TextEditState::load(ctx, id).unwrap_or_default();
+            let end = egui::text::CCursor::new(self.input_text.chars().count());
+            state.cursor.set_char_range(Some(egui::text::CCursorRange::one(end)));
+            state.store(ctx, id);
+        }
+    }
+
+    fn refresh_templates(&mut self) {
+        let Some(library) = self.template_library.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = library.list().await;
+            match result {
+                Ok(templates) => pending_ops.send(PendingOperation::Templates(templates)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Template error", &e))),
+            }
+        });
+    }
+
+    fn refresh_starred(&mut self) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = rag_system.list_starred(STARRED_LIST_SIZE).await;
+            match result {
+                Ok(entries) => pending_ops.send(PendingOperation::Starred(entries)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Starred error", &e))),
+            }
+        });
+    }
+
+    // Flips the star on an assistant message, the sidebar list is reloaded afterwards
+    fn toggle_star(&mut self, index: usize) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let Some(message) = self.chat_messages.get_mut(index) else {
+            return;
+        };
+        let Some(conversation_id) = message.conversation_id else {
+            return;
+        };
+        message.starred = !message.starred;
+        let starred = message.starred;
+        
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = match rag_system.set_starred(conversation_id, starred).await {
+                Ok(()) => rag_system.list_starred(STARRED_LIST_SIZE).await,
+                Err(e) => Err(e),
+            };
+            
+            match result {
+                Ok(entries) => pending_ops.send(PendingOperation::Starred(entries)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Starred error", &e))),
+            }
+        });
+    }
+
+    fn record_verdict(&mut self, index: usize, side: ComparisonSide) {
+        let prompt = index.checked_sub(1)
+            .and_then(|previous| self.chat_messages.get(previous))
+            .filter(|message| message.is_user)
+            .map(|message| message.content.clone())
+            .unwrap_or_default();
+        let Some(message) = self.chat_messages.get_mut(index) else {
+            return;
+        };
+        let Some(comparison) = message.comparison.as_mut() else {
+            return;
+        };
+        comparison.verdict = Some(side);
+        
+        let record = ComparisonRecord {
+            timestamp: Local::now(),
+            prompt,
+            left_model: message.model_used.clone().unwrap_or_default(),
+            right_model: comparison.right.model_used.clone().unwrap_or_default(),
+            left_conversation_id: message.conversation_id,
+            right_conversation_id: comparison.right.conversation_id,
+            verdict: side,
+        };
+        let Some(analytics) = self.analytics_engine.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            if let Err(e) = analytics.record_comparison(record).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Comparison error", &e)));
+            }
+        });
+    }
+
+    fn set_feedback(&mut self, index: usize, value: i64) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let Some(message) = self.chat_messages.get_mut(index) else {
+            return;
+        };
+        let Some(conversation_id) = message.conversation_id else {
+            return;
+        };
+        message.feedback = value;
+        
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            if let Err(e) = rag_system.set_feedback(conversation_id, value).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Feedback error", &e)));
+            }
+        });
+        self.analytics_refresh_due = Some(std::time::Instant::now() + ANALYTICS_REFRESH_DELAY);
+    }
+
+    // Saves or deletes a template, then reloads the list
+    fn update_template(&mut self, template: PromptTemplate, delete: bool) {
+        let Some(library) = self.template_library.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = if delete {
+                library.delete(template.id).await
+            } else {
+                library.save(template).await
+            };
+            let result = match result {
+                Ok(()) => library.list().await,
+                Err(e) => Err(e),
+            };
+            
+            match result {
+                Ok(templates) => pending_ops.send(PendingOperation::Templates(templates)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Template error", &e))),
+            }
+        });
+    }
+
+    // Replaces the input with the template and selects its first unfilled placeholder
+    fn insert_template(&mut self, ctx: &egui::Context, template: &PromptTemplate) {
+        let attachment = self.attachments.iter()
+            .find(|attachment| attachment.kind == AttachmentKind::Text)
+            .map(|attachment| &*attachment.content);
+        let (text, placeholder) = TemplateLibrary::expand(&template.body, &self.input_text, attachment);
+        self.input_text = text;
+        
+        let (start, end) = placeholder.unwrap_or_else(|| {
+            let length = self.input_text.chars().count();
+            (length, length)
+        });
+        focus_chat_input(ctx, start, end);
+    }
+
+    fn use_example_prompt(&mut self, ctx: &egui::Context, prompt: String) {
+        self.input_text = prompt;
+        let length = self.input_text.chars().count();
+        focus_chat_input(ctx, length, length);
+    }
+
+    fn add_file_to_knowledge_base(&mut self, index: usize) {
+
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let Some(Attachment { name, content, kind: AttachmentKind::Text, .. }) = self.attachments.get(index).map(|attachment| Attachment::clone(attachment)) else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = match rag_system.add_document(&name, &content).await {
+                Ok(_) => rag_system.list_documents().await,
+                Err(e) => Err(e),
+            };
+            
+            match result {
+                Ok(documents) => pending_ops.send(PendingOperation::Documents(documents)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Document error", &e))),
+            }
+        });
+    }
+
+    fn pick_repository(&mut self) {
+        let Some(root) = FileHandler::pick_repository() else {
+            return;
+        };
+        match repo::list(&root, self.attachment_limit()) {
+            Ok(listing) => self.repo_listing = Some(listing),
+            Err(e) => self.ui_errors.push(UiError::new(e.to_string(), Severity::Warning)),
+        }
+    }
+
+    // Reads the ticked files and adds them to the knowledge base under their repo-relative
+    // paths, tagged with the branch and commit they were read at
+    fn add_repository_to_knowledge_base(&mut self, listing: RepoListing) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let limit = self.attachment_limit();
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let revision = listing.revision();
+            let results = match tokio::task::spawn_blocking(move || repo::read_selected(&listing, limit)).await {
+                Ok(results) => results,
+                Err(e) => {
+                    pending_ops.send(PendingOperation::Error(UiError::new(format!("Repository error: {}", e), Severity::Warning)));
+                    return;
+                }
+            };
+            
+            let mut documents = Vec::new();
+            let mut skipped = Vec::new();
+            for result in results {
+                match result {
+                    Ok(document) => documents.push(document),
+                    Err(e) => skipped.push(e.to_string()),
+                }
+            }
+            
+            let result = match rag_system.add_documents(documents, revision).await {
+                Ok(_) => rag_system.list_documents().await,
+                Err(e) => Err(e),
+            };
+            
+            if !skipped.is_empty() {
+                pending_ops.send(PendingOperation::Error(UiError::new(
+                    format!("{} repository file(s) skipped: {}", skipped.len(), skipped.join("; ")),
+                    Severity::Warning,
+                )));
+            }
+            match result {
+                Ok(documents) => pending_ops.send(PendingOperation::Documents(documents)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Document error", &e))),
+            }
+        });
+    }
+
+    fn remove_document(&mut self, name: String) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        
+        self.known_documents.retain(|document| document != &name);
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            if let Err(e) = rag_system.remove_document(&name).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Document error", &e)));
+            }
+        });
+    }
+
+    fn backup_database(&mut self) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let default_name = format!("conversations_backup_{}.db", Local::now().format("%Y%m%d_%H%M%S"));
+        let Some(path) = FileHandler::pick_save_path(&default_name, "SQLite database", &["db"]) else {
+            return;
+        };
+        
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let status = match rag_system.backup_to(path.clone()).await {
+                Ok(()) => format!("Backup saved to {}", path.display()),
+                Err(e) => format!("Backup failed: {}", e),
+            };
+            
+            pending_ops.send(PendingOperation::BackupStatus(status));
+        });
+    }
+
+    fn import_chatgpt_export(&mut self) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let Some(path) = FileHandler::pick_open_path("ChatGPT conversations.json", &["json"]) else {
+            return;
+        };
+        self.backup_status = Some(format!("Importing {}…", path.display()));
+        
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            match import::import_chatgpt(&rag_system, path).await {
+                Ok(report) => pending_ops.send(PendingOperation::ChatGptImported(report)),
+                Err(e) => pending_ops.send(PendingOperation::BackupStatus(format!("Import failed: {}", e))),
+            }
+        });
+    }
+
+    fn export_session_to_obsidian(&mut self, session_id: String) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let vault = self.config.obsidian_vault.trim();
+        if vault.is_empty() {
+            return;
+        }
+        let vault = std::path::PathBuf::from(vault);
+        self.obsidian_status = Some("Exporting…".to_string());
+        
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let status = match obsidian::export_session(&rag_system, vault, session_id).await {
+                Ok(path) => format!("Wrote {}", path.display()),
+                Err(e) => format!("Export failed: {}", e),
+            };
+            pending_ops.send(PendingOperation::ObsidianStatus(status));
+        });
+    }
+
+    // Quiet unless it fails, it runs after every answer
+    fn auto_export_session(&mut self) {
+        if !self.config.obsidian_auto_export {
+            return;
+        }
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let vault = self.config.obsidian_vault.trim();
+        if vault.is_empty() {
+            return;
+        }
+        let vault = std::path::PathBuf::from(vault);
+        let session_id = self.session_id.clone();
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            if let Err(e) = obsidian::export_session(&rag_system, vault, session_id).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Obsidian export error", &e)));
+            }
+        });
+    }
+
+    fn restore_database(&mut self, path: std::path::PathBuf) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = rag_system.restore_from(path.clone()).await;
+            
+            match result {
+                Ok(()) => {
+                    pending_ops.send(PendingOperation::BackupStatus(format!("Restored from {}", path.display())));
+                    pending_ops.send(PendingOperation::DatabaseRestored);
+                }
+                Err(e) => pending_ops.send(PendingOperation::BackupStatus(format!("Restore failed: {}", e))),
+            }
+        });
+    }
+
+    fn render_recovery_prompt(&mut self, ctx: &egui::Context) {
+        let Some(snapshot) = &self.pending_recovery else {
+            return;
+        };
+        
+        let mut choice = None;
+        egui::Window::new("Restore previous session?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("The app did not close cleanly last time.");
+                ui.label(format!(
+                    "{} message(s) and {} attachment(s) from {} can be restored.",
+                    snapshot.messages.len(),
+                    snapshot.attachments.len(),
+                    snapshot.saved_at.format("%Y-%m-%d %H:%M"),
+                ));
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("♻ Restore").clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("Discard").clicked() {
+                        choice = Some(false);
+                    }
+                });
+            });
+        
+        let Some(restore) = choice else {
+            return;
+        };
+        let Some(snapshot) = self.pending_recovery.take() else {
+            return;
+        };
+        if restore {
+            self.chat_messages = snapshot.messages;
+            self.attachments = snapshot.attachments;
+            if !snapshot.draft.trim().is_empty() {
+                self.input_text = snapshot.draft;
+            }
+            if !snapshot.model.is_empty() {
+                self.model_name = snapshot.model;
+            }
+            if !snapshot.session_id.is_empty() {
+                self.session_id = snapshot.session_id;
+            }
+            // Written again on the next frame
+            self.recovery_saved_len = usize::MAX;
+        } else if let Err(e) = config::clear_recovery() {
+            eprintln!("Error removing recovery file: {}", e);
+        }
+    }
+
+    fn render_restore_confirmation(&mut self, ctx: &egui::Context) {
+        let Some(path) = self.pending_restore.clone() else {
+            return;
+        };
+        
+        let mut confirmed = false;
+        let mut cancelled = false;
+        
+        egui::Window::new("Restore database?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("Restore conversations from {}?", path.display()));
+                ui.label(egui::RichText::new("This overwrites all current conversations, tags and documents.")
+                    .color(egui::Color32::from_rgb(239, 68, 68)));
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("♻ Restore").clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+        
+        if confirmed {
+            self.pending_restore = None;
+            self.restore_database(path);
+        } else if cancelled {
+            self.pending_restore = None;
+        }
+    }
+
+    fn apply_tag_action(&mut self, action: TagAction) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        
+        // Update the visible entry right away, the database write happens in the background
+        let (conversation_id, tag, adding) = match action {
+            TagAction::Add(id, tag) => (id, RagSystem::normalize_tag(&tag), true),
+            TagAction::Remove(id, tag) => (id, tag, false),
+        };
+        if tag.is_empty() {
+            return;
+        }
+        
+        if let Some(entry) = self.history_entries.iter_mut().find(|entry| entry.id == conversation_id) {
+            if adding {
+                if !entry.tags.contains(&tag) {
+                    entry.tags.push(tag.clone());
+                }
+            } else {
+                entry.tags.retain(|existing| existing != &tag);
+            }
+        }
+        
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = if adding {
+                rag_system.add_tag(conversation_id, &tag).await
+            } else {
+                rag_system.remove_tag(conversation_id, &tag).await
+            };
+            
+            let operation = match result {
+                Ok(()) => match rag_system.list_tags().await {
+                    Ok(tags) => PendingOperation::Tags(tags),
+                    Err(e) => PendingOperation::Error(UiError::from_error("Tag error", &e)),
+                },
+                Err(e) => PendingOperation::Error(UiError::from_error("Tag error", &e)),
+            };
+            
+            pending_ops.send(operation);
+        });
+    }
+
+    fn refresh_history(&mut self) {
+        if let Some(rag_system) = &self.rag_system {
+            let rag_system = rag_system.clone();
+            let filter = self.history_filter();
+            let offset = self.history_page * HISTORY_PAGE_SIZE;
+            let pending_ops = self.pending_operations.clone();
+            let rt = self.rt.clone();
+
+            rt.spawn(async move {
+                match rag_system.list_conversations(offset, HISTORY_PAGE_SIZE, &filter).await {
+                    Ok(entries) => {
+                        pending_ops.send(PendingOperation::History(entries));
+                    }
+                    Err(e) => {
+                        pending_ops.send(PendingOperation::Error(UiError::from_error("History error", &e)));
+                    }
+                }
+            });
+        }
+    }
+
+    fn load_history_entry(&mut self, entry: &ConversationEntry) {
+        self.chat_messages.push(ChatMessage {
+            content: entry.prompt.clone(),
+            is_user: true,
+            timestamp: entry.timestamp,
+            model_used: None,
+            response_time: None,
+            reasoning: None,
+            conversation_id: None,
+            truncated: false,
+            starred: false,
+            feedback: 0,
+            comparison: None,
+            annotations: Vec::new(),
+            tokens: None,
+            external: false,
+            queued: None,
+            history_dropped: 0,
+        });
+        self.chat_messages.push(ChatMessage {
+            content: entry.response.clone(),
+            is_user: false,
+            timestamp: entry.timestamp,
+            model_used: Some(entry.model_used.clone()),
+            response_time: Some(entry.response_time_ms),
+            reasoning: entry.reasoning.clone(),
+            conversation_id: Some(entry.id),
+            truncated: entry.status == ConversationStatus::Cancelled,
+            starred: entry.starred,
+            feedback: entry.feedback,
+            comparison: None,
+            annotations: Vec::new(),
+            tokens: None,
+            external: false,
+            queued: None,
+            history_dropped: 0,
+        });
+    }
+
+    fn check_async_updates(&mut self, ctx: &egui::Context) {
+        while let Ok(op) = self.pending_receiver.try_recv() {
+            match op {
+                PendingOperation::ResponseChunk(chunk) => {
+                    self.streaming_text.push_str(&chunk);
+                }
+                PendingOperation::Response(payload) => {
+                    self.push_assistant_message(payload, false);
+                    self.request_follow_ups();
+                    self.request_session_title();
+                    self.refresh_sessions();
+                    self.auto_export_session();
+                }
+                PendingOperation::Stopped(payload) => {
+                    self.push_assistant_message(payload, true);
+                }
+                PendingOperation::ExternalPrompt(prompt) => {
+                    self.chat_messages.push(ChatMessage {
+                        content: prompt,
+                        is_user: true,
+                        timestamp: Local::now(),
+                        model_used: None,
+                        response_time: None,
+                        reasoning: None,
+                        conversation_id: None,
+                        truncated: false,
+                        starred: false,
+                        feedback: 0,
+                        comparison: None,
+                        annotations: Vec::new(),
+                        tokens: None,
+                        external: true,
+                        queued: None,
+                        history_dropped: 0,
+                    });
+                }
+                PendingOperation::ExternalResponse(response) => {
+                    let mut message = compared_message(response);
+                    message.external = true;
+                    self.chat_messages.push(message);
+                    self.refresh_sessions();
+                    self.refresh_history();
+                    self.auto_export_session();
+                }
+                PendingOperation::Comparison(left, right) => {
+                    let mut message = compared_message(left);
+                    message.comparison = Some(Box::new(Comparison {
+                        right: compared_message(right),
+                        verdict: None,
+                    }));
+                    self.chat_messages.push(message);
+                    self.request_session_title();
+                    self.refresh_sessions();
+                    self.auto_export_session();
+                }
+                PendingOperation::Analytics(analytics) => {
+                    self.analytics = analytics;
+                    self.analytics_updated_at = Some(Local::now());
+                }
+                PendingOperation::DailyUsage(daily_usage) => {
+                    self.daily_usage = daily_usage;
+                }
+                PendingOperation::TopKeywords(keywords) => {
+                    self.top_keywords = keywords;
+                }
+                PendingOperation::LatencyCorrelation(correlation) => {
+                    self.latency_correlation = correlation;
+                }
+                PendingOperation::RagSuggestions(mut suggestions) => {
+                    self.rag_debounce.finish();
+                    // Keep exclusions for conversations that are still suggested
+                    for suggestion in &mut suggestions {
+                        suggestion.excluded = self.rag_suggestions.iter().any(|old| {
+                            old.excluded
+                                && old.entry.id == suggestion.entry.id
+                                && old.source == suggestion.source
+                        });
+                    }
+                    self.rag_suggestions = suggestions;
+                    self.expanded_suggestion = None;
+                }
+                PendingOperation::History(entries) => {
+                    self.history_entries = entries;
+                }
+                PendingOperation::Tags(tags) => {
+                    self.known_tags = tags;
+                }
+                PendingOperation::Documents(documents) => {
+                    self.known_documents = documents;
+                }
+                PendingOperation::Templates(templates) => {
+                    self.templates = templates;
+                }
+                PendingOperation::PromptHistory(mut prompts) => {
+                    // Prompts sent while loading are newer than anything in the database
+                    prompts.append(&mut self.prompt_history);
+                    prompts.dedup();
+                    self.prompt_history = prompts;
+                }
+                PendingOperation::BackupStatus(status) => {
+                    self.backup_status = Some(status);
+                }
+                PendingOperation::WebhookStatus(status) => {
+                    self.webhook_status = Some(status);
+                }
+                PendingOperation::ObsidianStatus(status) => {
+                    self.obsidian_status = Some(status);
+                }
+                PendingOperation::ConnectionChecked(reachable) => {
+                    self.connection_inflight = false;
+                    self.connected = Some(reachable);
+                    if reachable {
+                        self.drain_outbox();
+                    }
+                }
+                PendingOperation::Outbox(items) => {
+                    // Prompts still queued from an earlier run show up at the end of the chat
+                    for item in &items {
+                        if !self.chat_messages.iter().any(|message| message.queued == Some(item.id)) {
+                            self.chat_messages.push(ChatMessage {
+                                content: item.prompt.clone(),
+                                is_user: true,
+                                timestamp: item.created_at,
+                                model_used: None,
+                                response_time: None,
+                                reasoning: None,
+                                conversation_id: None,
+                                truncated: false,
+                                starred: false,
+                                feedback: 0,
+                                comparison: None,
+                                annotations: Vec::new(),
+                                tokens: None,
+                                external: false,
+                                queued: Some(item.id),
+                                history_dropped: 0,
+                            });
+                        }
+                    }
+                    self.outbox = items;
+                    self.drain_outbox();
+                }
+                PendingOperation::Queued { provisional, result } => {
+                    let index = self.chat_messages.iter().position(|message| message.queued == Some(provisional));
+                    match (result, index) {
+                        (Ok(item), Some(index)) => {
+                            self.chat_messages[index].queued = Some(item.id);
+                            self.outbox.push(item);
+                            // The server may have come back while the row was written
+                            self.drain_outbox();
+                        }
+                        // Cancelled before the row was written
+                        (Ok(item), None) => {
+                            if let Some(rag_system) = self.rag_system.clone() {
+                                let pending_ops = self.pending_operations.clone();
+                                self.rt.spawn(async move {
+                                    if let Err(e) = rag_system.remove_outbox(item.id).await {
+                                        pending_ops.send(PendingOperation::Error(UiError::from_error("Outbox error", &e)));
+                                    }
+                                });
+                            }
+                        }
+                        (Err(e), index) => {
+                            // The prompt goes back into the input rather than being lost
+                            if let Some(index) = index {
+                                let message = self.chat_messages.remove(index);
+                                self.message_heights.clear();
+                                if self.input_text.trim().is_empty() {
+                                    self.input_text = message.content;
+                                }
+                            }
+                            self.ui_errors.push(e);
+                        }
+                    }
+                }
+                PendingOperation::OutboxSent { outbox_id, response } => {
+                    self.outbox.retain(|item| item.id != outbox_id);
+                    self.outbox_sending = None;
+                    self.outbox_continue = true;
+                    // Answers for prompts no longer in the chat are still saved to their session
+                    if let Some(index) = self.chat_messages.iter().position(|message| message.queued == Some(outbox_id)) {
+                        self.chat_messages[index].queued = None;
+                        self.chat_messages.insert(index + 1, compared_message(response));
+                        self.message_heights.clear();
+                    }
+                    self.refresh_sessions();
+                }
+                PendingOperation::OutboxHeld(_) => {
+                    self.outbox_sending = None;
+                    self.connected = Some(false);
+                }
+                PendingOperation::RunningModels(result) => {
+                    self.running_models_inflight = false;
+                    // After a failure nothing is shown, the old list may no longer be true
+                    match result {
+                        Ok(models) => {
+                            self.running_models = Some(models);
+                            self.running_models_error = None;
+                        }
+                        Err(e) => {
+                            self.running_models = None;
+                            self.running_models_error = Some(format!("Unavailable: {}", e));
+                        }
+                    }
+                }
+                PendingOperation::Transcribed(result) => {
+                    self.transcribing = false;
+                    match result {
+                        Ok(text) => {
+                            if !self.input_text.is_empty() && !self.input_text.ends_with(char::is_whitespace) {
+                                self.input_text.push(' ');
+                            }
+                            self.input_text.push_str(&text);
+                            self.draft_changed_at = Some(std::time::Instant::now());
+                            if self.config.voice_auto_send && !self.is_loading {
+                                self.send_message(ctx);
+                            }
+                        }
+                        Err(e) => self.ui_errors.push(UiError::new(e, Severity::Error)),
+                    }
+                }
+                PendingOperation::ContextWindow { model, tokens } => {
+                    if model == self.model_name {
+                        self.context_window = tokens;
+                    }
+                }
+                PendingOperation::ConversationsDeleted => {
+                    self.refresh_history();
+                    self.refresh_starred();
+                    self.refresh_sessions();
+                    self.update_analytics();
+                }
+                PendingOperation::LastSession(entries) => {
+                    if entries.is_empty() {
+                        self.ui_errors.push(UiError::new("No earlier session to reopen", Severity::Warning));
+                    }
+                    for entry in &entries {
+                        self.load_history_entry(entry);
+                    }
+                }
+                PendingOperation::Starred(entries) => {
+                    self.starred_entries = entries;
+                }
+                PendingOperation::FollowUps { conversation_id, questions } => {
+                    if !questions.is_empty() {
+                        self.follow_ups = Some((conversation_id, questions));
+                    }
+                }
+                PendingOperation::CommandFinished(run) => {
+                    self.command_running = false;
+                    if let Some(run) = run {
+                        self.send_command_output(ctx, run);
+                    }
+                }
+                PendingOperation::PromptPreview { input, result } => {
+                    self.prompt_preview = Some((input, result));
+                }
+                PendingOperation::Models(models) => {
+                    self.available_models = models;
+                }
+                PendingOperation::Retried { parent, parent_id, response } => {
+                    // The retried answer may have moved, or gone, if messages were deleted meanwhile.
+                    // A gone one takes the retry with it, a saved copy is still in the history.
+                    let position = self.chat_messages.iter().position(|message| {
+                        !message.is_user && message.timestamp == parent && message.conversation_id == parent_id
+                    });
+                    if let Some(position) = position {
+                        self.chat_messages.insert(position + 1, compared_message(response));
+                        self.message_heights.clear();
+                    }
+                    self.refresh_sessions();
+                }
+                PendingOperation::Sessions(sessions) => {
+                    self.sessions = sessions;
+                }
+                PendingOperation::SessionOpened { session_id, entries } => {
+                    self.clear_chat();
+                    for entry in &entries {
+                        self.load_history_entry(entry);
+                    }
+                    self.session_id = session_id;
+                    self.session_title_requested = true;
+                }
+                PendingOperation::ChatImported(saved) => {
+                    for (index, conversation_id) in saved {
+                        if let Some(message) = self.chat_messages.get_mut(index) {
+                            message.conversation_id = Some(conversation_id);
+                        }
+                    }
+                    self.refresh_history();
+                    self.refresh_sessions();
+                    self.update_analytics();
+                }
+                PendingOperation::ChatGptImported(report) => {
+                    self.backup_status = Some(format!(
+                        "Imported {} conversations ({} exchanges), skipped {}",
+                        report.imported, report.turns, report.skipped,
+                    ));
+                    self.refresh_history();
+                    self.refresh_sessions();
+                    self.update_analytics();
+                }
+                PendingOperation::DatabaseRestored => {
+                    // Everything cached from the old database is stale now
+                    self.rag_suggestions.clear();
+                    self.expanded_suggestion = None;
+                    self.history_page = 0;
+                    self.refresh_history();
+                    self.refresh_tags();
+                    self.refresh_documents();
+                    self.refresh_templates();
+                    self.refresh_starred();
+                    self.refresh_sessions();
+                    self.update_analytics();
+                }
+                PendingOperation::IndexProgress(progress) => {
+                    if progress.finished {
+                        self.index_cancel = None;
+                    }
+                    self.index_progress = Some(progress);
+                }
+                PendingOperation::LoadingComplete => {
+                    let focused = ctx.input(|i| i.viewport().focused).unwrap_or(true);
+                    if self.is_loading && !focused && self.config.notify_on_finish {
+                        if let Some(message) = self.chat_messages.last().filter(|message| !message.is_user) {
+                            notifier::response_finished(ctx.clone(), &message.content, self.config.notify_sound);
+                        }
+                    }
+                    self.is_loading = false;
+                    self.generation_cancel = None;
+                    self.outbox_sending = None;
+                    self.streaming_text.clear();
+                    self.interactive_busy.store(false, Ordering::Relaxed);
+                    if std::mem::take(&mut self.outbox_continue) {
+                        self.drain_outbox();
+                    }
+                    self.refresh_tags();
+                    // Each completion pushes the deadline back, so a burst refreshes once
+                    self.analytics_refresh_due = Some(std::time::Instant::now() + ANALYTICS_REFRESH_DELAY);
+                }
+                PendingOperation::GenerationError { message, prompt } => {
+                    // Put the prompt back so a failed request doesn't lose it
+                    if self.input_text.trim().is_empty() {
+                        self.input_text = prompt;
+                    }
+                    self.chat_messages.push(ChatMessage {
+                        content: format!("Error: {}", message),
+                        is_user: false,
+                        timestamp: Local::now(),
+                        model_used: Some("Error".to_string()),
+                        response_time: None,
+                        reasoning: None,
+                        conversation_id: None,
+                        truncated: false,
+                        starred: false,
+                        feedback: 0,
+                        comparison: None,
+                        annotations: Vec::new(),
+                        tokens: None,
+                        external: false,
+                        queued: None,
+                        history_dropped: 0,
+                    });
+                    self.is_loading = false;
+                }
+                PendingOperation::RagFailed(error) => {
+                    self.rag_debounce.finish();
+                    self.ui_errors.push(error);
+                }
+                PendingOperation::Error(error) => {
+                    self.ui_errors.push(error);
+                }
+            }
+        }
+
+        if self.analytics_refresh_due.is_some_and(|due| std::time::Instant::now() >= due) {
+            self.analytics_refresh_due = None;
+            self.update_analytics();
+        }
+
+        self.debounced_rag_update(ctx);
+    }
+
+    fn debounced_rag_update(&mut self, ctx: &egui::Context) {
+        let now = std::time::Instant::now();
+        if self.rag_debounce.poll(&self.input_text, now) {
+            if !self.update_rag_suggestions() {
+                self.rag_debounce.finish();
+            }
+        } else if let Some(wait) = self.rag_debounce.remaining(now) {
+            ctx.request_repaint_after(wait);
+        }
+    }
+
+    fn handle_url_change(&mut self, url: String) {
+        self.backend = self.backend.with_url(url);
+        self.handle_backend_change();
+    }
+
+    // A different kind of server or API key needs a new backend, not just a new URL
+    fn rebuild_backend(&mut self) {
+        self.backend = Arc::from(backend_from_config(&self.config, &self.ollama_url));
+        self.handle_backend_change();
+    }
+
+    // Stops any running server and starts a new one if the API is enabled
+    fn restart_api_server(&mut self) {
+        self.api_server = None;
+        self.api_status = None;
+        if !self.config.api_enabled {
+            return;
+        }
+        
+        let bridge = Arc::new(ApiBridge {
+            settings: self.api_settings.clone(),
+            rag_system: self.rag_system.clone(),
+            analytics_engine: self.analytics_engine.clone(),
+            plugins: self.plugin_manager.clone(),
+            pending_ops: self.pending_operations.clone(),
+        });
+        let pending_ops = self.pending_operations.clone();
+        let started = self.rt.block_on(server::start(
+            &self.config.api_bind,
+            &self.config.api_token,
+            bridge,
+            self.rag_system.clone(),
+            self.analytics_engine.clone(),
+            move |e| pending_ops.send(PendingOperation::Error(UiError::from_error("API server stopped", &e))),
+        ));
+        match started {
+            Ok(server) => {
+                self.api_status = Some(format!("Listening on http://{}", server.address));
+                self.api_server = Some(server);
+                self.sync_api_settings();
+            }
+            Err(e) => self.api_status = Some(format!("Not serving: {}", e)),
+        }
+    }
+
+    fn send_test_webhook(&mut self) {
+        let Some(webhook) = self.webhook.clone() else {
+            return;
+        };
+        self.webhook_status = Some("Sending…".to_string());
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let status = match webhook.send(&WebhookPayload::test()).await {
+                Ok(()) => "Test webhook delivered".to_string(),
+                Err(e) => format!("Test webhook failed: {}", e),
+            };
+            pending_ops.send(PendingOperation::WebhookStatus(status));
+        });
+    }
+
+    // API requests use whatever backend, model and session the chat is on right now
+    fn sync_api_settings(&mut self) {
+        if self.api_server.is_none() {
+            return;
+        }
+        let mut settings = self.api_settings.write().unwrap_or_else(std::sync::PoisonError::into_inner);
+        settings.backend = self.backend.clone();
+        settings.keep_failed = self.config.keep_failed_generations;
+        settings.webhook = self.webhook.clone();
+        if settings.model != self.model_name {
+            settings.model = self.model_name.clone();
+        }
+        if settings.session_id != self.session_id {
+            settings.session_id = self.session_id.clone();
+        }
+    }
+
+    fn handle_backend_change(&mut self) {
+        // A new server gets checked right away
+        self.connected = None;
+        self.connection_checked = None;
+        // Whatever was loaded belongs to the previous server
+        self.running_models = None;
+        self.running_models_error = None;
+        self.plugin_models.set_backend(self.backend.clone());
+        self.refresh_context_window();
+        self.refresh_models();
+    }
+
+    fn refresh_context_window(&mut self) {
+        let backend = self.backend.clone();
+        let model = self.model_name.clone();
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            // Unreachable servers or unknown models fall back to the configured default
+            let tokens = match backend.context_window(&model).await {
+                Ok(tokens) => tokens,
+                Err(e) => {
+                    eprintln!("Could not read context window for {}: {}", model, e);
+                    None
+                }
+            };
+            pending_ops.send(PendingOperation::ContextWindow { model, tokens });
+        });
+    }
+
+    fn refresh_models(&mut self) {
+        let backend = self.backend.clone();
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            match backend.list_models().await {
+                Ok(models) => pending_ops.send(PendingOperation::Models(models)),
+                Err(e) => eprintln!("Could not list models: {}", e),
+            }
+        });
+    }
+
+    fn context_limit(&self) -> usize {
+        self.context_window.unwrap_or(self.config.default_num_ctx)
+    }
+
+    fn prompt_tokens(&self) -> usize {
+        self.input_tokens + self.context_tokens
+    }
+
+    fn over_context_window(&self) -> bool {
+        self.prompt_tokens() > self.context_limit()
+    }
+
+    // Counts the input on its own so typing doesn't re-tokenize large attachments
+    fn update_token_counts(&mut self) {
+        if self.counted_input != self.input_text {
+            self.input_tokens = self.token_counter.count(&self.input_text);
+            self.counted_input = self.input_text.clone();
+        }
+        
+        let included: Vec<i64> = self.rag_suggestions.iter()
+            .filter(|suggestion| !suggestion.excluded)
+            .map(|suggestion| suggestion.entry.id)
+            .collect();
+        let raw: Vec<bool> = self.attachments.iter().map(|attachment| attachment.include_raw).collect();
+        let context_key = (raw, included, self.enable_rag);
+        if self.counted_context != context_key {
+            let total = self.token_counter.count(&self.build_final_prompt());
+            self.context_tokens = total.saturating_sub(self.input_tokens);
+            self.counted_context = context_key;
+        }
+    }
+
+    fn load_file(&mut self) {
+        if let Some(path) = FileHandler::pick_attachment() {
+            self.attach_path(path);
+        }
+    }
+
+    fn attachment_limit(&self) -> u64 {
+        self.config.max_attachment_kb.max(1) * 1024
+    }
+
+    fn load_options(&self, truncation: Option<Truncation>) -> LoadOptions {
+        LoadOptions {
+            max_text_bytes: self.attachment_limit(),
+            truncation,
+            notebook_outputs: self.config.notebook_outputs,
+        }
+    }
+
+    // Over-limit text files wait for the user to choose the first or last part,
+    // archives for the user to pick the entries to extract
+    fn attach_path(&mut self, path: std::path::PathBuf) {
+        if archive::is_archive(&path) {
+            match archive::list(&path) {
+                Ok(listing) => self.archive_listing = Some(listing),
+                Err(e) => self.add_attachment(Err(e)),
+            }
+            return;
+        }
+        let limit = self.attachment_limit();
+        match FileHandler::oversized_text(&path, limit) {
+            Some(len) => self.oversized_files.push((path, len)),
+            None => self.add_attachment(FileHandler::load_attachment(&path, self.load_options(None))),
+        }
+    }
+
+    fn add_attachment(&mut self, result: Result<Attachment, AppError>) {
+        match result {
+            Ok(attachment) => {
+                if let Some(warning) = &attachment.warning {
+                    self.ui_errors.push(UiError::new(warning.clone(), Severity::Warning));
+                }
+                self.attachments.push(Arc::new(attachment));
+            }
+            Err(e) => self.chat_messages.push(ChatMessage {
+                content: format!("⚠ Could not attach file: {}", e),
+                is_user: false,
+                timestamp: Local::now(),
+                model_used: Some("Attachment".to_string()),
+                response_time: None,
+                reasoning: None,
+                conversation_id: None,
+                truncated: false,
+                starred: false,
+                feedback: 0,
+                comparison: None,
+                annotations: Vec::new(),
+                tokens: None,
+                external: false,
+                queued: None,
+                history_dropped: 0,
+            }),
+        }
+    }
+
+    // Ctrl+F searches the open chat, Ctrl+Shift+F searches every saved conversation
+    fn handle_search_shortcuts(&mut self, ctx: &egui::Context) {
+        let (search_history, search_chat, close) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::F),
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::F),
+                self.chat_search_open && i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+        
+        if search_history {
+            self.show_history = true;
+            self.focus_history_search = true;
+            self.refresh_history();
+        } else if search_chat {
+            self.chat_search_open = true;
+            self.chat_search_scroll = true;
+            self.focus_chat_search = true;
+        }
+        if close {
+            self.chat_search_open = false;
+            self.chat_search_query.clear();
+        }
+    }
+
+    // Indices of messages containing the search query
+    fn chat_search_matches(&self) -> Vec<usize> {
+        if !self.chat_search_open || self.chat_search_query.is_empty() {
+            return Vec::new();
+        }
+        self.chat_messages.iter()
+            .enumerate()
+            .filter(|(_, message)| !find_matches(&message.content, &self.chat_search_query).is_empty())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn step_chat_search(&mut self, forward: bool) {
+        let count = self.chat_search_matches().len();
+        if count == 0 {
+            return;
+        }
+        self.chat_search_current = if forward {
+            (self.chat_search_current + 1) % count
+        } else {
+            (self.chat_search_current + count - 1) % count
+        };
+        self.chat_search_scroll = true;
+    }
+
+    fn render_chat_search_bar(&mut self, ui: &mut egui::Ui) {
+        let matches = self.chat_search_matches();
+        if self.chat_search_current >= matches.len() {
+            self.chat_search_current = 0;
+        }
+        
+        let mut step = None;
+        let mut close = false;
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            let response = ui.add(egui::TextEdit::singleline(&mut self.chat_search_query)
+                .hint_text("Find in chat")
+                .desired_width(240.0));
+            if std::mem::take(&mut self.focus_chat_search) {
+                response.request_focus();
+            }
+            if response.changed() {
+                self.chat_search_current = 0;
+                self.chat_search_scroll = true;
+            }
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                step = Some(!ui.input(|i| i.modifiers.shift));
+                response.request_focus();
+            }
+            
+            let label = if matches.is_empty() {
+                if self.chat_search_query.is_empty() { String::new() } else { "No matches".to_string() }
+            } else {
+                format!("{} of {}", self.chat_search_current + 1, matches.len())
+            };
+            ui.label(egui::RichText::new(label).size(12.0).color(egui::Color32::GRAY));
+            
+            if ui.small_button("⬆").on_hover_text("Previous (Shift+Enter)").clicked() {
+                step = Some(false);
+            }
+            if ui.small_button("⬇").on_hover_text("Next (Enter)").clicked() {
+                step = Some(true);
+            }
+            if ui.small_button("❌").clicked() {
+                close = true;
+            }
+        });
+        
+        if let Some(forward) = step {
+            self.step_chat_search(forward);
+        }
+        if close {
+            self.chat_search_open = false;
+            self.chat_search_query.clear();
+        }
+    }
+
+    // Message text with search matches highlighted
+    fn message_text(&self, ui: &egui::Ui, text: &str, is_current_match: bool) -> egui::text::LayoutJob {
+        self.message_text_in(ui, text, is_current_match, egui::FontId::proportional(14.0))
+    }
+
+    fn message_text_in(&self, ui: &egui::Ui, text: &str, is_current_match: bool, font: egui::FontId) -> egui::text::LayoutJob {
+        let color = ui.visuals().text_color();
+        let mut job = egui::text::LayoutJob::default();
+        
+        let ranges = if self.chat_search_open {
+            find_matches(text, &self.chat_search_query)
+        } else {
+            Vec::new()
+        };
+        let highlight = if is_current_match {
+            egui::Color32::from_rgb(245, 158, 11)
+        } else {
+            egui::Color32::from_rgb(120, 90, 20)
+        };
+        
+        let mut position = 0;
+        for range in ranges {
+            job.append(&text[position..range.start], 0.0, egui::TextFormat::simple(font.clone(), color));
+            job.append(&text[range.clone()], 0.0, egui::TextFormat {
+                background: highlight,
+                ..egui::TextFormat::simple(font.clone(), egui::Color32::BLACK)
+            });
+            position = range.end;
+        }
+        job.append(&text[position..], 0.0, egui::TextFormat::simple(font, color));
+        
+        job
+    }
+
+    fn render_error_banner(&mut self, ui: &mut egui::Ui) {
+        let now = Local::now();
+        self.ui_errors.retain(|error| {
+            error.severity == Severity::Error
+                || (now - error.timestamp).num_seconds() < WARNING_LIFETIME_SECONDS
+        });
+        if self.ui_errors.is_empty() {
+            return;
+        }
+        
+        let mut dismissed = None;
+        for (index, error) in self.ui_errors.iter().enumerate() {
+            let (fill, icon) = match error.severity {
+                Severity::Warning => (egui::Color32::from_rgb(120, 90, 20), "⚠"),
+                Severity::Error => (egui::Color32::from_rgb(127, 29, 29), "⛔"),
+            };
+            egui::Frame::none()
+                .fill(fill)
+                .rounding(egui::Rounding::same(8.0))
+                .inner_margin(egui::Margin::symmetric(12.0, 6.0))
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} {}", icon, error.message));
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.small_button("✖").clicked() {
+                                dismissed = Some(index);
+                            }
+                            ui.label(egui::RichText::new(error.timestamp.format("%H:%M:%S").to_string())
+                                .size(11.0)
+                                .color(egui::Color32::LIGHT_GRAY));
+                        });
+                    });
+                });
+            ui.add_space(4.0);
+        }
+        if let Some(index) = dismissed {
+            self.ui_errors.remove(index);
+        }
+        
+        // Keep repainting so warnings disappear on time
+        if self.ui_errors.iter().any(|error| error.severity == Severity::Warning) {
+            ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+        }
+    }
+
+    fn render_copied_toast(&self, ctx: &egui::Context) {
+        let copied_at = ctx.data(|d| d.get_temp::<f64>(egui::Id::new(COPIED_TOAST_ID)));
+        let Some(copied_at) = copied_at else {
+            return;
+        };
+        let elapsed = ctx.input(|i| i.time) - copied_at;
+        if elapsed > COPIED_TOAST_SECONDS {
+            return;
+        }
+        
+        egui::Area::new(egui::Id::new(COPIED_TOAST_ID))
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -96.0])
+            .order(egui::Order::Tooltip)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label("Copied ✓");
+                });
+            });
+        ctx.request_repaint_after(std::time::Duration::from_secs_f64(COPIED_TOAST_SECONDS - elapsed));
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        let options = self.load_options(None);
+        for file in dropped {
+            let result = match (file.path, &file.bytes) {
+                (Some(path), _) => {
+                    self.attach_path(path);
+                    continue;
+                }
+                (None, Some(bytes)) => FileHandler::attachment_from_bytes(file.name.clone(), bytes.to_vec(), options),
+                (None, None) => Err(AppError::Other(format!("{} could not be read", file.name))),
+            };
+            self.add_attachment(result);
+        }
+        
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("file_drop_overlay")));
+            let screen = ctx.screen_rect();
+            painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(180));
+            painter.text(
+                screen.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop file to attach",
+                egui::FontId::proportional(24.0),
+                egui::Color32::WHITE,
+            );
+        }
+    }
+
+    fn section_open(&self, title: &str) -> bool {
+        self.config.open_sections.iter().any(|open| open == title)
+    }
+
+    fn record_section(&mut self, title: &str, open: bool) {
+        if open != self.section_open(title) {
+            if open {
+                self.config.open_sections.push(title.to_string());
+            } else {
+                self.config.open_sections.retain(|section| section != title);
+            }
+        }
+    }
+
+    fn track_window(&mut self, ctx: &egui::Context) {
+        let (outer_rect, inner_rect, monitor_size) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (viewport.outer_rect, viewport.inner_rect, viewport.monitor_size)
+        });
+        
+        // A position saved on a since-disconnected monitor would open the window out of reach
+        if !self.window_clamped {
+            if let (Some(outer), Some(monitor)) = (outer_rect, monitor_size) {
+                self.window_clamped = true;
+                let max_x = (monitor.x - outer.width()).max(0.0);
+                let max_y = (monitor.y - outer.height()).max(0.0);
+                let clamped = egui::pos2(outer.min.x.clamp(0.0, max_x), outer.min.y.clamp(0.0, max_y));
+                if clamped != outer.min {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(clamped));
+                    return;
+                }
+            }
+        }
+        
+        if let (Some(outer), Some(inner)) = (outer_rect, inner_rect) {
+            self.config.window = Some(WindowGeometry {
+                position: [outer.min.x, outer.min.y],
+                size: [inner.width(), inner.height()],
+            });
+        }
+    }
+
+    // Ctrl+= / Ctrl+- / Ctrl+0 and Ctrl+scroll, replacing egui's unclamped built-in zoom keys
+    fn handle_zoom_input(&mut self, ctx: &egui::Context) {
+        let (zoom_in, zoom_out, reset, scroll_zoom) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::Equals)
+                    || i.consume_key(egui::Modifiers::COMMAND, egui::Key::Plus),
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::Minus),
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::Num0),
+                i.zoom_delta(),
+            )
+        });
+        
+        let mut zoom = self.config.zoom;
+        if zoom_in {
+            zoom += ZOOM_STEP;
+        }
+        if zoom_out {
+            zoom -= ZOOM_STEP;
+        }
+        if reset {
+            zoom = 1.0;
+        }
+        zoom *= scroll_zoom;
+        self.config.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        
+        if ctx.zoom_factor() != self.config.zoom {
+            ctx.set_zoom_factor(self.config.zoom);
+        }
+    }
+
+    fn persist_draft(&mut self, ctx: &egui::Context) {
+        if self.input_text == self.saved_draft {
+            self.draft_changed_at = None;
+            return;
+        }
+        
+        // Typing restarts the timer (see render_input_area), so the file is written once it pauses
+        let changed_at = *self.draft_changed_at.get_or_insert_with(std::time::Instant::now);
+        if changed_at.elapsed() < DRAFT_SAVE_DELAY {
+            ctx.request_repaint_after(DRAFT_SAVE_DELAY);
+            return;
+        }
+        
+        match config::save_draft(&self.input_text) {
+            Ok(()) => self.saved_draft = self.input_text.clone(),
+            Err(e) => eprintln!("Error saving draft: {}", e),
+        }
+        self.draft_changed_at = None;
+    }
+
+    // Written whenever a message is added and every few seconds in between, so a streaming
+    // answer is kept too. Nothing is written while the last session's snapshot is unanswered.
+    fn persist_recovery(&mut self, ctx: &egui::Context) {
+        if self.pending_recovery.is_some() {
+            return;
+        }
+        
+        let appended = self.chat_messages.len() != self.recovery_saved_len;
+        let interval = std::time::Duration::from_secs(self.config.autosave_seconds);
+        let due = !interval.is_zero() && self.recovery_saved_at.elapsed() >= interval;
+        if !appended && !due {
+            if !interval.is_zero() {
+                ctx.request_repaint_after(interval);
+            }
+            return;
+        }
+        
+        self.recovery_saved_at = std::time::Instant::now();
+        self.recovery_saved_len = self.chat_messages.len();
+        let result = if self.chat_messages.is_empty() && self.attachments.is_empty() {
+            config::clear_recovery()
+        } else {
+            config::save_recovery(&SessionSnapshot {
+                saved_at: Local::now(),
+                messages: self.chat_messages.clone(),
+                attachments: self.attachments.clone(),
+                draft: self.input_text.clone(),
+                model: self.model_name.clone(),
+                session_id: self.session_id.clone(),
+            })
+        };
+        if let Err(e) = result {
+            eprintln!("Error saving recovery file: {}", e);
+        }
+    }
+
+    // The first close request is held back while answers are still being saved. Streaming is
+    // stopped so the partial answer gets saved, indexing is cancelled, and the window closes
+    // once the saves finish or SHUTDOWN_TIMEOUT runs out.
+    fn handle_close_request(&mut self, ctx: &egui::Context) {
+        while let Some(result) = self.save_tasks.try_join_next() {
+            if let Err(e) = result {
+                eprintln!("Save task failed: {}", e);
+            }
+        }
+        
+        if self.closing_since.is_none() {
+            if !ctx.input(|i| i.viewport().close_requested()) || self.save_tasks.is_empty() {
+                return;
+            }
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.closing_since = Some(std::time::Instant::now());
+            self.stop_generation();
+            if let Some(cancel) = &self.index_cancel {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        let Some(closing_since) = self.closing_since else {
+            return;
+        };
+        
+        let timed_out = closing_since.elapsed() >= SHUTDOWN_TIMEOUT;
+        if !self.save_tasks.is_empty() && !timed_out {
+            if ctx.input(|i| i.viewport().close_requested()) {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            }
+            ctx.request_repaint_after(std::time::Duration::from_millis(50));
+            return;
+        }
+        
+        // Whatever arrived while waiting goes into the snapshot
+        self.check_async_updates(ctx);
+        if timed_out {
+            eprintln!("Closing with {} unsaved answers, keeping the recovery file", self.save_tasks.len());
+            self.keep_recovery = true;
+            self.recovery_saved_len = usize::MAX;
+            self.persist_recovery(ctx);
+        }
+        self.save_tasks.abort_all();
+        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+    }
+
+    fn render_shutdown_overlay(&self, ctx: &egui::Context) {
+        let Some(closing_since) = self.closing_since else {
+            return;
+        };
+        let remaining = SHUTDOWN_OVERLAY_DELAY.saturating_sub(closing_since.elapsed());
+        if !remaining.is_zero() {
+            ctx.request_repaint_after(remaining);
+            return;
+        }
+        
+        let screen = ctx.screen_rect();
+        ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("shutdown_shade")))
+            .rect_filled(screen, 0.0, egui::Color32::from_black_alpha(160));
+        egui::Area::new(egui::Id::new("shutdown_overlay"))
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .order(egui::Order::Tooltip)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Finishing up…");
+                    });
+                });
+            });
+    }
+
+    fn persist_config(&mut self, ctx: &egui::Context) {
+        self.config.show_sidebar = self.show_sidebar;
+        self.config.show_history = self.show_history;
+        self.track_window(ctx);
+        
+        if self.config == self.saved_config {
+            self.config_dirty_since = None;
+            return;
+        }
+        
+        let dirty_since = *self.config_dirty_since.get_or_insert_with(std::time::Instant::now);
+        if dirty_since.elapsed() < CONFIG_SAVE_DELAY {
+            ctx.request_repaint_after(CONFIG_SAVE_DELAY);
+            return;
+        }
+        
+        match self.config.save() {
+            Ok(()) => self.saved_config = self.config.clone(),
+            Err(e) => eprintln!("Error saving config: {}", e),
+        }
+        self.config_dirty_since = None;
+    }
+
+    // The cleared messages stay on the undo stack for a while
+    fn clear_chat(&mut self) {
+        let messages = std::mem::take(&mut self.chat_messages);
+        let session_id = std::mem::replace(&mut self.session_id, new_session_id());
+        if !messages.is_empty() {
+            self.undo_stack.push((UndoItem::Chat(messages, session_id), std::time::Instant::now()));
+        }
+        self.session_title_requested = false;
+        self.editing_message = None;
+    }
+
+    fn remove_attachment(&mut self, index: usize) {
+        if index < self.attachments.len() {
+            let attachment = self.attachments.remove(index);
+            self.undo_stack.push((UndoItem::Attachment(index, attachment), std::time::Instant::now()));
+        }
+    }
+
+    // Deleting a prompt that has an answer asks first whether to take the answer with it
+    fn delete_message(&mut self, index: usize) {
+        let has_answer = self.chat_messages.get(index).is_some_and(|message| message.is_user)
+            && self.chat_messages.get(index + 1).is_some_and(|message| !message.is_user);
+        if has_answer {
+            self.confirm_delete = Some(index);
+        } else {
+            self.remove_messages(vec![index]);
+        }
+    }
+
+    fn remove_messages(&mut self, mut indices: Vec<usize>) {
+        indices.sort_unstable();
+        indices.dedup();
+        indices.retain(|&index| index < self.chat_messages.len());
+        if indices.is_empty() {
+            return;
+        }
+        
+        let mut removed = Vec::new();
+        for &index in indices.iter().rev() {
+            removed.push((index, self.chat_messages.remove(index)));
+        }
+        removed.reverse();
+        
+        self.editing_message = None;
+        self.message_heights.clear();
+        self.undo_stack.push((UndoItem::Messages(removed), std::time::Instant::now()));
+    }
+
+    // Drops undo entries past their window, deleted messages leave the database only now
+    fn expire_undo(&mut self) {
+        let (expired, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.undo_stack)
+            .into_iter()
+            .partition(|(_, removed_at)| removed_at.elapsed() >= UNDO_TIMEOUT);
+        self.undo_stack = kept;
+        
+        let ids: Vec<i64> = expired.iter().flat_map(|(item, _)| item.conversation_ids()).collect();
+        self.delete_conversations(ids);
+    }
+
+    fn delete_conversations(&mut self, ids: Vec<i64>) {
+        if ids.is_empty() {
+            return;
+        }
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            for id in ids {
+                if let Err(e) = rag_system.delete_conversation(id).await {
+                    pending_ops.send(PendingOperation::Error(UiError::from_error("Delete failed", &e)));
+                    return;
+                }
+            }
+            pending_ops.send(PendingOperation::ConversationsDeleted);
+        });
+    }
+
+    fn render_delete_confirmation(&mut self, ctx: &egui::Context) {
+        let Some(index) = self.confirm_delete else {
+            return;
+        };
+        
+        let mut choice = None;
+        egui::Window::new("Delete message?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("This prompt has an answer. Delete the answer as well?");
+                ui.label(egui::RichText::new("A deleted answer is also removed from history and RAG context.")
+                    .size(12.0)
+                    .color(egui::Color32::GRAY));
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("🗑 Delete both").clicked() {
+                        choice = Some(vec![index, index + 1]);
+                    }
+                    if ui.button("Prompt only").clicked() {
+                        choice = Some(vec![index]);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(Vec::new());
+                    }
+                });
+            });
+        
+        if let Some(indices) = choice {
+            self.confirm_delete = None;
+            self.remove_messages(indices);
+        }
+    }
+
+    fn render_oversized_file(&mut self, ctx: &egui::Context) {
+        let Some((path, len)) = self.oversized_files.first() else {
+            return;
+        };
+        let limit_kb = self.attachment_limit() / 1024;
+        
+        let mut choice = None;
+        egui::Window::new("File too large")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                ui.label(format!("{} is {} KB, over the {} KB attachment limit.", name, len / 1024, limit_kb));
+                ui.label(egui::RichText::new("Part of it can be attached instead, marked as truncated for the model.")
+                    .size(12.0)
+                    .color(egui::Color32::GRAY));
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button(format!("Attach first {} KB", limit_kb)).clicked() {
+                        choice = Some(Some(Truncation::Head));
+                    }
+                    if ui.button(format!("Attach last {} KB", limit_kb)).clicked() {
+                        choice = Some(Some(Truncation::Tail));
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(None);
+                    }
+                });
+            });
+        
+        if let Some(truncation) = choice {
+            let (path, _) = self.oversized_files.remove(0);
+            if truncation.is_some() {
+                let result = FileHandler::load_attachment(&path, self.load_options(truncation));
+                self.add_attachment(result);
+            }
+        }
+    }
+
+    fn render_archive_listing(&mut self, ctx: &egui::Context) {
+        let Some(listing) = &mut self.archive_listing else {
+            return;
+        };
+        let selected: Vec<u64> = listing.entries.iter().filter(|entry| entry.selected).map(|entry| entry.size).collect();
+        let selected_bytes: u64 = selected.iter().sum();
+        let over_limit = selected_bytes > archive::MAX_EXTRACTED_BYTES;
+        
+        let mut choice = None;
+        egui::Window::new(format!("📦 {}", listing.name))
+            .collapsible(false)
+            .resizable(true)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                if listing.entries.is_empty() {
+                    ui.label("This archive has no files that can be extracted.");
+                }
+                ui.horizontal(|ui| {
+                    if ui.small_button("All").clicked() {
+                        listing.entries.iter_mut().for_each(|entry| entry.selected = true);
+                    }
+                    if ui.small_button("None").clicked() {
+                        listing.entries.iter_mut().for_each(|entry| entry.selected = false);
+                    }
+                });
+                egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                    for entry in &mut listing.entries {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut entry.selected, entry.path.as_str());
+                            ui.label(egui::RichText::new(format_size(entry.size)).size(12.0).color(egui::Color32::GRAY));
+                        });
+                    }
+                });
+                ui.add_space(8.0);
+                let summary = format!("{} files selected, {}", selected.len(), format_size(selected_bytes));
+                if over_limit {
+                    ui.label(egui::RichText::new(format!(
+                        "{}, over the {} extraction limit",
+                        summary,
+                        format_size(archive::MAX_EXTRACTED_BYTES),
+                    )).color(egui::Color32::from_rgb(239, 68, 68)));
+                } else {
+                    ui.label(summary);
+                }
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!selected.is_empty() && !over_limit, egui::Button::new("Extract and attach")).clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(false);
+                    }
+                });
+            });
+        
+        if let Some(extract) = choice {
+            let Some(listing) = self.archive_listing.take() else {
+                return;
+            };
+            if extract {
+                match archive::extract(&listing, self.load_options(None)) {
+                    Ok(results) => results.into_iter().for_each(|result| self.add_attachment(result)),
+                    Err(e) => self.add_attachment(Err(e)),
+                }
+            }
+        }
+    }
+
+    fn render_repo_listing(&mut self, ctx: &egui::Context) {
+        let Some(listing) = &mut self.repo_listing else {
+            return;
+        };
+        let selected: Vec<u64> = listing.files.iter().filter(|file| file.selected).map(|file| file.size).collect();
+        let tokens: u64 = selected.iter().map(|&size| repo::estimated_tokens(size)).sum();
+        
+        let mut choice = None;
+        egui::Window::new(format!("🗂 {}", listing.name))
+            .collapsible(false)
+            .resizable(true)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                if let Some(revision) = listing.revision() {
+                    ui.label(egui::RichText::new(revision).monospace().color(egui::Color32::GRAY));
+                }
+                ui.label(egui::RichText::new("Files ignored by .gitignore are not listed.")
+                    .size(12.0)
+                    .color(egui::Color32::GRAY));
+                ui.add_space(4.0);
+                egui::ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                    render_repo_tree(ui, &mut listing.files, 0);
+                });
+                ui.add_space(8.0);
+                ui.label(format!(
+                    "{} files selected, {}, about {} tokens",
+                    selected.len(),
+                    format_size(selected.iter().sum()),
+                    tokens,
+                ));
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!selected.is_empty(), egui::Button::new("Add to knowledge base")).clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(false);
+                    }
+                });
+            });
+        
+        if let Some(add) = choice {
+            let Some(listing) = self.repo_listing.take() else {
+                return;
+            };
+            if add {
+                self.add_repository_to_knowledge_base(listing);
+            }
+        }
+    }
+
+    // Commands are never run without the user clicking Run on this dialog
+    fn render_command_approval(&mut self, ctx: &egui::Context) {
+        if self.pending_command.is_none() && !self.command_running {
+            self.pending_command = self.command_queue.pop();
+        }
+        let Some(request) = &self.pending_command else {
+            return;
+        };
+        
+        let mut choice = None;
+        egui::Window::new("Run command?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("The assistant wants to run:");
+                ui.add_space(4.0);
+                egui::Frame::none()
+                    .fill(egui::Color32::from_rgb(32, 33, 35))
+                    .rounding(egui::Rounding::same(6.0))
+                    .inner_margin(egui::Margin::same(8.0))
+                    .show(ui, |ui| {
+                        ui.label(egui::RichText::new(&request.command).monospace());
+                    });
+                ui.add_space(4.0);
+                if request.allowed {
+                    ui.label(egui::RichText::new("Its output will be sent back as your next message.")
+                        .size(12.0)
+                        .color(egui::Color32::GRAY));
+                } else {
+                    ui.label(egui::RichText::new("This program is not on the plugin's allowlist.")
+                        .size(12.0)
+                        .color(egui::Color32::from_rgb(239, 68, 68)));
+                }
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(request.allowed && !self.is_loading, egui::Button::new("▶ Run")).clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("Dismiss").clicked() {
+                        choice = Some(false);
+                    }
+                });
+            });
+        
+        match choice {
+            Some(true) => {
+                if let Some(request) = self.pending_command.take() {
+                    self.run_approved_command(ctx, request.command);
+                }
+            }
+            Some(false) => self.pending_command = None,
+            None => {}
+        }
+    }
+
+    fn run_approved_command(&mut self, ctx: &egui::Context, command: String) {
+        self.command_running = true;
+        let analytics_engine = self.analytics_engine.clone();
+        let pending_ops = self.pending_operations.clone();
+        let ctx = ctx.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let run = match tokio::task::spawn_blocking(move || plugins::run_command(&command)).await {
+                Ok(run) => run,
+                Err(e) => {
+                    pending_ops.send(PendingOperation::Error(UiError::new(format!("Command failed: {}", e), Severity::Warning)));
+                    pending_ops.send(PendingOperation::CommandFinished(None));
+                    return;
+                }
+            };
+            if let Some(analytics) = &analytics_engine {
+                if let Err(e) = analytics.record_command(run.clone()).await {
+                    pending_ops.send(PendingOperation::Error(UiError::from_error("Error recording command", &e)));
+                }
+            }
+            pending_ops.send(PendingOperation::CommandFinished(Some(run)));
+            ctx.request_repaint();
+        });
+    }
+
+    // Sent straight away, or left in the input when another response is still coming
+    fn send_command_output(&mut self, ctx: &egui::Context, run: CommandRun) {
+        let status = match (run.timed_out, run.exit_code) {
+            (true, _) => "timed out".to_string(),
+            (false, Some(code)) => format!("exited with code {}", code),
+            (false, None) => "did not finish".to_string(),
+        };
+        let message = format!("Output of `{}` ({}):\n\n```\n{}\n```", run.command, status, run.output.trim_end());
+        if self.is_loading {
+            self.input_text = message;
+            return;
+        }
+        let draft = std::mem::replace(&mut self.input_text, message);
+        self.send_message(ctx);
+        self.input_text = draft;
+    }
+
+    fn undo(&mut self) {
+        self.expire_undo();
+        match self.undo_stack.pop() {
+            // Anything sent since goes after the restored chat
+            Some((UndoItem::Chat(messages, session_id), _)) => {
+                // The old session carries on unless something was already sent in the new one
+                if self.chat_messages.is_empty() {
+                    self.session_id = session_id;
+                    self.session_title_requested = true;
+                }
+                self.chat_messages.splice(0..0, messages);
+            }
+            Some((UndoItem::Attachment(index, attachment), _)) => {
+                let index = index.min(self.attachments.len());
+                self.attachments.insert(index, attachment);
+            }
+            Some((UndoItem::Messages(messages), _)) => {
+                for (index, message) in messages {
+                    let index = index.min(self.chat_messages.len());
+                    self.chat_messages.insert(index, message);
+                }
+                self.message_heights.clear();
+            }
+            None => {}
+        }
+    }
+
+    // Ctrl+Z in a text field stays the field's own undo
+    fn handle_undo_shortcut(&mut self, ctx: &egui::Context) {
+        if self.undo_stack.is_empty() || ctx.memory(|memory| memory.focused().is_some()) {
+            return;
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z)) {
+            self.undo();
+        }
+    }
+
+    fn request_follow_ups(&mut self) {
+        self.follow_ups = None;
+        if !self.config.suggest_follow_ups {
+            return;
+        }
+        let [.., prompt, answer] = self.chat_messages.as_slice() else {
+            return;
+        };
+        let Some(conversation_id) = answer.conversation_id.filter(|_| prompt.is_user && !answer.is_user) else {
+            return;
+        };
+        
+        let suggester = FollowUpSuggester {
+            client: self.backend.background(),
+            model: answer.model_used.clone().unwrap_or_else(|| self.model_name.clone()),
+            prompt: prompt.content.clone(),
+            answer: answer.content.clone(),
+            interactive_busy: self.interactive_busy.clone(),
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let questions = suggester.run().await.unwrap_or_else(|e| {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Follow-up suggestions failed", &e)));
+                Vec::new()
+            });
+            pending_ops.send(PendingOperation::FollowUps { conversation_id, questions });
+        });
+    }
+
+    fn refresh_sessions(&mut self) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = rag_system.list_sessions(SESSION_LIST_SIZE).await;
+            match result {
+                Ok(sessions) => pending_ops.send(PendingOperation::Sessions(sessions)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Sessions error", &e))),
+            }
+        });
+    }
+
+    fn open_session(&mut self, session_id: String) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = rag_system.session_conversations(session_id.clone()).await;
+            match result {
+                Ok(entries) => pending_ops.send(PendingOperation::SessionOpened { session_id, entries }),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Sessions error", &e))),
+            }
+        });
+    }
+
+    // Link back to the session the open one was branched from
+    fn render_branch_breadcrumb(&mut self, ui: &mut egui::Ui) {
+        let Some(parent_id) = self.sessions.iter()
+            .find(|session| session.id == self.session_id)
+            .and_then(|session| session.parent_id.clone())
+        else {
+            return;
+        };
+        let parent_title = self.sessions.iter()
+            .find(|session| session.id == parent_id)
+            .and_then(|session| session.title.clone())
+            .unwrap_or_else(|| "the original chat".to_string());
+        
+        let mut open_parent = false;
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("🌿 Branched from").size(12.0).color(egui::Color32::GRAY));
+            open_parent = ui.link(egui::RichText::new(parent_title).size(12.0)).clicked();
+        });
+        if open_parent {
+            self.open_session(parent_id);
+        }
+    }
+
+    fn rename_session(&mut self, session_id: String, title: String) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        if let Some(session) = self.sessions.iter_mut().find(|session| session.id == session_id) {
+            session.title = Some(title.clone());
+        }
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            if let Err(e) = rag_system.rename_session(session_id, title).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Sessions error", &e)));
+            }
+        });
+    }
+
+    // Names the session after its first answer, in the background and only once per session
+    fn request_session_title(&mut self) {
+        if self.session_title_requested {
+            return;
+        }
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let [.., prompt, answer] = self.chat_messages.as_slice() else {
+            return;
+        };
+        if !prompt.is_user || answer.is_user || answer.conversation_id.is_none() {
+            return;
+        }
+        self.session_title_requested = true;
+        
+        let titler = SessionTitler {
+            rag: rag_system.clone(),
+            client: self.backend.background(),
+            model: answer.model_used.clone().unwrap_or_else(|| self.model_name.clone()),
+            session_id: self.session_id.clone(),
+            prompt: prompt.content.clone(),
+            answer: answer.content.clone(),
+            interactive_busy: self.interactive_busy.clone(),
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            match titler.run().await {
+                Ok(Some(_)) => {
+                    if let Ok(sessions) = rag_system.list_sessions(SESSION_LIST_SIZE).await {
+                        pending_ops.send(PendingOperation::Sessions(sessions));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("Error generating session title", &e))),
+            }
+        });
+    }
+
+    fn reopen_last_session(&mut self) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = rag_system.last_session().await;
+            match result {
+                Ok(entries) => pending_ops.send(PendingOperation::LastSession(entries)),
+                Err(e) => pending_ops.send(PendingOperation::Error(UiError::from_error("History error", &e))),
+            }
+        });
+    }
+
+    fn render_clear_confirmation(&mut self, ctx: &egui::Context) {
+        if !self.confirm_clear_chat {
+            return;
+        }
+        
+        let mut confirmed = false;
+        let mut cancelled = false;
+        
+        egui::Window::new("Start a new chat?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("This clears {} messages from the chat.", self.chat_messages.len()));
+                ui.label(egui::RichText::new("Saved conversations stay in history. Ctrl+Z brings the chat back for 30 seconds.")
+                    .size(12.0)
+                    .color(egui::Color32::GRAY));
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("➕ New Chat").clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+        
+        if confirmed {
+            self.confirm_clear_chat = false;
+            self.clear_chat();
+        } else if cancelled {
+            self.confirm_clear_chat = false;
+        }
+    }
+
+    fn render_undo_toast(&mut self, ctx: &egui::Context) {
+        self.expire_undo();
+        let Some((item, removed_at)) = self.undo_stack.last() else {
+            return;
+        };
+        let text = match item {
+            UndoItem::Chat(messages, _) => format!("Cleared {} messages", messages.len()),
+            UndoItem::Attachment(_, attachment) => format!("Removed {}", attachment.name),
+            UndoItem::Messages(messages) => format!("Deleted {} messages", messages.len()),
+        };
+        let remaining = UNDO_TIMEOUT.saturating_sub(removed_at.elapsed());
+        
+        let mut undo_clicked = false;
+        egui::Area::new(egui::Id::new("undo_toast"))
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -140.0])
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(text);
+                        undo_clicked = ui.link("Undo").on_hover_text("Ctrl+Z").clicked();
+                    });
+                });
+            });
+        
+        if undo_clicked {
+            self.undo();
+        }
+        ctx.request_repaint_after(remaining);
+    }
+
+    fn import_chat(&mut self) {
+        let Some(path) = FileHandler::pick_open_path("Chat export", &["json"]) else {
+            return;
+        };
+        let result = std::fs::read_to_string(&path)
+            .map_err(AppError::from)
+            .and_then(|json| export::from_json(&json));
+        let chat = match result {
+            Ok(chat) => chat,
+            Err(e) => {
+                self.ui_errors.push(UiError::new(format!("Import failed: {}", e), Severity::Warning));
+                return;
+            }
+        };
+        
+        // Ids from another database mean nothing here
+        self.chat_messages = chat.messages;
+        for message in &mut self.chat_messages {
+            message.conversation_id = None;
+        }
+        self.editing_message = None;
+        
+        if self.import_to_history {
+            self.save_imported_chat(chat.model);
+        }
+    }
+
+    // Stores each prompt/response pair as a conversation, grouped under one session
+    fn save_imported_chat(&mut self, model: String) {
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        
+        let mut pairs = Vec::new();
+        for (index, window) in self.chat_messages.windows(2).enumerate() {
+            let (prompt, response) = (&window[0], &window[1]);
+            if !prompt.is_user || response.is_user {
+                continue;
+            }
+            let entry = ConversationEntry {
+                id: 0,
+                timestamp: prompt.timestamp,
+                prompt: prompt.content.clone(),
+                response: response.content.clone(),
+                model_used: response.model_used.clone().unwrap_or_else(|| model.clone()),
+                response_time_ms: response.response_time.unwrap_or(0),
+                file_context: None,
+                tags: Vec::new(),
+                status: ConversationStatus::Ok,
+                reasoning: response.reasoning.clone(),
+                first_token_ms: None,
+                starred: response.starred,
+                feedback: response.feedback,
+                backend: None,
+                source: None,
+            };
+            pairs.push((index, entry));
+        }
+        
+        let session_id = format!("import-{}", Local::now().format("%Y%m%d%H%M%S"));
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let mut saved = Vec::new();
+            for (index, entry) in pairs {
+                match rag_system.save_conversation(&entry).await {
+                    Ok(id) => {
+                        saved.push((index, id));
+                        saved.push((index + 1, id));
+                    }
+                    Err(e) => {
+                        pending_ops.send(PendingOperation::Error(UiError::from_error("Error saving imported chat", &e)));
+                        break;
+                    }
+                }
+            }
+            
+            let ids: Vec<i64> = saved.iter().step_by(2).map(|(_, id)| *id).collect();
+            let session_result = rag_system.assign_session(ids, session_id).await;
+            
+            if let Err(e) = session_result {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Error saving imported chat", &e)));
+            }
+            pending_ops.send(PendingOperation::ChatImported(saved));
+        });
+    }
+
+    fn export_chat(&self) {
+        let options = ExportOptions {
+            include_reasoning: self.export_include_reasoning,
+            include_response_times: self.export_include_response_times,
+        };
+        let result = match self.export_format {
+            ExportFormat::Text => Ok(export::to_text(&self.chat_messages, &options)),
+            ExportFormat::Markdown => Ok(export::to_markdown(&self.chat_messages, &self.model_name, &options)),
+            ExportFormat::Json => {
+                let settings = ExportSettings {
+                    enable_rag: self.enable_rag,
+                    rag_hybrid: self.config.rag_hybrid,
+                    embedding_model: self.embedding_model.clone(),
+                };
+                export::to_json(&self.chat_messages, &self.model_name, settings, &options)
+            }
+        };
+        
+        let result = result.and_then(|content| {
+            FileHandler::save_text_file(&content, "chat_export", self.export_format)
+        });
+        if let Err(e) = result {
+            eprintln!("Error saving chat: {}", e);
+        }
+    }
+}
+
+impl eframe::App for TouristApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_close_request(ctx);
+        self.sync_api_settings();
+        self.handle_zoom_input(ctx);
+        self.handle_dropped_files(ctx);
+        self.handle_search_shortcuts(ctx);
+        self.handle_undo_shortcut(ctx);
+        self.check_async_updates(ctx);
+        self.poll_connection(ctx);
+        
+        // Responses, chunks and other task results repaint through OpSender when they arrive
+        if let Some(due) = self.analytics_refresh_due {
+            ctx.request_repaint_after(due.saturating_duration_since(std::time::Instant::now()));
+        }
+
+        // Sidebar
+        egui::SidePanel::left("sidebar")
+            .resizable(false)
+            .exact_width(280.0)
+            .show_animated(ctx, self.show_sidebar, |ui| {
+                self.render_sidebar(ui);
+            });
+
+        // History browser
+        egui::SidePanel::right("history_panel")
+            .resizable(true)
+            .default_width(340.0)
+            .show_animated(ctx, self.show_history, |ui| {
+                self.render_history_panel(ui);
             });
 
         // Main chat area
         egui::CentralPanel::default().show(ctx, |ui| {
             self.render_chat_interface(ctx, ui);
         });
+
+        self.render_suggestion_popup(ctx);
+        self.render_recovery_prompt(ctx);
+        self.render_restore_confirmation(ctx);
+        self.render_clear_confirmation(ctx);
+        self.render_delete_confirmation(ctx);
+        self.render_command_approval(ctx);
+        self.render_oversized_file(ctx);
+        self.render_archive_listing(ctx);
+        self.render_repo_listing(ctx);
+        self.render_undo_toast(ctx);
+        self.render_copied_toast(ctx);
+        self.render_shutdown_overlay(ctx);
+        self.persist_config(ctx);
+        self.persist_draft(ctx);
+        self.persist_recovery(ctx);
+    }
+
+    // Deletions still inside their undo window are made final before closing
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        #[cfg(feature = "speech")]
+        if let Some(speaker) = self.speaker.take() {
+            speaker.stop();
+        }
+        
+        // A clean exit has nothing to recover
+        if !self.keep_recovery {
+            if let Err(e) = config::clear_recovery() {
+                eprintln!("Error removing recovery file: {}", e);
+            }
+        }
+        
+        let ids: Vec<i64> = self.undo_stack.iter().flat_map(|(item, _)| item.conversation_ids()).collect();
+        let Some(rag_system) = self.rag_system.clone() else {
+            return;
+        };
+        self.rt.block_on(async move {
+            for id in ids {
+                if let Err(e) = rag_system.delete_conversation(id).await {
+                    eprintln!("Delete failed: {}", e);
+                }
+            }
+        });
     }
 }
 
 impl TouristApp {
-    fn set_modern_theme(&self, ctx: &egui::Context) {
+    fn set_modern_theme(ctx: &egui::Context) {
         let mut visuals = egui::Visuals::dark();
         
         // Modern chat interface colors
@@ -386,110 +4264,1273 @@ impl TouristApp {
             ui.add_space(20.0);
         });
 
-        ui.separator();
-        ui.add_space(16.0);
+        ui.separator();
+        ui.add_space(16.0);
+
+        // New Chat Button
+        if ui.add_sized([260.0, 36.0], egui::Button::new("➕ New Chat")).clicked() {
+            if self.chat_messages.is_empty() {
+                self.clear_chat();
+            } else {
+                self.confirm_clear_chat = true;
+            }
+        }
+        ui.add_space(8.0);
+
+        if ui.add_sized([260.0, 36.0], egui::Button::new("📜 History")).clicked() {
+            self.show_history = !self.show_history;
+            if self.show_history {
+                self.refresh_history();
+            }
+        }
+        ui.add_space(12.0);
+
+        // Settings Section
+        let openness = egui::CollapsingHeader::new("⚙️ Settings")
+            .default_open(self.section_open("⚙️ Settings"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+            
+                ui.label("Model:");
+                if ui.text_edit_singleline(&mut self.model_name).lost_focus() {
+                    self.refresh_context_window();
+                }
+                ui.checkbox(&mut self.compare_mode, "Compare with a second model")
+                    .on_hover_text("Send each prompt to both models and show the answers side by side");
+                if self.compare_mode {
+                    ui.text_edit_singleline(&mut self.compare_model);
+                }
+                ui.add_space(8.0);
+            
+                ui.label("Zoom:");
+                ui.add(egui::Slider::new(&mut self.config.zoom, MIN_ZOOM..=MAX_ZOOM).step_by(0.05).suffix("x"))
+                    .on_hover_text("Ctrl+= / Ctrl+- / Ctrl+0, or Ctrl+scroll");
+                ui.add_space(8.0);
+            
+                ui.label("Fold answers longer than:");
+                ui.add(egui::Slider::new(&mut self.config.collapse_after_lines, 0..=200).suffix(" lines"))
+                    .on_hover_text("0 never folds");
+                ui.add_space(8.0);
+            
+                ui.horizontal(|ui| {
+                    ui.label("Text attachment limit:");
+                    ui.add(egui::DragValue::new(&mut self.config.max_attachment_kb).range(1..=102_400).suffix(" KB"));
+                });
+                ui.checkbox(&mut self.config.notebook_outputs, "Include notebook cell outputs");
+                ui.horizontal(|ui| {
+                    ui.label("Autosave chat every:");
+                    ui.add(egui::DragValue::new(&mut self.config.autosave_seconds).range(0..=3600).suffix(" s"))
+                        .on_hover_text("For recovery after a crash. 0 saves only when a message is added.");
+                });
+                ui.add_space(8.0);
+            
+                ui.label("Backend:");
+                let previous = self.config.backend;
+                egui::ComboBox::from_id_source("backend")
+                    .selected_text(self.config.backend.label())
+                    .show_ui(ui, |ui| {
+                        for kind in BackendKind::ALL {
+                            ui.selectable_value(&mut self.config.backend, kind, kind.label());
+                        }
+                    });
+                if self.config.backend != previous {
+                    self.rebuild_backend();
+                }
+                match self.config.backend {
+                    BackendKind::Ollama => {
+                        ui.label("Ollama URL:");
+                        if ui.text_edit_singleline(&mut self.ollama_url).changed() {
+                            self.handle_url_change(self.ollama_url.clone());
+                        }
+                        // Applied once editing is done, a new backend starts with an empty queue
+                        let concurrent = ui.horizontal(|ui| {
+                            ui.label("Parallel requests:");
+                            ui.add(egui::DragValue::new(&mut self.config.ollama_max_concurrent).range(1..=16))
+                                .on_hover_text("Chat comes first, titles, follow-ups and embeddings wait for a free slot")
+                        }).inner;
+                        let interval = ui.horizontal(|ui| {
+                            ui.label("Gap between requests:");
+                            ui.add(egui::DragValue::new(&mut self.config.ollama_min_interval_ms).range(0..=10_000).suffix(" ms"))
+                                .on_hover_text("Spaces out requests to a shared server")
+                        }).inner;
+                        if [concurrent, interval].iter().any(|response| response.drag_stopped() || response.lost_focus()) {
+                            self.rebuild_backend();
+                        }
+                    }
+                    BackendKind::OpenAiCompat => {
+                        ui.label("Server URL:");
+                        if ui.text_edit_singleline(&mut self.config.openai_url)
+                            .on_hover_text("The /v1 root, e.g. http://localhost:1234/v1 for LM Studio")
+                            .changed()
+                        {
+                            self.handle_url_change(self.config.openai_url.clone());
+                        }
+                        ui.label("API key:");
+                        let key = egui::TextEdit::singleline(&mut self.config.openai_api_key)
+                            .password(true)
+                            .hint_text("optional");
+                        if ui.add(key).changed() {
+                            self.rebuild_backend();
+                        }
+                    }
+                }
+                ui.add_space(8.0);
+            
+                ui.checkbox(&mut self.enable_rag, "🧠 Enable RAG");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.rag_use_history, "Chat history");
+                    ui.checkbox(&mut self.rag_use_documents, "Documents");
+                });
+                ui.add_space(8.0);
+            
+                ui.label("🏷 Active tag:");
+                ui.text_edit_singleline(&mut self.active_tag);
+            
+                if !self.known_tags.is_empty() {
+                    let mut selected_tag = None;
+                    ui.horizontal_wrapped(|ui| {
+                        for tag in &self.known_tags {
+                            if ui.selectable_label(self.active_tag == *tag, tag).clicked() {
+                                selected_tag = Some(tag.clone());
+                            }
+                        }
+                    });
+                    if let Some(tag) = selected_tag {
+                        // Clicking the active tag again clears it
+                        self.active_tag = if self.active_tag == tag { String::new() } else { tag };
+                    }
+                }
+            
+                ui.checkbox(&mut self.rag_filter_by_tag, "Only use context with this tag");
+                ui.checkbox(&mut self.rag_current_model_only, "Only use context from the current model");
+                ui.checkbox(&mut self.rag_exclude_downvoted, "Skip answers rated 👎");
+            
+                egui::ComboBox::from_label("Context age")
+                    .selected_text(history_range_label(self.rag_max_age_days))
+                    .show_ui(ui, |ui| {
+                        for days in [None, Some(7), Some(30), Some(90), Some(365)] {
+                            ui.selectable_value(&mut self.rag_max_age_days, days, history_range_label(days));
+                        }
+                    });
+                ui.add_space(8.0);
+            
+                ui.checkbox(&mut self.stream_responses, "Stream responses")
+                    .on_hover_text("Show the response as it is generated");
+                ui.checkbox(&mut self.config.notify_on_finish, "Notify when a response is ready")
+                    .on_hover_text("Only while the window is in the background");
+                ui.add_enabled(self.config.notify_on_finish, egui::Checkbox::new(&mut self.config.notify_sound, "Play a sound"));
+                ui.checkbox(&mut self.config.suggest_follow_ups, "Suggest follow-up questions")
+                    .on_hover_text("Runs a short extra generation after each answer");
+                ui.checkbox(&mut self.config.chat_history, "Send the chat history")
+                    .on_hover_text("The model sees earlier messages. The oldest are left out when they don't fit its context window.");
+                if self.config.chat_history {
+                    ui.label("System prompt:");
+                    ui.add(egui::TextEdit::multiline(&mut self.config.system_prompt)
+                        .desired_rows(2)
+                        .desired_width(f32::INFINITY));
+                    ui.add(egui::Slider::new(&mut self.config.history_keep_turns, 1..=20).text("messages always sent"));
+                }
+                ui.checkbox(&mut self.config.keep_failed_generations, "Keep failed generations")
+                    .on_hover_text("Save errors to the database for debugging. They are never used as RAG context.");
+                ui.add_space(8.0);
+            
+                ui.label("Embedding model:");
+                ui.text_edit_singleline(&mut self.embedding_model);
+                ui.add_space(4.0);
+            
+                ui.checkbox(&mut self.config.rag_hybrid, "Hybrid retrieval (keyword + vector)");
+                ui.add_enabled(
+                    self.config.rag_hybrid,
+                    egui::Slider::new(&mut self.config.hybrid_keyword_weight, 0.0..=1.0).text("keyword weight"),
+                );
+                ui.add_space(4.0);
+            
+                if let Some(cancel) = self.index_cancel.clone() {
+                    let progress = self.index_progress.unwrap_or_default();
+                    let fraction = if progress.total == 0 { 0.0 } else { progress.indexed as f32 / progress.total as f32 };
+                    ui.add(egui::ProgressBar::new(fraction)
+                        .text(format!("{} / {} indexed", progress.indexed, progress.total)));
+                    if ui.button("⏹ Cancel indexing").clicked() {
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                } else {
+                    if ui.button("🗂 Index old conversations").clicked() {
+                        self.start_indexing(ui.ctx());
+                    }
+                    if let Some(progress) = self.index_progress {
+                        ui.label(egui::RichText::new(format!("Last run indexed {} of {}", progress.indexed, progress.total))
+                            .size(11.0)
+                            .color(egui::Color32::GRAY));
+                    }
+                }
+            }).openness;
+        self.record_section("⚙️ Settings", openness > 0.5);
+        
+        ui.add_space(12.0);
+
+        // File Upload Section
+        let openness = egui::CollapsingHeader::new("📁 File Context")
+            .default_open(self.section_open("📁 File Context"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+            
+                ui.horizontal(|ui| {
+                    if ui.button("📎 Attach File").clicked() {
+                        self.load_file();
+                    }
+                    if ui.add_enabled(self.rag_system.is_some(), egui::Button::new("🗂 Attach git repo"))
+                        .on_hover_text("Add a repository's files to the knowledge base")
+                        .clicked()
+                    {
+                        self.pick_repository();
+                    }
+                });
+            
+                let mut attachment_to_remove = None;
+                let mut attachment_to_index = None;
+                for (index, attachment) in self.attachments.iter_mut().enumerate() {
+                    ui.add_space(4.0);
+                    ui.horizontal(|ui| {
+                        match attachment.kind {
+                            AttachmentKind::Text => {
+                                ui.label(format!("📄 {}", attachment.name))
+                                    .on_hover_text(attachment_location(attachment));
+                                if let Some(warning) = &attachment.warning {
+                                    ui.label(egui::RichText::new("⚠").color(egui::Color32::from_rgb(245, 158, 11)))
+                                        .on_hover_text(warning);
+                                }
+                                let details = match attachment.encoding.filter(|&encoding| encoding != "UTF-8") {
+                                    Some(encoding) => format!("{} chars · {}", attachment.content.chars().count(), encoding),
+                                    None => format!("{} chars", attachment.content.chars().count()),
+                                };
+                                ui.label(egui::RichText::new(details)
+                                    .size(11.0)
+                                    .color(egui::Color32::GRAY));
+                                if ui.small_button("📚").on_hover_text("Add to knowledge base").clicked() {
+                                    attachment_to_index = Some(index);
+                                }
+                            }
+                            AttachmentKind::Image => {
+                                ui.label(format!("🖼 {}", attachment.name))
+                                    .on_hover_text(attachment_location(attachment));
+                            }
+                        }
+                        if ui.small_button("❌").clicked() {
+                            attachment_to_remove = Some(index);
+                        }
+                    });
+                    if let Some(summary) = &attachment.summary {
+                        egui::CollapsingHeader::new(egui::RichText::new("Summary").size(12.0).color(egui::Color32::GRAY))
+                            .id_source(("attachment_summary", index))
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                ui.label(egui::RichText::new(summary).monospace().size(11.0));
+                            });
+                        let mut include_raw = attachment.include_raw;
+                        if ui.checkbox(&mut include_raw, "Send raw content instead")
+                            .on_hover_text("By default the model gets this summary rather than the whole file")
+                            .changed()
+                        {
+                            Arc::make_mut(attachment).include_raw = include_raw;
+                        }
+                    }
+                }
+                if let Some(index) = attachment_to_index {
+                    self.add_file_to_knowledge_base(index);
+                }
+                if let Some(index) = attachment_to_remove {
+                    self.remove_attachment(index);
+                }
+            
+                // Knowledge base
+                ui.add_space(8.0);
+                ui.label(egui::RichText::new("📚 Knowledge base").strong());
+                if self.known_documents.is_empty() {
+                    ui.label(egui::RichText::new("No documents yet").size(11.0).color(egui::Color32::GRAY));
+                }
+            
+                let mut document_to_remove = None;
+                for document in &self.known_documents {
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(format!("📄 {}", document)).size(12.0));
+                        if ui.small_button("❌").clicked() {
+                            document_to_remove = Some(document.clone());
+                        }
+                    });
+                }
+                if let Some(document) = document_to_remove {
+                    self.remove_document(document);
+                }
+            }).openness;
+        self.record_section("📁 File Context", openness > 0.5);
+
+        ui.add_space(12.0);
+
+        // Analytics Section
+        let openness = egui::CollapsingHeader::new("📊 Analytics")
+            .default_open(self.section_open("📊 Analytics"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+            
+                ui.label(format!("Total Requests: {}", self.analytics.total_requests));
+                ui.label(format!("Avg Response: {:.0}ms", self.analytics.avg_response_time));
+                if let (Some(streamed), Some(first_token)) = (self.analytics.avg_streamed_response_time, self.analytics.avg_first_token_time) {
+                    ui.label(egui::RichText::new(format!("Streamed: {:.0}ms total · {:.0}ms to first token", streamed, first_token))
+                        .size(11.0)
+                        .color(egui::Color32::GRAY));
+                }
+                if let Some(buffered) = self.analytics.avg_buffered_response_time {
+                    ui.label(egui::RichText::new(format!("Non-streamed: {:.0}ms", buffered))
+                        .size(11.0)
+                        .color(egui::Color32::GRAY));
+                }
+                ui.label(format!(
+                    "p50 {}ms · p90 {}ms · p99 {}ms",
+                    self.analytics.p50_response_time,
+                    self.analytics.p90_response_time,
+                    self.analytics.p99_response_time,
+                ));
+                ui.label(egui::RichText::new(format!(
+                    "Range: {}ms – {}ms",
+                    self.analytics.min_response_time,
+                    self.analytics.max_response_time,
+                )).size(11.0).color(egui::Color32::GRAY));
+                ui.label(format!("Model: {}", self.analytics.most_used_model));
+                ui.label(format!("Tokens: {}", self.analytics.total_tokens));
+                ui.label(format!(
+                    "Today: {} requests in {} sessions",
+                    self.analytics.requests_today,
+                    self.analytics.sessions_today,
+                ));
+                if self.analytics.sessions_today > 0 {
+                    ui.label(egui::RichText::new(format!("Avg session: {:.0} min", self.analytics.avg_session_minutes))
+                        .size(11.0)
+                        .color(egui::Color32::GRAY));
+                }
+                ui.label(format!(
+                    "Errors today: {} ({:.1}% overall)",
+                    self.analytics.errors_today,
+                    self.analytics.error_rate * 100.0,
+                ));
+                if self.analytics.plugin_requests > 0 {
+                    ui.label(egui::RichText::new(format!(
+                        "Plugin requests: {} today, {} total",
+                        self.analytics.plugin_requests_today,
+                        self.analytics.plugin_requests,
+                    )).size(11.0).color(egui::Color32::GRAY));
+                }
+                for feedback in &self.analytics.feedback_by_model {
+                    ui.label(egui::RichText::new(format!(
+                        "{}: {:.0}% 👍 of {} rated",
+                        feedback.model,
+                        feedback.positive_rate() * 100.0,
+                        feedback.positive + feedback.negative,
+                    )).size(11.0).color(egui::Color32::GRAY));
+                }
+            
+                if !self.analytics.recent_errors.is_empty() {
+                    ui.collapsing("Recent errors", |ui| {
+                        for error in &self.analytics.recent_errors {
+                            ui.label(egui::RichText::new(format!(
+                                "{} · {} · {}",
+                                error.timestamp.format("%b %d %H:%M"),
+                                error.kind,
+                                error.model,
+                            )).size(11.0).color(egui::Color32::from_rgb(239, 68, 68)))
+                            .on_hover_text(format!("{}\n{}", error.message, error.url));
+                        }
+                    });
+                }
+            
+                ui.add_space(8.0);
+                let mut usage_days = self.usage_days;
+                ui.horizontal(|ui| {
+                    ui.label("Usage:");
+                    for days in USAGE_RANGES {
+                        ui.selectable_value(&mut usage_days, days, format!("{}d", days));
+                    }
+                });
+                if usage_days != self.usage_days {
+                    self.usage_days = usage_days;
+                    self.update_analytics();
+                }
+                self.render_usage_chart(ui);
+            
+                ui.add_space(8.0);
+                ui.collapsing("Prompt size vs response time", |ui| {
+                    self.render_latency_correlation(ui);
+                });
+            
+                ui.add_space(8.0);
+                let mut topic_days = self.topic_days;
+                ui.horizontal(|ui| {
+                    ui.label("Topics:");
+                    for days in TOPIC_RANGES {
+                        let label = days.map_or("All".to_string(), |days| format!("{}d", days));
+                        ui.selectable_value(&mut topic_days, days, label);
+                    }
+                });
+                if topic_days != self.topic_days {
+                    self.topic_days = topic_days;
+                    self.update_analytics();
+                }
+                self.render_top_keywords(ui);
+            
+                if self.backend.name() == "ollama" {
+                    ui.add_space(8.0);
+                    self.render_running_models(ui);
+                }
+            
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("🔄 Refresh").clicked() {
+                        self.update_analytics();
+                    }
+                    if let Some(updated_at) = self.analytics_updated_at {
+                        ui.label(egui::RichText::new(format!("Updated {}", updated_at.format("%H:%M:%S")))
+                            .size(11.0)
+                            .color(egui::Color32::GRAY));
+                    }
+                });
+            }).openness;
+        self.record_section("📊 Analytics", openness > 0.5);
+
+        ui.add_space(12.0);
+
+        // RAG Suggestions
+        if self.enable_rag && !self.rag_suggestions.is_empty() {
+            let openness = egui::CollapsingHeader::new("🧠 Similar Conversations")
+                .default_open(self.section_open("🧠 Similar Conversations"))
+                .show(ui, |ui| {
+                    ui.add_space(8.0);
+                
+                    egui::ScrollArea::vertical()
+                        .max_height(150.0)
+                        .show(ui, |ui| {
+                            for (i, suggestion) in self.rag_suggestions.iter().take(3).enumerate() {
+                                let response = ui.group(|ui| {
+                                    ui.horizontal(|ui| {
+                                        ui.label(egui::RichText::new(format!("#{}", i + 1)).size(12.0));
+                                        ui.label(egui::RichText::new(format!("{:.0}%", suggestion.score * 100.0))
+                                            .size(11.0)
+                                            .color(egui::Color32::from_rgb(99, 102, 241)));
+                                        let source = match &suggestion.source {
+                                            ContextSource::Conversation => "💬 chat".to_string(),
+                                            ContextSource::Document { .. } => "📄 document".to_string(),
+                                        };
+                                        ui.label(egui::RichText::new(source).size(11.0).color(egui::Color32::GRAY));
+                                        if suggestion.excluded {
+                                            ui.label(egui::RichText::new("excluded").size(11.0).color(egui::Color32::GRAY));
+                                        }
+                                    });
+                                
+                                    let mut preview = egui::RichText::new(text::preview(&suggestion.entry.prompt, 60)).size(11.0);
+                                    if suggestion.excluded {
+                                        preview = preview.strikethrough().color(egui::Color32::GRAY);
+                                    }
+                                    ui.label(preview);
+                                }).response.interact(egui::Sense::click());
+                            
+                                if response.on_hover_text("Click to expand").clicked() {
+                                    self.expanded_suggestion = Some(i);
+                                }
+                                ui.add_space(4.0);
+                            }
+                        });
+                }).openness;
+            self.record_section("🧠 Similar Conversations", openness > 0.5);
+            ui.add_space(12.0);
+        }
+
+        // Prompt templates
+        let openness = egui::CollapsingHeader::new("📝 Templates")
+            .default_open(self.section_open("📝 Templates"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                let mut to_edit = None;
+                let mut to_delete = None;
+                for template in &self.templates {
+                    ui.horizontal(|ui| {
+                        ui.label(&template.name).on_hover_text(&template.body);
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.small_button("🗑").clicked() {
+                                to_delete = Some(template.clone());
+                            }
+                            if ui.small_button("✏").clicked() {
+                                to_edit = Some(template.clone());
+                            }
+                        });
+                    });
+                }
+                if let Some(template) = to_edit {
+                    self.template_draft = Some(template);
+                }
+                if let Some(template) = to_delete {
+                    self.update_template(template, true);
+                }
+                
+                ui.add_space(4.0);
+                let mut save = false;
+                let mut cancel = false;
+                if let Some(draft) = &mut self.template_draft {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut draft.name);
+                    ui.label("Prompt:");
+                    ui.add(egui::TextEdit::multiline(&mut draft.body).desired_rows(3));
+                    ui.label(egui::RichText::new("{{input}} takes the current message, {{file}} the first attached file")
+                        .size(11.0)
+                        .color(egui::Color32::GRAY));
+                    ui.horizontal(|ui| {
+                        let valid = !draft.name.trim().is_empty() && !draft.body.trim().is_empty();
+                        save = ui.add_enabled(valid, egui::Button::new("💾 Save")).clicked();
+                        cancel = ui.button("Cancel").clicked();
+                    });
+                } else if ui.button("➕ New template").clicked() {
+                    self.template_draft = Some(PromptTemplate::default());
+                }
+                
+                if save {
+                    if let Some(draft) = self.template_draft.take() {
+                        self.update_template(draft, false);
+                    }
+                } else if cancel {
+                    self.template_draft = None;
+                }
+            }).openness;
+        self.record_section("📝 Templates", openness > 0.5);
+
+        ui.add_space(12.0);
+
+        // Chat sessions, double-click one to rename it
+        let openness = egui::CollapsingHeader::new("💬 Sessions")
+            .default_open(self.section_open("💬 Sessions"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                if self.sessions.is_empty() {
+                    ui.label(egui::RichText::new("Past chats will show up here").size(11.0).color(egui::Color32::GRAY));
+                }
+                
+                let mut to_open = None;
+                let mut to_rename = None;
+                let mut to_export = None;
+                let mut finish_rename = false;
+                for session in &self.sessions {
+                    let title = session.title.as_deref().unwrap_or("New chat");
+                    
+                    if let Some((id, buffer)) = &mut self.renaming_session {
+                        if *id == session.id {
+                            let response = ui.add(egui::TextEdit::singleline(buffer).desired_width(f32::INFINITY));
+                            response.request_focus();
+                            if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                                finish_rename = true;
+                            } else if response.lost_focus() {
+                                let title = buffer.trim();
+                                if !title.is_empty() {
+                                    to_rename = Some((id.clone(), title.to_string()));
+                                }
+                                finish_rename = true;
+                            }
+                            continue;
+                        }
+                    }
+                    
+                    // Branches are indented under a marker, their parent is named on hover
+                    let response = ui.horizontal(|ui| {
+                        let mut hover = format!(
+                            "{} · {} conversations",
+                            session.last_active.format("%Y-%m-%d %H:%M"),
+                            session.conversations
+                        );
+                        if let Some(parent_id) = &session.parent_id {
+                            ui.add_space(12.0);
+                            ui.label(egui::RichText::new("↳").color(egui::Color32::GRAY));
+                            let parent = self.sessions.iter()
+                                .find(|parent| parent.id == *parent_id)
+                                .and_then(|parent| parent.title.as_deref())
+                                .unwrap_or("another chat");
+                            hover.push_str(&format!("\nBranched from {}", parent));
+                        }
+                        ui.selectable_label(session.id == self.session_id, title).on_hover_text(hover)
+                    }).inner;
+                    if response.double_clicked() {
+                        self.renaming_session = Some((session.id.clone(), title.to_string()));
+                    } else if response.clicked() && session.id != self.session_id {
+                        to_open = Some(session.id.clone());
+                    }
+                    response.context_menu(|ui| {
+                        if ui.add_enabled(!self.config.obsidian_vault.trim().is_empty(), egui::Button::new("Export to Obsidian")).clicked() {
+                            to_export = Some(session.id.clone());
+                            ui.close_menu();
+                        }
+                    });
+                }
+                if finish_rename {
+                    self.renaming_session = None;
+                }
+                if let Some((session_id, title)) = to_rename {
+                    self.rename_session(session_id, title);
+                }
+                if let Some(session_id) = to_open {
+                    self.open_session(session_id);
+                }
+                if let Some(session_id) = to_export {
+                    self.export_session_to_obsidian(session_id);
+                }
+            }).openness;
+        self.record_section("💬 Sessions", openness > 0.5);
+
+        ui.add_space(12.0);
+
+        // Plugins
+        let openness = egui::CollapsingHeader::new("🧩 Plugins")
+            .default_open(self.section_open("🧩 Plugins"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                match self.plugin_manager.try_write() {
+                    Ok(mut manager) => {
+                        let count = manager.plugin_count();
+                        let mut moved = None;
+                        for (position, plugin) in manager.plugins_mut().enumerate() {
+                            let mut enabled = plugin.is_enabled();
+                            let stage = match (plugin.runs_in(Stage::PrePrompt), plugin.runs_in(Stage::PostResponse)) {
+                                (true, true) => "Rewrites prompts and answers",
+                                (true, false) => "Rewrites prompts before sending",
+                                (false, true) => "Rewrites answers before they are shown",
+                                (false, false) => "Has nothing to do yet",
+                            };
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(position > 0, egui::Button::new("▲").small()).on_hover_text("Run earlier").clicked() {
+                                    moved = Some((plugin.name().to_string(), true));
+                                }
+                                if ui.add_enabled(position + 1 < count, egui::Button::new("▼").small()).on_hover_text("Run later").clicked() {
+                                    moved = Some((plugin.name().to_string(), false));
+                                }
+                                if ui.checkbox(&mut enabled, plugin.name()).on_hover_text(stage).changed() {
+                                    plugin.set_enabled(enabled);
+                                }
+                                let mut required = plugin.is_required();
+                                if ui.add_enabled(enabled, egui::Checkbox::new(&mut required, "required"))
+                                    .on_hover_text("Fail the request instead of skipping this plugin when it errors")
+                                    .changed()
+                                {
+                                    plugin.set_required(required);
+                                }
+                            });
+                            if enabled && render_plugin_settings(ui, plugin.as_mut()) {
+                                self.config.plugin_settings.insert(plugin.name().to_string(), plugin.config());
+                            }
+                        }
+                        if let Some((name, up)) = moved {
+                            if up {
+                                manager.move_up(&name);
+                            } else {
+                                manager.move_down(&name);
+                            }
+                            self.config.plugin_order = manager.order();
+                        }
+                    }
+                    // A generation is using them right now
+                    Err(_) => {
+                        ui.label(egui::RichText::new("Busy…").size(11.0).color(egui::Color32::GRAY));
+                        ui.ctx().request_repaint_after(std::time::Duration::from_millis(200));
+                    }
+                }
+                
+                ui.add_space(4.0);
+                egui::CollapsingHeader::new("Regex rules")
+                    .default_open(false)
+                    .show(ui, |ui| self.render_regex_rules(ui));
+            }).openness;
+        self.record_section("🧩 Plugins", openness > 0.5);
+
+        ui.add_space(12.0);
+
+        // Starred answers
+        let openness = egui::CollapsingHeader::new("⭐ Starred")
+            .default_open(self.section_open("⭐ Starred"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                if self.starred_entries.is_empty() {
+                    ui.label(egui::RichText::new("Star an answer to keep it here").size(11.0).color(egui::Color32::GRAY));
+                }
+                
+                let mut to_view = None;
+                for entry in &self.starred_entries {
+                    let preview = text::truncate_chars(&entry.prompt, 40);
+                    let response = ui.selectable_label(false, preview)
+                        .on_hover_text(format!("{} · {}", entry.timestamp.format("%Y-%m-%d %H:%M"), entry.model_used));
+                    if response.clicked() {
+                        to_view = Some(entry.clone());
+                    }
+                }
+                if let Some(entry) = to_view {
+                    self.load_history_entry(&entry);
+                }
+            }).openness;
+        self.record_section("⭐ Starred", openness > 0.5);
+
+        ui.add_space(12.0);
+
+        let openness = egui::CollapsingHeader::new("🌐 Local API")
+            .default_open(self.section_open("🌐 Local API"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                let mut restart = ui.checkbox(&mut self.config.api_enabled, "Serve HTTP API").changed();
+                ui.label("Listen on:");
+                ui.text_edit_singleline(&mut self.config.api_bind);
+                ui.label("Bearer token:");
+                ui.add(egui::TextEdit::singleline(&mut self.config.api_token)
+                    .password(true)
+                    .hint_text("required unless on 127.0.0.1"));
+                if self.config.api_enabled {
+                    restart |= ui.button("↻ Restart").on_hover_text("Apply address and token changes").clicked();
+                }
+                if restart {
+                    self.restart_api_server();
+                }
+                
+                if let Some(status) = &self.api_status {
+                    ui.add_space(4.0);
+                    ui.label(egui::RichText::new(status).size(11.0).color(egui::Color32::GRAY));
+                }
+                ui.add_space(8.0);
+            }).openness;
+        self.record_section("🌐 Local API", openness > 0.5);
+
+        ui.add_space(12.0);
+
+        let openness = egui::CollapsingHeader::new("🔔 Webhook")
+            .default_open(self.section_open("🔔 Webhook"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                ui.label("POST each saved answer to:");
+                let mut changed = ui.add(egui::TextEdit::singleline(&mut self.config.webhook_url)
+                    .hint_text("https://example.com/hook")).changed();
+                ui.label("Secret header:");
+                changed |= ui.add(egui::TextEdit::singleline(&mut self.config.webhook_secret)
+                    .password(true)
+                    .hint_text("sent as X-Webhook-Secret")).changed();
+                if changed {
+                    self.webhook = Webhook::new(&self.config.webhook_url, &self.config.webhook_secret);
+                    self.webhook_status = None;
+                }
+                
+                if ui.add_enabled(self.webhook.is_some(), egui::Button::new("📨 Send test webhook")).clicked() {
+                    self.send_test_webhook();
+                }
+                if let Some(status) = &self.webhook_status {
+                    ui.add_space(4.0);
+                    ui.label(egui::RichText::new(status).size(11.0).color(egui::Color32::GRAY));
+                }
+                ui.add_space(8.0);
+            }).openness;
+        self.record_section("🔔 Webhook", openness > 0.5);
+
+        ui.add_space(12.0);
+
+        let openness = egui::CollapsingHeader::new("🗒 Obsidian")
+            .default_open(self.section_open("🗒 Obsidian"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
+                
+                ui.label("Vault folder:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.config.obsidian_vault)
+                        .desired_width(ui.available_width() - 40.0)
+                        .hint_text("notes are written here"));
+                    if ui.button("📂").on_hover_text("Choose folder").clicked() {
+                        if let Some(path) = FileHandler::pick_vault() {
+                            self.config.obsidian_vault = path.display().to_string();
+                        }
+                    }
+                });
+                ui.checkbox(&mut self.config.obsidian_auto_export, "Export after every answer")
+                    .on_hover_text("Keeps the open session's note up to date");
+                
+                let can_export = self.rag_system.is_some() && !self.config.obsidian_vault.trim().is_empty();
+                if ui.add_enabled(can_export, egui::Button::new("📤 Export this session")).clicked() {
+                    self.export_session_to_obsidian(self.session_id.clone());
+                }
+                if let Some(status) = &self.obsidian_status {
+                    ui.add_space(4.0);
+                    ui.label(egui::RichText::new(status).size(11.0).color(egui::Color32::GRAY));
+                }
+                ui.add_space(8.0);
+            }).openness;
+        self.record_section("🗒 Obsidian", openness > 0.5);
 
-        // New Chat Button
-        if ui.add_sized([260.0, 36.0], egui::Button::new("➕ New Chat")).clicked() {
-            self.clear_chat();
-        }
         ui.add_space(12.0);
 
-        // Settings Section
-        ui.collapsing("⚙️ Settings", |ui| {
-            ui.add_space(8.0);
+        #[cfg(feature = "speech")]
+        self.render_speech_settings(ui);
+
+        #[cfg(feature = "voice")]
+        self.render_voice_settings(ui);
+
+        // Database backup
+        let openness = egui::CollapsingHeader::new("🗄 Database")
+            .default_open(self.section_open("🗄 Database"))
+            .show(ui, |ui| {
+                ui.add_space(8.0);
             
-            ui.label("Model:");
-            ui.text_edit_singleline(&mut self.model_name);
-            ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("💾 Backup").clicked() {
+                        self.backup_database();
+                    }
+                    if ui.button("♻ Restore").clicked() {
+                        if let Some(path) = FileHandler::pick_open_path("SQLite database", &["db", "sqlite"]) {
+                            self.pending_restore = Some(path);
+                        }
+                    }
+                });
+                if ui.button("📥 Import ChatGPT export")
+                    .on_hover_text("conversations.json from a ChatGPT data export")
+                    .clicked()
+                {
+                    self.import_chatgpt_export();
+                }
             
-            ui.label("Ollama URL:");
-            if ui.text_edit_singleline(&mut self.ollama_url).changed() {
-                self.handle_url_change();
-            }
-            ui.add_space(8.0);
+                if let Some(status) = &self.backup_status {
+                    ui.add_space(4.0);
+                    ui.label(egui::RichText::new(status).size(11.0).color(egui::Color32::GRAY));
+                }
+                ui.add_space(8.0);
             
-            ui.checkbox(&mut self.enable_rag, "🧠 Enable RAG");
-        });
-        
+                let encrypted = self.rag_system.as_ref().is_some_and(RagSystem::is_encrypted);
+                ui.label("Text copies of answers:");
+                let previous = self.config.text_dump;
+                ui.add_enabled_ui(!encrypted, |ui| {
+                    egui::ComboBox::from_id_source("text_dump")
+                        .selected_text(if encrypted { TextDump::Off.label() } else { self.config.text_dump.label() })
+                        .show_ui(ui, |ui| {
+                            for mode in TextDump::ALL {
+                                ui.selectable_value(&mut self.config.text_dump, mode, mode.label());
+                            }
+                        });
+                }).response.on_disabled_hover_text("Off while the database is encrypted");
+                if self.config.text_dump != previous {
+                    if let Some(rag) = &self.rag_system {
+                        rag.set_text_dump(self.config.text_dump);
+                    }
+                }
+                
+                ui.add_space(8.0);
+                if encrypted {
+                    ui.label(egui::RichText::new("🔒 Encrypted with SQLCipher").size(12.0).color(egui::Color32::from_rgb(34, 197, 94)));
+                } else if cfg!(feature = "encryption") {
+                    ui.checkbox(&mut self.config.encrypt_database, "🔒 Encrypt database")
+                        .on_hover_text("Asks for a passphrase and encrypts the database the next time the app starts");
+                }
+            }).openness;
+        self.record_section("🗄 Database", openness > 0.5);
+
         ui.add_space(12.0);
 
-        // File Upload Section
-        ui.collapsing("📁 File Context", |ui| {
-            ui.add_space(8.0);
-            
-            if ui.button("📎 Attach File").clicked() {
-                self.load_file();
+        // Export Chat
+        ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
+            ui.add_space(16.0);
+            ui.horizontal(|ui| {
+                if ui.button("📂 Import Chat").clicked() {
+                    self.import_chat();
+                }
+                ui.checkbox(&mut self.import_to_history, "Save to history");
+            });
+            if ui.button("💾 Export Chat").clicked() {
+                self.export_chat();
             }
-            
-            if let Some(filename) = self.file_name.clone() {
-                ui.add_space(4.0);
-                ui.horizontal(|ui| {
-                    ui.label(format!("📄 {}", filename));
-                    if ui.small_button("❌").clicked() {
-                        self.file_content.clear();
-                        self.file_name = None;
+            ui.checkbox(&mut self.export_include_response_times, "Include response times");
+            ui.checkbox(&mut self.export_include_reasoning, "Include reasoning");
+            egui::ComboBox::from_id_source("export_format")
+                .selected_text(self.export_format.label())
+                .show_ui(ui, |ui| {
+                    for format in ExportFormat::ALL {
+                        ui.selectable_value(&mut self.export_format, format, format.label());
                     }
                 });
-                
-                if !self.file_content.is_empty() {
-                    ui.add_space(4.0);
-                    ui.label(format!("{} characters", self.file_content.len()));
+        });
+    }
+
+    // Polled while it's on screen, so an unused panel doesn't keep asking the server
+    fn render_running_models(&mut self, ui: &mut egui::Ui) {
+        let now = std::time::Instant::now();
+        let due = self.running_models_polled.map_or(now, |polled| polled + RUNNING_MODELS_POLL);
+        if now >= due && !self.running_models_inflight {
+            self.refresh_running_models();
+        }
+        ui.ctx().request_repaint_after(due.saturating_duration_since(now).max(std::time::Duration::from_secs(1)));
+        
+        ui.label(egui::RichText::new("Loaded models").strong());
+        if let Some(error) = &self.running_models_error {
+            ui.label(egui::RichText::new(error).size(11.0).color(egui::Color32::GRAY));
+            return;
+        }
+        let Some(models) = &self.running_models else {
+            ui.label(egui::RichText::new("Checking…").size(11.0).color(egui::Color32::GRAY));
+            return;
+        };
+        if models.is_empty() {
+            ui.label(egui::RichText::new("None loaded").size(11.0).color(egui::Color32::GRAY));
+        }
+        
+        let mut to_unload = None;
+        for model in models {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(&model.name).size(12.0));
+                if ui.small_button("⏏").on_hover_text("Unload").clicked() {
+                    to_unload = Some(model.name.clone());
                 }
+            });
+            let mut details = format!("{} in memory, {} in VRAM", format_size(model.size), format_size(model.size_vram));
+            if let Some(expires_at) = model.expires_at.filter(|expires_at| *expires_at > Local::now()) {
+                details.push_str(&format!(" · until {}", expires_at.format("%H:%M")));
             }
+            ui.label(egui::RichText::new(details).size(11.0).color(egui::Color32::GRAY));
+        }
+        if let Some(model) = to_unload {
+            self.unload_model(model);
+        }
+    }
+
+    fn refresh_running_models(&mut self) {
+        self.running_models_polled = Some(std::time::Instant::now());
+        self.running_models_inflight = true;
+        let client = OllamaClient::new(self.backend.base_url().to_string());
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let running = client.list_running().await.map_err(|e| e.to_string());
+            pending_ops.send(PendingOperation::RunningModels(running));
         });
+    }
 
-        ui.add_space(12.0);
+    fn unload_model(&mut self, model: String) {
+        let client = OllamaClient::new(self.backend.base_url().to_string());
+        let pending_ops = self.pending_operations.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            if let Err(e) = client.unload(&model).await {
+                pending_ops.send(PendingOperation::Error(UiError::from_error("Unload error", &e)));
+            }
+            let running = client.list_running().await.map_err(|e| e.to_string());
+            pending_ops.send(PendingOperation::RunningModels(running));
+        });
+    }
 
-        // Analytics Section
-        ui.collapsing("📊 Analytics", |ui| {
-            ui.add_space(8.0);
-            
-            ui.label(format!("Total Requests: {}", self.analytics.total_requests));
-            ui.label(format!("Avg Response: {:.0}ms", self.analytics.avg_response_time));
-            ui.label(format!("Model: {}", self.analytics.most_used_model));
-            
-            ui.add_space(8.0);
-            if ui.button("🔄 Refresh").clicked() {
-                self.update_analytics();
+    fn render_top_keywords(&self, ui: &mut egui::Ui) {
+        let Some(max_count) = self.top_keywords.first().map(|(_, count)| *count as f32) else {
+            ui.label(egui::RichText::new("No topics yet").size(11.0).color(egui::Color32::GRAY));
+            return;
+        };
+        
+        // Simple tag cloud, more frequent terms are drawn larger
+        ui.horizontal_wrapped(|ui| {
+            for (word, count) in &self.top_keywords {
+                let size = 11.0 + 7.0 * (*count as f32 / max_count);
+                ui.label(egui::RichText::new(word).size(size).color(egui::Color32::from_rgb(147, 197, 253)))
+                    .on_hover_text(format!("{} mentions", count));
             }
         });
+    }
 
-        ui.add_space(12.0);
+    fn render_latency_correlation(&self, ui: &mut egui::Ui) {
+        let correlation = &self.latency_correlation;
+        if correlation.points.is_empty() {
+            ui.label(egui::RichText::new("No successful requests in range").size(11.0).color(egui::Color32::GRAY));
+            return;
+        }
+        
+        let pearson = correlation.pearson.map_or("n/a".to_string(), |r| format!("{:.2}", r));
+        ui.label(egui::RichText::new(format!(
+            "r = {} · +{:.1}ms per token · {} requests",
+            pearson,
+            correlation.slope,
+            correlation.total_rows,
+        )).size(11.0).color(egui::Color32::GRAY));
+        
+        let max_tokens = correlation.points.iter().map(|point| point[0]).fold(0.0, f64::max);
+        let fit = Line::new(PlotPoints::from(vec![
+            [0.0, correlation.intercept],
+            [max_tokens, correlation.intercept + correlation.slope * max_tokens],
+        ]))
+        .name("Linear fit")
+        .color(egui::Color32::from_rgb(245, 158, 11));
+        let points = Points::new(PlotPoints::from(correlation.points.clone()))
+            .name("Requests")
+            .radius(2.0)
+            .color(egui::Color32::from_rgb(59, 130, 246));
+        
+        Plot::new("latency_correlation_plot")
+            .height(160.0)
+            .legend(Legend::default())
+            .x_axis_label("prompt tokens")
+            .y_axis_label("ms")
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .include_x(0.0)
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                plot_ui.points(points);
+                plot_ui.line(fit);
+            });
+    }
 
-        // RAG Suggestions
-        if self.enable_rag && !self.rag_suggestions.is_empty() {
-            ui.collapsing("🧠 Similar Conversations", |ui| {
+    fn render_usage_chart(&self, ui: &mut egui::Ui) {
+        if self.daily_usage.is_empty() {
+            ui.label(egui::RichText::new("No usage data yet").size(11.0).color(egui::Color32::GRAY));
+            return;
+        }
+        
+        // x is days relative to today, so the most recent bar sits at 0
+        let today = Local::now().date_naive();
+        let days_ago = |date: chrono::NaiveDate| -((today - date).num_days() as f64);
+        
+        let bars: Vec<Bar> = self.daily_usage.iter()
+            .map(|day| {
+                Bar::new(days_ago(day.date), day.requests as f64)
+                    .name(format!("{}: {} requests", day.date.format("%b %d"), day.requests))
+                    .width(0.8)
+            })
+            .collect();
+        let chart = BarChart::new(bars)
+            .name("Requests")
+            .color(egui::Color32::from_rgb(59, 130, 246));
+        
+        // Response time shares the request axis, scaled so its peak meets the tallest bar
+        let max_requests = self.daily_usage.iter().map(|day| day.requests).max().unwrap_or(0).max(1) as f64;
+        let max_response = self.daily_usage.iter().map(|day| day.avg_response_ms).fold(0.0, f64::max);
+        let scale = if max_response > 0.0 { max_requests / max_response } else { 0.0 };
+        let points: Vec<[f64; 2]> = self.daily_usage.iter()
+            .filter(|day| day.requests > 0)
+            .map(|day| [days_ago(day.date), day.avg_response_ms * scale])
+            .collect();
+        let line = Line::new(PlotPoints::from(points))
+            .name(format!("Avg response (peak {:.0}ms)", max_response))
+            .color(egui::Color32::from_rgb(245, 158, 11));
+        
+        Plot::new("usage_plot")
+            .height(140.0)
+            .legend(Legend::default())
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(chart);
+                plot_ui.line(line);
+            });
+    }
+
+    fn render_suggestion_popup(&mut self, ctx: &egui::Context) {
+        let Some(index) = self.expanded_suggestion else {
+            return;
+        };
+        let Some(suggestion) = self.rag_suggestions.get_mut(index) else {
+            self.expanded_suggestion = None;
+            return;
+        };
+        
+        let mut open = true;
+        egui::Window::new(format!("Similar conversation #{} • {:.0}% match", index + 1, suggestion.score * 100.0))
+            .id(egui::Id::new("rag_suggestion_popup"))
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                let (source_label, prompt_label, response_label) = match &suggestion.source {
+                    ContextSource::Conversation => (
+                        format!(
+                            "From a previous conversation • {} • {}",
+                            suggestion.entry.timestamp.format("%Y-%m-%d %H:%M"),
+                            suggestion.entry.model_used
+                        ),
+                        "Prompt",
+                        "Response",
+                    ),
+                    ContextSource::Document { name, revision } => (
+                        format!("From your documents ({})", rag::document_label(name, revision.as_deref())),
+                        "Document",
+                        "Excerpt",
+                    ),
+                };
+                ui.label(egui::RichText::new(source_label).size(11.0).color(egui::Color32::GRAY));
                 ui.add_space(8.0);
                 
+                ui.label(egui::RichText::new(prompt_label).strong());
                 egui::ScrollArea::vertical()
+                    .id_source("suggestion_prompt")
                     .max_height(150.0)
                     .show(ui, |ui| {
-                        for (i, suggestion) in self.rag_suggestions.iter().take(3).enumerate() {
-                            ui.group(|ui| {
-                                ui.label(egui::RichText::new(format!("#{}", i + 1)).size(12.0));
-                                ui.label(egui::RichText::new(
-                                    if suggestion.prompt.len() > 60 {
-                                        format!("{}...", &suggestion.prompt[..60])
-                                    } else {
-                                        suggestion.prompt.clone()
-                                    }
-                                ).size(11.0));
-                            });
-                            ui.add_space(4.0);
-                        }
+                        ui.label(&suggestion.entry.prompt);
                     });
+                ui.add_space(8.0);
+                
+                ui.label(egui::RichText::new(response_label).strong());
+                egui::ScrollArea::vertical()
+                    .id_source("suggestion_response")
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        ui.label(&suggestion.entry.response);
+                    });
+                
+                ui.separator();
+                let mut use_as_context = !suggestion.excluded;
+                if ui.checkbox(&mut use_as_context, "Use as context").changed() {
+                    suggestion.excluded = !use_as_context;
+                }
             });
-            ui.add_space(12.0);
+        
+        if !open {
+            self.expanded_suggestion = None;
         }
+    }
 
-        // Export Chat
-        ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
-            ui.add_space(16.0);
-            if ui.button("💾 Export Chat").clicked() {
-                self.export_chat();
+    fn render_history_panel(&mut self, ui: &mut egui::Ui) {
+        ui.add_space(12.0);
+        ui.horizontal(|ui| {
+            ui.heading("📜 History");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.small_button("❌").clicked() {
+                    self.show_history = false;
+                }
+            });
+        });
+        ui.separator();
+        ui.add_space(8.0);
+
+        // Filters
+        let mut filters_changed = false;
+        
+        ui.label("Search:");
+        let search = ui.text_edit_singleline(&mut self.history_filter_text);
+        if std::mem::take(&mut self.focus_history_search) {
+            search.request_focus();
+        }
+        filters_changed |= search.changed();
+        ui.add_space(4.0);
+        
+        ui.label("Model:");
+        filters_changed |= ui.text_edit_singleline(&mut self.history_model_filter).changed();
+        ui.add_space(4.0);
+        
+        egui::ComboBox::from_label("Range")
+            .selected_text(history_range_label(self.history_days))
+            .show_ui(ui, |ui| {
+                for days in [None, Some(1), Some(7), Some(30), Some(90)] {
+                    filters_changed |= ui
+                        .selectable_value(&mut self.history_days, days, history_range_label(days))
+                        .changed();
+                }
+            });
+        
+        if filters_changed {
+            self.history_page = 0;
+            self.refresh_history();
+        }
+        
+        ui.add_space(8.0);
+
+        // Pagination
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.history_page > 0, egui::Button::new("◀ Prev")).clicked() {
+                self.history_page -= 1;
+                self.refresh_history();
+            }
+            
+            ui.label(format!("Page {}", self.history_page + 1));
+            
+            let has_next = self.history_entries.len() == HISTORY_PAGE_SIZE;
+            if ui.add_enabled(has_next, egui::Button::new("Next ▶")).clicked() {
+                self.history_page += 1;
+                self.refresh_history();
+            }
+        });
+        
+        ui.separator();
+
+        // Entries
+        let mut entry_to_load = None;
+        let mut tag_action = None;
+        
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            if self.history_entries.is_empty() {
+                ui.label(egui::RichText::new("No conversations found").color(egui::Color32::GRAY));
+            }
+            
+            for entry in &self.history_entries {
+                ui.group(|ui| {
+                    ui.set_width(ui.available_width());
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(format!(
+                            "{} • {}",
+                            entry.timestamp.format("%Y-%m-%d %H:%M"),
+                            entry.model_used
+                        )).size(11.0).color(egui::Color32::GRAY));
+                        
+                        if entry.status != ConversationStatus::Ok {
+                            ui.label(egui::RichText::new(format!("⚠ {}", entry.status.as_str()))
+                                .size(11.0)
+                                .color(egui::Color32::from_rgb(239, 68, 68)));
+                        }
+                    });
+                    
+                    let preview = text::truncate_chars(&entry.prompt, 120);
+                    ui.label(egui::RichText::new(preview).size(13.0));
+                    
+                    // Tag editor
+                    ui.horizontal_wrapped(|ui| {
+                        for tag in &entry.tags {
+                            ui.label(egui::RichText::new(format!("🏷 {}", tag))
+                                .size(11.0)
+                                .color(egui::Color32::from_rgb(99, 102, 241)));
+                            if ui.small_button("x").clicked() {
+                                tag_action = Some(TagAction::Remove(entry.id, tag.clone()));
+                            }
+                        }
+                        
+                        match &mut self.history_tag_editing {
+                            Some((id, text)) if *id == entry.id => {
+                                let response = ui.add(egui::TextEdit::singleline(text)
+                                    .desired_width(100.0)
+                                    .hint_text("new tag"));
+                                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                    tag_action = Some(TagAction::Add(entry.id, text.clone()));
+                                }
+                            }
+                            _ => {
+                                if ui.small_button("🏷 +").clicked() {
+                                    self.history_tag_editing = Some((entry.id, String::new()));
+                                }
+                            }
+                        }
+                    });
+                    
+                    if ui.small_button("↩ Load into chat").clicked() {
+                        entry_to_load = Some(entry.clone());
+                    }
+                });
+                ui.add_space(4.0);
             }
         });
+        
+        if let Some(entry) = entry_to_load {
+            self.load_history_entry(&entry);
+        }
+        
+        if let Some(action) = tag_action {
+            if matches!(action, TagAction::Add(..)) {
+                self.history_tag_editing = None;
+            }
+            self.apply_tag_action(action);
+        }
     }
 
     fn render_chat_interface(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
@@ -497,30 +5538,89 @@ impl TouristApp {
         ui.horizontal(|ui| {
             if ui.button("☰").clicked() {
                 self.show_sidebar = !self.show_sidebar;
+                if self.show_sidebar {
+                    self.refresh_tags();
+                    self.refresh_documents();
+                }
             }
             
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                ui.label(egui::RichText::new(&self.model_name).size(14.0).color(egui::Color32::GRAY));
+                let (color, status) = match self.connected {
+                    Some(true) => (egui::Color32::from_rgb(34, 197, 94), "Connected".to_string()),
+                    Some(false) => (egui::Color32::from_rgb(239, 68, 68), "Server unreachable, new prompts are queued".to_string()),
+                    None => (egui::Color32::GRAY, "Checking connection…".to_string()),
+                };
+                let mut tooltip = format!("{}\n{}", status, self.backend.base_url());
+                if !self.outbox.is_empty() {
+                    tooltip.push_str(&format!("\n{} prompt(s) queued", self.outbox.len()));
+                }
+                let waiting = self.backend.queue_depth();
+                if waiting > 0 {
+                    tooltip.push_str(&format!("\n{} request(s) waiting for a free slot", waiting));
+                }
+                ui.label(egui::RichText::new("●").size(12.0).color(color)).on_hover_text(tooltip);
+                
+                let header = if self.compare_mode && !self.compare_model.trim().is_empty() {
+                    format!("{} vs {}", self.model_name, self.compare_model.trim())
+                } else {
+                    self.model_name.clone()
+                };
+                ui.label(egui::RichText::new(header).size(14.0).color(egui::Color32::GRAY));
             });
         });
 
         ui.separator();
+        
+        self.render_branch_breadcrumb(ui);
+        self.render_error_banner(ui);
+        
+        if self.chat_search_open {
+            self.render_chat_search_bar(ui);
+            ui.separator();
+        }
 
         // Chat messages area
-        egui::ScrollArea::vertical()
-            .stick_to_bottom(true)
-            .show(ui, |ui| {
+        let jump = self.chat_scroll_jump.take();
+        let mut scroll_area = egui::ScrollArea::vertical().stick_to_bottom(self.chat_at_bottom);
+        if jump == Some(ScrollJump::Top) {
+            scroll_area = scroll_area.vertical_scroll_offset(0.0);
+        }
+        let output = scroll_area.show(ui, |ui| {
                 if self.chat_messages.is_empty() {
-                    self.render_welcome_message(ui);
+                    match self.render_welcome_message(ui) {
+                        Some(WelcomeAction::Prompt(prompt)) => self.use_example_prompt(ctx, prompt),
+                        Some(WelcomeAction::ReopenLastSession) => self.reopen_last_session(),
+                        None => {}
+                    }
                 } else {
                     self.render_chat_messages(ui);
                 }
+                self.chat_search_scroll = false;
                 
                 // Show loading indicator
-                if self.is_loading {
-                    self.render_loading_message(ui);
+                if self.is_loading && self.render_loading_message(ui) {
+                    self.stop_generation();
+                }
+                
+                if jump == Some(ScrollJump::Bottom) {
+                    ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
                 }
             });
+        
+        let max_offset = (output.content_size.y - output.inner_rect.height()).max(0.0);
+        let offset = output.state.offset.y;
+        self.chat_at_bottom = jump == Some(ScrollJump::Bottom) || offset >= max_offset - BOTTOM_SLACK;
+        
+        // Anything new arriving while scrolled up raises the pill instead of moving the view
+        let content_len = self.chat_messages.len() + self.streaming_text.len();
+        if content_len != self.seen_content_len {
+            self.chat_unseen |= !self.chat_at_bottom && content_len > self.seen_content_len;
+            self.seen_content_len = content_len;
+        }
+        if self.chat_at_bottom {
+            self.chat_unseen = false;
+        }
+        self.render_scroll_controls(ctx, output.inner_rect, offset);
 
         // Input area at bottom
         ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
@@ -528,7 +5628,95 @@ impl TouristApp {
         });
     }
 
-    fn render_welcome_message(&self, ui: &mut egui::Ui) {
+    fn render_scroll_controls(&mut self, ctx: &egui::Context, area: egui::Rect, offset: f32) {
+        if self.chat_unseen {
+            egui::Area::new(egui::Id::new("new_message_pill"))
+                .fixed_pos(egui::pos2(area.center().x - 60.0, area.bottom() - 44.0))
+                .order(egui::Order::Foreground)
+                .show(ctx, |ui| {
+                    let pill = egui::Button::new(egui::RichText::new("↓ New message").color(egui::Color32::WHITE))
+                        .fill(egui::Color32::from_rgb(99, 102, 241))
+                        .rounding(egui::Rounding::same(16.0));
+                    if ui.add(pill).clicked() {
+                        self.chat_scroll_jump = Some(ScrollJump::Bottom);
+                    }
+                });
+        }
+        
+        let show_top = offset > area.height();
+        let show_bottom = !self.chat_at_bottom;
+        if !show_top && !show_bottom {
+            return;
+        }
+        egui::Area::new(egui::Id::new("chat_scroll_buttons"))
+            .fixed_pos(egui::pos2(area.right() - 44.0, area.bottom() - 80.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                if ui.add_enabled(show_top, egui::Button::new("⬆")).on_hover_text("Jump to top").clicked() {
+                    self.chat_scroll_jump = Some(ScrollJump::Top);
+                }
+                if ui.add_enabled(show_bottom, egui::Button::new("⬇")).on_hover_text("Jump to bottom").clicked() {
+                    self.chat_scroll_jump = Some(ScrollJump::Bottom);
+                }
+            });
+    }
+
+    // Editor for the regex rewrite plugin's rules, with a live preview on sample text
+    fn render_regex_rules(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let mut to_remove = None;
+        
+        for (index, rule) in self.config.regex_rules.iter_mut().enumerate() {
+            ui.push_id(("regex_rule", index), |ui| {
+                ui.horizontal(|ui| {
+                    changed |= ui.add(egui::TextEdit::singleline(&mut rule.pattern).hint_text("pattern").desired_width(90.0)).changed();
+                    ui.label("→");
+                    changed |= ui.add(egui::TextEdit::singleline(&mut rule.replacement).hint_text("replacement").desired_width(90.0)).changed();
+                    if ui.small_button("✖").on_hover_text("Remove rule").clicked() {
+                        to_remove = Some(index);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    changed |= ui.radio_value(&mut rule.stage, Stage::PrePrompt, "prompt").changed();
+                    changed |= ui.radio_value(&mut rule.stage, Stage::PostResponse, "answer").changed();
+                });
+                if let Some(Some(error)) = self.regex_rule_errors.get(index) {
+                    ui.label(egui::RichText::new(error).size(11.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                }
+            });
+            ui.add_space(4.0);
+        }
+        if let Some(index) = to_remove {
+            self.config.regex_rules.remove(index);
+            changed = true;
+        }
+        if ui.button("➕ Add rule").clicked() {
+            self.config.regex_rules.push(RegexRule {
+                pattern: String::new(),
+                replacement: String::new(),
+                stage: Stage::PrePrompt,
+            });
+            changed = true;
+        }
+        if changed {
+            self.regex_rule_errors = self.regex_rules.replace(&self.config.regex_rules);
+        }
+        
+        ui.add_space(8.0);
+        ui.label(egui::RichText::new("Test").size(12.0));
+        ui.add(egui::TextEdit::multiline(&mut self.regex_sample).hint_text("Sample text").desired_rows(2));
+        if !self.regex_sample.is_empty() {
+            for (stage, label) in [(Stage::PrePrompt, "As a prompt:"), (Stage::PostResponse, "As an answer:")] {
+                ui.label(egui::RichText::new(label).size(11.0).color(egui::Color32::GRAY));
+                ui.label(egui::RichText::new(self.regex_rules.apply(stage, &self.regex_sample)).monospace().size(12.0));
+            }
+        }
+    }
+
+    // Returns the example prompt or recovery link that was clicked
+    fn render_welcome_message(&self, ui: &mut egui::Ui) -> Option<WelcomeAction> {
+        let mut clicked = None;
+        
         ui.vertical_centered(|ui| {
             ui.add_space(100.0);
             
@@ -542,43 +5730,189 @@ impl TouristApp {
             ui.add_space(24.0);
             
             ui.label("Start a conversation by typing a message below");
+            
+            if self.rag_system.is_some() {
+                ui.add_space(8.0);
+                if ui.link("↺ Reopen last session").clicked() {
+                    clicked = Some(WelcomeAction::ReopenLastSession);
+                }
+            }
+            
+            if self.config.example_prompts.is_empty() {
+                return;
+            }
+            ui.add_space(24.0);
+            
+            let card_width = 260.0;
+            let columns = ((ui.available_width() / (card_width + 12.0)) as usize).clamp(1, 3);
+            let grid_width = columns as f32 * (card_width + 12.0);
+            ui.allocate_ui_with_layout([grid_width, 0.0].into(), egui::Layout::top_down(egui::Align::Center), |ui| {
+                egui::Grid::new("example_prompts")
+                    .spacing([12.0, 12.0])
+                    .show(ui, |ui| {
+                        for (index, prompt) in self.config.example_prompts.iter().enumerate() {
+                            let card = egui::Button::new(egui::RichText::new(prompt.trim_end()).size(14.0))
+                                .fill(egui::Color32::from_rgb(52, 53, 65))
+                                .rounding(egui::Rounding::same(12.0))
+                                .wrap()
+                                .min_size([card_width, 56.0].into());
+                            if ui.add_sized([card_width, 56.0], card).clicked() {
+                                clicked = Some(WelcomeAction::Prompt(prompt.clone()));
+                            }
+                            if (index + 1) % columns == 0 {
+                                ui.end_row();
+                            }
+                        }
+                    });
+            });
         });
+        
+        clicked
     }
 
-    fn render_chat_messages(&self, ui: &mut egui::Ui) {
-        for message in &self.chat_messages {
+    // Returns the index of a user message the user asked to edit.
+    // Only messages near the visible area are laid out, the rest are skipped using their last
+    // measured height (or an estimate before they were ever shown).
+    fn render_chat_messages(&mut self, ui: &mut egui::Ui) {
+        let mut message_action = None;
+        let current_match = self.chat_search_matches().get(self.chat_search_current).copied();
+        
+        let width = ui.available_width();
+        if (width - self.message_heights_width).abs() > 1.0 {
+            self.message_heights.clear();
+            self.message_heights_width = width;
+        }
+        let mut heights = std::mem::take(&mut self.message_heights);
+        heights.truncate(self.chat_messages.len());
+        for message in &self.chat_messages[heights.len()..] {
+            heights.push(estimate_message_height(message, width));
+        }
+        
+        let mut follow_up = None;
+        let visible = ui.clip_rect().expand2(egui::vec2(0.0, RENDER_MARGIN));
+        for (index, message) in self.chat_messages.iter().enumerate() {
+            let is_current_match = current_match == Some(index);
+            let top = ui.cursor().top();
+            let bottom = top + heights[index];
+            if (bottom < visible.top() || top > visible.bottom())
+                && !(is_current_match && self.chat_search_scroll)
+            {
+                ui.add_space(heights[index]);
+                continue;
+            }
+            
             ui.add_space(16.0);
             
-            if message.is_user {
-                self.render_user_message(ui, message);
+            let (action, rect) = if message.is_user {
+                self.render_user_message(ui, message, is_current_match)
             } else {
-                self.render_assistant_message(ui, message);
+                match &message.comparison {
+                    Some(comparison) => self.render_comparison(ui, message, comparison, is_current_match),
+                    None => self.render_assistant_message(ui, message, is_current_match),
+                }
+            };
+            if let Some(action) = action {
+                message_action = Some((index, action));
+            }
+            
+            let is_last = index + 1 == self.chat_messages.len();
+            if let Some((_, questions)) = self.follow_ups.as_ref()
+                .filter(|(id, _)| is_last && !self.is_loading && message.conversation_id == Some(*id))
+            {
+                ui.add_space(8.0);
+                ui.horizontal_wrapped(|ui| {
+                    ui.add_space(52.0);
+                    for question in questions {
+                        let chip = egui::Button::new(egui::RichText::new(question).size(12.0))
+                            .rounding(egui::Rounding::same(12.0));
+                        if ui.add(chip).clicked() {
+                            follow_up = Some(question.clone());
+                        }
+                    }
+                });
+            }
+            
+            if is_current_match && self.chat_search_scroll {
+                ui.scroll_to_rect(rect, Some(egui::Align::Center));
             }
+            heights[index] = ui.cursor().top() - top;
         }
+        self.message_heights = heights;
         ui.add_space(20.0);
+        
+        if let Some(question) = follow_up {
+            self.follow_ups = None;
+            self.use_example_prompt(ui.ctx(), question);
+        }
+        
+        match message_action {
+            Some((index, MessageAction::ToggleStar)) => self.toggle_star(index),
+            Some((index, MessageAction::Feedback(value))) => self.set_feedback(index, value),
+            Some((index, MessageAction::Verdict(side))) => self.record_verdict(index, side),
+            Some((index, MessageAction::Edit)) => self.begin_edit(index),
+            Some((index, MessageAction::Quote)) => self.quote_in_reply(index),
+            Some((index, MessageAction::Regenerate)) => self.regenerate(index, ui.ctx()),
+            Some((index, MessageAction::RetryWith(model))) => self.retry_with_model(index, model, ui.ctx()),
+            Some((index, MessageAction::ViewInHistory)) => self.view_in_history(index),
+            Some((index, MessageAction::Delete)) => self.delete_message(index),
+            #[cfg(feature = "speech")]
+            Some((index, MessageAction::Speak)) => self.toggle_speech(index),
+            Some((index, MessageAction::CancelQueued)) => self.cancel_queued(index),
+            Some((index, MessageAction::Branch)) => self.branch_from(index),
+            None => {}
+        }
     }
 
-    fn render_user_message(&self, ui: &mut egui::Ui, message: &ChatMessage) {
-        ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+    // Returns the edit button or menu entry that was clicked, and the message's screen rect
+    fn render_user_message(&self, ui: &mut egui::Ui, message: &ChatMessage, is_current_match: bool) -> (Option<MessageAction>, egui::Rect) {
+        let mut action = None;
+        
+        let response = ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
             ui.allocate_ui_with_layout([ui.available_width() * 0.7, 0.0].into(), egui::Layout::top_down(egui::Align::LEFT), |ui| {
                 egui::Frame::none()
                     .fill(egui::Color32::from_rgb(52, 53, 65))
                     .rounding(egui::Rounding::same(12.0))
                     .inner_margin(egui::Margin::same(12.0))
                     .show(ui, |ui| {
-                        ui.label(egui::RichText::new(&message.content).size(14.0));
-                    });
+                        ui.label(self.message_text(ui, &message.content, is_current_match));
+                    })
+                    .response
+                    .interact(egui::Sense::click())
+                    .context_menu(|ui| action = self.message_context_menu(ui, message));
                 
                 ui.add_space(4.0);
+                let hovered = ui.rect_contains_pointer(ui.min_rect());
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     ui.label(egui::RichText::new(message.timestamp.format("%H:%M").to_string()).size(11.0).color(egui::Color32::GRAY));
+                    
+                    if let Some(id) = message.queued {
+                        if self.outbox_sending == Some(id) {
+                            ui.spinner();
+                        } else if ui.small_button("✕").on_hover_text("Remove from the queue").clicked() {
+                            action = Some(MessageAction::CancelQueued);
+                        }
+                        ui.label(egui::RichText::new("⏳ Queued").size(11.0).color(egui::Color32::from_rgb(245, 158, 11)))
+                            .on_hover_text("Sent once the server is reachable again");
+                    } else if hovered && !self.is_loading
+                        && ui.small_button("✏").on_hover_text("Edit and resend").clicked()
+                    {
+                        action = Some(MessageAction::Edit);
+                    }
+                    if hovered && ui.small_button("📋").on_hover_text("Copy").clicked() {
+                        copy_to_clipboard(ui, message.content.clone());
+                    }
                 });
             });
         });
+        
+        (action, response.response.rect)
     }
 
-    fn render_assistant_message(&self, ui: &mut egui::Ui, message: &ChatMessage) {
-        ui.horizontal(|ui| {
+    // Returns the star/feedback button that was clicked, and the message's screen rect
+    fn render_assistant_message(&self, ui: &mut egui::Ui, message: &ChatMessage, is_current_match: bool) -> (Option<MessageAction>, egui::Rect) {
+        let mut action = None;
+        
+        let rect = ui.horizontal(|ui| {
             // Avatar
             ui.add_space(8.0);
             egui::Frame::none()
@@ -597,12 +5931,66 @@ impl TouristApp {
                     .rounding(egui::Rounding::same(12.0))
                     .inner_margin(egui::Margin::same(12.0))
                     .show(ui, |ui| {
-                        ui.label(egui::RichText::new(&message.content).size(14.0));
-                    });
+                        if let Some(reasoning) = &message.reasoning {
+                            egui::CollapsingHeader::new(egui::RichText::new("Show reasoning").size(12.0).color(egui::Color32::GRAY))
+                                .id_source(("reasoning", message.timestamp.timestamp_nanos_opt()))
+                                .default_open(false)
+                                .show(ui, |ui| {
+                                    ui.label(egui::RichText::new(reasoning).size(13.0).italics().color(egui::Color32::GRAY));
+                                });
+                            ui.add_space(4.0);
+                        }
+                        self.render_message_body(ui, message, is_current_match);
+                        if message.truncated {
+                            ui.label(egui::RichText::new("(stopped)").size(12.0).italics().color(egui::Color32::GRAY));
+                        }
+                        if message.history_dropped > 0 {
+                            ui.label(egui::RichText::new(format!("({} earlier messages left out to fit the context window)", message.history_dropped))
+                                .size(12.0).italics().color(egui::Color32::GRAY));
+                        }
+                    })
+                    .response
+                    .interact(egui::Sense::click())
+                    .context_menu(|ui| action = self.message_context_menu(ui, message));
                 
                 ui.add_space(4.0);
+                let hovered = ui.rect_contains_pointer(ui.min_rect());
                 ui.horizontal(|ui| {
                     ui.label(egui::RichText::new(message.timestamp.format("%H:%M").to_string()).size(11.0).color(egui::Color32::GRAY));
+                    if message.external {
+                        ui.label(egui::RichText::new("🌐 API").size(11.0).color(egui::Color32::from_rgb(100, 170, 255)))
+                            .on_hover_text("Sent through the local HTTP API");
+                    }
+                    
+                    if hovered && ui.small_button("📋").on_hover_text("Copy").clicked() {
+                        copy_to_clipboard(ui, message.content.clone());
+                    }
+                    
+                    #[cfg(feature = "speech")]
+                    let speaking = self.speaker.as_ref().and_then(Speaker::playing) == message.timestamp.timestamp_nanos_opt();
+                    #[cfg(feature = "speech")]
+                    if speaking || hovered {
+                        let (icon, hint) = if speaking { ("⏹", "Stop reading") } else { ("🔊", "Read aloud") };
+                        if ui.small_button(icon).on_hover_text(hint).clicked() {
+                            action = Some(MessageAction::Speak);
+                        }
+                    }
+                    
+                    // Starring and rating need the saved conversation row
+                    if message.conversation_id.is_some() && (hovered || message.starred) {
+                        let (icon, hint) = if message.starred { ("★", "Unstar") } else { ("☆", "Star") };
+                        if ui.small_button(icon).on_hover_text(hint).clicked() {
+                            action = Some(MessageAction::ToggleStar);
+                        }
+                    }
+                    if message.conversation_id.is_some() && (hovered || message.feedback != 0) {
+                        // Clicking the active rating again clears it
+                        for (value, icon, hint) in [(1, "👍", "Helpful"), (-1, "👎", "Not helpful")] {
+                            if ui.add(egui::SelectableLabel::new(message.feedback == value, icon)).on_hover_text(hint).clicked() {
+                                action = Some(MessageAction::Feedback(if message.feedback == value { 0 } else { value }));
+                            }
+                        }
+                    }
                     
                     if let Some(model) = &message.model_used {
                         ui.label(egui::RichText::new("•").size(11.0).color(egui::Color32::GRAY));
@@ -613,12 +6001,226 @@ impl TouristApp {
                         ui.label(egui::RichText::new("•").size(11.0).color(egui::Color32::GRAY));
                         ui.label(egui::RichText::new(format!("{}ms", response_time)).size(11.0).color(egui::Color32::GRAY));
                     }
+                    
+                    if let Some((prompt_tokens, response_tokens)) = message.tokens {
+                        ui.label(egui::RichText::new("•").size(11.0).color(egui::Color32::GRAY));
+                        ui.label(egui::RichText::new(format!("{} → {} tokens", prompt_tokens, response_tokens))
+                            .size(11.0)
+                            .color(egui::Color32::GRAY));
+                    }
+                    
+                    for annotation in &message.annotations {
+                        ui.label(egui::RichText::new("•").size(11.0).color(egui::Color32::GRAY));
+                        ui.label(egui::RichText::new(annotation).size(11.0).color(egui::Color32::GRAY));
+                    }
                 });
             });
-        });
+        }).response.rect;
+        
+        (action, rect)
+    }
+
+    // Long answers show their first lines behind a fade until expanded, and long code
+    // blocks fold on their own. A search hit anywhere in the message shows all of it.
+    fn render_message_body(&self, ui: &mut egui::Ui, message: &ChatMessage, is_current_match: bool) {
+        let id = ui.make_persistent_id(("expanded", message.timestamp.timestamp_nanos_opt()));
+        let limit = self.config.collapse_after_lines;
+        let line_count = message.content.lines().count();
+        let has_match = self.chat_search_open && !find_matches(&message.content, &self.chat_search_query).is_empty();
+        let collapsible = limit > 0 && line_count > limit && !has_match;
+        let expanded = ui.data(|d| d.get_temp::<bool>(id)).unwrap_or(false);
+        let collapsed = collapsible && !expanded;
+        
+        let text = if collapsed {
+            message.content
+                .match_indices('\n')
+                .nth(limit - 1)
+                .map_or(message.content.as_str(), |(end, _)| &message.content[..end])
+        } else {
+            message.content.as_str()
+        };
+        
+        for (index, segment) in split_code_blocks(text).into_iter().enumerate() {
+            match segment {
+                MessageSegment::Text(text) => {
+                    ui.label(self.message_text(ui, text, is_current_match));
+                }
+                MessageSegment::Code { language, body } => {
+                    let lines = body.lines().count();
+                    let title = if language.is_empty() { "code".to_string() } else { language.to_string() };
+                    egui::CollapsingHeader::new(egui::RichText::new(format!("{} · {} lines", title, lines)).size(12.0).color(egui::Color32::GRAY))
+                        .id_source((id, index))
+                        .default_open(lines <= CODE_FOLD_LINES || has_match)
+                        .show(ui, |ui| {
+                            egui::Frame::none()
+                                .fill(egui::Color32::from_rgb(20, 20, 22))
+                                .rounding(egui::Rounding::same(6.0))
+                                .inner_margin(egui::Margin::same(8.0))
+                                .show(ui, |ui| {
+                                    let font = egui::FontId::monospace(13.0);
+                                    ui.label(self.message_text_in(ui, body.trim_end_matches('\n'), is_current_match, font));
+                                });
+                        });
+                }
+            }
+        }
+        
+        if collapsed {
+            // Fade the last visible lines into the bubble background
+            let bottom = ui.min_rect().bottom();
+            let rect = egui::Rect::from_min_max(
+                egui::pos2(ui.min_rect().left(), bottom - FADE_HEIGHT),
+                egui::pos2(ui.min_rect().right(), bottom),
+            );
+            let fill = egui::Color32::from_rgb(32, 33, 35);
+            let mut mesh = egui::Mesh::default();
+            mesh.colored_vertex(rect.left_top(), egui::Color32::TRANSPARENT);
+            mesh.colored_vertex(rect.right_top(), egui::Color32::TRANSPARENT);
+            mesh.colored_vertex(rect.left_bottom(), fill);
+            mesh.colored_vertex(rect.right_bottom(), fill);
+            mesh.add_triangle(0, 1, 2);
+            mesh.add_triangle(1, 2, 3);
+            ui.painter().add(egui::Shape::mesh(mesh));
+        }
+        
+        if collapsible {
+            let label = if collapsed {
+                format!("Show more ({} more lines) ▼", line_count - limit)
+            } else {
+                "Show less ▲".to_string()
+            };
+            if ui.small_button(label).clicked() {
+                ui.data_mut(|d| d.insert_temp(id, !expanded));
+            }
+        }
+    }
+
+    // Right-click menu on a chat bubble, copying happens here and everything else is returned
+    fn message_context_menu(&self, ui: &mut egui::Ui, message: &ChatMessage) -> Option<MessageAction> {
+        let mut action = None;
+        let mut retry = None;
+        let mut item = |ui: &mut egui::Ui, enabled: bool, label: &str, clicked: MessageAction| {
+            if ui.add_enabled(enabled, egui::Button::new(label)).clicked() {
+                action = Some(clicked);
+                ui.close_menu();
+            }
+        };
+        
+        copy_menu_items(ui, message);
+        item(ui, true, "💬 Quote in reply", MessageAction::Quote);
+        ui.separator();
+        if message.is_user {
+            item(ui, !self.is_loading, "✏ Edit", MessageAction::Edit);
+        } else {
+            let saved = message.conversation_id.is_some();
+            let star_label = if message.starred { "★ Unstar" } else { "☆ Star" };
+            item(ui, saved, star_label, MessageAction::ToggleStar);
+            item(ui, !self.is_loading, "🔄 Regenerate", MessageAction::Regenerate);
+            ui.add_enabled_ui(!self.is_loading, |ui| {
+                ui.menu_button("🔁 Retry with…", |ui| {
+                    let others: Vec<&String> = self.available_models.iter()
+                        .filter(|model| message.model_used.as_ref() != Some(*model))
+                        .collect();
+                    if others.is_empty() {
+                        ui.label(egui::RichText::new("No other models found").color(egui::Color32::GRAY));
+                    }
+                    for model in others {
+                        if ui.button(model).clicked() {
+                            retry = Some(MessageAction::RetryWith(model.clone()));
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+        }
+        item(ui, self.rag_system.is_some(), "📜 View in history", MessageAction::ViewInHistory);
+        item(ui, self.rag_system.is_some() && !self.is_loading, "🌿 Branch from here", MessageAction::Branch);
+        ui.separator();
+        item(ui, !self.is_loading, "🗑 Delete", MessageAction::Delete);
+        
+        action.or(retry)
+    }
+
+    // Two answers to the same prompt side by side, with a control to pick the better one
+    fn render_comparison(
+        &self,
+        ui: &mut egui::Ui,
+        message: &ChatMessage,
+        comparison: &Comparison,
+        is_current_match: bool,
+    ) -> (Option<MessageAction>, egui::Rect) {
+        let mut action = None;
+        
+        let rect = ui.vertical(|ui| {
+            ui.columns(2, |columns| {
+                columns[0].push_id("left", |ui| self.render_compared_answer(ui, message, is_current_match));
+                columns[1].push_id("right", |ui| self.render_compared_answer(ui, &comparison.right, false));
+            });
+            
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                ui.add_space(8.0);
+                match comparison.verdict {
+                    Some(side) => {
+                        let preferred = match side {
+                            ComparisonSide::Left => &message.model_used,
+                            ComparisonSide::Right => &comparison.right.model_used,
+                        };
+                        ui.label(egui::RichText::new(format!("Preferred: {}", preferred.as_deref().unwrap_or("?")))
+                            .size(11.0)
+                            .color(egui::Color32::GRAY));
+                    }
+                    None => {
+                        if ui.small_button("◀ Prefer left").clicked() {
+                            action = Some(MessageAction::Verdict(ComparisonSide::Left));
+                        }
+                        if ui.small_button("Prefer right ▶").clicked() {
+                            action = Some(MessageAction::Verdict(ComparisonSide::Right));
+                        }
+                    }
+                }
+            });
+        }).response.rect;
+        
+        (action, rect)
+    }
+
+    fn render_compared_answer(&self, ui: &mut egui::Ui, message: &ChatMessage, is_current_match: bool) {
+        egui::Frame::none()
+            .fill(egui::Color32::from_rgb(32, 33, 35))
+            .rounding(egui::Rounding::same(12.0))
+            .inner_margin(egui::Margin::same(12.0))
+            .show(ui, |ui| {
+                ui.label(egui::RichText::new(message.model_used.as_deref().unwrap_or("?")).size(12.0).strong());
+                ui.add_space(4.0);
+                if let Some(reasoning) = &message.reasoning {
+                    egui::CollapsingHeader::new(egui::RichText::new("Show reasoning").size(12.0).color(egui::Color32::GRAY))
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            ui.label(egui::RichText::new(reasoning).size(13.0).italics().color(egui::Color32::GRAY));
+                        });
+                    ui.add_space(4.0);
+                }
+                ui.label(self.message_text(ui, &message.content, is_current_match));
+            })
+            .response
+            .interact(egui::Sense::click())
+            .context_menu(|ui| copy_menu_items(ui, message));
+        
+        if let Some(response_time) = message.response_time {
+            ui.label(egui::RichText::new(format!("{}ms", response_time)).size(11.0).color(egui::Color32::GRAY));
+        }
+        for annotation in &message.annotations {
+            ui.label(egui::RichText::new(annotation).size(11.0).color(egui::Color32::GRAY));
+        }
     }
 
-    fn render_loading_message(&self, ui: &mut egui::Ui) {
+    // Returns true when the stop button was clicked
+    fn render_loading_message(&self, ui: &mut egui::Ui) -> bool {
+        let mut stop_clicked = false;
+        // Reasoning is hidden while streaming, the answer shows as soon as it starts
+        let partial = ParsedResponse::parse(&self.streaming_text);
+        
         ui.add_space(16.0);
         ui.horizontal(|ui| {
             ui.add_space(8.0);
@@ -636,17 +6238,137 @@ impl TouristApp {
                 .rounding(egui::Rounding::same(12.0))
                 .inner_margin(egui::Margin::same(12.0))
                 .show(ui, |ui| {
+                    if !partial.answer.is_empty() {
+                        ui.label(egui::RichText::new(&partial.answer).size(14.0));
+                        ui.add_space(8.0);
+                    }
+                    
                     ui.horizontal(|ui| {
                         ui.spinner();
                         ui.add_space(8.0);
-                        ui.label("Thinking...");
+                        ui.label(if partial.answer.is_empty() { "Thinking..." } else { "Generating..." });
                         
                         if let Some(start_time) = self.last_response_time {
                             ui.label(format!("{}ms", start_time.elapsed().as_millis()));
+                            ui.ctx().request_repaint_after(ELAPSED_REFRESH);
+                        }
+                        
+                        ui.add_space(8.0);
+                        if ui.button("⏹ Stop").clicked() {
+                            stop_clicked = true;
                         }
                     });
                 });
         });
+        
+        stop_clicked
+    }
+
+    // Shows what the pre-prompt plugins will send, as a word diff against the typed text
+    fn render_prompt_preview(&mut self, ui: &mut egui::Ui) {
+        let active = self.plugin_manager.try_read().map_or(true, |manager| manager.has_stage(Stage::PrePrompt));
+        if !active || self.input_text.trim().is_empty() {
+            return;
+        }
+        
+        egui::CollapsingHeader::new(egui::RichText::new("Preview processed prompt").size(12.0).color(egui::Color32::GRAY))
+            .id_source("prompt_preview")
+            .default_open(false)
+            .show(ui, |ui| {
+                self.schedule_prompt_preview(ui.ctx());
+                
+                let Some((input, result)) = &self.prompt_preview else {
+                    ui.spinner();
+                    return;
+                };
+                let run = match result {
+                    Ok(run) => run,
+                    Err(e) => {
+                        ui.label(egui::RichText::new(e).size(12.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                        return;
+                    }
+                };
+                
+                let mut job = egui::text::LayoutJob::default();
+                for (kind, text) in diff::diff_words(input, &run.text) {
+                    let mut format = egui::TextFormat {
+                        font_id: egui::FontId::proportional(13.0),
+                        color: egui::Color32::LIGHT_GRAY,
+                        ..Default::default()
+                    };
+                    match kind {
+                        DiffKind::Same => {}
+                        DiffKind::Added => {
+                            format.color = egui::Color32::from_rgb(134, 239, 172);
+                            format.background = egui::Color32::from_rgb(20, 83, 45);
+                        }
+                        DiffKind::Removed => {
+                            format.color = egui::Color32::from_rgb(252, 165, 165);
+                            format.background = egui::Color32::from_rgb(127, 29, 29);
+                            format.strikethrough = egui::Stroke::new(1.0, format.color);
+                        }
+                    }
+                    job.append(&text, 0.0, format);
+                }
+                
+                let response = ui.label(job);
+                if !run.steps.is_empty() {
+                    response.on_hover_ui(|ui| {
+                        for (name, output) in &run.steps {
+                            ui.label(egui::RichText::new(format!("After {}:", name)).strong());
+                            ui.label(output);
+                            ui.add_space(4.0);
+                        }
+                    });
+                }
+                if !run.skipped.is_empty() {
+                    ui.label(egui::RichText::new(format!("Not previewed, calls the model: {}", run.skipped.join(", ")))
+                        .size(11.0)
+                        .color(egui::Color32::GRAY));
+                }
+                if *input != self.input_text {
+                    ui.label(egui::RichText::new("Updating…").size(11.0).italics().color(egui::Color32::GRAY));
+                }
+                for failure in &run.failures {
+                    ui.label(egui::RichText::new(failure.to_string()).size(11.0).color(egui::Color32::from_rgb(245, 158, 11)));
+                }
+            });
+    }
+
+    // Runs the same plugin chain a send does, once the input has been still for a moment
+    fn schedule_prompt_preview(&mut self, ctx: &egui::Context) {
+        let current = self.prompt_preview.as_ref().is_some_and(|(input, _)| *input == self.input_text);
+        if current || self.prompt_preview_sent.as_deref() == Some(self.input_text.as_str()) {
+            return;
+        }
+        
+        let typed_at = match &self.prompt_preview_typed {
+            Some((input, at)) if *input == self.input_text => *at,
+            _ => {
+                let now = std::time::Instant::now();
+                self.prompt_preview_typed = Some((self.input_text.clone(), now));
+                now
+            }
+        };
+        if typed_at.elapsed() < PROMPT_PREVIEW_DELAY {
+            ctx.request_repaint_after(PROMPT_PREVIEW_DELAY);
+            return;
+        }
+        
+        let input = self.input_text.clone();
+        self.prompt_preview_sent = Some(input.clone());
+        let plugins = self.plugin_manager.clone();
+        let plugin_context = self.plugin_context(self.chat_messages.len(), &self.model_name)
+            .with_attachments(self.attachments.clone());
+        let pending_ops = self.pending_operations.clone();
+        let ctx = ctx.clone();
+        let rt = self.rt.clone();
+        
+        rt.spawn(async move {
+            let result = plugins.read().await.preview(&input, &plugin_context).await.map_err(|e| e.to_string());
+            pending_ops.send(PendingOperation::PromptPreview { input, result });
+            ctx.request_repaint();
+        });
     }
 
     fn render_input_area(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
@@ -657,19 +6379,77 @@ impl TouristApp {
             .rounding(egui::Rounding::same(16.0))
             .inner_margin(egui::Margin::symmetric(16.0, 12.0))
             .show(ui, |ui| {
+                if self.draft_restored {
+                    ui.label(egui::RichText::new("Draft restored").size(11.0).italics().color(egui::Color32::GRAY));
+                }
+                if self.transcribing {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(egui::RichText::new("Transcribing…").size(11.0).italics().color(egui::Color32::GRAY));
+                    });
+                }
+                if self.editing_message.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new("✏ Editing an earlier message").size(12.0).color(egui::Color32::from_rgb(147, 197, 253)));
+                        ui.checkbox(&mut self.edit_as_branch, "Branch")
+                            .on_hover_text("Send as a new turn instead of replacing the conversation from this message on");
+                        if ui.small_button("Cancel").clicked() {
+                            self.editing_message = None;
+                            self.input_text.clear();
+                        }
+                    });
+                    ui.add_space(4.0);
+                }
+                
+                self.render_long_paste(ui);
+                
                 ui.horizontal(|ui| {
                     // File attachment indicator
-                    if self.file_name.is_some() {
-                        ui.label(egui::RichText::new("📎").color(egui::Color32::from_rgb(99, 102, 241)));
+                    // Attachment chips, the full path is shown on hover
+                    for attachment in &self.attachments {
+                        ui.label(egui::RichText::new(format!("📎 {}", attachment.name))
+                            .size(12.0)
+                            .color(egui::Color32::from_rgb(99, 102, 241)))
+                            .on_hover_text(attachment_location(attachment));
+                    }
+                    
+                    // Quick insert from the template library
+                    let mut chosen_template = None;
+                    if !self.templates.is_empty() {
+                        ui.menu_button("📝", |ui| {
+                            for template in &self.templates {
+                                if ui.button(&template.name).on_hover_text(&template.body).clicked() {
+                                    chosen_template = Some(template.clone());
+                                    ui.close_menu();
+                                }
+                            }
+                        }).response.on_hover_text("Insert template");
+                    }
+                    if let Some(template) = chosen_template {
+                        self.insert_template(ctx, &template);
+                    }
+                    
+                    if ui.small_button("📋").on_hover_text("Attach an image from the clipboard").clicked() {
+                        self.paste_clipboard_image();
                     }
                     
+                    #[cfg(feature = "voice")]
+                    self.render_mic_button(ui);
+                    
                     // Text input
+                    self.handle_prompt_recall(ctx);
+                    self.handle_paste(ctx);
                     let response = egui::TextEdit::multiline(&mut self.input_text)
+                        .id(egui::Id::new(CHAT_INPUT_ID))
                         .desired_width(ui.available_width() - 60.0)
                         .desired_rows(1)
                         .hint_text("Type your message...")
                         .show(ui);
                     
+                    if response.response.changed() {
+                        self.draft_changed_at = Some(std::time::Instant::now());
+                    }
+                    
                     // Handle Enter key
                     if response.response.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter) && !i.modifiers.shift) {
                         self.send_message(ctx);
@@ -682,10 +6462,327 @@ impl TouristApp {
                         .fill(egui::Color32::from_rgb(99, 102, 241))
                         .rounding(egui::Rounding::same(8.0));
                     
-                    if ui.add_enabled(!self.is_loading && !self.input_text.trim().is_empty(), send_button).clicked() {
+                    let blocked = self.over_context_window() && !self.allow_context_overflow;
+                    let can_send = !self.is_loading && !self.input_text.trim().is_empty() && !blocked;
+                    if ui.add_enabled(can_send, send_button)
+                        .on_disabled_hover_text(if blocked {
+                            "The prompt is larger than the model's context window, the start would be cut off"
+                        } else {
+                            ""
+                        })
+                        .clicked()
+                    {
                         self.send_message(ctx);
                     }
                 });
+                
+                self.render_prompt_preview(ui);
+                
+                self.update_token_counts();
+                if !self.input_text.is_empty() {
+                    let limit = self.context_limit();
+                    let used = self.prompt_tokens();
+                    let ratio = used as f32 / limit.max(1) as f32;
+                    let color = if ratio > 1.0 {
+                        egui::Color32::from_rgb(239, 68, 68)
+                    } else if ratio > 0.75 {
+                        egui::Color32::from_rgb(245, 158, 11)
+                    } else {
+                        egui::Color32::from_rgb(34, 197, 94)
+                    };
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(format!("~{} / {} tokens", used, limit))
+                            .size(11.0)
+                            .color(color))
+                            .on_hover_text(format!(
+                                "Message {}, attachments and context {}. Window from {}.",
+                                self.input_tokens,
+                                self.context_tokens,
+                                if self.context_window.is_some() { "the model's num_ctx" } else { "the configured default" },
+                            ));
+                        if ratio > 1.0 {
+                            ui.checkbox(&mut self.allow_context_overflow, "Send anyway");
+                        }
+                    });
+                }
+            });
+    }
+}
+
+fn history_range_label(days: Option<i64>) -> String {
+    match days {
+        None => "All time".to_string(),
+        Some(1) => "Last 24 hours".to_string(),
+        Some(days) => format!("Last {} days", days),
+    }
+}
+
+// One directory level of the repository picker. `files` all share their first `depth`
+// path components and are sorted, so each subdirectory is a contiguous run.
+fn render_repo_tree(ui: &mut egui::Ui, files: &mut [RepoFile], depth: usize) {
+    let mut start = 0;
+    while start < files.len() {
+        let parts: Vec<String> = files[start].path.split('/').map(str::to_string).collect();
+        if parts.len() == depth + 1 {
+            let file = &mut files[start];
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut file.selected, parts[depth].as_str());
+                ui.label(egui::RichText::new(format_size(file.size)).size(12.0).color(egui::Color32::GRAY));
+            });
+            start += 1;
+            continue;
+        }
+        
+        let prefix = format!("{}/", parts[..=depth].join("/"));
+        let end = start + files[start..].iter().take_while(|file| file.path.starts_with(&prefix)).count();
+        let directory = &mut files[start..end];
+        let mut all = directory.iter().all(|file| file.selected);
+        egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), ui.make_persistent_id(&prefix), false)
+            .show_header(ui, |ui| {
+                if ui.checkbox(&mut all, format!("📁 {}", parts[depth])).changed() {
+                    directory.iter_mut().for_each(|file| file.selected = all);
+                }
+            })
+            .body(|ui| render_repo_tree(ui, directory, depth + 1));
+        start = end;
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 * 1024 {
+        format!("{:.1} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+fn attachment_location(attachment: &Attachment) -> String {
+    let location = match (&attachment.path, attachment.origin) {
+        (Some(path), _) => path.display().to_string(),
+        (None, AttachmentOrigin::Clipboard) => format!("{} (pasted from the clipboard)", attachment.name),
+        (None, _) => format!("{} (dropped without a path)", attachment.name),
+    };
+    let size = format_size(attachment.len);
+    match attachment.modified {
+        Some(modified) => format!("{}\n{}, modified {}", location, size, modified.format("%Y-%m-%d %H:%M")),
+        None => format!("{}\n{}", location, size),
+    }
+}
+
+// Byte ranges of case-insensitive, non-overlapping occurrences of `query` in `text`
+fn find_matches(text: &str, query: &str) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    if query.is_empty() {
+        return ranges;
+    }
+    
+    let mut skip_until = 0;
+    for (start, _) in text.char_indices() {
+        if start < skip_until {
+            continue;
+        }
+        let mut text_chars = text[start..].char_indices();
+        let mut end = None;
+        let mut matched = true;
+        for query_char in query.chars() {
+            match text_chars.next() {
+                Some((offset, text_char)) if text_char.to_lowercase().eq(query_char.to_lowercase()) => {
+                    end = Some(start + offset + text_char.len_utf8());
+                }
+                _ => {
+                    matched = false;
+                    break;
+                }
+            }
+        }
+        if let (true, Some(end)) = (matched, end) {
+            ranges.push(start..end);
+            skip_until = end;
+        }
+    }
+    
+    ranges
+}
+
+fn copy_to_clipboard(ui: &egui::Ui, text: String) {
+    ui.output_mut(|o| o.copied_text = text);
+    let now = ui.input(|i| i.time);
+    ui.ctx().data_mut(|d| d.insert_temp(egui::Id::new(COPIED_TOAST_ID), now));
+}
+
+fn copy_menu_items(ui: &mut egui::Ui, message: &ChatMessage) {
+    if ui.button("📋 Copy").clicked() {
+        copy_to_clipboard(ui, message.content.clone());
+        ui.close_menu();
+    }
+    if ui.button("📝 Copy as Markdown").clicked() {
+        copy_to_clipboard(ui, message_markdown(message));
+        ui.close_menu();
+    }
+}
+
+fn compared_message(response: ComparedResponse) -> ChatMessage {
+    let (content, reasoning, annotations) = match response.result {
+        Ok(parsed) => (parsed.answer, parsed.reasoning, parsed.annotations),
+        Err(e) => (format!("Error: {}", e), None, Vec::new()),
+    };
+    ChatMessage {
+        content,
+        is_user: false,
+        timestamp: Local::now(),
+        model_used: Some(response.model),
+        response_time: Some(response.response_time),
+        reasoning,
+        conversation_id: response.conversation_id,
+        truncated: false,
+        starred: false,
+        feedback: 0,
+        comparison: None,
+        annotations,
+        tokens: None,
+        external: false,
+        queued: None,
+        history_dropped: 0,
+    }
+}
+
+// Form generated from a plugin's settings schema, returns whether anything changed
+fn render_plugin_settings(ui: &mut egui::Ui, plugin: &mut dyn Plugin) -> bool {
+    let schema = plugin.settings_schema();
+    if schema.is_empty() {
+        return false;
+    }
+    
+    let mut config = plugin.config();
+    let mut changed = false;
+    ui.indent(("plugin_settings", plugin.name().to_string()), |ui| {
+        for field in &schema {
+            let value = config.get(field.key).cloned().unwrap_or(serde_json::Value::Null);
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(field.label).size(12.0));
+                let edited = match field.kind {
+                    SettingKind::Text => {
+                        let mut text = value.as_str().unwrap_or_default().to_string();
+                        ui.text_edit_singleline(&mut text).changed().then(|| serde_json::Value::from(text))
+                    }
+                    SettingKind::Number => {
+                        let mut number = value.as_f64().unwrap_or_default();
+                        ui.add(egui::DragValue::new(&mut number).speed(1.0)).changed()
+                            .then(|| serde_json::json!(number.max(0.0).round() as u64))
+                    }
+                    SettingKind::Bool => {
+                        let mut flag = value.as_bool().unwrap_or_default();
+                        ui.checkbox(&mut flag, "").changed().then(|| serde_json::Value::from(flag))
+                    }
+                };
+                if let (Some(edited), Some(fields)) = (edited, config.as_object_mut()) {
+                    fields.insert(field.key.to_string(), edited);
+                    changed = true;
+                }
             });
+        }
+    });
+    
+    if changed {
+        plugin.set_config(config);
+    }
+    changed
+}
+
+// The server the settings point at. Ollama's URL isn't part of the config.
+fn backend_from_config(config: &AppConfig, ollama_url: &str) -> Box<dyn LlmBackend> {
+    match config.backend {
+        BackendKind::Ollama => Box::new(OllamaClient::new(ollama_url.to_string()).with_limits(
+            config.ollama_max_concurrent,
+            std::time::Duration::from_millis(config.ollama_min_interval_ms),
+        )),
+        BackendKind::OpenAiCompat => Box::new(OpenAiCompatBackend::new(
+            config.openai_url.clone(),
+            Some(config.openai_api_key.clone()),
+        )),
+    }
+}
+
+fn new_session_id() -> String {
+    format!("chat-{}", Local::now().format("%Y%m%d%H%M%S%3f"))
+}
+
+enum MessageSegment<'a> {
+    Text(&'a str),
+    Code { language: &'a str, body: &'a str },
+}
+
+// Splits on ``` fences, an unterminated fence runs to the end of the text
+fn split_code_blocks(text: &str) -> Vec<MessageSegment<'_>> {
+    let mut segments = Vec::new();
+    let mut segment_start = 0;
+    let mut code_language = None;
+    let mut offset = 0;
+    
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let trimmed = line.trim();
+        if !trimmed.starts_with("```") {
+            continue;
+        }
+        
+        match code_language.take() {
+            None => {
+                let before = text[segment_start..line_start].trim_end_matches('\n');
+                if !before.trim().is_empty() {
+                    segments.push(MessageSegment::Text(before));
+                }
+                code_language = Some(trimmed.trim_start_matches('`').trim());
+            }
+            Some(language) => {
+                segments.push(MessageSegment::Code { language, body: &text[segment_start..line_start] });
+            }
+        }
+        segment_start = offset;
+    }
+    
+    let rest = &text[segment_start..];
+    match code_language {
+        Some(language) => segments.push(MessageSegment::Code { language, body: rest }),
+        None if !rest.trim().is_empty() => segments.push(MessageSegment::Text(rest.trim_end_matches('\n'))),
+        None => {}
+    }
+    segments
+}
+
+// Focuses the chat input with the given character range selected
+fn focus_chat_input(ctx: &egui::Context, start: usize, end: usize) {
+    let id = egui::Id::new(CHAT_INPUT_ID);
+    let mut state = egui::text_edit::TextEditState::load(ctx, id).unwrap_or_default();
+    state.cursor.set_char_range(Some(egui::text::CCursorRange::two(
+        egui::text::CCursor::new(start),
+        egui::text::CCursor::new(end),
+    )));
+    state.store(ctx, id);
+    ctx.memory_mut(|memory| memory.request_focus(id));
+}
+
+// Rough height of a message bubble before it has been measured
+fn estimate_message_height(message: &ChatMessage, width: f32) -> f32 {
+    let chars_per_line = (width * 0.7 / 7.5).max(20.0) as usize;
+    let lines: usize = message.content
+        .lines()
+        .map(|line| line.chars().count() / chars_per_line + 1)
+        .sum();
+    16.0 + 24.0 + lines.max(1) as f32 * 18.0 + 24.0
+}
+
+// The message as a Markdown blockquote headed by who wrote it and when
+fn message_markdown(message: &ChatMessage) -> String {
+    let mut header = if message.is_user { "**You**".to_string() } else { "**Assistant**".to_string() };
+    if let Some(model) = message.model_used.as_ref().filter(|_| !message.is_user) {
+        header.push_str(&format!(" · {}", model));
     }
-}
\ No newline at end of file
+    header.push_str(&format!(" · {}", message.timestamp.format("%Y-%m-%d %H:%M")));
+    
+    let body: Vec<String> = message.content.lines().map(|line| format!("> {}", line).trim_end().to_string()).collect();
+    format!("> {}\n>\n{}\n", header, body.join("\n"))
+}
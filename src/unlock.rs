@@ -0,0 +1,128 @@
+// unlock.rs
+use eframe::egui;
+use crate::config::{self, AppConfig};
+use crate::db;
+use crate::rag::RagSystem;
+use crate::ui::TouristApp;
+
+// Asks for the database passphrase before the app starts, when the database is encrypted
+// or is to be encrypted now. The passphrase is only ever kept in memory.
+pub struct Launcher {
+    app: Option<Box<TouristApp>>,
+    config: AppConfig,
+    // Choosing a passphrase for a database that isn't encrypted yet
+    choosing: bool,
+    passphrase: String,
+    confirmation: String,
+    error: Option<String>,
+}
+
+impl Launcher {
+    pub fn new(config: AppConfig, ctx: egui::Context) -> Self {
+        let encrypted = db::is_encrypted_in(config::data_dir());
+        let app = (!encrypted && !config.encrypt_database).then(|| Box::new(TouristApp::new(config.clone(), ctx)));
+        Self {
+            app,
+            config,
+            choosing: !encrypted,
+            passphrase: String::new(),
+            confirmation: String::new(),
+            error: None,
+        }
+    }
+
+    fn submit(&mut self, ctx: &egui::Context) {
+        if self.passphrase.is_empty() {
+            self.error = Some("Enter a passphrase".to_string());
+            return;
+        }
+        let dir = config::data_dir();
+        if self.choosing {
+            if self.passphrase != self.confirmation {
+                self.error = Some("The passphrases don't match".to_string());
+                return;
+            }
+            if db::exists_in(dir) {
+                if let Err(e) = db::encrypt_in(dir, &self.passphrase) {
+                    self.error = Some(e.to_string());
+                    return;
+                }
+            }
+        }
+
+        match RagSystem::new_encrypted_in(dir, &self.passphrase) {
+            Ok(rag) => {
+                self.passphrase.clear();
+                self.confirmation.clear();
+                self.config.encrypt_database = true;
+                self.app = Some(Box::new(TouristApp::with_rag(self.config.clone(), ctx.clone(), rag)));
+            }
+            Err(e) => {
+                self.passphrase.clear();
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+impl eframe::App for Launcher {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if let Some(app) = &mut self.app {
+            eframe::App::update(app.as_mut(), ctx, frame);
+            return;
+        }
+
+        let mut submit = false;
+        let mut skip = false;
+        egui::CentralPanel::default().show(ctx, |_| {});
+        egui::Window::new(if self.choosing { "Encrypt database" } else { "Unlock database" })
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                if self.choosing {
+                    ui.label("Choose a passphrase. Without it the history can't be opened again.");
+                } else {
+                    ui.label("The conversation database is encrypted.");
+                }
+                ui.add_space(8.0);
+                let field = ui.add(egui::TextEdit::singleline(&mut self.passphrase)
+                    .password(true)
+                    .hint_text("Passphrase"));
+                if self.error.is_none() && !self.choosing {
+                    field.request_focus();
+                }
+                let mut entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if self.choosing {
+                    let confirm = ui.add(egui::TextEdit::singleline(&mut self.confirmation)
+                        .password(true)
+                        .hint_text("Repeat passphrase"));
+                    entered = confirm.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                }
+                if let Some(error) = &self.error {
+                    ui.label(egui::RichText::new(error).size(12.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                }
+                ui.add_space(8.0);
+                let label = if self.choosing { "🔒 Encrypt" } else { "🔓 Unlock" };
+                ui.horizontal(|ui| {
+                    submit = ui.button(label).clicked() || entered;
+                    if self.choosing {
+                        skip = ui.button("Not now").on_hover_text("Start without encryption").clicked();
+                    }
+                });
+            });
+
+        if skip {
+            self.config.encrypt_database = false;
+            self.app = Some(Box::new(TouristApp::new(self.config.clone(), ctx.clone())));
+        } else if submit {
+            self.submit(ctx);
+        }
+    }
+
+    fn on_exit(&mut self, gl: Option<&eframe::glow::Context>) {
+        if let Some(app) = &mut self.app {
+            eframe::App::on_exit(app.as_mut(), gl);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// voice.rs
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use crate::models::AppError;
+
+// Whisper only takes 16 kHz mono
+const WHISPER_SAMPLE_RATE: u32 = 16_000;
+const MIN_RECORDING_SECONDS: f32 = 0.3;
+
+// Captured audio, mixed down to mono
+pub struct Recording {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+// Records from the default microphone until stopped. Holds the audio stream, which
+// can't leave the thread it was made on.
+pub struct Recorder {
+    stream: Stream,
+    samples: Arc<Mutex<Vec<f32>>>,
+    sample_rate: u32,
+}
+
+impl Recorder {
+    pub fn start() -> Result<Self, AppError> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| AppError::Other("No microphone found".to_string()))?;
+        let supported = device
+            .default_input_config()
+            .map_err(|e| microphone_error(&e.to_string()))?;
+        let config: StreamConfig = supported.clone().into();
+        let samples = Arc::new(Mutex::new(Vec::new()));
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, samples.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, samples.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, samples.clone()),
+            format => return Err(AppError::Other(format!("Unsupported microphone sample format {}", format))),
+        }?;
+        stream.play().map_err(|e| microphone_error(&e.to_string()))?;
+
+        Ok(Self { stream, samples, sample_rate: config.sample_rate.0 })
+    }
+
+    pub fn stop(self) -> Recording {
+        drop(self.stream);
+        let samples = std::mem::take(&mut *self.samples.lock().unwrap_or_else(PoisonError::into_inner));
+        Recording { samples, sample_rate: self.sample_rate }
+    }
+}
+
+fn build_stream<T>(device: &cpal::Device, config: &StreamConfig, samples: Arc<Mutex<Vec<f32>>>) -> Result<Stream, AppError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let mut samples = samples.lock().unwrap_or_else(PoisonError::into_inner);
+                samples.extend(data.chunks(channels).map(|frame| {
+                    frame.iter().map(|sample| sample.to_sample::<f32>()).sum::<f32>() / frame.len() as f32
+                }));
+            },
+            |e| eprintln!("Microphone error: {}", e),
+            None,
+        )
+        .map_err(|e| microphone_error(&e.to_string()))
+}
+
+// Opening the stream is where a denied permission shows up
+fn microphone_error(detail: &str) -> AppError {
+    AppError::Other(format!("Could not open the microphone, check that the app may use it: {}", detail))
+}
+
+// Runs the recording through a local whisper.cpp model. Blocking, and slow on long
+// recordings, so it belongs on a blocking task.
+pub fn transcribe(model_path: &Path, recording: Recording) -> Result<String, AppError> {
+    if recording.samples.len() < (recording.sample_rate as f32 * MIN_RECORDING_SECONDS) as usize {
+        return Err(AppError::Other("The recording was too short, hold the button while speaking".to_string()));
+    }
+    let context = whisper_context(model_path)?;
+    let audio = resample(&recording.samples, recording.sample_rate, WHISPER_SAMPLE_RATE);
+
+    let mut state = context.create_state().map_err(whisper_error)?;
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_special(false);
+    params.set_print_timestamps(false);
+    state.full(params, &audio).map_err(whisper_error)?;
+
+    let mut text = String::new();
+    for segment in 0..state.full_n_segments().map_err(whisper_error)? {
+        text.push_str(&state.full_get_segment_text(segment).map_err(whisper_error)?);
+    }
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err(AppError::Other("No speech was recognized".to_string()));
+    }
+    Ok(text)
+}
+
+// Loading a model takes seconds, so the last one loaded is kept
+fn whisper_context(model_path: &Path) -> Result<Arc<WhisperContext>, AppError> {
+    static LOADED: Mutex<Option<(PathBuf, Arc<WhisperContext>)>> = Mutex::new(None);
+
+    let mut loaded = LOADED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((path, context)) = loaded.as_ref() {
+        if path == model_path {
+            return Ok(context.clone());
+        }
+    }
+    if !model_path.is_file() {
+        return Err(AppError::Other(format!(
+            "Whisper model not found at {}, download a ggml model and set its path in settings",
+            model_path.display()
+        )));
+    }
+    let path = model_path
+        .to_str()
+        .ok_or_else(|| AppError::Other(format!("Unreadable model path {}", model_path.display())))?;
+    let context = Arc::new(WhisperContext::new_with_params(path, WhisperContextParameters::default()).map_err(whisper_error)?);
+    *loaded = Some((model_path.to_path_buf(), context.clone()));
+    Ok(context)
+}
+
+fn whisper_error(error: whisper_rs::WhisperError) -> AppError {
+    AppError::Other(format!("Transcription failed: {}", error))
+}
+
+// Linear interpolation, plenty for speech
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let step = from as f64 / to as f64;
+    let len = (samples.len() as f64 / step) as usize;
+    (0..len)
+        .map(|index| {
+            let position = index as f64 * step;
+            let before = position.floor() as usize;
+            let after = (before + 1).min(samples.len() - 1);
+            let weight = (position - before as f64) as f32;
+            samples[before] * (1.0 - weight) + samples[after] * weight
+        })
+        .collect()
+}
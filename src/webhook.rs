@@ -0,0 +1,113 @@
+// webhook.rs
+use chrono::{DateTime, Local};
+use reqwest::Client;
+use serde::Serialize;
+use std::time::Duration;
+use crate::models::{AppError, ConversationEntry};
+use crate::ollama::{request_error, status_error};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+// The first try plus at most two retries
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+pub const SECRET_HEADER: &str = "X-Webhook-Secret";
+
+#[derive(Serialize)]
+pub struct WebhookPayload {
+    pub id: i64,
+    pub timestamp: DateTime<Local>,
+    pub model: String,
+    pub prompt: String,
+    pub response: String,
+    pub response_time_ms: i64,
+    pub session: String,
+}
+
+impl WebhookPayload {
+    pub fn new(conversation_id: i64, entry: &ConversationEntry, session_id: &str) -> Self {
+        Self {
+            id: conversation_id,
+            timestamp: entry.timestamp,
+            model: entry.model_used.clone(),
+            prompt: entry.prompt.clone(),
+            response: entry.response.clone(),
+            response_time_ms: entry.response_time_ms,
+            session: session_id.to_string(),
+        }
+    }
+
+    // What the settings' test button sends
+    pub fn test() -> Self {
+        Self {
+            id: 0,
+            timestamp: Local::now(),
+            model: "test".to_string(),
+            prompt: "Test webhook".to_string(),
+            response: "If you can read this, the webhook works.".to_string(),
+            response_time_ms: 0,
+            session: "test".to_string(),
+        }
+    }
+}
+
+// POSTs each saved conversation to a URL. The secret goes in SECRET_HEADER and is
+// replaced by *** in every error this returns.
+#[derive(Clone)]
+pub struct Webhook {
+    client: Client,
+    url: String,
+    secret: Option<String>,
+}
+
+impl Webhook {
+    // None when no URL is set
+    pub fn new(url: &str, secret: &str) -> Option<Self> {
+        let url = url.trim();
+        if url.is_empty() {
+            return None;
+        }
+        let client = Client::builder().timeout(TIMEOUT).build().unwrap_or_default();
+        Some(Self {
+            client,
+            url: url.to_string(),
+            secret: Some(secret.trim().to_string()).filter(|secret| !secret.is_empty()),
+        })
+    }
+
+    // The error lists every attempt's failure, so nothing is lost to the retries
+    pub async fn send(&self, payload: &WebhookPayload) -> Result<(), AppError> {
+        let mut attempt = 1;
+        let mut failures = Vec::new();
+        loop {
+            match self.try_send(payload).await {
+                Ok(()) => return Ok(()),
+                Err(e) => failures.push(format!("attempt {}: {}", attempt, self.redact(&e.to_string()))),
+            }
+            if attempt >= ATTEMPTS {
+                return Err(AppError::Other(failures.join("; ")));
+            }
+            tokio::time::sleep(RETRY_DELAY * attempt).await;
+            attempt += 1;
+        }
+    }
+
+    async fn try_send(&self, payload: &WebhookPayload) -> Result<(), AppError> {
+        let mut request = self.client.post(&self.url).json(payload);
+        if let Some(secret) = &self.secret {
+            request = request.header(SECRET_HEADER, secret);
+        }
+        let response = request.send().await.map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(status_error(response, None).await);
+        }
+        Ok(())
+    }
+
+    // Servers sometimes echo headers back in error bodies
+    fn redact(&self, text: &str) -> String {
+        match &self.secret {
+            Some(secret) => text.replace(secret.as_str(), "***"),
+            None => text.to_string(),
+        }
+    }
+}
@@ -0,0 +1,407 @@
+// writer.rs
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::any::Any;
+use std::path::PathBuf;
+use std::sync::Arc;
+use chrono::Local;
+use tokio::sync::{mpsc, oneshot};
+use crate::models::{
+    AppError, CommandRun, ComparisonRecord, ConversationEntry, ErrorRecord, ImportedSession, PluginRequest, PromptTemplate,
+};
+use crate::db;
+use crate::rag::RagSystem;
+use crate::tokens::TokenCounter;
+
+// Where a command sends its result. The caller may have stopped waiting, the write happens anyway.
+pub type Reply<T> = oneshot::Sender<Result<T, AppError>>;
+
+// Work for `RagSystem::with_transaction`, its value comes back boxed and is downcast there
+pub type TransactionJob = Box<dyn FnOnce(&Transaction) -> Result<Box<dyn Any + Send>, AppError> + Send>;
+
+// Every write the app makes. The writer thread runs them one at a time in the order they
+// were sent, so this list is also the place to batch or reorder writes.
+pub enum DbCommand {
+    InsertConversation { entry: ConversationEntry, reply: Reply<i64> },
+    ImportSessions { sessions: Vec<ImportedSession>, reply: Reply<Vec<bool>> },
+    AssignSession { conversation_ids: Vec<i64>, session_id: String, reply: Reply<()> },
+    RenameSession { session_id: String, title: String, reply: Reply<()> },
+    BranchSession { parent_id: String, branch_id: String, conversation_ids: Vec<i64>, reply: Reply<()> },
+    EnqueueOutbox { prompt: String, model: String, session_id: String, tags: Vec<String>, reply: Reply<i64> },
+    RemoveOutbox { id: i64, reply: Reply<()> },
+    SetParentResponse { conversation_id: i64, parent_id: i64, reply: Reply<()> },
+    SetStarred { conversation_id: i64, starred: bool, reply: Reply<()> },
+    SetFeedback { conversation_id: i64, value: i64, reply: Reply<()> },
+    DeleteConversation { conversation_id: i64, reply: Reply<()> },
+    MarkSuperseded { conversation_ids: Vec<i64>, reply: Reply<()> },
+    StoreEmbeddings { model: String, embeddings: Vec<(i64, Vec<f32>)>, reply: Reply<()> },
+    // (name, chunks) pairs, each replacing any earlier chunks of that name
+    AddDocuments { documents: Vec<(String, Vec<String>)>, revision: Option<String>, reply: Reply<usize> },
+    RemoveDocument { name: String, reply: Reply<()> },
+    // `tag` is already normalized
+    AddTag { conversation_id: i64, tag: String, reply: Reply<()> },
+    RemoveTag { conversation_id: i64, tag: String, reply: Reply<()> },
+    RecordError { record: ErrorRecord, reply: Reply<()> },
+    RecordComparison { record: ComparisonRecord, reply: Reply<()> },
+    RecordCommand { run: CommandRun, reply: Reply<()> },
+    RecordPluginRequest { request: PluginRequest, reply: Reply<()> },
+    SaveTemplate { template: PromptTemplate, reply: Reply<()> },
+    DeleteTemplate { id: i64, reply: Reply<()> },
+    Backup { path: PathBuf, reply: Reply<()> },
+    Restore { path: PathBuf, reply: Reply<()> },
+    // Caller-supplied batch, committed if it returns Ok
+    Transaction { job: TransactionJob, reply: Reply<Box<dyn Any + Send>> },
+}
+
+// The writer thread's loop. Ends once every sender, and with them every Database clone, is gone.
+pub fn run(mut connection: Connection, key: Option<Arc<String>>, mut commands: mpsc::UnboundedReceiver<DbCommand>) {
+    while let Some(command) = commands.blocking_recv() {
+        command.execute(&mut connection, key.as_deref().map(String::as_str));
+    }
+}
+
+impl DbCommand {
+    fn execute(self, connection: &mut Connection, key: Option<&str>) {
+        match self {
+            DbCommand::InsertConversation { entry, reply } => {
+                answer(reply, in_transaction(connection, |tx| insert_conversation(tx, &entry)));
+            }
+            DbCommand::ImportSessions { sessions, reply } => {
+                answer(reply, in_transaction(connection, |tx| import_sessions(tx, &sessions)));
+            }
+            DbCommand::AssignSession { conversation_ids, session_id, reply } => {
+                answer(reply, in_transaction(connection, |tx| assign_session(tx, &conversation_ids, &session_id)));
+            }
+            DbCommand::RenameSession { session_id, title, reply } => {
+                let result = connection
+                    .execute("UPDATE sessions SET title = ?1 WHERE id = ?2", params![title, session_id])
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::BranchSession { parent_id, branch_id, conversation_ids, reply } => {
+                answer(reply, in_transaction(connection, |tx| branch_session(tx, &parent_id, &branch_id, &conversation_ids)));
+            }
+            DbCommand::EnqueueOutbox { prompt, model, session_id, tags, reply } => {
+                let tags: Vec<String> = tags
+                    .iter()
+                    .map(|tag| RagSystem::normalize_tag(tag))
+                    .filter(|tag| !tag.is_empty())
+                    .collect();
+                let result = connection
+                    .execute(
+                        "INSERT INTO outbox (created_at, prompt, model, session_id, tags) VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![Local::now().to_rfc3339(), prompt, model, session_id, tags.join(",")],
+                    )
+                    .map(|_| connection.last_insert_rowid());
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::RemoveOutbox { id, reply } => {
+                let result = connection.execute("DELETE FROM outbox WHERE id = ?1", [id]).map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::SetParentResponse { conversation_id, parent_id, reply } => {
+                let result = connection
+                    .execute(
+                        "UPDATE conversations SET parent_response_id = ?1 WHERE id = ?2",
+                        params![parent_id, conversation_id],
+                    )
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::SetStarred { conversation_id, starred, reply } => {
+                let result = connection
+                    .execute("UPDATE conversations SET starred = ?1 WHERE id = ?2", params![starred, conversation_id])
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::SetFeedback { conversation_id, value, reply } => {
+                let result = connection
+                    .execute("UPDATE conversations SET feedback = ?1 WHERE id = ?2", params![value, conversation_id])
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            // Tags and embeddings go with it through ON DELETE CASCADE
+            DbCommand::DeleteConversation { conversation_id, reply } => {
+                let result = connection.execute("DELETE FROM conversations WHERE id = ?1", [conversation_id]).map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::MarkSuperseded { conversation_ids, reply } => {
+                answer(reply, in_transaction(connection, |tx| mark_superseded(tx, &conversation_ids)));
+            }
+            DbCommand::StoreEmbeddings { model, embeddings, reply } => {
+                answer(reply, in_transaction(connection, |tx| store_embeddings(tx, &model, &embeddings)));
+            }
+            DbCommand::AddDocuments { documents, revision, reply } => {
+                answer(reply, in_transaction(connection, |tx| add_documents(tx, &documents, revision.as_deref())));
+            }
+            DbCommand::RemoveDocument { name, reply } => {
+                let result = connection.execute("DELETE FROM documents WHERE name = ?1", [&name]).map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::AddTag { conversation_id, tag, reply } => {
+                answer(reply, attach_tag(connection, conversation_id, &tag).map_err(AppError::from));
+            }
+            DbCommand::RemoveTag { conversation_id, tag, reply } => {
+                let result = connection
+                    .execute(
+                        "DELETE FROM conversation_tags
+                         WHERE conversation_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+                        params![conversation_id, tag],
+                    )
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::RecordError { record, reply } => {
+                let result = connection
+                    .execute(
+                        "INSERT INTO errors (timestamp, kind, message, model, url) VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![record.timestamp.to_rfc3339(), record.kind, record.message, record.model, record.url],
+                    )
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::RecordComparison { record, reply } => {
+                let result = connection
+                    .execute(
+                        "INSERT INTO comparisons (timestamp, prompt, left_model, right_model,
+                                                  left_conversation_id, right_conversation_id, verdict)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            record.timestamp.to_rfc3339(),
+                            record.prompt,
+                            record.left_model,
+                            record.right_model,
+                            record.left_conversation_id,
+                            record.right_conversation_id,
+                            record.verdict.as_str()
+                        ],
+                    )
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::RecordCommand { run, reply } => {
+                let result = connection
+                    .execute(
+                        "INSERT INTO command_runs (timestamp, command, exit_code, timed_out, duration_ms, output)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![run.timestamp.to_rfc3339(), run.command, run.exit_code, run.timed_out, run.duration_ms, run.output],
+                    )
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::RecordPluginRequest { request, reply } => {
+                let result = connection
+                    .execute(
+                        "INSERT INTO plugin_requests (timestamp, plugin, purpose, model, response_time_ms, success)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![
+                            request.timestamp.to_rfc3339(),
+                            request.plugin,
+                            request.purpose,
+                            request.model,
+                            request.response_time_ms,
+                            request.success
+                        ],
+                    )
+                    .map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            // Inserts when the template has no id yet, otherwise updates it in place
+            DbCommand::SaveTemplate { template, reply } => {
+                let result = if template.id == 0 {
+                    connection.execute(
+                        "INSERT INTO prompt_templates (name, body) VALUES (?1, ?2)",
+                        params![template.name, template.body],
+                    )
+                } else {
+                    connection.execute(
+                        "UPDATE prompt_templates SET name = ?1, body = ?2 WHERE id = ?3",
+                        params![template.name, template.body, template.id],
+                    )
+                };
+                answer(reply, result.map(drop).map_err(AppError::from));
+            }
+            DbCommand::DeleteTemplate { id, reply } => {
+                let result = connection.execute("DELETE FROM prompt_templates WHERE id = ?1", [id]).map(drop);
+                answer(reply, result.map_err(AppError::from));
+            }
+            DbCommand::Backup { path, reply } => {
+                answer(reply, db::backup(connection, &path, key));
+            }
+            DbCommand::Restore { path, reply } => {
+                answer(reply, db::restore(connection, &path));
+            }
+            DbCommand::Transaction { job, reply } => {
+                answer(reply, in_transaction(connection, job));
+            }
+        }
+    }
+}
+
+fn answer<T>(reply: Reply<T>, result: Result<T, AppError>) {
+    let _ = reply.send(result);
+}
+
+// Committed if `f` returns Ok, rolled back when the transaction is dropped otherwise
+fn in_transaction<T>(
+    connection: &mut Connection,
+    f: impl FnOnce(&Transaction) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let tx = connection.transaction()?;
+    let value = f(&tx)?;
+    tx.commit()?;
+    Ok(value)
+}
+
+fn insert_conversation(connection: &Connection, entry: &ConversationEntry) -> Result<i64, AppError> {
+    let counter = TokenCounter::shared();
+    let prompt_tokens = counter.count(&entry.prompt) as i64;
+    // Reasoning is generated output too, so it counts toward the response
+    let response_tokens = (counter.count(&entry.response)
+        + entry.reasoning.as_deref().map_or(0, |reasoning| counter.count(reasoning))) as i64;
+
+    connection.prepare_cached(
+        "INSERT INTO conversations (timestamp, prompt, response, model_used, response_time_ms, file_context, status,
+                                    prompt_tokens, response_tokens, reasoning, first_token_ms, backend, source)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+    )?.execute(
+        params![
+            entry.timestamp.to_rfc3339(),
+            entry.prompt,
+            entry.response,
+            entry.model_used,
+            entry.response_time_ms,
+            entry.file_context.as_deref().unwrap_or(""),
+            entry.status.as_str(),
+            prompt_tokens,
+            response_tokens,
+            entry.reasoning,
+            entry.first_token_ms,
+            entry.backend,
+            entry.source
+        ],
+    )?;
+
+    let conversation_id = connection.last_insert_rowid();
+    for tag in &entry.tags {
+        let tag = RagSystem::normalize_tag(tag);
+        if !tag.is_empty() {
+            attach_tag(connection, conversation_id, &tag)?;
+        }
+    }
+    Ok(conversation_id)
+}
+
+fn attach_tag(connection: &Connection, conversation_id: i64, tag: &str) -> Result<(), rusqlite::Error> {
+    connection.prepare_cached("INSERT OR IGNORE INTO tags (name) VALUES (?1)")?.execute([tag])?;
+    connection.prepare_cached(
+        "INSERT OR IGNORE INTO conversation_tags (conversation_id, tag_id)
+         SELECT ?1, id FROM tags WHERE name = ?2",
+    )?.execute(params![conversation_id, tag])?;
+    Ok(())
+}
+
+// A session imported before is left alone and reported as false
+fn import_sessions(tx: &Transaction, sessions: &[ImportedSession]) -> Result<Vec<bool>, AppError> {
+    let mut imported = Vec::with_capacity(sessions.len());
+    for session in sessions {
+        let inserted = tx
+            .prepare_cached("INSERT OR IGNORE INTO sessions (id, title, created_at) VALUES (?1, ?2, ?3)")?
+            .execute(params![session.id, session.title, session.created_at.to_rfc3339()])?;
+        if inserted > 0 {
+            for entry in &session.entries {
+                let conversation_id = insert_conversation(tx, entry)?;
+                tx.prepare_cached("UPDATE conversations SET session_id = ?1 WHERE id = ?2")?
+                    .execute(params![session.id, conversation_id])?;
+            }
+        }
+        imported.push(inserted > 0);
+    }
+    Ok(imported)
+}
+
+fn assign_session(tx: &Transaction, conversation_ids: &[i64], session_id: &str) -> Result<(), AppError> {
+    tx.prepare_cached("INSERT OR IGNORE INTO sessions (id, created_at) VALUES (?1, ?2)")?
+        .execute(params![session_id, Local::now().to_rfc3339()])?;
+    let mut update = tx.prepare_cached("UPDATE conversations SET session_id = ?1 WHERE id = ?2")?;
+    for conversation_id in conversation_ids {
+        update.execute(params![session_id, conversation_id])?;
+    }
+    Ok(())
+}
+
+// The conversations are copied, with their timestamps and tags, so either session can carry
+// on without changing the other
+fn branch_session(tx: &Transaction, parent_id: &str, branch_id: &str, conversation_ids: &[i64]) -> Result<(), AppError> {
+    let title: Option<String> = tx
+        .query_row("SELECT title FROM sessions WHERE id = ?1", [parent_id], |row| row.get(0))
+        .optional()?
+        .flatten();
+    tx.execute(
+        "INSERT INTO sessions (id, title, created_at, parent_id) VALUES (?1, ?2, ?3, ?4)",
+        params![branch_id, title.map(|title| format!("{} (branch)", title)), Local::now().to_rfc3339(), parent_id],
+    )?;
+
+    let mut copy = tx.prepare_cached(
+        "INSERT INTO conversations (timestamp, prompt, response, model_used, response_time_ms, file_context, status,
+                                    prompt_tokens, response_tokens, reasoning, first_token_ms, backend, source,
+                                    session_id, branched_from)
+         SELECT timestamp, prompt, response, model_used, response_time_ms, file_context, status,
+                prompt_tokens, response_tokens, reasoning, first_token_ms, backend, source, ?1, id
+         FROM conversations WHERE id = ?2",
+    )?;
+    let mut copy_tags = tx.prepare_cached(
+        "INSERT INTO conversation_tags (conversation_id, tag_id)
+         SELECT ?1, tag_id FROM conversation_tags WHERE conversation_id = ?2",
+    )?;
+    for conversation_id in conversation_ids {
+        // A conversation deleted in the meantime copies nothing, and has no tags to copy
+        if copy.execute(params![branch_id, conversation_id])? == 1 {
+            copy_tags.execute(params![tx.last_insert_rowid(), conversation_id])?;
+        }
+    }
+    Ok(())
+}
+
+fn mark_superseded(tx: &Transaction, conversation_ids: &[i64]) -> Result<(), AppError> {
+    let now = Local::now().to_rfc3339();
+    let mut update = tx.prepare_cached(
+        "UPDATE conversations SET superseded_at = ?1 WHERE id = ?2 AND superseded_at IS NULL",
+    )?;
+    for conversation_id in conversation_ids {
+        update.execute(params![now, conversation_id])?;
+    }
+    Ok(())
+}
+
+fn store_embeddings(tx: &Transaction, model: &str, embeddings: &[(i64, Vec<f32>)]) -> Result<(), AppError> {
+    let mut insert = tx.prepare_cached(
+        "INSERT OR REPLACE INTO conversation_embeddings (conversation_id, model, vector)
+         VALUES (?1, ?2, ?3)",
+    )?;
+    for (conversation_id, vector) in embeddings {
+        let bytes: Vec<u8> = vector.iter().flat_map(|value| value.to_le_bytes()).collect();
+        insert.execute(params![conversation_id, model, bytes])?;
+    }
+    Ok(())
+}
+
+fn add_documents(tx: &Transaction, documents: &[(String, Vec<String>)], revision: Option<&str>) -> Result<usize, AppError> {
+    let added_at = Local::now().to_rfc3339();
+    let mut delete = tx.prepare_cached("DELETE FROM documents WHERE name = ?1")?;
+    let mut insert = tx.prepare_cached(
+        "INSERT INTO documents (name, chunk_index, content, added_at, revision) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    let mut total = 0;
+
+    for (name, chunks) in documents {
+        // Re-adding a document replaces its previous chunks
+        delete.execute([name])?;
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            insert.execute(params![name, index as i64, chunk, added_at, revision])?;
+        }
+        total += chunks.len();
+    }
+
+    Ok(total)
+}
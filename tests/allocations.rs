@@ -0,0 +1,50 @@
+// allocations.rs
+// Counts every byte allocated, so sending a message with large attachments can be checked
+// for copying them. The counter is shared by the whole binary, so this file holds one test.
+use rustai::file_handler::FileHandler;
+use rustai::models::AttachmentKind;
+use rustai::pipeline;
+use rustai::plugins::{PluginContext, Stage};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ATTACHMENT_BYTES: usize = 4 * 1024 * 1024;
+
+// What send_message builds from the attachments, plus the copies a comparison's two jobs share
+#[test]
+fn sending_shares_attachment_content() {
+    let mut image = FileHandler::attachment_from_text("A".repeat(ATTACHMENT_BYTES));
+    image.kind = AttachmentKind::Image;
+    let text = FileHandler::attachment_from_text("fn main() {}\n".repeat(ATTACHMENT_BYTES / 13));
+    let attachments = vec![Arc::new(image), Arc::new(text)];
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let images = pipeline::image_payloads(&attachments);
+    let context = PluginContext::new(Stage::PrePrompt, "mock".to_string()).with_attachments(attachments.clone());
+    let compared = (images.clone(), context.clone());
+    let allocated = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    assert_eq!(images.len(), 1);
+    assert!(Arc::ptr_eq(&images[0], &attachments[0].content));
+    assert!(allocated < 64 * 1024, "allocated {} bytes", allocated);
+    drop(compared);
+}
@@ -0,0 +1,54 @@
+// analytics_scale.rs
+mod common;
+
+use chrono::{Duration, Local};
+use common::temp_rag;
+use rustai::analytics::AnalyticsEngine;
+use rusqlite::params;
+use std::time::Instant;
+
+const ROWS: usize = 50_000;
+// Generous for a debug build on a slow CI machine, a full scan per figure took several seconds
+const ANALYTICS_LIMIT: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn analytics_over_fifty_thousand_rows_stays_fast() {
+    let (_dir, rag) = temp_rag();
+    let models = ["llama3", "qwen", "mistral", "phi"];
+    let now = Local::now();
+
+    // Written directly in one transaction, saving them one by one would dominate the test
+    rag.with_transaction(move |tx| {
+        let mut insert = tx.prepare_cached(
+            "INSERT INTO conversations (timestamp, prompt, response, model_used, response_time_ms, status,
+                                        prompt_tokens, response_tokens)
+             VALUES (?1, ?2, ?3, ?4, ?5, 'ok', ?6, ?7)",
+        )?;
+        for i in 0..ROWS {
+            // Spread over the last ninety days, a minute apart within each day
+            let timestamp = now - Duration::days((i % 90) as i64) - Duration::minutes((i / 90) as i64);
+            insert.execute(params![
+                timestamp.to_rfc3339(),
+                format!("question {} about topic {}", i, i % 50),
+                format!("answer {}", i),
+                models[i % models.len()],
+                (100 + i % 900) as i64,
+                12,
+                40,
+            ])?;
+        }
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let analytics = AnalyticsEngine::new(rag.database());
+    let started = Instant::now();
+    let summary = analytics.get_analytics().await.unwrap();
+    let daily = analytics.get_daily_counts(30).await.unwrap();
+    let elapsed = started.elapsed();
+
+    assert_eq!(summary.total_requests, ROWS);
+    assert_eq!(daily.len(), 30);
+    assert!(elapsed < ANALYTICS_LIMIT, "analytics took {:?}", elapsed);
+}
@@ -0,0 +1,51 @@
+// analytics_sessions.rs
+mod common;
+
+use chrono::{DateTime, Duration, Local};
+use common::{entry_at, temp_rag};
+use rustai::analytics::AnalyticsEngine;
+
+fn start_of_today() -> DateTime<Local> {
+    Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|start| start.and_local_timezone(Local).earliest())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn three_clusters_in_a_day_are_three_sessions() {
+    let (_dir, rag) = temp_rag();
+    let midnight = start_of_today();
+
+    // Three bursts of three requests, five minutes apart, hours between the bursts
+    for hour in [8, 12, 18] {
+        for minute in [0, 5, 10] {
+            let timestamp = midnight + Duration::hours(hour) + Duration::minutes(minute);
+            rag.save_conversation(&entry_at("question", "answer", timestamp)).await.unwrap();
+        }
+    }
+    // Yesterday's request counts toward the totals only
+    rag.save_conversation(&entry_at("question", "answer", midnight - Duration::hours(2))).await.unwrap();
+
+    let analytics = AnalyticsEngine::new(rag.database()).get_analytics().await.unwrap();
+
+    assert_eq!(analytics.sessions_today, 3);
+    assert_eq!(analytics.requests_today, 9);
+    assert_eq!(analytics.total_requests, 10);
+    assert!((analytics.avg_session_minutes - 10.0).abs() < 0.01);
+}
+
+#[tokio::test]
+async fn a_gap_just_over_the_limit_splits_a_session() {
+    let (_dir, rag) = temp_rag();
+    let first = start_of_today() + Duration::hours(9);
+    let gap = Duration::minutes(rustai::analytics::SESSION_GAP_MINUTES) + Duration::seconds(1);
+    for timestamp in [first, first + gap] {
+        rag.save_conversation(&entry_at("question", "answer", timestamp)).await.unwrap();
+    }
+
+    let analytics = AnalyticsEngine::new(rag.database()).get_analytics().await.unwrap();
+
+    assert_eq!(analytics.sessions_today, 2);
+}
@@ -0,0 +1,38 @@
+// common/mod.rs
+// Helpers shared by the integration tests. Each test file uses a different subset.
+#![allow(dead_code)]
+use chrono::{DateTime, Local};
+use rustai::models::{ConversationEntry, ConversationStatus};
+use rustai::rag::RagSystem;
+use tempfile::TempDir;
+
+// A RagSystem in a fresh directory, removed when the TempDir is dropped
+pub fn temp_rag() -> (TempDir, RagSystem) {
+    let dir = TempDir::new().expect("create temp dir");
+    let rag = RagSystem::new_in(dir.path()).expect("open database");
+    (dir, rag)
+}
+
+pub fn entry(prompt: &str, response: &str) -> ConversationEntry {
+    entry_at(prompt, response, Local::now())
+}
+
+pub fn entry_at(prompt: &str, response: &str, timestamp: DateTime<Local>) -> ConversationEntry {
+    ConversationEntry {
+        id: 0,
+        timestamp,
+        prompt: prompt.to_string(),
+        response: response.to_string(),
+        model_used: "test-model".to_string(),
+        response_time_ms: 100,
+        file_context: None,
+        tags: Vec::new(),
+        status: ConversationStatus::Ok,
+        reasoning: None,
+        first_token_ms: None,
+        starred: false,
+        feedback: 0,
+        backend: Some("mock".to_string()),
+        source: None,
+    }
+}
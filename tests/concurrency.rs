@@ -0,0 +1,46 @@
+// concurrency.rs
+mod common;
+
+use rustai::analytics::AnalyticsEngine;
+use std::time::Duration;
+
+const WRITERS: usize = 8;
+const SAVES_PER_WRITER: usize = 25;
+const READERS: usize = 8;
+const READS_PER_READER: usize = 25;
+
+// Saves and analytics reads racing each other must neither fail with "database is locked"
+// nor lose a row
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_saves_and_reads_all_succeed() {
+    let (_dir, rag) = common::temp_rag();
+    let analytics = AnalyticsEngine::new(rag.database());
+
+    let mut tasks = Vec::new();
+    for writer in 0..WRITERS {
+        let rag = rag.clone();
+        tasks.push(tokio::spawn(async move {
+            for save in 0..SAVES_PER_WRITER {
+                let prompt = format!("prompt {} from writer {}", save, writer);
+                rag.save_conversation(&common::entry(&prompt, "response")).await.expect("save");
+            }
+        }));
+    }
+    for _ in 0..READERS {
+        let analytics = analytics.clone();
+        tasks.push(tokio::spawn(async move {
+            for _ in 0..READS_PER_READER {
+                analytics.get_analytics().await.expect("read analytics");
+            }
+        }));
+    }
+
+    let all = futures::future::try_join_all(tasks);
+    tokio::time::timeout(Duration::from_secs(60), all)
+        .await
+        .expect("stress test timed out")
+        .expect("task panicked");
+
+    let totals = analytics.get_analytics().await.expect("final read");
+    assert_eq!(totals.total_requests, WRITERS * SAVES_PER_WRITER);
+}
@@ -0,0 +1,98 @@
+// filters.rs
+mod common;
+
+use chrono::{DateTime, Local, Utc};
+use common::{entry, temp_rag};
+use rusqlite::params;
+use rustai::models::ConversationFilter;
+use rustai::rag::RagSystem;
+
+fn at(rfc3339: &str) -> DateTime<Local> {
+    rfc3339.parse::<DateTime<Utc>>().unwrap().with_timezone(&Local)
+}
+
+async fn prompts(rag: &RagSystem, filter: ConversationFilter) -> Vec<String> {
+    let mut prompts: Vec<String> = rag
+        .list_conversations(0, 50, &filter)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.prompt)
+        .collect();
+    prompts.sort();
+    prompts
+}
+
+// Rows written on machines in different time zones, stored with their own offsets
+async fn seed_offsets(rag: &RagSystem) {
+    rag.with_transaction(|tx| {
+        let mut insert = tx.prepare_cached(
+            "INSERT INTO conversations (timestamp, prompt, response, model_used, response_time_ms, status,
+                                        prompt_tokens, response_tokens)
+             VALUES (?1, ?2, 'answer', 'test-model', 100, 'ok', 1, 1)",
+        )?;
+        // 05:00 UTC, sorts after the next row as a string
+        insert.execute(params!["2026-03-01T10:00:00+05:00", "kolkata-ish"])?;
+        // 07:00 UTC
+        insert.execute(params!["2026-03-01T07:00:00+00:00", "london"])?;
+        // 09:00 UTC, sorts first as a string
+        insert.execute(params!["2026-03-01T01:00:00-08:00", "seattle"])?;
+        Ok(())
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn date_bounds_compare_instants_across_offsets() {
+    let (_dir, rag) = temp_rag();
+    seed_offsets(&rag).await;
+
+    let after = ConversationFilter { after: Some(at("2026-03-01T06:00:00Z")), ..Default::default() };
+    assert_eq!(prompts(&rag, after).await, ["london", "seattle"]);
+
+    let before = ConversationFilter { before: Some(at("2026-03-01T08:00:00Z")), ..Default::default() };
+    assert_eq!(prompts(&rag, before).await, ["kolkata-ish", "london"]);
+
+    let between = ConversationFilter {
+        after: Some(at("2026-03-01T06:00:00Z")),
+        before: Some(at("2026-03-01T08:00:00Z")),
+        ..Default::default()
+    };
+    assert_eq!(prompts(&rag, between).await, ["london"]);
+}
+
+#[tokio::test]
+async fn text_filter_takes_wildcards_literally() {
+    let (_dir, rag) = temp_rag();
+    for prompt in ["100% sure", "1000 sure", "snake_case name", "snakeXcase name", "back\\slash"] {
+        rag.save_conversation(&entry(prompt, "answer")).await.unwrap();
+    }
+
+    let text = |text: &str| ConversationFilter { text: Some(text.to_string()), ..Default::default() };
+    assert_eq!(prompts(&rag, text("100%")).await, ["100% sure"]);
+    assert_eq!(prompts(&rag, text("snake_case")).await, ["snake_case name"]);
+    assert_eq!(prompts(&rag, text("back\\slash")).await, ["back\\slash"]);
+}
+
+#[tokio::test]
+async fn model_tag_and_feedback_filters_combine() {
+    let (_dir, rag) = temp_rag();
+    let mut other_model = entry("from qwen", "answer");
+    other_model.model_used = "qwen".to_string();
+    rag.save_conversation(&other_model).await.unwrap();
+    let tagged = rag.save_conversation(&entry("tagged", "answer")).await.unwrap();
+    rag.add_tag(tagged, "Work").await.unwrap();
+    let downvoted = rag.save_conversation(&entry("downvoted", "answer")).await.unwrap();
+    rag.add_tag(downvoted, "work").await.unwrap();
+    rag.set_feedback(downvoted, -1).await.unwrap();
+
+    let model = ConversationFilter { model: Some("qwen".to_string()), ..Default::default() };
+    assert_eq!(prompts(&rag, model).await, ["from qwen"]);
+
+    let tag = ConversationFilter { tag: Some("work".to_string()), ..Default::default() };
+    assert_eq!(prompts(&rag, tag).await, ["downvoted", "tagged"]);
+
+    let upvoted_only = ConversationFilter { tag: Some("work".to_string()), exclude_downvoted: true, ..Default::default() };
+    assert_eq!(prompts(&rag, upvoted_only).await, ["tagged"]);
+}
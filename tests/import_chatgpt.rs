@@ -0,0 +1,73 @@
+// import_chatgpt.rs
+mod common;
+
+use common::temp_rag;
+use rustai::import::{import_chatgpt, parse_chatgpt, CHATGPT_SOURCE};
+use std::path::PathBuf;
+
+const FIXTURE: &str = include_str!("fixtures/chatgpt_conversations.json");
+
+fn fixture_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/chatgpt_conversations.json")
+}
+
+#[test]
+fn follows_the_kept_branch_and_drops_system_and_tool_nodes() {
+    let (sessions, skipped) = parse_chatgpt(FIXTURE).unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(skipped, 2);
+
+    let session = &sessions[0];
+    assert_eq!(session.id, "chatgpt-conv-branched");
+    assert_eq!(session.title, "Borrow checker help");
+    assert_eq!(session.created_at.timestamp(), 1_700_000_000);
+
+    let exchanges: Vec<(&str, &str)> = session.entries
+        .iter()
+        .map(|entry| (entry.prompt.as_str(), entry.response.as_str()))
+        .collect();
+    assert_eq!(exchanges, [
+        ("Why does the borrow checker reject this?", "Because the reference outlives the value."),
+        ("How do I fix it?", "Clone the value or shorten the borrow."),
+    ]);
+    assert_eq!(session.entries[1].model_used, "gpt-4o");
+    assert_eq!(session.entries[1].response_time_ms, 10_000);
+    assert_eq!(session.entries[1].timestamp.timestamp(), 1_700_000_110);
+    assert!(session.entries.iter().all(|entry| entry.source.as_deref() == Some(CHATGPT_SOURCE)));
+}
+
+#[test]
+fn without_a_current_node_the_newest_reply_is_kept() {
+    let (sessions, _) = parse_chatgpt(FIXTURE).unwrap();
+    let session = &sessions[1];
+    assert_eq!(session.id, "chatgpt-conv-no-current");
+    assert_eq!(session.title, "Imported from ChatGPT");
+    assert_eq!(session.entries.len(), 1);
+    assert_eq!(session.entries[0].response, "Hi there");
+    assert_eq!(session.entries[0].model_used, "chatgpt");
+}
+
+#[test]
+fn rejects_a_file_that_is_not_an_export() {
+    assert!(parse_chatgpt("{\"conversations\": []}").is_err());
+}
+
+#[tokio::test]
+async fn import_creates_sessions_and_skips_them_the_second_time() {
+    let (_dir, rag) = temp_rag();
+
+    let report = import_chatgpt(&rag, fixture_path()).await.unwrap();
+    assert_eq!(report.imported, 2);
+    assert_eq!(report.turns, 3);
+    assert_eq!(report.skipped, 2);
+
+    let title = rag.session_title("chatgpt-conv-branched".to_string()).await.unwrap();
+    assert_eq!(title.as_deref(), Some("Borrow checker help"));
+    let entries = rag.session_conversations("chatgpt-conv-branched".to_string()).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].prompt, "Why does the borrow checker reject this?");
+
+    let again = import_chatgpt(&rag, fixture_path()).await.unwrap();
+    assert_eq!(again.imported, 0);
+    assert_eq!(again.skipped, 4);
+}
@@ -0,0 +1,33 @@
+// ingestion.rs
+mod common;
+
+use common::temp_rag;
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: usize = 50;
+const PARAGRAPHS_PER_DOCUMENT: usize = 100;
+// One transaction per chunk took several seconds for this many, one for the batch should
+// stay well under a second even in a debug build
+const INGEST_LIMIT: Duration = Duration::from_secs(1);
+
+// Paragraphs too long to share a chunk, so each becomes exactly one
+fn document(index: usize) -> String {
+    (0..PARAGRAPHS_PER_DOCUMENT)
+        .map(|paragraph| format!("Document {} paragraph {}. {}", index, paragraph, "lorem ipsum ".repeat(80)))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn five_thousand_chunks_ingest_in_one_batch_quickly() {
+    let (_dir, rag) = temp_rag();
+    let documents: Vec<(String, String)> = (0..DOCUMENTS).map(|index| (format!("doc-{}.md", index), document(index))).collect();
+
+    let started = Instant::now();
+    let chunks = rag.add_documents(documents, Some("v1".to_string())).await.unwrap();
+    let elapsed = started.elapsed();
+
+    assert_eq!(chunks, DOCUMENTS * PARAGRAPHS_PER_DOCUMENT);
+    assert_eq!(rag.list_documents().await.unwrap().len(), DOCUMENTS);
+    assert!(elapsed < INGEST_LIMIT, "ingesting {} chunks took {:?}", chunks, elapsed);
+}
@@ -0,0 +1,72 @@
+// ollama_errors.rs
+use rustai::backend::LlmBackend;
+use rustai::models::AppError;
+use rustai::ollama::OllamaClient;
+use tokio::sync::Notify;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const MISSING_MODEL: &str = "missing-model";
+
+async fn server_answering_404(endpoint: &str) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(endpoint))
+        .respond_with(ResponseTemplate::new(404).set_body_string(r#"{"error":"model 'missing-model' not found"}"#))
+        .mount(&server)
+        .await;
+    server
+}
+
+fn client(server: &MockServer) -> OllamaClient {
+    OllamaClient::new(format!("{}/api/generate", server.uri()))
+}
+
+fn assert_model_not_found(result: Result<impl std::fmt::Debug, AppError>) {
+    match result {
+        Err(AppError::ModelNotFound(model)) => assert_eq!(model, MISSING_MODEL),
+        other => panic!("expected ModelNotFound, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn generate_404_is_model_not_found() {
+    let server = server_answering_404("/api/generate").await;
+    let result = client(&server).generate_response(MISSING_MODEL, "hello", &[], None).await;
+    assert_model_not_found(result);
+}
+
+#[tokio::test]
+async fn stream_404_is_model_not_found() {
+    let server = server_answering_404("/api/generate").await;
+    let cancel = Notify::new();
+    let result = client(&server)
+        .generate_stream(MISSING_MODEL, "hello", &[], &cancel, &mut |_| {})
+        .await;
+    assert_model_not_found(result);
+}
+
+#[tokio::test]
+async fn embed_404_is_model_not_found() {
+    let server = server_answering_404("/api/embeddings").await;
+    let result = client(&server).embed(MISSING_MODEL, "hello").await;
+    assert_model_not_found(result);
+}
+
+// Listing models names no model, so a 404 there is just an HTTP error
+#[tokio::test]
+async fn list_models_404_stays_http() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/tags"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("404 page not found"))
+        .mount(&server)
+        .await;
+    match client(&server).list_models().await {
+        Err(AppError::Http { status, body }) => {
+            assert_eq!(status, 404);
+            assert_eq!(body, "404 page not found");
+        }
+        other => panic!("expected Http, got {:?}", other),
+    }
+}
@@ -0,0 +1,171 @@
+// ollama_http.rs
+use rustai::backend::LlmBackend;
+use rustai::models::{AppError, ChatTurn};
+use rustai::ollama::OllamaClient;
+use serde_json::json;
+use tokio::sync::Notify;
+use wiremock::matchers::{body_json, body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn client(server: &MockServer) -> OllamaClient {
+    OllamaClient::new(format!("{}/api/generate", server.uri()))
+}
+
+#[tokio::test]
+async fn generate_sends_model_prompt_and_limit() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_json(json!({
+            "model": "llama3",
+            "prompt": "hello",
+            "stream": false,
+            "options": { "num_predict": 32 },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "response": "hi there", "done": true })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let reply = client(&server).generate_response("llama3", "hello", &[], Some(32)).await.expect("generate");
+    assert_eq!(reply, "hi there");
+}
+
+#[tokio::test]
+async fn generate_json_asks_for_json_format() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_partial_json(json!({ "format": "json", "stream": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "response": "{}" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let reply = client(&server).generate_json("llama3", "title this", 20).await.expect("generate");
+    assert_eq!(reply, "{}");
+}
+
+#[tokio::test]
+async fn chat_posts_messages_to_api_chat() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_json(json!({
+            "model": "llama3",
+            "messages": [
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello!" },
+                { "role": "user", "content": "How are you?" },
+            ],
+            "stream": false,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "message": { "role": "assistant", "content": "Fine, thanks." },
+            "done": true,
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let messages = [ChatTurn::user("Hi"), ChatTurn::assistant("Hello!"), ChatTurn::user("How are you?")];
+    let reply = client(&server).chat("llama3", &messages, None).await.expect("chat");
+    assert_eq!(reply, "Fine, thanks.");
+}
+
+#[tokio::test]
+async fn stream_joins_newline_delimited_chunks() {
+    let server = MockServer::start().await;
+    let body = concat!(
+        r#"{"response":"Hello","done":false}"#, "\n",
+        r#"{"response":", world","done":false}"#, "\n",
+        r#"{"response":"","done":true}"#, "\n",
+    );
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_partial_json(json!({ "stream": true })))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&server)
+        .await;
+
+    let cancel = Notify::new();
+    let mut chunks = Vec::new();
+    let generation = client(&server)
+        .generate_stream("llama3", "hello", &[], &cancel, &mut |chunk| chunks.push(chunk))
+        .await
+        .expect("stream");
+    assert_eq!(generation.text, "Hello, world");
+    assert_eq!(chunks, vec!["Hello".to_string(), ", world".to_string()]);
+    assert!(generation.first_token_ms.is_some());
+}
+
+#[tokio::test]
+async fn stream_error_line_is_model_not_found() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("{\"error\":\"model \\\"llama3\\\" not found\"}\n"))
+        .mount(&server)
+        .await;
+
+    let cancel = Notify::new();
+    let result = client(&server).generate_stream("llama3", "hello", &[], &cancel, &mut |_| {}).await;
+    assert!(matches!(result, Err(AppError::ModelNotFound(model)) if model == "llama3"));
+}
+
+#[tokio::test]
+async fn server_error_keeps_status_and_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("  out of memory \n"))
+        .mount(&server)
+        .await;
+
+    match client(&server).generate_response("llama3", "hello", &[], None).await {
+        Err(AppError::Http { status, body }) => {
+            assert_eq!(status, 500);
+            assert_eq!(body, "out of memory");
+        }
+        other => panic!("expected Http, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn undecodable_body_is_parse_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+        .mount(&server)
+        .await;
+
+    let result = client(&server).generate_response("llama3", "hello", &[], None).await;
+    assert!(matches!(result, Err(AppError::Parse(_))));
+}
+
+#[tokio::test]
+async fn unreachable_server_is_connection_error() {
+    // Started only to get a free port, then dropped so nothing listens there
+    let server = MockServer::start().await;
+    let client = client(&server);
+    drop(server);
+
+    let result = client.generate_response("llama3", "hello", &[], None).await;
+    assert!(matches!(result, Err(AppError::Connection(_))));
+}
+
+#[tokio::test]
+async fn list_models_reads_tags_sorted() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/tags"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "models": [{ "name": "mistral:latest" }, { "name": "llama3:8b" }],
+        })))
+        .mount(&server)
+        .await;
+
+    let models = client(&server).list_models().await.expect("list");
+    assert_eq!(models, vec!["llama3:8b".to_string(), "mistral:latest".to_string()]);
+}
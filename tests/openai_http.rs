@@ -0,0 +1,77 @@
+// openai_http.rs
+use rustai::backend::LlmBackend;
+use rustai::models::AppError;
+use rustai::openai::OpenAiCompatBackend;
+use serde_json::json;
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn backend(server: &MockServer) -> OpenAiCompatBackend {
+    OpenAiCompatBackend::new(format!("{}/v1", server.uri()), Some("secret".to_string()))
+}
+
+async fn server_answering(status: u16, body: &str) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(status).set_body_string(body))
+        .mount(&server)
+        .await;
+    server
+}
+
+#[tokio::test]
+async fn completion_sends_bearer_key_and_user_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(header("authorization", "Bearer secret"))
+        .and(body_partial_json(json!({
+            "model": "qwen",
+            "messages": [{ "role": "user", "content": "hello" }],
+            "stream": false,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "role": "assistant", "content": "hi" } }],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let reply = backend(&server).generate_response("qwen", "hello", &[], None).await.expect("generate");
+    assert_eq!(reply, "hi");
+}
+
+#[tokio::test]
+async fn openai_model_not_found_code_is_model_not_found() {
+    let body = r#"{"error":{"message":"The model `qwen` does not exist","type":"invalid_request_error","code":"model_not_found"}}"#;
+    let server = server_answering(404, body).await;
+    let result = backend(&server).generate_response("qwen", "hello", &[], None).await;
+    assert!(matches!(result, Err(AppError::ModelNotFound(model)) if model == "qwen"));
+}
+
+#[tokio::test]
+async fn plain_message_about_missing_model_is_model_not_found() {
+    let server = server_answering(404, r#"{"error":"Model not found: qwen"}"#).await;
+    let result = backend(&server).generate_response("qwen", "hello", &[], None).await;
+    assert!(matches!(result, Err(AppError::ModelNotFound(_))));
+}
+
+// A wrong base URL also gives 404, which says nothing about the model
+#[tokio::test]
+async fn unrelated_404_stays_http() {
+    let server = server_answering(404, "Cannot POST /v1/chat/completions").await;
+    match backend(&server).generate_response("qwen", "hello", &[], None).await {
+        Err(AppError::Http { status, body }) => {
+            assert_eq!(status, 404);
+            assert_eq!(body, "Cannot POST /v1/chat/completions");
+        }
+        other => panic!("expected Http, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn model_not_found_message_does_not_mention_ollama() {
+    let error = AppError::ModelNotFound("qwen".to_string());
+    assert!(!error.to_string().to_lowercase().contains("ollama"));
+}
@@ -0,0 +1,165 @@
+// pipeline.rs
+mod common;
+
+use rustai::backend::{LlmBackend, MockBackend};
+use rustai::models::{AppError, ChatTurn, ConversationFilter, ConversationStatus, PendingOperation, RetrievalOptions};
+use rustai::pipeline::{ApiBridge, ApiSettings, GenerationJob, OpSender};
+use rustai::plugins::{PluginContext, PluginManager, Stage};
+use rustai::rag::RagSystem;
+use rustai::server::{AskHandler, AskRequest};
+use std::sync::{mpsc, Arc};
+use tokio::sync::{Notify, RwLock};
+
+// An OpSender with nothing to wake, and the receiving end the window would drain
+fn op_channel() -> (OpSender, mpsc::Receiver<PendingOperation>) {
+    let (tx, rx) = mpsc::channel();
+    (OpSender::new(tx, || {}), rx)
+}
+
+fn job(backend: MockBackend, rag: &RagSystem, keep_failed: bool, pending_ops: OpSender) -> GenerationJob {
+    GenerationJob {
+        backend: Arc::new(backend),
+        rag_system: Some(rag.clone()),
+        analytics_engine: None,
+        prompt: "What is Rust?".to_string(),
+        original_prompt: "What is Rust?".to_string(),
+        images: Vec::new(),
+        file_context: None,
+        tags: Vec::new(),
+        keep_failed,
+        session_id: "chat-test".to_string(),
+        plugins: Arc::new(RwLock::new(PluginManager::new())),
+        plugin_context: PluginContext::new(Stage::PrePrompt, "mock".to_string()),
+        webhook: None,
+        pending_ops,
+    }
+}
+
+// Prompt in, streamed reply out, saved, then found again by listing and by retrieval
+#[tokio::test]
+async fn prompt_response_save_retrieve() {
+    let (_dir, rag) = common::temp_rag();
+    let backend = MockBackend::new().then_reply("Use serde_json::from_str to parse JSON in Rust.");
+
+    let prompt = "How do I parse JSON in Rust?";
+    let cancel = Notify::new();
+    let mut chunks = Vec::new();
+    let generation = backend
+        .generate_stream("mock", prompt, &[], &cancel, &mut |chunk| chunks.push(chunk))
+        .await
+        .expect("generate");
+    assert!(!generation.cancelled);
+    assert_eq!(chunks.concat(), generation.text);
+    assert_eq!(backend.prompts(), vec![prompt.to_string()]);
+
+    let mut entry = common::entry(prompt, &generation.text);
+    entry.model_used = "mock".to_string();
+    let id = rag.save_conversation(&entry).await.expect("save");
+
+    let listed = rag.list_conversations(0, 10, &ConversationFilter::default()).await.expect("list");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, id);
+    assert_eq!(listed[0].prompt, prompt);
+    assert_eq!(listed[0].response, generation.text);
+    assert_eq!(listed[0].model_used, "mock");
+
+    let similar = rag
+        .find_similar_responses("parse json with serde", 5, &RetrievalOptions::default())
+        .await
+        .expect("retrieve");
+    assert_eq!(similar.first().map(|scored| scored.entry.id), Some(id));
+}
+
+#[tokio::test]
+async fn chat_answers_the_last_message() {
+    let backend = MockBackend::new().then_reply("Paris");
+    let messages = [
+        ChatTurn::system("Answer in one word."),
+        ChatTurn::user("What is the capital of France?"),
+    ];
+    let reply = backend.chat("mock", &messages, None).await.expect("chat");
+    assert_eq!(reply, "Paris");
+    assert_eq!(backend.prompts(), vec!["What is the capital of France?".to_string()]);
+}
+
+// A failed generation leaves nothing behind to retrieve
+#[tokio::test]
+async fn injected_failure_saves_nothing() {
+    let (_dir, rag) = common::temp_rag();
+    let backend = MockBackend::new().then_fail(|model| AppError::ModelNotFound(model.to_string()));
+
+    let result = backend.generate_response("llama3", "hello", &[], None).await;
+    assert!(matches!(result, Err(AppError::ModelNotFound(model)) if model == "llama3"));
+
+    let listed = rag.list_conversations(0, 10, &ConversationFilter::default()).await.expect("list");
+    assert!(listed.is_empty());
+}
+
+// A job's answer is saved and filed under its session
+#[tokio::test]
+async fn generation_job_saves_the_answer_in_its_session() {
+    let (_dir, rag) = common::temp_rag();
+    let (pending_ops, ops) = op_channel();
+    let job = job(MockBackend::new().then_reply("A systems language."), &rag, false, pending_ops);
+
+    let response = job.run("mock".to_string()).await;
+    assert_eq!(response.result.as_ref().map(|parsed| parsed.answer.as_str()), Ok("A systems language."));
+    let id = response.conversation_id.expect("saved");
+
+    let session = rag.session_conversations("chat-test".to_string()).await.expect("session");
+    assert_eq!(session.iter().map(|entry| entry.id).collect::<Vec<_>>(), vec![id]);
+    assert_eq!(session[0].prompt, "What is Rust?");
+    assert!(ops.try_iter().all(|op| !matches!(op, PendingOperation::Error(_))));
+}
+
+#[tokio::test]
+async fn failed_job_is_only_saved_when_kept() {
+    let (_dir, rag) = common::temp_rag();
+    let (pending_ops, _ops) = op_channel();
+
+    let dropped = job(MockBackend::new().then_fail(|_| AppError::Timeout), &rag, false, pending_ops.clone());
+    let response = dropped.run("mock".to_string()).await;
+    assert!(response.result.is_err());
+    assert_eq!(response.conversation_id, None);
+    assert!(rag.list_conversations(0, 10, &ConversationFilter::default()).await.expect("list").is_empty());
+
+    let kept = job(MockBackend::new().then_fail(|_| AppError::Timeout), &rag, true, pending_ops);
+    let response = kept.run("mock".to_string()).await;
+    assert!(response.result.is_err());
+    assert!(response.conversation_id.is_some());
+    let listed = rag.list_conversations(0, 10, &ConversationFilter::default()).await.expect("list");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].status, ConversationStatus::Error);
+}
+
+// API requests are answered and mirrored into the open chat, prompt first
+#[tokio::test]
+async fn api_bridge_answers_and_mirrors_into_the_chat() {
+    let (_dir, rag) = common::temp_rag();
+    let (pending_ops, ops) = op_channel();
+    let bridge = ApiBridge {
+        settings: Arc::new(std::sync::RwLock::new(ApiSettings {
+            backend: Arc::new(MockBackend::new().then_reply("Pong")),
+            model: "mock".to_string(),
+            session_id: "chat-api".to_string(),
+            keep_failed: false,
+            webhook: None,
+        })),
+        rag_system: Some(rag.clone()),
+        analytics_engine: None,
+        plugins: Arc::new(RwLock::new(PluginManager::new())),
+        pending_ops,
+    };
+
+    let request: AskRequest = serde_json::from_value(serde_json::json!({ "prompt": "Ping" })).expect("request");
+    let response = bridge.ask(request).await.expect("ask");
+    assert_eq!(response.response, "Pong");
+    assert_eq!(response.model, "mock");
+    assert!(response.conversation_id.is_some());
+
+    let ops: Vec<PendingOperation> = ops.try_iter().collect();
+    assert!(matches!(ops.as_slice(), [
+        PendingOperation::ExternalPrompt(prompt),
+        PendingOperation::ExternalResponse(mirrored),
+    ] if prompt == "Ping" && mirrored.conversation_id == response.conversation_id));
+}
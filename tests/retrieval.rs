@@ -0,0 +1,70 @@
+// retrieval.rs
+mod common;
+
+use chrono::{Duration, Local};
+use common::{entry, entry_at, temp_rag};
+use rustai::models::{HybridQuery, RetrievalOptions};
+
+#[tokio::test]
+async fn more_matched_keywords_beat_a_newer_partial_match() {
+    let (_dir, rag) = temp_rag();
+    let older = rag
+        .save_conversation(&entry_at(
+            "Parsing JSON in Rust",
+            "Use serde_json to parse the string into a struct.",
+            Local::now() - Duration::days(30),
+        ))
+        .await
+        .unwrap();
+    for prompt in ["Rust lifetimes explained", "Rust borrow checker errors", "Cooking rice"] {
+        rag.save_conversation(&entry(prompt, "Some answer.")).await.unwrap();
+    }
+
+    let options = RetrievalOptions { include_documents: false, ..Default::default() };
+    let results = rag.find_similar_responses("How do I parse JSON in Rust", 5, &options).await.unwrap();
+
+    assert_eq!(results[0].entry.id, older);
+    assert!(results.iter().all(|result| result.entry.prompt != "Cooking rice"));
+    assert!(results[0].score > results[1].score);
+}
+
+// One axis per topic, so a query's embedding can be pointed at a topic without sharing a word
+fn axis(index: usize) -> Vec<f32> {
+    let mut vector = vec![0.0; 8];
+    vector[index] = 1.0;
+    vector
+}
+
+#[tokio::test]
+async fn hybrid_finds_exact_identifiers_and_paraphrases() {
+    let (_dir, rag) = temp_rag();
+    let identifier = rag
+        .save_conversation(&entry("Where is parse_config_v2 called?", "parse_config_v2 runs once in main before the window opens."))
+        .await
+        .unwrap();
+    let shutdown = rag
+        .save_conversation(&entry("Why does the program hang when closing?", "Worker threads are joined on exit, make them stop sooner."))
+        .await
+        .unwrap();
+    let pasta = rag
+        .save_conversation(&entry("How long should pasta boil?", "Eight to ten minutes in salted water."))
+        .await
+        .unwrap();
+    rag.store_embeddings("test-embed", vec![(identifier, axis(0)), (shutdown, axis(1)), (pasta, axis(2))])
+        .await
+        .unwrap();
+
+    let options = |embedding| RetrievalOptions {
+        hybrid: Some(HybridQuery { embedding, keyword_weight: 0.6 }),
+        include_documents: false,
+        ..Default::default()
+    };
+
+    // The embedding points elsewhere, the identifier match has to carry it
+    let exact = rag.find_similar_responses("parse_config_v2", 3, &options(axis(2))).await.unwrap();
+    assert_eq!(exact[0].entry.id, identifier);
+
+    // No word in common with the saved exchange, only the embedding connects them
+    let paraphrase = rag.find_similar_responses("speed up quitting", 3, &options(axis(1))).await.unwrap();
+    assert_eq!(paraphrase[0].entry.id, shutdown);
+}
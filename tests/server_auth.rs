@@ -0,0 +1,100 @@
+// server_auth.rs
+use async_trait::async_trait;
+use rustai::models::AppError;
+use rustai::server::{self, ApiServer, AskHandler, AskRequest, AskResponse};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Default)]
+struct CountingHandler {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl AskHandler for CountingHandler {
+    async fn ask(&self, request: AskRequest) -> Result<AskResponse, AppError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(AskResponse {
+            response: format!("echo: {}", request.prompt),
+            reasoning: None,
+            model: "mock".to_string(),
+            response_time_ms: 0,
+            conversation_id: None,
+        })
+    }
+}
+
+async fn serve(handler: Arc<CountingHandler>) -> ApiServer {
+    server::start("127.0.0.1:0", "secret", handler, None, None, |_| {}).await.unwrap()
+}
+
+fn ask_url(server: &ApiServer) -> String {
+    format!("http://{}/api/ask", server.address)
+}
+
+#[tokio::test]
+async fn malformed_body_without_token_is_unauthorized_not_a_parse_error() {
+    let handler = Arc::new(CountingHandler::default());
+    let server = serve(handler.clone()).await;
+
+    let response = reqwest::Client::new()
+        .post(ask_url(&server))
+        .header("content-type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(handler.calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn wrong_token_is_rejected() {
+    let handler = Arc::new(CountingHandler::default());
+    let server = serve(handler.clone()).await;
+
+    for token in ["secres", "secret2", ""] {
+        let response = reqwest::Client::new()
+            .post(ask_url(&server))
+            .bearer_auth(token)
+            .json(&serde_json::json!({ "prompt": "hello" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 401, "token {:?}", token);
+    }
+    assert_eq!(handler.calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn right_token_reaches_the_handler() {
+    let handler = Arc::new(CountingHandler::default());
+    let server = serve(handler.clone()).await;
+
+    let response = reqwest::Client::new()
+        .post(ask_url(&server))
+        .bearer_auth("secret")
+        .json(&serde_json::json!({ "prompt": "hello" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["response"], "echo: hello");
+    assert_eq!(handler.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn other_routes_need_the_token_too() {
+    let server = serve(Arc::new(CountingHandler::default())).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/api/analytics", server.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,62 @@
+// storage.rs
+mod common;
+
+use rustai::analytics::AnalyticsEngine;
+use rustai::migrations;
+use rustai::models::{ConversationFilter, RetrievalOptions};
+use rustai::rag::RagSystem;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn saved_conversation_is_listed_and_retrieved() {
+    let (_dir, rag) = common::temp_rag();
+    let id = rag.save_conversation(&common::entry("How do tokio channels work", "Senders and receivers.")).await.unwrap();
+    rag.save_conversation(&common::entry("Best pizza dough", "Flour and water.")).await.unwrap();
+
+    let listed = rag.list_conversations(0, 10, &ConversationFilter::default()).await.unwrap();
+    assert_eq!(listed.len(), 2);
+
+    let options = RetrievalOptions { include_documents: false, ..Default::default() };
+    let found = rag.find_similar_responses("tokio channels", 5, &options).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].entry.id, id);
+    assert_eq!(found[0].entry.response, "Senders and receivers.");
+}
+
+#[tokio::test]
+async fn analytics_opened_on_the_same_directory_sees_saved_rows() {
+    let (dir, rag) = common::temp_rag();
+    for prompt in ["one", "two", "three"] {
+        rag.save_conversation(&common::entry(prompt, "answer")).await.unwrap();
+    }
+
+    let analytics = AnalyticsEngine::new_in(dir.path()).unwrap().get_analytics().await.unwrap();
+    assert_eq!(analytics.total_requests, 3);
+    assert_eq!(analytics.most_used_model, "test-model");
+}
+
+#[tokio::test]
+async fn directories_do_not_share_data() {
+    let (_first, rag) = common::temp_rag();
+    let (_second, other) = common::temp_rag();
+    rag.save_conversation(&common::entry("only in the first", "answer")).await.unwrap();
+
+    let listed = other.list_conversations(0, 10, &ConversationFilter::default()).await.unwrap();
+    assert!(listed.is_empty());
+}
+
+#[tokio::test]
+async fn fresh_directory_is_migrated_and_reopening_keeps_rows() {
+    let dir = TempDir::new().unwrap();
+    {
+        let rag = RagSystem::new_in(dir.path()).unwrap();
+        rag.save_conversation(&common::entry("kept", "answer")).await.unwrap();
+    }
+
+    let rag = RagSystem::new_in(dir.path()).unwrap();
+    let version = rag.database().read(|connection| migrations::current_version(connection)).await.unwrap();
+    assert_eq!(version, migrations::latest_version());
+    let listed = rag.list_conversations(0, 10, &ConversationFilter::default()).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].prompt, "kept");
+}